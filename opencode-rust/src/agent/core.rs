@@ -6,6 +6,12 @@ pub struct Agent {
     tools: Vec<Arc<dyn Tool>>,
 }
 
+impl Default for Agent {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Agent {
     pub fn new() -> Self {
         Self { tools: Vec::new() }
@@ -4,6 +4,12 @@ pub struct Session {
     id: Uuid,
 }
 
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Session {
     pub fn new() -> Self {
         Self { id: Uuid::new_v4() }
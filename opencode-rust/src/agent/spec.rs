@@ -8,20 +8,15 @@ use serde_json::Value as JsonValue;
 use crate::tool::core::Tool;
 use crate::util::config::{AgentConfig, AgentMode as ConfigAgentMode};
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum AgentMode {
     Primary,
     Subagent,
+    #[default]
     All,
 }
 
-impl Default for AgentMode {
-    fn default() -> Self {
-        AgentMode::All
-    }
-}
-
 impl From<ConfigAgentMode> for AgentMode {
     fn from(value: ConfigAgentMode) -> Self {
         match value {
@@ -94,6 +89,8 @@ impl ToolRules {
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct AgentBudgets {
+    /// Output tokens the agent may produce over its session, like a
+    /// provider's `max_tokens`; the prompt it is sent does not count.
     pub max_tokens: Option<u32>,
     pub tool_timeout: Option<Duration>,
     pub wall_clock: Option<Duration>,
@@ -159,6 +156,7 @@ pub struct AgentSpec {
     pub tool_rules: ToolRules,
     pub budgets: AgentBudgets,
     pub report_format: Option<String>,
+    pub diagnostics: bool,
 }
 
 impl AgentSpec {
@@ -175,6 +173,7 @@ impl AgentSpec {
             tool_rules: ToolRules::inherit(),
             budgets: AgentBudgets::default(),
             report_format: None,
+            diagnostics: true,
         }
     }
 
@@ -210,10 +209,10 @@ impl AgentSpec {
         if let Some(report_format) = &definition.report_format {
             self.report_format = Some(report_format.clone());
         }
-        if let Some(prompt) = &definition.prompt {
-            if !prompt.trim().is_empty() {
-                self.prompt_sections = vec![prompt.clone()];
-            }
+        if let Some(prompt) = &definition.prompt
+            && !prompt.trim().is_empty()
+        {
+            self.prompt_sections = vec![prompt.clone()];
         }
         if !definition.prompt_sections.is_empty() {
             self.prompt_sections = definition.prompt_sections.clone();
@@ -224,27 +223,31 @@ impl AgentSpec {
         if let Some(budget_definition) = &definition.budgets {
             self.budgets.merge_definition(budget_definition);
         }
+        if let Some(diagnostics) = definition.diagnostics {
+            self.diagnostics = diagnostics;
+        }
     }
 
     fn extract_extra(&mut self, extra: &HashMap<String, JsonValue>) {
-        if let Some(value) = extra.get("promptSections") {
-            if let Some(sections) = Self::parse_string_array(value) {
-                if !sections.is_empty() {
-                    self.prompt_sections = sections;
-                }
-            }
-        }
-        if let Some(value) = extra.get("budgets") {
-            if let Some(definition) = AgentBudgetsDefinition::from_json(value) {
-                self.budgets.merge_definition(&definition);
-            }
-        }
-        if let Some(value) = extra.get("reportFormat") {
-            if let Some(report_format) = value.as_str() {
-                if !report_format.trim().is_empty() {
-                    self.report_format = Some(report_format.to_string());
-                }
-            }
+        if let Some(value) = extra.get("promptSections")
+            && let Some(sections) = Self::parse_string_array(value)
+            && !sections.is_empty()
+        {
+            self.prompt_sections = sections;
+        }
+        if let Some(value) = extra.get("budgets")
+            && let Some(definition) = AgentBudgetsDefinition::from_json(value)
+        {
+            self.budgets.merge_definition(&definition);
+        }
+        if let Some(value) = extra.get("reportFormat")
+            && let Some(report_format) = value.as_str()
+            && !report_format.trim().is_empty()
+        {
+            self.report_format = Some(report_format.to_string());
+        }
+        if let Some(diagnostics) = extra.get("diagnostics").and_then(JsonValue::as_bool) {
+            self.diagnostics = diagnostics;
         }
     }
 
@@ -280,6 +283,7 @@ pub struct AgentRuntimeDefinition {
     #[serde(default)]
     pub budgets: Option<AgentBudgetsDefinition>,
     pub mode: Option<AgentMode>,
+    pub diagnostics: Option<bool>,
 }
 
 #[derive(Debug, Clone, Default, Deserialize)]
@@ -351,6 +355,11 @@ impl ModelHandle {
     pub fn id(&self) -> &str {
         &self.id
     }
+
+    /// The provider half of a `provider/model` ID.
+    pub fn provider(&self) -> Option<&str> {
+        self.id.split_once('/').map(|(provider, _)| provider)
+    }
 }
 
 impl std::fmt::Display for ModelHandle {
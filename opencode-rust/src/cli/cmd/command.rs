@@ -0,0 +1,46 @@
+use clap::{Args, Subcommand};
+
+use crate::util::config::Info;
+
+#[derive(Args, Debug)]
+pub struct CommandCommand {
+    #[command(subcommand)]
+    pub action: CommandAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CommandAction {
+    /// List custom commands from config and markdown files
+    #[command(name = "list", alias = "ls")]
+    List,
+}
+
+pub async fn execute(cmd: &CommandCommand, config: &Info) -> anyhow::Result<()> {
+    match &cmd.action {
+        CommandAction::List => {
+            let mut commands: Vec<_> = config.command.iter().flatten().collect();
+            if commands.is_empty() {
+                println!("No custom commands found");
+                return Ok(());
+            }
+            commands.sort_by(|a, b| a.0.cmp(b.0));
+            let width = commands
+                .iter()
+                .map(|(name, _)| name.len())
+                .max()
+                .unwrap_or(0)
+                + 1;
+            for (name, command) in commands {
+                let mut line = format!("/{name:<width$}");
+                if let Some(description) = &command.description {
+                    line.push_str(&format!("  {description}"));
+                }
+                if let Some(agent) = &command.agent {
+                    line.push_str(&format!("  (agent: {agent})"));
+                }
+                println!("{}", line.trim_end());
+            }
+        }
+    }
+    Ok(())
+}
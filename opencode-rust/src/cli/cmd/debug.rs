@@ -1,6 +1,14 @@
+use std::path::Path;
+
 use clap::{Args, Subcommand};
 use tracing::info;
 
+use crate::lsp::LspManager;
+use crate::search::{self, SearchOptions};
+use crate::snapshot::Snapshot;
+use crate::util::config::Info;
+use crate::util::paths;
+
 #[derive(Args, Debug)]
 pub struct DebugCommand {
     #[command(subcommand)]
@@ -43,12 +51,18 @@ pub struct DebugSnapshotArgs {
 
 #[derive(Subcommand, Debug)]
 pub enum DebugSnapshotCommand {
+    /// Record the current worktree and print the snapshot hash
     #[command(name = "track")]
     Track,
+    /// List files changed since a snapshot
     #[command(name = "patch")]
     Patch(DebugHashArg),
+    /// Show a unified diff from a snapshot to the worktree
     #[command(name = "diff")]
     Diff(DebugHashArg),
+    /// Restore the worktree to a snapshot
+    #[command(name = "restore")]
+    Restore(DebugHashArg),
 }
 
 #[derive(Args, Debug)]
@@ -146,13 +160,15 @@ pub struct DebugUriArg {
     pub uri: String,
 }
 
-pub async fn execute(cmd: &DebugCommand) -> anyhow::Result<()> {
+pub async fn execute(cmd: &DebugCommand, config: &Info) -> anyhow::Result<()> {
     match &cmd.action {
         DebugAction::Wait => {
             info!("debug wait");
         }
         DebugAction::Paths => {
             info!("debug paths");
+            println!("config  {}", paths::config_dir().display());
+            println!("data    {}", paths::data_dir().display());
         }
         DebugAction::Config => {
             info!("debug config");
@@ -160,28 +176,75 @@ pub async fn execute(cmd: &DebugCommand) -> anyhow::Result<()> {
         DebugAction::Scrap => {
             info!("debug scrap");
         }
-        DebugAction::Snapshot(snapshot) => match &snapshot.command {
-            DebugSnapshotCommand::Track => {
-                info!("debug snapshot track");
-            }
-            DebugSnapshotCommand::Patch(hash) => {
-                info!(hash = %hash.hash, "debug snapshot patch");
-            }
-            DebugSnapshotCommand::Diff(hash) => {
-                info!(hash = %hash.hash, "debug snapshot diff");
-            }
-        },
-        DebugAction::Ripgrep(rg) => match &rg.command {
-            DebugRipgrepCommand::Tree(args) => {
-                info!(limit = ?args.limit, "debug rg tree");
-            }
-            DebugRipgrepCommand::Files(args) => {
-                info!(query = ?args.query, glob = ?args.glob, limit = ?args.limit, "debug rg files");
+        DebugAction::Snapshot(args) => {
+            let snapshot = Snapshot::from_info(std::env::current_dir()?, config);
+            match &args.command {
+                DebugSnapshotCommand::Track => {
+                    info!("debug snapshot track");
+                    match snapshot.track().await? {
+                        Some(hash) => println!("{hash}"),
+                        None => eprintln!("snapshots are disabled in config"),
+                    }
+                }
+                DebugSnapshotCommand::Patch(hash) => {
+                    info!(hash = %hash.hash, "debug snapshot patch");
+                    let patch = snapshot.patch(&hash.hash).await?;
+                    for file in patch.files {
+                        println!("{}", file.display());
+                    }
+                }
+                DebugSnapshotCommand::Diff(hash) => {
+                    info!(hash = %hash.hash, "debug snapshot diff");
+                    println!("{}", snapshot.diff(&hash.hash).await?);
+                }
+                DebugSnapshotCommand::Restore(hash) => {
+                    info!(hash = %hash.hash, "debug snapshot restore");
+                    snapshot.restore(&hash.hash).await?;
+                }
             }
-            DebugRipgrepCommand::Search(args) => {
-                info!(pattern = %args.pattern, glob = ?args.glob, limit = ?args.limit, "debug rg search");
+        }
+        DebugAction::Ripgrep(rg) => {
+            let root = std::env::current_dir()?;
+            match &rg.command {
+                DebugRipgrepCommand::Tree(args) => {
+                    info!(limit = ?args.limit, "debug rg tree");
+                    let limit = args
+                        .limit
+                        .map_or(search::DEFAULT_TREE_LIMIT, |l| l as usize);
+                    println!("{}", search::tree(&root, limit)?);
+                }
+                DebugRipgrepCommand::Files(args) => {
+                    info!(query = ?args.query, glob = ?args.glob, limit = ?args.limit, "debug rg files");
+                    let options = SearchOptions::new().with_globs(args.glob.clone());
+                    let query = args.query.as_deref().map(str::to_lowercase);
+                    let files = search::files(&root, &options)?
+                        .into_iter()
+                        .filter(|file| {
+                            query.as_deref().is_none_or(|query| {
+                                file.to_string_lossy().to_lowercase().contains(query)
+                            })
+                        })
+                        .take(args.limit.map_or(usize::MAX, |l| l as usize));
+                    for file in files {
+                        println!("{}", file.display());
+                    }
+                }
+                DebugRipgrepCommand::Search(args) => {
+                    info!(pattern = %args.pattern, glob = ?args.glob, limit = ?args.limit, "debug rg search");
+                    let options = SearchOptions::new()
+                        .with_globs(args.glob.clone())
+                        .with_limit(args.limit.map(|l| l as usize));
+                    for found in search::search(&root, &args.pattern, &options)? {
+                        println!(
+                            "{}:{}:{}",
+                            found.path.display(),
+                            found.line_number,
+                            found.line
+                        );
+                    }
+                }
             }
-        },
+        }
         DebugAction::File(file) => match &file.command {
             DebugFileCommand::Search(args) => {
                 info!(query = %args.query, "debug file search");
@@ -199,6 +262,12 @@ pub async fn execute(cmd: &DebugCommand) -> anyhow::Result<()> {
         DebugAction::Lsp(lsp) => match &lsp.command {
             DebugLspCommand::Diagnostics(path) => {
                 info!(path = %path.path, "debug lsp diagnostics");
+                let lsp = LspManager::from_info(std::env::current_dir()?, config);
+                let diagnostics = lsp.diagnostics(Path::new(&path.path)).await;
+                lsp.shutdown().await;
+                for diagnostic in diagnostics? {
+                    println!("{}", diagnostic.pretty());
+                }
             }
             DebugLspCommand::Symbols(query) => {
                 info!(query = %query.query, "debug lsp symbols");
@@ -2,12 +2,14 @@ pub mod acp;
 pub mod agent;
 pub mod attach;
 pub mod auth;
+pub mod command;
 pub mod debug;
 pub mod export;
 pub mod github;
 pub mod mcp;
 pub mod run;
 pub mod serve;
+pub mod session;
 pub mod stats;
 pub mod tui;
 pub mod upgrade;
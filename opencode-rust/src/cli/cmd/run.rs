@@ -2,23 +2,41 @@ use std::path::PathBuf;
 use std::sync::Arc;
 
 use crate::agent::registry::{AgentRegistry, parse_agents_source};
+use crate::agent::session::Session;
 use crate::agent::spec::ModelHandle;
+use crate::command::{self, RenderedCommand};
+use crate::hook::HookRunner;
+use crate::lsp::LspManager;
+use crate::session::store::{self, SessionStore, StoredSession};
+use crate::session::todo::{self, TodoLists};
 use crate::session::{
-    AgentEvent, LocalModel, ProjectContext, SessionPrompts, SessionRequest, SessionResult,
-    SessionRuntime, SubagentOutcome,
+    AgentEvent, Compactor, FailurePolicy, LanguageModel, LocalModel, ProjectContext,
+    RateLimitedModel, RateLimiter, RetryPolicy, RetryingModel, SessionPrompts, SessionRequest,
+    SessionResult, SessionRuntime, SubagentInvocation, SubagentOutcome, SubtaskFailure, TokenUsage,
 };
+use crate::snapshot::{Patch, Snapshot};
 use crate::tool::{
     bash::BashTool,
-    core::Tool,
+    core::{Tool, ToolContext},
     echo::EchoTool,
+    edit::EditTool,
+    file_time::FileTimes,
     fs::{ListFilesTool, ReadFileTool, WriteFileTool},
+    patch::ApplyPatchTool,
+    search::{GlobTool, GrepTool},
+    todo::{TodoReadTool, TodoWriteTool},
     web::WebFetchTool,
+    web_search::WebSearchTool,
 };
 use crate::util::config::Info;
+use crate::watcher::WatchOptions;
 use clap::{Args, ValueEnum};
 use serde::Serialize;
+use tokio::io::AsyncReadExt;
 use tokio::sync::mpsc;
-use tracing::info;
+use tracing::{info, warn};
+
+const DEFAULT_MODEL: &str = "openai/gpt-4o";
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
 pub enum OutputFormat {
@@ -28,7 +46,9 @@ pub enum OutputFormat {
 
 #[derive(Args, Debug)]
 pub struct Run {
-    /// Message to send
+    /// Message to send, or a tool name followed by its arguments. A tool
+    /// argument given as `-` is read from standard input; arguments that
+    /// start with `-`, such as a patch, must follow `--`.
     #[arg()]
     pub message: Vec<String>,
 
@@ -94,23 +114,20 @@ pub async fn execute(cmd: &Run, config: &Info) -> anyhow::Result<()> {
         "run command"
     );
 
-    let tools: Vec<Arc<dyn Tool>> = vec![
-        Arc::new(EchoTool),
-        Arc::new(BashTool),
-        Arc::new(ReadFileTool),
-        Arc::new(WriteFileTool),
-        Arc::new(ListFilesTool),
-        Arc::new(WebFetchTool),
-    ];
-
-    if let Some((tool_name, args)) = cmd.message.split_first() {
-        if let Some(tool) = tools.iter().find(|tool| tool.name() == tool_name) {
-            info!(tool = tool.name(), "executing tool invocation");
-            let output = tool.execute(args).await?;
-            println!("{}", output);
-            return Ok(());
-        }
+    let project_root = std::env::current_dir()?;
+    let lsp = Arc::new(LspManager::from_info(project_root.clone(), config));
+    let hooks = Arc::new(HookRunner::from_info(project_root.clone(), config));
+    let watcher = hooks.clone().watch(WatchOptions::from(config)).await;
+    let store = SessionStore::for_project(&project_root);
+    let stored = load_session(cmd, &store).await?;
+    let todos = Arc::new(TodoLists::new());
+    if let Some(stored) = &stored {
+        todos.set(stored.id, stored.todos.clone());
     }
+    let (event_tx, event_rx) = mpsc::channel(32);
+    let printer = tokio::spawn(print_events(event_rx, cmd.format));
+
+    let tools = tools(config, &lsp, &hooks, &todos, &event_tx);
 
     let mut registry = AgentRegistry::from_info(config);
     if let Some(source) = &cmd.agents_json {
@@ -119,54 +136,112 @@ pub async fn execute(cmd: &Run, config: &Info) -> anyhow::Result<()> {
     }
     registry.ensure_primary();
 
-    let default_model = cmd
-        .model
-        .clone()
-        .or_else(|| config.model.clone())
-        .unwrap_or_else(|| "openai/gpt-4o".to_string());
+    let registry = Arc::new(registry);
 
-    let project_root = std::env::current_dir()?;
-    let context = Arc::new(ProjectContext::gather(project_root, config)?);
-    let (event_tx, mut event_rx) = mpsc::channel(32);
-    let runtime = SessionRuntime::new(
+    let context = Arc::new(ProjectContext::gather(project_root.clone(), config)?);
+    let default_model = default_model(config, cmd.model.as_deref());
+    let snapshot = Arc::new(Snapshot::from_info(project_root.clone(), config));
+    let model = language_model(config, Some(&event_tx));
+    let runtime = session_runtime(
+        config,
         context,
-        Arc::new(registry),
-        Arc::new(LocalModel::default()),
-        tools.clone(),
-        event_tx.clone(),
-        ModelHandle::new(default_model),
-    );
-
-    tokio::spawn(async move {
-        while let Some(event) = event_rx.recv().await {
-            match event {
-                AgentEvent::Started {
-                    session_id,
-                    agent,
-                    objective,
-                } => {
-                    info!(%session_id, %agent, %objective, "subagent started");
-                }
-                AgentEvent::Completed {
-                    session_id,
-                    agent,
-                    summary,
-                } => {
-                    info!(%session_id, %agent, %summary, "subagent completed");
-                }
-            }
+        registry.clone(),
+        tools,
+        event_tx,
+        model,
+        default_model,
+    )
+    .with_hooks(hooks)
+    .with_snapshot(snapshot.clone())
+    .into_shared_with_task();
+
+    if cmd.command.is_none()
+        && let Some((tool_name, args)) = cmd.message.split_first()
+        && let Some(tool) = runtime.tools().iter().find(|tool| tool.name() == tool_name)
+    {
+        info!(tool = tool.name(), "executing tool invocation");
+        let agent_name = cmd
+            .agent
+            .as_deref()
+            .unwrap_or(registry.default_agent_name());
+        let session_id = stored
+            .as_ref()
+            .map_or_else(|| Session::new().id(), |stored| stored.id);
+        let args = stdin_args(args).await?;
+        let file_times = Arc::new(FileTimes::new());
+        if let Some(stored) = &stored {
+            file_times.restore(session_id, &stored.file_times);
+        }
+        let ctx = ToolContext::new(session_id, registry.require_spec(agent_name)?)
+            .with_file_times(file_times.clone());
+        let before = track_snapshot(&snapshot).await;
+        let output = tool.execute_with_context(&ctx, &args).await;
+        let patch = snapshot_patch(&snapshot, before.as_deref()).await;
+        lsp.shutdown().await;
+        if let Some(watcher) = &watcher {
+            watcher.abort();
         }
-    });
+        drop(runtime);
+        let _ = printer.await;
+        let mut stored =
+            stored.unwrap_or_else(|| StoredSession::new(session_id, store::title_for(&message)));
+        stored.todos = todos.get(session_id);
+        stored.file_times = file_times.session(session_id);
+        if let Ok(output) = &output {
+            stored.record_tool(tool_name, output, before, patch);
+        }
+        store.save(&stored).await?;
+        println!("{}", output?);
+        return Ok(());
+    }
 
-    let objective = build_objective(cmd, &message);
+    let request = match &cmd.command {
+        Some(name) => {
+            let command = command::lookup(config, name)?;
+            let rendered = command::render(name, command, &cmd.message, &project_root).await;
+            command_request(cmd, &registry, rendered)
+        }
+        None => RunRequest::Session(SessionRequest {
+            agent: cmd.agent.clone(),
+            model: None,
+            objective: build_objective(cmd, &message),
+            subtasks: Vec::new(),
+            max_parallel: None,
+            failure_policy: FailurePolicy::default(),
+            synthesize: false,
+            history: Vec::new(),
+            session_id: None,
+        }),
+    };
 
-    let request = SessionRequest {
-        agent: cmd.agent.clone(),
-        objective,
-        subtasks: Vec::new(),
+    let (prompt, result) = match request {
+        RunRequest::Session(request) => {
+            let mut request = request;
+            if let Some(stored) = &stored {
+                request = request
+                    .with_history(stored.context_messages().to_vec())
+                    .with_session_id(stored.id);
+            }
+            (request.objective.clone(), runtime.execute(request).await)
+        }
+        RunRequest::Delegate { parent, invocation } => (
+            invocation.objective.clone(),
+            runtime.delegate(parent.as_deref(), invocation).await,
+        ),
     };
+    lsp.shutdown().await;
+    if let Some(watcher) = &watcher {
+        watcher.abort();
+    }
+    drop(runtime);
+    let _ = printer.await;
+    let result = result?;
+
+    let mut stored = StoredSession::with_turn(stored, &prompt, &result);
+    stored.todos = todos.get(stored.id);
+    store.save(&stored).await?;
+    info!(session = %stored.id, "saved session");
 
-    let result = runtime.execute(request).await?;
     if matches!(cmd.format, OutputFormat::Json) {
         let report = RunReport::from(&result);
         let serialized = serde_json::to_string_pretty(&report)?;
@@ -174,26 +249,317 @@ pub async fn execute(cmd: &Run, config: &Info) -> anyhow::Result<()> {
         return Ok(());
     }
 
-    let SessionResult { primary, subtasks } = result;
+    let SessionResult {
+        primary,
+        subtasks,
+        failures,
+        synthesis,
+        ..
+    } = result;
+    if let Some(synthesis) = synthesis {
+        println!("{}", synthesis.summary);
+        return Ok(());
+    }
     println!("{}", primary.summary);
     for outcome in subtasks {
         println!("[{}] {}", outcome.agent, outcome.summary);
     }
+    for failure in failures {
+        println!(
+            "[{}] {}: {}",
+            failure.agent,
+            failure.kind.as_str(),
+            failure.error
+        );
+    }
 
     Ok(())
 }
 
-fn build_objective(cmd: &Run, message: &str) -> String {
-    let mut objective = String::new();
-    if let Some(command) = &cmd.command {
-        objective.push_str(command);
-        if !message.is_empty() {
-            objective.push_str(" -- ");
-            objective.push_str(message);
+/// Reports agent events as they arrive: todo list updates are shown on
+/// stderr, and with `--format json` every event is written there as a JSON
+/// line.
+async fn print_events(mut events: mpsc::Receiver<AgentEvent>, format: OutputFormat) {
+    while let Some(event) = events.recv().await {
+        if matches!(format, OutputFormat::Json) {
+            if let Ok(line) = serde_json::to_string(&event) {
+                eprintln!("{line}");
+            }
+            continue;
+        }
+        match event {
+            AgentEvent::Started {
+                session_id,
+                agent,
+                objective,
+            } => {
+                info!(%session_id, %agent, %objective, "subagent started");
+            }
+            AgentEvent::Completed {
+                session_id,
+                agent,
+                summary,
+                timed_out,
+                budget_exhausted,
+                usage,
+            } => {
+                info!(
+                    %session_id,
+                    %agent,
+                    %summary,
+                    timed_out,
+                    budget_exhausted,
+                    input_tokens = usage.input_tokens,
+                    output_tokens = usage.output_tokens,
+                    "subagent completed"
+                );
+            }
+            AgentEvent::Failed {
+                session_id,
+                agent,
+                error,
+            } => {
+                warn!(%session_id, %agent, %error, "subagent failed");
+            }
+            AgentEvent::Cancelled {
+                session_id,
+                agent,
+                reason,
+            } => {
+                warn!(%session_id, %agent, %reason, "subagent cancelled");
+            }
+            AgentEvent::Compacted {
+                session_id,
+                agent,
+                tokens_before,
+                tokens_after,
+            } => {
+                info!(%session_id, %agent, tokens_before, tokens_after, "session history compacted");
+            }
+            AgentEvent::Queued {
+                session_id,
+                agent,
+                provider,
+                wait_ms,
+            } => {
+                info!(%session_id, %agent, %provider, wait_ms, "subagent queued for rate limit");
+            }
+            AgentEvent::Retrying {
+                session_id,
+                agent,
+                attempt,
+                delay_ms,
+                error,
+            } => {
+                warn!(%session_id, %agent, attempt, delay_ms, %error, "retrying completion");
+            }
+            AgentEvent::TodoUpdated { agent, todos, .. } => {
+                eprintln!("[{agent}] {}", todo::render(&todos));
+            }
+        }
+    }
+}
+
+/// Tracks the worktree before a tool run. A failed snapshot is logged and
+/// only leaves the run without a revert record.
+async fn track_snapshot(snapshot: &Snapshot) -> Option<String> {
+    match snapshot.track().await {
+        Ok(hash) => hash,
+        Err(err) => {
+            warn!("failed to track snapshot: {err:#}");
+            None
         }
-    } else {
-        objective.push_str(message);
     }
+}
+
+async fn snapshot_patch(snapshot: &Snapshot, hash: Option<&str>) -> Option<Patch> {
+    match snapshot.patch(hash?).await {
+        Ok(patch) => Some(patch),
+        Err(err) => {
+            warn!("failed to compute snapshot patch: {err:#}");
+            None
+        }
+    }
+}
+
+/// Replaces tool arguments given as `-` with standard input, so long or
+/// dash-led values such as patches can be piped in.
+async fn stdin_args(args: &[String]) -> anyhow::Result<Vec<String>> {
+    if !args.iter().any(|arg| arg == "-") {
+        return Ok(args.to_vec());
+    }
+    let mut input = String::new();
+    tokio::io::stdin().read_to_string(&mut input).await?;
+    Ok(args
+        .iter()
+        .map(|arg| {
+            if arg == "-" {
+                input.clone()
+            } else {
+                arg.clone()
+            }
+        })
+        .collect())
+}
+
+/// Resolves `--session <id>` or `--continue` to a previously saved session.
+async fn load_session(cmd: &Run, store: &SessionStore) -> anyhow::Result<Option<StoredSession>> {
+    if let Some(id) = &cmd.session {
+        let id = uuid::Uuid::parse_str(id)
+            .map_err(|err| anyhow::anyhow!("invalid session id '{id}': {err}"))?;
+        return Ok(Some(store.get(id).await?));
+    }
+    if cmd.r#continue {
+        return store.latest().await;
+    }
+    Ok(None)
+}
+
+/// What a run executes.
+#[derive(Debug)]
+enum RunRequest {
+    Session(SessionRequest),
+    /// A subtask command, run as a child session of `parent` with no turn of
+    /// the parent's own.
+    Delegate {
+        parent: Option<String>,
+        invocation: SubagentInvocation,
+    },
+}
+
+/// Runs a rendered custom command on its configured agent and model, or as a
+/// child subagent session when the command is a subtask.
+fn command_request(cmd: &Run, registry: &AgentRegistry, rendered: RenderedCommand) -> RunRequest {
+    let agent = rendered
+        .agent
+        .clone()
+        .or_else(|| cmd.agent.clone())
+        .unwrap_or_else(|| registry.default_agent_name().to_string());
+    let model = rendered.model.as_deref().map(ModelHandle::from);
+    let objective = build_objective(cmd, &rendered.prompt);
+
+    if !rendered.runs_as_subtask(registry, &agent) {
+        return RunRequest::Session(SessionRequest {
+            agent: Some(agent),
+            model,
+            objective,
+            subtasks: Vec::new(),
+            max_parallel: None,
+            failure_policy: FailurePolicy::default(),
+            synthesize: false,
+            history: Vec::new(),
+            session_id: None,
+        });
+    }
+
+    let mut invocation = SubagentInvocation::new(agent, objective);
+    if let Some(model) = model {
+        invocation = invocation.with_model(model);
+    }
+    RunRequest::Delegate {
+        parent: cmd.agent.clone(),
+        invocation,
+    }
+}
+
+/// The model agents run on unless their definition names one: `requested`,
+/// then the configured model.
+pub(crate) fn default_model(config: &Info, requested: Option<&str>) -> ModelHandle {
+    let id = requested
+        .or(config.model.as_deref())
+        .unwrap_or(DEFAULT_MODEL);
+    ModelHandle::new(id)
+}
+
+/// The tools agents run with, reporting todo updates to `event_tx`.
+pub(crate) fn tools(
+    config: &Info,
+    lsp: &Arc<LspManager>,
+    hooks: &Arc<HookRunner>,
+    todos: &Arc<TodoLists>,
+    event_tx: &mpsc::Sender<AgentEvent>,
+) -> Vec<Arc<dyn Tool>> {
+    let mut tools: Vec<Arc<dyn Tool>> = vec![
+        Arc::new(EchoTool),
+        Arc::new(BashTool::new()),
+        Arc::new(ReadFileTool),
+        Arc::new(
+            WriteFileTool::new()
+                .with_lsp(lsp.clone())
+                .with_hooks(hooks.clone()),
+        ),
+        Arc::new(
+            EditTool::new()
+                .with_lsp(lsp.clone())
+                .with_hooks(hooks.clone()),
+        ),
+        Arc::new(
+            ApplyPatchTool::new()
+                .with_lsp(lsp.clone())
+                .with_hooks(hooks.clone()),
+        ),
+        Arc::new(ListFilesTool),
+        Arc::new(GrepTool),
+        Arc::new(GlobTool),
+        Arc::new(WebFetchTool),
+        Arc::new(TodoWriteTool::new(todos.clone()).with_events(event_tx.clone())),
+        Arc::new(TodoReadTool::new(todos.clone())),
+    ];
+    // A broken search section only costs the web_search tool, not the run.
+    match WebSearchTool::from_info(config) {
+        Ok(Some(search)) => tools.push(Arc::new(search)),
+        Ok(None) => {}
+        Err(err) => warn!("skipping web_search: {err:#}"),
+    }
+    tools
+}
+
+/// The configured model. Every attempt, retries included, first waits for
+/// the provider's rate limits; retryable failures are retried. Build it once
+/// per process so its users share one limiter. Queued and retrying events go
+/// to `event_tx` when given.
+pub(crate) fn language_model(
+    config: &Info,
+    event_tx: Option<&mpsc::Sender<AgentEvent>>,
+) -> Arc<dyn LanguageModel> {
+    let limiter = Arc::new(RateLimiter::from_info(config));
+    let limited = RateLimitedModel::new(Arc::new(LocalModel), limiter);
+    let Some(event_tx) = event_tx else {
+        return Arc::new(RetryingModel::new(
+            Arc::new(limited),
+            RetryPolicy::default(),
+        ));
+    };
+    let limited = limited.with_events(event_tx.clone());
+    Arc::new(
+        RetryingModel::new(Arc::new(limited), RetryPolicy::default()).with_events(event_tx.clone()),
+    )
+}
+
+/// A session runtime on `model`, with history compaction on the same model.
+/// Its events go to `event_tx`.
+pub(crate) fn session_runtime(
+    config: &Info,
+    context: Arc<ProjectContext>,
+    registry: Arc<AgentRegistry>,
+    tools: Vec<Arc<dyn Tool>>,
+    event_tx: mpsc::Sender<AgentEvent>,
+    model: Arc<dyn LanguageModel>,
+    default_model: ModelHandle,
+) -> SessionRuntime {
+    let compactor = Compactor::from_info(config, model.clone(), &default_model);
+    SessionRuntime::new(context, registry, model, tools, event_tx, default_model)
+        .with_compactor(Arc::new(compactor))
+}
+
+/// Compacts stored sessions outside of a run, with the configured small
+/// model.
+pub(crate) fn compactor(config: &Info, model: Arc<dyn LanguageModel>) -> Compactor {
+    Compactor::from_info(config, model, &default_model(config, None))
+}
+
+fn build_objective(cmd: &Run, body: &str) -> String {
+    let mut objective = body.to_string();
 
     if !cmd.file.is_empty() {
         let attachments: Vec<String> = cmd
@@ -230,6 +596,11 @@ fn build_objective(cmd: &Run, message: &str) -> String {
 struct RunReport {
     primary: SubtaskReport,
     subtasks: Vec<SubtaskReport>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    failures: Vec<FailureReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    synthesis: Option<SubtaskReport>,
+    usage: TokenUsage,
 }
 
 impl From<&SessionResult> for RunReport {
@@ -238,29 +609,70 @@ impl From<&SessionResult> for RunReport {
         Self {
             primary: SubtaskReport::from(&result.primary),
             subtasks,
+            failures: result.failures.iter().map(FailureReport::from).collect(),
+            synthesis: result.synthesis.as_ref().map(SubtaskReport::from),
+            usage: result.usage(),
         }
     }
 }
 
 #[derive(Debug, Serialize)]
 struct SubtaskReport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
     agent: String,
     objective: String,
     session_id: String,
     summary: String,
     model: String,
     raw_output: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    timed_out: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    budget_exhausted: bool,
+    usage: TokenUsage,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snapshot: Option<String>,
 }
 
 impl From<&SubagentOutcome> for SubtaskReport {
     fn from(outcome: &SubagentOutcome) -> Self {
         Self {
+            id: outcome.id.clone(),
             agent: outcome.agent.clone(),
             objective: outcome.objective.clone(),
             session_id: outcome.session_id.to_string(),
             summary: outcome.summary.clone(),
             model: outcome.model.id().to_string(),
             raw_output: outcome.raw_output.clone(),
+            timed_out: outcome.timed_out,
+            budget_exhausted: outcome.budget_exhausted,
+            usage: outcome.usage,
+            snapshot: outcome.snapshot.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct FailureReport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    agent: String,
+    objective: String,
+    session_id: String,
+    status: &'static str,
+    error: String,
+}
+
+impl From<&SubtaskFailure> for FailureReport {
+    fn from(failure: &SubtaskFailure) -> Self {
+        Self {
+            id: failure.id.clone(),
+            agent: failure.agent.clone(),
+            objective: failure.objective.clone(),
+            session_id: failure.session_id.to_string(),
+            status: failure.kind.as_str(),
+            error: failure.error.clone(),
         }
     }
 }
@@ -274,23 +686,38 @@ mod tests {
     #[test]
     fn serializes_run_report_to_json() {
         let outcome = SubagentOutcome {
+            id: None,
             agent: "primary".to_string(),
             objective: "Ship feature".to_string(),
             session_id: Uuid::nil(),
             summary: "done".to_string(),
             model: ModelHandle::new("test/model"),
             raw_output: "<prompt>".to_string(),
+            timed_out: false,
+            budget_exhausted: false,
+            usage: TokenUsage::new(120, 4),
+            snapshot: None,
+            patch: None,
         };
         let result = SessionResult {
             primary: outcome.clone(),
             subtasks: vec![SubagentOutcome {
+                id: Some("build".to_string()),
                 agent: "builder".to_string(),
                 objective: "Compile".to_string(),
                 session_id: Uuid::new_v4(),
                 summary: "compiled".to_string(),
                 model: ModelHandle::new("child/model"),
                 raw_output: "child".to_string(),
+                timed_out: false,
+                budget_exhausted: false,
+                usage: TokenUsage::new(80, 6),
+                snapshot: None,
+                patch: None,
             }],
+            failures: Vec::new(),
+            synthesis: None,
+            compaction: None,
         };
 
         let report = RunReport::from(&result);
@@ -298,6 +725,12 @@ mod tests {
         assert_eq!(value["primary"]["agent"], "primary");
         assert_eq!(value["primary"]["model"], "test/model");
         assert_eq!(value["subtasks"].as_array().map(|a| a.len()), Some(1));
+        assert_eq!(value["subtasks"][0]["id"], "build");
+        assert!(value["primary"].get("id").is_none());
+        assert!(value.get("synthesis").is_none());
+        assert_eq!(value["primary"]["usage"]["output_tokens"], 4);
+        assert_eq!(value["usage"]["input_tokens"], 200);
+        assert_eq!(value["usage"]["output_tokens"], 10);
     }
 
     #[test]
@@ -337,4 +770,55 @@ mod tests {
         let objective = build_objective(&cmd, &cmd.joined_message());
         assert!(objective.contains(SessionPrompts::build_switch().trim()));
     }
+
+    #[test]
+    fn subtask_commands_run_as_child_invocations() {
+        let cmd = Run {
+            message: vec!["src/lib.rs".to_string()],
+            command: Some("review".to_string()),
+            r#continue: false,
+            session: None,
+            share: false,
+            model: None,
+            agent: None,
+            format: OutputFormat::Default,
+            file: Vec::new(),
+            agents_json: None,
+        };
+        let mut registry = AgentRegistry::new();
+        registry.apply_runtime_map(
+            &parse_agents_source(r#"{"reviewer": {"mode": "subagent"}}"#).expect("agents"),
+        );
+        let rendered = RenderedCommand {
+            name: "review".to_string(),
+            agent: Some("reviewer".to_string()),
+            model: Some("review/model".to_string()),
+            subtask: None,
+            prompt: "Review src/lib.rs".to_string(),
+        };
+
+        let RunRequest::Delegate { parent, invocation } =
+            command_request(&cmd, &registry, rendered.clone())
+        else {
+            panic!("expected a delegated subtask");
+        };
+        assert_eq!(parent, None);
+        assert_eq!(invocation.agent, "reviewer");
+        assert_eq!(invocation.objective, "Review src/lib.rs");
+        assert_eq!(
+            invocation.model.as_ref().map(|m| m.id()),
+            Some("review/model")
+        );
+
+        let inline = RenderedCommand {
+            subtask: Some(false),
+            ..rendered
+        };
+        let RunRequest::Session(request) = command_request(&cmd, &registry, inline) else {
+            panic!("expected an inline session");
+        };
+        assert!(request.subtasks.is_empty());
+        assert_eq!(request.agent.as_deref(), Some("reviewer"));
+        assert_eq!(request.model.as_ref().map(|m| m.id()), Some("review/model"));
+    }
 }
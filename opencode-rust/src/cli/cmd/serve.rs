@@ -1,17 +1,73 @@
+use std::sync::Arc;
+
 use clap::Args;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
 use tracing::info;
 
+use crate::agent::registry::AgentRegistry;
+use crate::cli::cmd::run;
+use crate::hook::HookRunner;
+use crate::lsp::LspManager;
+use crate::server::{self, AppState};
+use crate::session::ProjectContext;
+use crate::session::revert::SessionRevert;
+use crate::session::store::SessionStore;
+use crate::session::todo::TodoLists;
+use crate::snapshot::Snapshot;
+use crate::util::config::Info;
+use crate::watcher::WatchOptions;
+
 #[derive(Args, Debug)]
 pub struct ServeCommand {
     /// Port to listen on
     #[arg(short, long, default_value_t = 0)]
     pub port: u16,
     /// Hostname to bind
-    #[arg(short, long, default_value = "127.0.0.1")]
+    #[arg(long, default_value = "127.0.0.1")]
     pub hostname: String,
 }
 
-pub async fn execute(cmd: &ServeCommand) -> anyhow::Result<()> {
+pub async fn execute(cmd: &ServeCommand, config: &Info) -> anyhow::Result<()> {
     info!(port = cmd.port, hostname = %cmd.hostname, "serve command");
-    Ok(())
+    let project_root = std::env::current_dir()?;
+    let lsp = Arc::new(LspManager::from_info(project_root.clone(), config));
+    let hooks = Arc::new(HookRunner::from_info(project_root.clone(), config));
+    let _watcher = hooks.clone().watch(WatchOptions::from(config)).await;
+    let snapshot = Arc::new(Snapshot::from_info(project_root.clone(), config));
+    let revert = SessionRevert::new(
+        Arc::new(SessionStore::for_project(&project_root)),
+        snapshot.clone(),
+    );
+
+    let todos = Arc::new(TodoLists::new());
+    let (event_tx, event_rx) = mpsc::channel(32);
+    let tools = run::tools(config, &lsp, &hooks, &todos, &event_tx);
+    let mut registry = AgentRegistry::from_info(config);
+    registry.ensure_primary();
+    let context = Arc::new(ProjectContext::gather(project_root, config)?);
+    let model = run::language_model(config, Some(&event_tx));
+    let runtime = run::session_runtime(
+        config,
+        context,
+        Arc::new(registry),
+        tools,
+        event_tx,
+        model.clone(),
+        run::default_model(config, None),
+    )
+    .with_hooks(hooks)
+    .with_snapshot(snapshot)
+    .into_shared_with_task();
+
+    let listener = TcpListener::bind((cmd.hostname.as_str(), cmd.port)).await?;
+    println!(
+        "opencode server listening on http://{}",
+        listener.local_addr()?
+    );
+    let state = AppState::new(Arc::new(revert))
+        .with_compactor(Arc::new(run::compactor(config, model)))
+        .with_runtime(runtime, todos);
+    let _forwarder = state.forward_events(event_rx);
+    server::serve(listener, state).await
 }
@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use clap::{Args, Subcommand};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::cli::cmd::run;
+use crate::session::revert::SessionRevert;
+use crate::session::store::{Role, SessionStore, StoredSession};
+use crate::snapshot::Snapshot;
+use crate::util::config::Info;
+
+#[derive(Args, Debug)]
+pub struct SessionCommand {
+    #[command(subcommand)]
+    pub action: SessionAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SessionAction {
+    /// List sessions of the current project
+    #[command(name = "list", alias = "ls")]
+    List,
+    /// Show the messages of a session
+    #[command(name = "messages")]
+    Messages(SessionIdArg),
+    /// Roll files and history back to before a message
+    #[command(name = "revert")]
+    Revert(SessionRevertArgs),
+    /// Reapply the changes undone by the last revert
+    #[command(name = "unrevert")]
+    Unrevert(SessionIdArg),
+    /// Summarize older messages so the session takes less context
+    #[command(name = "compact")]
+    Compact(SessionIdArg),
+}
+
+#[derive(Args, Debug)]
+pub struct SessionIdArg {
+    /// Session id
+    pub session: String,
+}
+
+#[derive(Args, Debug)]
+pub struct SessionRevertArgs {
+    /// Session id
+    pub session: String,
+    /// Id of the first message to revert
+    pub message: String,
+}
+
+pub async fn execute(cmd: &SessionCommand, config: &Info) -> anyhow::Result<()> {
+    let project_root = std::env::current_dir()?;
+    let store = Arc::new(SessionStore::for_project(&project_root));
+    let reverter = || {
+        SessionRevert::new(
+            store.clone(),
+            Arc::new(Snapshot::from_info(project_root.clone(), config)),
+        )
+    };
+
+    match &cmd.action {
+        SessionAction::List => {
+            for session in store.list().await? {
+                let reverted = if session.revert.is_some() {
+                    " (reverted)"
+                } else {
+                    ""
+                };
+                let usage = session.usage;
+                if usage.total() == 0 {
+                    println!("{}  {}{reverted}", session.id, session.title);
+                } else {
+                    println!(
+                        "{}  {}{reverted}  ({} in / {} out tokens)",
+                        session.id, session.title, usage.input_tokens, usage.output_tokens
+                    );
+                }
+            }
+        }
+        SessionAction::Messages(args) => {
+            let session = store.get(parse_id(&args.session)?).await?;
+            print_messages(&session);
+        }
+        SessionAction::Revert(args) => {
+            info!(session = %args.session, message = %args.message, "session revert");
+            let session = reverter()
+                .revert(parse_id(&args.session)?, parse_id(&args.message)?)
+                .await?;
+            if let Some(diff) = session
+                .revert
+                .as_ref()
+                .and_then(|revert| revert.diff.as_deref())
+                && !diff.is_empty()
+            {
+                println!("{diff}");
+            }
+        }
+        SessionAction::Unrevert(args) => {
+            info!(session = %args.session, "session unrevert");
+            let session = reverter().unrevert(parse_id(&args.session)?).await?;
+            print_messages(&session);
+        }
+        SessionAction::Compact(args) => {
+            info!(session = %args.session, "session compact");
+            let (_, compaction) = run::compactor(config, run::language_model(config, None))
+                .compact_session(&store, parse_id(&args.session)?)
+                .await?;
+            match compaction {
+                Some(compaction) => println!(
+                    "Compacted ~{} tokens to ~{}.\n\n{}",
+                    compaction.tokens_before, compaction.tokens_after, compaction.summary.content
+                ),
+                None => println!("Nothing to compact."),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_messages(session: &StoredSession) {
+    for message in session.visible_messages() {
+        let role = match message.role {
+            Role::User => "user",
+            Role::Assistant if message.summary => "summary",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+        };
+        let first_line = message.content.lines().next().unwrap_or_default();
+        println!("{}  {role:<9} [{}] {first_line}", message.id, message.agent);
+    }
+}
+
+fn parse_id(value: &str) -> anyhow::Result<Uuid> {
+    Uuid::parse_str(value).with_context(|| format!("invalid id '{value}'"))
+}
@@ -2,9 +2,10 @@ use clap::{Parser, Subcommand, ValueEnum};
 
 pub mod cmd;
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum, Default)]
 pub enum LogLevel {
     Debug,
+    #[default]
     Info,
     Warn,
     Error,
@@ -21,12 +22,6 @@ impl LogLevel {
     }
 }
 
-impl Default for LogLevel {
-    fn default() -> Self {
-        LogLevel::Info
-    }
-}
-
 #[derive(Parser, Debug)]
 #[command(
     name = "opencode",
@@ -55,12 +50,16 @@ pub enum Command {
     Auth(cmd::auth::AuthCommand),
     /// Manage agents
     Agent(cmd::agent::AgentCommand),
+    /// Manage custom commands
+    Command(cmd::command::CommandCommand),
     /// Upgrade opencode to a newer version
     Upgrade(cmd::upgrade::UpgradeCommand),
     /// List all available models
     Models,
     /// Starts a headless opencode server
     Serve(cmd::serve::ServeCommand),
+    /// Manage sessions
+    Session(cmd::session::SessionCommand),
     /// Show usage statistics
     Stats(cmd::stats::StatsCommand),
     /// Export a session as JSON
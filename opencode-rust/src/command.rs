@@ -0,0 +1,250 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use tokio::process::Command as ProcessCommand;
+use tracing::debug;
+
+use crate::agent::registry::AgentRegistry;
+use crate::agent::spec::AgentMode;
+use crate::util::config::{Command, Info};
+
+/// How long a ``!`command` `` may run before it is killed.
+pub const SHELL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A custom command with its template rendered for a concrete invocation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedCommand {
+    pub name: String,
+    pub agent: Option<String>,
+    pub model: Option<String>,
+    pub subtask: Option<bool>,
+    pub prompt: String,
+}
+
+impl RenderedCommand {
+    /// Commands run as a child session when they ask for it explicitly or when
+    /// they target a subagent without opting out.
+    pub fn runs_as_subtask(&self, registry: &AgentRegistry, agent: &str) -> bool {
+        match self.subtask {
+            Some(subtask) => subtask,
+            None => registry
+                .spec(agent)
+                .is_some_and(|spec| spec.mode == AgentMode::Subagent),
+        }
+    }
+}
+
+pub fn lookup<'a>(info: &'a Info, name: &str) -> Result<&'a Command> {
+    let name = name.trim_start_matches('/');
+    info.command
+        .as_ref()
+        .and_then(|commands| commands.get(name))
+        .ok_or_else(|| anyhow!("unknown command '{name}'"))
+}
+
+pub async fn render(
+    name: &str,
+    command: &Command,
+    arguments: &[String],
+    root: &Path,
+) -> RenderedCommand {
+    let prompt = substitute_arguments(&command.template, arguments);
+    let prompt = expand_shell(&prompt, root).await;
+    let prompt = expand_files(&prompt, root).await;
+    RenderedCommand {
+        name: name.trim_start_matches('/').to_string(),
+        agent: command.agent.clone(),
+        model: command.model.clone(),
+        subtask: command.subtask,
+        prompt,
+    }
+}
+
+/// Replaces `$ARGUMENTS` with every argument and `$1..$n` with the positional
+/// ones. The highest placeholder swallows any remaining arguments.
+pub fn substitute_arguments(template: &str, arguments: &[String]) -> String {
+    let highest = placeholders(template).into_iter().max().unwrap_or(0);
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(idx) = rest.find('$') {
+        output.push_str(&rest[..idx]);
+        let after = &rest[idx + 1..];
+        if let Some(remainder) = after.strip_prefix("ARGUMENTS") {
+            output.push_str(&arguments.join(" "));
+            rest = remainder;
+            continue;
+        }
+        let digits = after.chars().take_while(char::is_ascii_digit).count();
+        match after[..digits].parse::<usize>() {
+            Ok(position) if position > 0 => {
+                let value = if position == highest {
+                    arguments.get(position - 1..).map(|rest| rest.join(" "))
+                } else {
+                    arguments.get(position - 1).cloned()
+                };
+                output.push_str(&value.unwrap_or_default());
+                rest = &after[digits..];
+            }
+            _ => {
+                output.push('$');
+                rest = after;
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+fn placeholders(template: &str) -> Vec<usize> {
+    template
+        .split('$')
+        .skip(1)
+        .filter_map(|segment| {
+            let digits: String = segment.chars().take_while(char::is_ascii_digit).collect();
+            digits.parse().ok().filter(|position| *position > 0)
+        })
+        .collect()
+}
+
+/// Replaces every ``!`command` `` with the standard output of running it
+/// through `sh -c` in the project root. A command that fails also leaves its
+/// standard error and exit code; one that outlives `SHELL_TIMEOUT` is killed.
+pub async fn expand_shell(template: &str, root: &Path) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("!`") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find('`') else {
+            break;
+        };
+        output.push_str(&rest[..start]);
+        output.push_str(&run_shell(&after[..end], root).await);
+        rest = &after[end + 1..];
+    }
+    output.push_str(rest);
+    output
+}
+
+async fn run_shell(script: &str, root: &Path) -> String {
+    debug!(script, "expanding command shell snippet");
+    let output = ProcessCommand::new("sh")
+        .arg("-c")
+        .arg(script)
+        .current_dir(root)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = match tokio::time::timeout(SHELL_TIMEOUT, output).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(err)) => return format!("Error executing command: {err}"),
+        Err(_) => return format!("[command timed out after {}s]", SHELL_TIMEOUT.as_secs()),
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if output.status.success() {
+        return stdout.trim_end().to_string();
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let status = match output.status.code() {
+        Some(code) => format!("[command failed (exit {code})]"),
+        None => "[command failed (terminated by signal)]".to_string(),
+    };
+    [stdout.trim_end(), stderr.trim_end(), &status]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Appends the contents of every `@path` reference that names an existing
+/// file. References to missing paths are left as plain text.
+pub async fn expand_files(template: &str, root: &Path) -> String {
+    let mut output = template.to_string();
+    let mut seen = Vec::new();
+    for reference in file_references(template) {
+        if seen.contains(&reference) {
+            continue;
+        }
+        seen.push(reference.clone());
+        let path = resolve_reference(&reference, root);
+        if !path.is_file() {
+            continue;
+        }
+        match tokio::fs::read_to_string(&path).await {
+            Ok(content) => {
+                output.push_str(&format!(
+                    "\n\n<file path=\"{reference}\">\n{}\n</file>",
+                    content.trim_end()
+                ));
+            }
+            Err(err) => debug!(path = %path.display(), "skipping file reference: {err}"),
+        }
+    }
+    output
+}
+
+/// Finds `@path` references that are not part of a word (e-mail addresses)
+/// or quoted in backticks. Trailing sentence punctuation is not included.
+pub fn file_references(text: &str) -> Vec<String> {
+    let mut references = Vec::new();
+    let mut previous: Option<char> = None;
+    for (idx, ch) in text.char_indices() {
+        let standalone = previous.is_none_or(|p| !(p.is_alphanumeric() || p == '_' || p == '`'));
+        previous = Some(ch);
+        if ch != '@' || !standalone {
+            continue;
+        }
+        let tail = &text[idx + 1..];
+        let end = tail
+            .find(|c: char| c.is_whitespace() || c == '`' || c == ',')
+            .unwrap_or(tail.len());
+        let reference = tail[..end].trim_end_matches('.');
+        if !reference.is_empty() && reference != "." {
+            references.push(reference.to_string());
+        }
+    }
+    references
+}
+
+fn resolve_reference(reference: &str, root: &Path) -> PathBuf {
+    if let Some(relative) = reference.strip_prefix("~/")
+        && let Some(home) = std::env::var_os("HOME")
+    {
+        return PathBuf::from(home).join(relative);
+    }
+    root.join(reference)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn substitutes_arguments_and_positionals() {
+        let template = "Review $1 against $2: $ARGUMENTS ($5)";
+        let rendered = substitute_arguments(template, &args(&["a.rs", "b.rs"]));
+        assert_eq!(rendered, "Review a.rs against b.rs: a.rs b.rs ()");
+    }
+
+    #[test]
+    fn last_placeholder_takes_remaining_arguments() {
+        let rendered = substitute_arguments("Fix $1 by $2", &args(&["bug", "adding", "a", "test"]));
+        assert_eq!(rendered, "Fix bug by adding a test");
+        assert_eq!(
+            substitute_arguments("costs $ and $x", &[]),
+            "costs $ and $x"
+        );
+    }
+
+    #[test]
+    fn finds_standalone_file_references() {
+        let refs =
+            file_references("See @src/main.rs, mail me@example.com and `@skip`. Also @README.md.");
+        assert_eq!(refs, vec!["src/main.rs", "README.md"]);
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::process::Command;
+use tokio::task::JoinHandle;
+use tokio::time;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::tool::core::ToolContext;
+use crate::util::config::{HookCommand, HookConfig, Info};
+use crate::watcher::{self, FileEventKind, FileWatcher, WatchOptions};
+
+const HOOK_TIMEOUT: Duration = Duration::from_secs(60);
+const WATCHER_DEDUP_WINDOW: Duration = Duration::from_secs(2);
+const MAX_LOGGED_OUTPUT: usize = 2048;
+
+/// Session details exported to hook processes as `OPENCODE_*` variables.
+#[derive(Debug, Clone, Default)]
+pub struct HookContext {
+    pub session_id: Option<Uuid>,
+    pub agent: Option<String>,
+}
+
+impl From<&ToolContext> for HookContext {
+    fn from(ctx: &ToolContext) -> Self {
+        Self {
+            session_id: Some(ctx.session_id),
+            agent: Some(ctx.agent.name.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HookOutcome {
+    pub command: Vec<String>,
+    pub status: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub error: Option<String>,
+}
+
+impl HookOutcome {
+    pub fn success(&self) -> bool {
+        self.error.is_none() && self.status == Some(0)
+    }
+}
+
+/// Runs the `experimental.hook` commands. Hook failures are logged and
+/// reported in the returned outcomes but never propagated as errors.
+pub struct HookRunner {
+    root: PathBuf,
+    config: HookConfig,
+    recent_edits: Mutex<HashMap<PathBuf, Instant>>,
+}
+
+impl HookRunner {
+    pub fn from_info(root: impl Into<PathBuf>, info: &Info) -> Self {
+        let config = info
+            .experimental
+            .as_ref()
+            .and_then(|experimental| experimental.hook.clone())
+            .unwrap_or_default();
+        Self {
+            root: root.into(),
+            config,
+            recent_edits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.config
+            .file_edited
+            .as_ref()
+            .is_none_or(HashMap::is_empty)
+            && self
+                .config
+                .session_completed
+                .as_ref()
+                .is_none_or(Vec::is_empty)
+    }
+
+    /// Runs the `file_edited` hooks whose extension key matches `path`.
+    /// Keys may be written with or without the leading dot; `*` matches any file.
+    pub async fn file_edited(&self, path: &Path, ctx: &HookContext) -> Vec<HookOutcome> {
+        let path = self.absolute(path);
+        self.mark_edited(&path);
+        let commands = self.commands_for(&path);
+        let mut outcomes = Vec::new();
+        for hook in commands {
+            outcomes.push(self.run("file_edited", &hook, Some(&path), ctx).await);
+        }
+        // Hooks such as formatters rewrite the file, possibly after the
+        // window has passed; their own writes must not fire them again.
+        self.mark_edited(&path);
+        outcomes
+    }
+
+    pub async fn session_completed(&self, ctx: &HookContext) -> Vec<HookOutcome> {
+        let mut outcomes = Vec::new();
+        for hook in self.config.session_completed.iter().flatten() {
+            outcomes.push(self.run("session_completed", hook, None, ctx).await);
+        }
+        outcomes
+    }
+
+    /// Watches the project root and forwards its changes to the
+    /// `file_edited` hooks. Returns `None` when no such hooks are configured
+    /// or the watcher cannot start, which is logged.
+    pub async fn watch(self: Arc<Self>, options: WatchOptions) -> Option<JoinHandle<()>> {
+        if self
+            .config
+            .file_edited
+            .as_ref()
+            .is_none_or(HashMap::is_empty)
+        {
+            return None;
+        }
+        match watcher::watch(&self.root, options).await {
+            Ok(watcher) => Some(self.listen(watcher)),
+            Err(err) => {
+                warn!(root = %self.root.display(), "failed to watch for file_edited hooks: {err:#}");
+                None
+            }
+        }
+    }
+
+    /// Forwards watcher changes to the `file_edited` hooks. Changes a tool
+    /// already reported within the last couple of seconds are skipped so a
+    /// single edit does not fire its hooks twice.
+    pub fn listen(self: Arc<Self>, mut watcher: FileWatcher) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            while let Some(event) = watcher.next().await {
+                if event.kind == FileEventKind::Deleted || self.recently_edited(&event.path) {
+                    continue;
+                }
+                self.file_edited(&event.path, &HookContext::default()).await;
+            }
+            watcher.shutdown().await;
+        })
+    }
+
+    fn mark_edited(&self, path: &Path) {
+        if let Ok(mut recent) = self.recent_edits.lock() {
+            recent.insert(path.to_path_buf(), Instant::now());
+        }
+    }
+
+    fn recently_edited(&self, path: &Path) -> bool {
+        let Ok(mut recent) = self.recent_edits.lock() else {
+            return false;
+        };
+        recent.retain(|_, at| at.elapsed() < WATCHER_DEDUP_WINDOW);
+        recent.contains_key(path)
+    }
+
+    fn commands_for(&self, path: &Path) -> Vec<HookCommand> {
+        let Some(map) = &self.config.file_edited else {
+            return Vec::new();
+        };
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        let mut keys: Vec<&String> = map
+            .keys()
+            .filter(|key| {
+                let key = key.trim_start_matches('.');
+                key == "*" || Some(key) == extension
+            })
+            .collect();
+        keys.sort();
+        keys.into_iter()
+            .flat_map(|key| map[key].iter().cloned())
+            .collect()
+    }
+
+    async fn run(
+        &self,
+        event: &str,
+        hook: &HookCommand,
+        file: Option<&Path>,
+        ctx: &HookContext,
+    ) -> HookOutcome {
+        let mut outcome = HookOutcome {
+            command: hook.command.clone(),
+            status: None,
+            stdout: String::new(),
+            stderr: String::new(),
+            error: None,
+        };
+        let Some((program, args)) = hook.command.split_first() else {
+            outcome.error = Some("hook command is empty".to_string());
+            return outcome;
+        };
+        let file_str = file.map(|path| path.display().to_string());
+        let args: Vec<String> = args
+            .iter()
+            .map(|arg| match &file_str {
+                Some(file) => arg.replace("$FILE", file),
+                None => arg.clone(),
+            })
+            .collect();
+
+        let mut command = Command::new(program);
+        command
+            .args(&args)
+            .current_dir(&self.root)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .env("OPENCODE_HOOK_EVENT", event);
+        if let Some(file) = &file_str {
+            command.env("OPENCODE_FILE", file);
+        }
+        if let Some(session_id) = ctx.session_id {
+            command.env("OPENCODE_SESSION_ID", session_id.to_string());
+        }
+        if let Some(agent) = &ctx.agent {
+            command.env("OPENCODE_AGENT", agent);
+        }
+        if let Some(environment) = &hook.environment {
+            command.envs(environment);
+        }
+
+        debug!(event, command = ?hook.command, "running hook");
+        match time::timeout(HOOK_TIMEOUT, command.output()).await {
+            Ok(Ok(output)) => {
+                outcome.status = output.status.code();
+                outcome.stdout = String::from_utf8_lossy(&output.stdout).to_string();
+                outcome.stderr = String::from_utf8_lossy(&output.stderr).to_string();
+            }
+            Ok(Err(err)) => outcome.error = Some(format!("failed to spawn hook: {err}")),
+            Err(_) => {
+                outcome.error = Some(format!("hook timed out after {}s", HOOK_TIMEOUT.as_secs()))
+            }
+        }
+
+        let stdout = truncate_for_log(&outcome.stdout);
+        let stderr = truncate_for_log(&outcome.stderr);
+        if outcome.success() {
+            info!(event, command = ?hook.command, stdout, stderr, "hook completed");
+        } else {
+            warn!(
+                event,
+                command = ?hook.command,
+                status = ?outcome.status,
+                error = ?outcome.error,
+                stdout,
+                stderr,
+                "hook failed"
+            );
+        }
+        outcome
+    }
+
+    fn absolute(&self, path: &Path) -> PathBuf {
+        if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            self.root.join(path)
+        }
+    }
+}
+
+fn truncate_for_log(output: &str) -> &str {
+    let trimmed = output.trim();
+    if trimmed.len() <= MAX_LOGGED_OUTPUT {
+        return trimmed;
+    }
+    let mut end = MAX_LOGGED_OUTPUT;
+    while !trimmed.is_char_boundary(end) {
+        end -= 1;
+    }
+    &trimmed[..end]
+}
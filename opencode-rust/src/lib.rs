@@ -1,6 +1,12 @@
 pub mod agent;
 pub mod cli;
+pub mod command;
+pub mod hook;
+pub mod lsp;
+pub mod search;
+pub mod server;
 pub mod session;
+pub mod snapshot;
 pub mod tool;
 pub mod util;
 pub mod watcher;
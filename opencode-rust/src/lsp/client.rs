@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde_json::{Value as JsonValue, json};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::{Mutex, oneshot, watch};
+use tokio::task::JoinHandle;
+use tokio::time;
+use tracing::{debug, warn};
+
+use crate::lsp::{Diagnostic, language_id, path_to_uri, uri_to_path};
+use crate::util::config::LspServerConfig;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const DIAGNOSTICS_SETTLE: Duration = Duration::from_millis(150);
+
+type PendingMap = HashMap<i64, oneshot::Sender<Result<JsonValue>>>;
+type DiagnosticsMap = HashMap<PathBuf, (u64, Vec<Diagnostic>)>;
+
+struct Shared {
+    writer: Mutex<Box<dyn AsyncWrite + Send + Unpin>>,
+    pending: Mutex<PendingMap>,
+    diagnostics: Mutex<DiagnosticsMap>,
+    published: watch::Sender<u64>,
+    initialization: Option<JsonValue>,
+}
+
+pub struct LspClient {
+    shared: Arc<Shared>,
+    next_id: AtomicI64,
+    versions: Mutex<HashMap<PathBuf, i32>>,
+    reader: JoinHandle<()>,
+    child: Mutex<Option<Child>>,
+}
+
+impl LspClient {
+    pub async fn spawn(config: &LspServerConfig, root: &Path) -> Result<Self> {
+        let (program, args) = config
+            .command
+            .split_first()
+            .ok_or_else(|| anyhow!("LSP server command must not be empty"))?;
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true);
+        if let Some(env) = &config.env {
+            command.envs(env);
+        }
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("failed to spawn LSP server '{program}'"))?;
+        let stdin = child.stdin.take().context("LSP server stdin unavailable")?;
+        let stdout = child
+            .stdout
+            .take()
+            .context("LSP server stdout unavailable")?;
+        let initialization = config
+            .initialization
+            .as_ref()
+            .map(|map| JsonValue::Object(map.clone().into_iter().collect()));
+
+        let client = Self::connect(stdout, stdin, root, initialization).await?;
+        *client.child.lock().await = Some(child);
+        Ok(client)
+    }
+
+    pub async fn connect<R, W>(
+        reader: R,
+        writer: W,
+        root: &Path,
+        initialization: Option<JsonValue>,
+    ) -> Result<Self>
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        let (published, _) = watch::channel(0);
+        let shared = Arc::new(Shared {
+            writer: Mutex::new(Box::new(writer)),
+            pending: Mutex::new(HashMap::new()),
+            diagnostics: Mutex::new(HashMap::new()),
+            published,
+            initialization,
+        });
+        let reader = tokio::spawn(read_loop(BufReader::new(reader), shared.clone()));
+        let client = Self {
+            shared,
+            next_id: AtomicI64::new(1),
+            versions: Mutex::new(HashMap::new()),
+            reader,
+            child: Mutex::new(None),
+        };
+        client.initialize(root).await?;
+        Ok(client)
+    }
+
+    async fn initialize(&self, root: &Path) -> Result<()> {
+        let root_uri = path_to_uri(root);
+        let name = root
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "workspace".to_string());
+        self.request(
+            "initialize",
+            json!({
+                "processId": std::process::id(),
+                "rootUri": root_uri,
+                "workspaceFolders": [{ "uri": root_uri, "name": name }],
+                "initializationOptions": self.shared.initialization.clone(),
+                "capabilities": {
+                    "window": { "workDoneProgress": true },
+                    "workspace": { "configuration": true },
+                    "textDocument": {
+                        "synchronization": { "didOpen": true, "didChange": true },
+                        "publishDiagnostics": { "versionSupport": true }
+                    }
+                }
+            }),
+        )
+        .await
+        .context("LSP initialize failed")?;
+        self.notify("initialized", json!({})).await?;
+        if let Some(settings) = &self.shared.initialization {
+            self.notify(
+                "workspace/didChangeConfiguration",
+                json!({ "settings": settings }),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn request(&self, method: &str, params: JsonValue) -> Result<JsonValue> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (tx, rx) = oneshot::channel();
+        self.shared.pending.lock().await.insert(id, tx);
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        if let Err(err) = write_message(&self.shared, &message).await {
+            self.shared.pending.lock().await.remove(&id);
+            return Err(err);
+        }
+        match time::timeout(REQUEST_TIMEOUT, rx).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => bail!("LSP server closed before answering '{method}'"),
+            Err(_) => {
+                self.shared.pending.lock().await.remove(&id);
+                bail!("LSP request '{method}' timed out")
+            }
+        }
+    }
+
+    pub async fn notify(&self, method: &str, params: JsonValue) -> Result<()> {
+        let message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        write_message(&self.shared, &message).await
+    }
+
+    /// Opens the file on first use and sends its full content on later calls,
+    /// prompting the server to publish fresh diagnostics.
+    pub async fn touch_file(&self, path: &Path) -> Result<()> {
+        let text = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let uri = path_to_uri(path);
+        let mut versions = self.versions.lock().await;
+        match versions.get_mut(path) {
+            Some(version) => {
+                *version += 1;
+                self.notify(
+                    "textDocument/didChange",
+                    json!({
+                        "textDocument": { "uri": uri, "version": *version },
+                        "contentChanges": [{ "text": text }]
+                    }),
+                )
+                .await
+            }
+            None => {
+                versions.insert(path.to_path_buf(), 0);
+                self.notify(
+                    "textDocument/didOpen",
+                    json!({
+                        "textDocument": {
+                            "uri": uri,
+                            "languageId": language_id(path),
+                            "version": 0,
+                            "text": text
+                        }
+                    }),
+                )
+                .await
+            }
+        }
+    }
+
+    /// Touches the file and waits for the server to publish diagnostics for it.
+    /// Returns whatever is known once `timeout` elapses.
+    pub async fn diagnostics(&self, path: &Path, timeout: Duration) -> Result<Vec<Diagnostic>> {
+        let mut published = self.shared.published.subscribe();
+        let baseline = self.publish_sequence(path).await;
+        self.touch_file(path).await?;
+
+        let deadline = time::Instant::now() + timeout;
+        let mut settled_at = None;
+        loop {
+            let current = self.publish_sequence(path).await;
+            if current > baseline && settled_at.is_none() {
+                settled_at = Some(time::Instant::now() + DIAGNOSTICS_SETTLE);
+            }
+            let wake = settled_at.map_or(deadline, |settle| settle.min(deadline));
+            match time::timeout_at(wake, published.changed()).await {
+                Ok(Ok(())) => continue,
+                Ok(Err(_)) | Err(_) => break,
+            }
+        }
+        Ok(self.known_diagnostics(path).await)
+    }
+
+    pub async fn known_diagnostics(&self, path: &Path) -> Vec<Diagnostic> {
+        self.shared
+            .diagnostics
+            .lock()
+            .await
+            .get(path)
+            .map(|(_, diagnostics)| diagnostics.clone())
+            .unwrap_or_default()
+    }
+
+    async fn publish_sequence(&self, path: &Path) -> u64 {
+        self.shared
+            .diagnostics
+            .lock()
+            .await
+            .get(path)
+            .map_or(0, |(sequence, _)| *sequence)
+    }
+
+    pub async fn shutdown(&self) {
+        if let Err(err) = self.request("shutdown", JsonValue::Null).await {
+            debug!("LSP shutdown request failed: {err}");
+        }
+        let _ = self.notify("exit", JsonValue::Null).await;
+        if let Some(mut child) = self.child.lock().await.take()
+            && time::timeout(Duration::from_secs(2), child.wait())
+                .await
+                .is_err()
+        {
+            let _ = child.kill().await;
+        }
+        self.reader.abort();
+    }
+}
+
+impl Drop for LspClient {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+async fn write_message(shared: &Shared, message: &JsonValue) -> Result<()> {
+    let body = serde_json::to_vec(message)?;
+    let mut writer = shared.writer.lock().await;
+    writer
+        .write_all(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes())
+        .await?;
+    writer.write_all(&body).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+async fn read_message<R>(reader: &mut BufReader<R>) -> Result<Option<JsonValue>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.trim().eq_ignore_ascii_case("content-length")
+        {
+            content_length = Some(value.trim().parse::<usize>()?);
+        }
+    }
+    let length = content_length.ok_or_else(|| anyhow!("LSP message without Content-Length"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+async fn read_loop<R>(mut reader: BufReader<R>, shared: Arc<Shared>)
+where
+    R: AsyncRead + Unpin,
+{
+    loop {
+        let message = match read_message(&mut reader).await {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(err) => {
+                warn!("failed to read LSP message: {err}");
+                break;
+            }
+        };
+        let method = message.get("method").and_then(JsonValue::as_str);
+        let id = message.get("id").cloned();
+        match (method, id) {
+            (Some(method), Some(id)) => answer_server_request(&shared, method, id, &message).await,
+            (Some("textDocument/publishDiagnostics"), None) => {
+                record_diagnostics(&shared, &message["params"]).await
+            }
+            (Some(_), None) => {}
+            (None, Some(id)) => {
+                let Some(id) = id.as_i64() else { continue };
+                if let Some(tx) = shared.pending.lock().await.remove(&id) {
+                    let result = match message.get("error") {
+                        Some(error) => Err(anyhow!("LSP error: {error}")),
+                        None => Ok(message.get("result").cloned().unwrap_or(JsonValue::Null)),
+                    };
+                    let _ = tx.send(result);
+                }
+            }
+            (None, None) => {}
+        }
+    }
+    shared.pending.lock().await.clear();
+}
+
+async fn answer_server_request(shared: &Shared, method: &str, id: JsonValue, message: &JsonValue) {
+    let result = match method {
+        "workspace/configuration" => {
+            let items = message["params"]["items"]
+                .as_array()
+                .map_or(1, |items| items.len());
+            let settings = shared.initialization.clone().unwrap_or(JsonValue::Null);
+            JsonValue::Array(vec![settings; items])
+        }
+        "workspace/workspaceFolders" => JsonValue::Array(Vec::new()),
+        _ => JsonValue::Null,
+    };
+    let response = json!({ "jsonrpc": "2.0", "id": id, "result": result });
+    if let Err(err) = write_message(shared, &response).await {
+        debug!("failed to answer LSP request '{method}': {err}");
+    }
+}
+
+async fn record_diagnostics(shared: &Shared, params: &JsonValue) {
+    let Some(path) = params["uri"].as_str().and_then(uri_to_path) else {
+        return;
+    };
+    let diagnostics: Vec<Diagnostic> =
+        serde_json::from_value(params["diagnostics"].clone()).unwrap_or_default();
+    let sequence = {
+        let mut map = shared.diagnostics.lock().await;
+        let entry = map.entry(path).or_insert((0, Vec::new()));
+        entry.0 += 1;
+        entry.1 = diagnostics;
+        entry.0
+    };
+    debug!(sequence, "LSP diagnostics published");
+    shared.published.send_modify(|counter| *counter += 1);
+}
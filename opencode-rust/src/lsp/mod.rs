@@ -0,0 +1,301 @@
+pub mod client;
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+pub use client::LspClient;
+
+use crate::util::config::{Info, LspConfig, LspServerConfig};
+
+const DIAGNOSTICS_TIMEOUT: Duration = Duration::from_secs(3);
+pub const MAX_DIAGNOSTICS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub range: Range,
+    #[serde(default)]
+    pub severity: Option<u8>,
+    #[serde(default)]
+    pub source: Option<String>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// The spec leaves a missing severity to the client; like any value
+    /// other than warning, information or hint, it is treated as an error.
+    pub fn is_error(&self) -> bool {
+        !matches!(self.severity, Some(2..=4))
+    }
+
+    pub fn severity_label(&self) -> &'static str {
+        match self.severity {
+            Some(1) => "ERROR",
+            Some(2) => "WARN",
+            Some(3) => "INFO",
+            Some(4) => "HINT",
+            _ => "ERROR",
+        }
+    }
+
+    pub fn pretty(&self) -> String {
+        format!(
+            "{} [{}:{}] {}",
+            self.severity_label(),
+            self.range.start.line + 1,
+            self.range.start.character + 1,
+            self.message
+        )
+    }
+}
+
+/// Formats the error-level diagnostics of a freshly written file so they can be
+/// appended to a tool result. Returns `None` when the file is clean.
+pub fn report_errors(diagnostics: &[Diagnostic]) -> Option<String> {
+    let errors: Vec<&Diagnostic> = diagnostics.iter().filter(|d| d.is_error()).collect();
+    if errors.is_empty() {
+        return None;
+    }
+    let mut lines: Vec<String> = errors
+        .iter()
+        .take(MAX_DIAGNOSTICS)
+        .map(|d| d.pretty())
+        .collect();
+    if errors.len() > MAX_DIAGNOSTICS {
+        lines.push(format!("... and {} more", errors.len() - MAX_DIAGNOSTICS));
+    }
+    Some(format!(
+        "This file has errors, please fix\n<file_diagnostics>\n{}\n</file_diagnostics>",
+        lines.join("\n")
+    ))
+}
+
+struct LspServer {
+    name: String,
+    config: LspServerConfig,
+}
+
+impl LspServer {
+    fn handles(&self, path: &Path) -> bool {
+        let Some(extension) = path.extension().and_then(|ext| ext.to_str()) else {
+            return false;
+        };
+        self.config.extensions.as_ref().is_some_and(|extensions| {
+            extensions
+                .iter()
+                .any(|candidate| candidate.trim_start_matches('.') == extension)
+        })
+    }
+}
+
+/// Lazily starts the configured language servers and hands out diagnostics
+/// for the files they cover.
+pub struct LspManager {
+    root: PathBuf,
+    servers: Vec<LspServer>,
+    clients: Mutex<HashMap<String, Arc<LspClient>>>,
+    broken: Mutex<HashSet<String>>,
+}
+
+impl LspManager {
+    pub fn from_info(root: impl Into<PathBuf>, info: &Info) -> Self {
+        let mut servers = Vec::new();
+        for (name, config) in info.lsp.iter().flatten() {
+            match config {
+                LspConfig::Configurable(config) if !config.disabled.unwrap_or(false) => {
+                    servers.push(LspServer {
+                        name: name.clone(),
+                        config: config.clone(),
+                    });
+                }
+                _ => info!(server = %name, "LSP server is disabled"),
+            }
+        }
+        servers.sort_by(|a, b| a.name.cmp(&b.name));
+        Self {
+            root: root.into(),
+            servers,
+            clients: Mutex::new(HashMap::new()),
+            broken: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.servers.is_empty()
+    }
+
+    pub fn handles(&self, path: &Path) -> bool {
+        self.servers.iter().any(|server| server.handles(path))
+    }
+
+    /// Collects diagnostics for `path` from every server registered for its
+    /// extension. Servers that fail to start are skipped from then on.
+    pub async fn diagnostics(&self, path: &Path) -> Result<Vec<Diagnostic>> {
+        let path = self.absolute(path);
+        let mut diagnostics = Vec::new();
+        for server in self.servers.iter().filter(|server| server.handles(&path)) {
+            let Some(client) = self.client(server).await else {
+                continue;
+            };
+            match client.diagnostics(&path, DIAGNOSTICS_TIMEOUT).await {
+                Ok(found) => diagnostics.extend(found),
+                Err(err) => warn!(server = %server.name, "LSP diagnostics failed: {err}"),
+            }
+        }
+        Ok(diagnostics)
+    }
+
+    pub async fn shutdown(&self) {
+        let clients: Vec<Arc<LspClient>> =
+            self.clients.lock().await.drain().map(|(_, c)| c).collect();
+        for client in clients {
+            client.shutdown().await;
+        }
+    }
+
+    async fn client(&self, server: &LspServer) -> Option<Arc<LspClient>> {
+        if self.broken.lock().await.contains(&server.name) {
+            return None;
+        }
+        let mut clients = self.clients.lock().await;
+        if let Some(client) = clients.get(&server.name) {
+            return Some(client.clone());
+        }
+        match LspClient::spawn(&server.config, &self.root).await {
+            Ok(client) => {
+                let client = Arc::new(client);
+                clients.insert(server.name.clone(), client.clone());
+                Some(client)
+            }
+            Err(err) => {
+                warn!(server = %server.name, "failed to start LSP server: {err:#}");
+                self.broken.lock().await.insert(server.name.clone());
+                None
+            }
+        }
+    }
+
+    fn absolute(&self, path: &Path) -> PathBuf {
+        if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            self.root.join(path)
+        }
+    }
+}
+
+pub fn language_id(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()).unwrap_or("") {
+        "rs" => "rust",
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "typescriptreact",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "javascriptreact",
+        "py" => "python",
+        "go" => "go",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" => "cpp",
+        "java" => "java",
+        "rb" => "ruby",
+        "json" => "json",
+        "md" => "markdown",
+        "toml" => "toml",
+        "yaml" | "yml" => "yaml",
+        "sh" | "bash" => "shellscript",
+        _ => "plaintext",
+    }
+}
+
+pub fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    uri
+}
+
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?;
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' && idx + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[idx + 1..idx + 3]).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            idx += 3;
+        } else {
+            decoded.push(bytes[idx]);
+            idx += 1;
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(decoded).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(severity: u8, line: u32, message: &str) -> Diagnostic {
+        let position = Position { line, character: 4 };
+        Diagnostic {
+            range: Range {
+                start: position,
+                end: position,
+            },
+            severity: Some(severity),
+            source: None,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn reports_only_errors_and_caps_output() {
+        let mut diagnostics = vec![diagnostic(2, 0, "unused variable")];
+        for line in 0..25 {
+            diagnostics.push(diagnostic(1, line, "mismatched types"));
+        }
+
+        let report = report_errors(&diagnostics).expect("errors reported");
+        assert!(report.starts_with("This file has errors"));
+        assert!(report.contains("ERROR [1:5] mismatched types"));
+        assert!(!report.contains("unused variable"));
+        assert!(report.contains("... and 5 more"));
+    }
+
+    #[test]
+    fn clean_files_produce_no_report() {
+        assert!(report_errors(&[diagnostic(2, 0, "warning only")]).is_none());
+    }
+
+    #[test]
+    fn round_trips_file_uris() {
+        let path = Path::new("/tmp/my project/main#1.rs");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///tmp/my%20project/main%231.rs");
+        assert_eq!(uri_to_path(&uri).as_deref(), Some(path));
+    }
+}
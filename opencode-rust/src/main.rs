@@ -13,13 +13,16 @@ async fn main() -> anyhow::Result<()> {
     log::init(LogConfig::new(level, opts.print_logs))?;
 
     let config_path = Path::new("opencode.json");
-    let config: Info = if config_path.exists() {
+    let mut config: Info = if config_path.exists() {
         let config_str = tokio::fs::read_to_string(config_path).await?;
         config::parse_info(&config_str)?
     } else {
         Info::default()
     };
 
+    let project_root = std::env::current_dir()?;
+    config::load_commands(&mut config, &config::command_directories(&project_root)).await?;
+
     info!(?config, "Loaded config");
 
     match opts.command {
@@ -35,6 +38,9 @@ async fn main() -> anyhow::Result<()> {
         Command::Agent(agent_cmd) => {
             cmd::agent::execute(&agent_cmd).await?;
         }
+        Command::Command(command_cmd) => {
+            cmd::command::execute(&command_cmd, &config).await?;
+        }
         Command::Upgrade(upgrade_cmd) => {
             cmd::upgrade::execute(&upgrade_cmd).await?;
         }
@@ -42,7 +48,10 @@ async fn main() -> anyhow::Result<()> {
             info!("Listing models");
         }
         Command::Serve(serve_cmd) => {
-            cmd::serve::execute(&serve_cmd).await?;
+            cmd::serve::execute(&serve_cmd, &config).await?;
+        }
+        Command::Session(session_cmd) => {
+            cmd::session::execute(&session_cmd, &config).await?;
         }
         Command::Stats(stats_cmd) => {
             cmd::stats::execute(&stats_cmd).await?;
@@ -63,7 +72,7 @@ async fn main() -> anyhow::Result<()> {
             cmd::tui::execute(&tui_cmd).await?;
         }
         Command::Debug(debug_cmd) => {
-            cmd::debug::execute(&debug_cmd).await?;
+            cmd::debug::execute(&debug_cmd, &config).await?;
         }
         Command::Github(github_cmd) => {
             cmd::github::execute(&github_cmd).await?;
@@ -0,0 +1,329 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result, bail};
+use ignore::overrides::OverrideBuilder;
+use ignore::{DirEntry, Match, WalkBuilder, WalkState};
+use regex::Regex;
+
+use crate::watcher::IGNORED_FOLDERS;
+
+/// Files whose first bytes contain a NUL are treated as binary and skipped.
+const BINARY_PROBE: usize = 8192;
+pub const DEFAULT_TREE_LIMIT: usize = 50;
+
+/// Filters shared by the file listing and content search.
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    /// Ripgrep-style globs: plain globs select paths, `!glob` excludes them.
+    /// Gitignored paths stay excluded either way.
+    pub globs: Vec<String>,
+    pub limit: Option<usize>,
+    /// Also skip the folders the file watcher ignores (`node_modules`,
+    /// `target`, ...) even when they are not gitignored.
+    pub skip_ignored_folders: bool,
+}
+
+impl SearchOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_globs(mut self, globs: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.globs.extend(globs.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn with_limit(mut self, limit: Option<usize>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn skip_ignored_folders(mut self) -> Self {
+        self.skip_ignored_folders = true;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchMatch {
+    /// Path relative to the search root.
+    pub path: PathBuf,
+    pub line_number: usize,
+    pub line: String,
+    pub modified: Option<SystemTime>,
+}
+
+/// Lists files below `root` in parallel, honoring `.gitignore`, `.ignore`
+/// and the option globs. Hidden files are included, `.git` is not. Paths are
+/// relative to `root` and sorted.
+pub fn files(root: &Path, options: &SearchOptions) -> Result<Vec<PathBuf>> {
+    let found = Mutex::new(Vec::new());
+    let count = AtomicUsize::new(0);
+    let limit = options.limit.unwrap_or(usize::MAX);
+    walker(root, options)?.build_parallel().run(|| {
+        Box::new(|entry| {
+            let Some(entry) = file_entry(entry) else {
+                return WalkState::Continue;
+            };
+            if count.fetch_add(1, Ordering::Relaxed) >= limit {
+                return WalkState::Quit;
+            }
+            if let Ok(mut found) = found.lock() {
+                found.push(relative(root, entry.path()));
+            }
+            WalkState::Continue
+        })
+    });
+    let mut files = found.into_inner().unwrap_or_default();
+    files.sort();
+    Ok(files)
+}
+
+/// Searches file contents for `pattern` line by line. `limit` caps the total
+/// number of matches; results are ordered by path and line.
+pub fn search(root: &Path, pattern: &str, options: &SearchOptions) -> Result<Vec<SearchMatch>> {
+    let regex = Regex::new(pattern).with_context(|| format!("invalid pattern '{pattern}'"))?;
+    let found = Mutex::new(Vec::new());
+    let count = AtomicUsize::new(0);
+    let limit = options.limit.unwrap_or(usize::MAX);
+    walker(root, options)?.build_parallel().run(|| {
+        Box::new(|entry| {
+            let Some(entry) = file_entry(entry) else {
+                return WalkState::Continue;
+            };
+            let Ok(bytes) = fs::read(entry.path()) else {
+                return WalkState::Continue;
+            };
+            if bytes[..bytes.len().min(BINARY_PROBE)].contains(&0) {
+                return WalkState::Continue;
+            }
+            let modified = entry.metadata().ok().and_then(|meta| meta.modified().ok());
+            let text = String::from_utf8_lossy(&bytes);
+            let mut matches = Vec::new();
+            for (idx, line) in text.lines().enumerate() {
+                if !regex.is_match(line) {
+                    continue;
+                }
+                if count.fetch_add(1, Ordering::Relaxed) >= limit {
+                    break;
+                }
+                matches.push(SearchMatch {
+                    path: relative(root, entry.path()),
+                    line_number: idx + 1,
+                    line: line.to_string(),
+                    modified,
+                });
+            }
+            if let Ok(mut found) = found.lock() {
+                found.extend(matches);
+            }
+            if count.load(Ordering::Relaxed) >= limit {
+                WalkState::Quit
+            } else {
+                WalkState::Continue
+            }
+        })
+    });
+    let mut matches = found.into_inner().unwrap_or_default();
+    matches.sort_by(|a, b| (&a.path, a.line_number).cmp(&(&b.path, b.line_number)));
+    matches.truncate(limit);
+    Ok(matches)
+}
+
+/// Renders the project layout as a tab-indented tree, directories first.
+/// Nodes are taken breadth first until `limit` entries are shown; the rest
+/// is summarised as `[N truncated]` markers.
+pub fn tree(root: &Path, limit: usize) -> Result<String> {
+    let mut full = Node::default();
+    for file in files(root, &SearchOptions::default())? {
+        let parts = components(&file);
+        if parts.iter().any(|part| part == ".opencode") {
+            continue;
+        }
+        full.insert(&parts);
+    }
+    full.sort();
+
+    let mut result = Node::default();
+    let mut processed = 0;
+    let mut current: Vec<Vec<String>> = vec![Vec::new()];
+    while !current.is_empty() {
+        let mut next = Vec::new();
+        let mut widest = 0;
+        for path in &current {
+            let node = full.get(path).expect("path taken from tree");
+            widest = widest.max(node.children.len());
+            next.extend(
+                node.children
+                    .iter()
+                    .map(|child| child_path(path, &child.name)),
+            );
+        }
+
+        'fill: for idx in 0..widest {
+            for path in &current {
+                let node = full.get(path).expect("path taken from tree");
+                let Some(child) = node.children.get(idx) else {
+                    continue;
+                };
+                result.insert(&child_path(path, &child.name));
+                processed += 1;
+                if processed >= limit {
+                    break 'fill;
+                }
+            }
+        }
+
+        if processed >= limit {
+            for path in current.iter().chain(&next) {
+                let node = full.get(path).expect("path taken from tree");
+                if let Some(shown) = result.get_mut(path)
+                    && shown.children.len() != node.children.len()
+                {
+                    let hidden = node.children.len() - shown.children.len();
+                    shown
+                        .children
+                        .push(Node::named(format!("[{hidden} truncated]")));
+                }
+            }
+            break;
+        }
+        current = next;
+    }
+
+    let mut lines = Vec::new();
+    for child in &result.children {
+        child.render(0, &mut lines);
+    }
+    Ok(lines.join("\n"))
+}
+
+fn walker(root: &Path, options: &SearchOptions) -> Result<WalkBuilder> {
+    if !root.is_dir() {
+        bail!("no such directory: {}", root.display());
+    }
+    let mut globs = OverrideBuilder::new(root);
+    for glob in &options.globs {
+        globs
+            .add(glob)
+            .with_context(|| format!("invalid glob '{glob}'"))?;
+    }
+    let globs = globs.build()?;
+    let skip_ignored_folders = options.skip_ignored_folders;
+
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .hidden(false)
+        .follow_links(true)
+        .require_git(false)
+        // Globs are applied here rather than as walker overrides: overrides
+        // take precedence over .gitignore, while these only narrow results.
+        .filter_entry(move |entry| {
+            let name = entry.file_name();
+            if name == ".git" {
+                return false;
+            }
+            let is_dir = entry.file_type().is_some_and(|kind| kind.is_dir());
+            if skip_ignored_folders
+                && is_dir
+                && name
+                    .to_str()
+                    .is_some_and(|name| IGNORED_FOLDERS.contains(&name))
+            {
+                return false;
+            }
+            match globs.matched(entry.path(), is_dir) {
+                Match::Ignore(_) => false,
+                Match::Whitelist(_) => true,
+                Match::None => is_dir || globs.num_whitelists() == 0,
+            }
+        });
+    Ok(builder)
+}
+
+fn file_entry(entry: Result<DirEntry, ignore::Error>) -> Option<DirEntry> {
+    entry
+        .ok()
+        .filter(|entry| entry.file_type().is_some_and(|kind| kind.is_file()))
+}
+
+fn relative(root: &Path, path: &Path) -> PathBuf {
+    path.strip_prefix(root).unwrap_or(path).to_path_buf()
+}
+
+fn components(path: &Path) -> Vec<String> {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .collect()
+}
+
+fn child_path(parent: &[String], name: &str) -> Vec<String> {
+    let mut path = parent.to_vec();
+    path.push(name.to_string());
+    path
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    name: String,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn named(name: String) -> Self {
+        Self {
+            name,
+            children: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, parts: &[String]) {
+        let mut node = self;
+        for part in parts {
+            let idx = match node.children.iter().position(|child| &child.name == part) {
+                Some(idx) => idx,
+                None => {
+                    node.children.push(Node::named(part.clone()));
+                    node.children.len() - 1
+                }
+            };
+            node = &mut node.children[idx];
+        }
+    }
+
+    fn get(&self, parts: &[String]) -> Option<&Node> {
+        parts.iter().try_fold(self, |node, part| {
+            node.children.iter().find(|child| &child.name == part)
+        })
+    }
+
+    fn get_mut(&mut self, parts: &[String]) -> Option<&mut Node> {
+        parts.iter().try_fold(self, |node, part| {
+            node.children.iter_mut().find(|child| &child.name == part)
+        })
+    }
+
+    fn sort(&mut self) {
+        self.children.sort_by(|a, b| {
+            a.children
+                .is_empty()
+                .cmp(&b.children.is_empty())
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        for child in &mut self.children {
+            child.sort();
+        }
+    }
+
+    fn render(&self, depth: usize, lines: &mut Vec<String>) {
+        let suffix = if self.children.is_empty() { "" } else { "/" };
+        lines.push(format!("{}{}{suffix}", "\t".repeat(depth), self.name));
+        for child in &self.children {
+            child.render(depth + 1, lines);
+        }
+    }
+}
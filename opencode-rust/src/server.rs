@@ -0,0 +1,304 @@
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures_util::stream::{self, Stream};
+use serde::Deserialize;
+use serde_json::json;
+use thiserror::Error;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::session::compaction::Compactor;
+use crate::session::revert::SessionRevert;
+use crate::session::store::{MessageRecord, SessionStore, StoreError, StoredSession};
+use crate::session::todo::{Todo, TodoLists};
+use crate::session::{AgentEvent, SessionRequest, SessionRuntime};
+
+#[derive(Clone)]
+pub struct AppState {
+    pub store: Arc<SessionStore>,
+    pub revert: Arc<SessionRevert>,
+    pub compactor: Option<Arc<Compactor>>,
+    pub runtime: Option<Arc<SessionRuntime>>,
+    /// The todo lists the runtime's todo tools write.
+    pub todos: Arc<TodoLists>,
+    /// Agent events streamed to clients of `GET /event`.
+    pub events: broadcast::Sender<AgentEvent>,
+    /// Sessions with a request in progress that changes them.
+    busy: Arc<Mutex<HashSet<Uuid>>>,
+}
+
+/// A second request tried to change a session while another one was still
+/// running on it.
+#[derive(Debug, Error)]
+#[error("session {0} is busy with another request")]
+pub struct SessionBusy(pub Uuid);
+
+/// Marks a session busy until dropped.
+struct SessionClaim {
+    busy: Arc<Mutex<HashSet<Uuid>>>,
+    id: Uuid,
+}
+
+impl Drop for SessionClaim {
+    fn drop(&mut self) {
+        self.busy
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .remove(&self.id);
+    }
+}
+
+impl AppState {
+    pub fn new(revert: Arc<SessionRevert>) -> Self {
+        Self {
+            store: revert.store().clone(),
+            revert,
+            compactor: None,
+            runtime: None,
+            todos: Arc::new(TodoLists::new()),
+            events: broadcast::channel(256).0,
+            busy: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Enables `POST /session/{id}/compact`.
+    pub fn with_compactor(mut self, compactor: Arc<Compactor>) -> Self {
+        self.compactor = Some(compactor);
+        self
+    }
+
+    /// Enables `POST /session` and `POST /session/{id}/message`, which run
+    /// prompts on `runtime`. `todos` must be the lists its todo tools write.
+    pub fn with_runtime(mut self, runtime: Arc<SessionRuntime>, todos: Arc<TodoLists>) -> Self {
+        self.runtime = Some(runtime);
+        self.todos = todos;
+        self
+    }
+
+    /// Publishes every event a session runtime reports on `events`, until
+    /// the runtime's sender is dropped.
+    pub fn forward_events(&self, mut events: mpsc::Receiver<AgentEvent>) -> JoinHandle<()> {
+        let state = self.clone();
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                state.publish(event);
+            }
+        })
+    }
+
+    /// Sends `event` to every connected `GET /event` client; it is dropped
+    /// when none are listening.
+    pub fn publish(&self, event: AgentEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// Claims session `id` for a request that loads, changes and saves it,
+    /// failing while another such request holds it.
+    fn claim(&self, id: Uuid) -> Result<SessionClaim, SessionBusy> {
+        let mut busy = self.busy.lock().unwrap_or_else(|err| err.into_inner());
+        if !busy.insert(id) {
+            return Err(SessionBusy(id));
+        }
+        Ok(SessionClaim {
+            busy: self.busy.clone(),
+            id,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PromptBody {
+    pub prompt: String,
+    #[serde(default)]
+    pub agent: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevertBody {
+    #[serde(alias = "messageID")]
+    pub message_id: Uuid,
+}
+
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/event", get(stream_events))
+        .route("/session", get(list_sessions).post(create_session))
+        .route("/session/{id}", get(get_session))
+        .route(
+            "/session/{id}/message",
+            get(list_messages).post(prompt_session),
+        )
+        .route("/session/{id}/todo", get(list_todos))
+        .route("/session/{id}/revert", post(revert_session))
+        .route("/session/{id}/unrevert", post(unrevert_session))
+        .route("/session/{id}/compact", post(compact_session))
+        .with_state(state)
+}
+
+pub async fn serve(listener: TcpListener, state: AppState) -> anyhow::Result<()> {
+    info!(addr = ?listener.local_addr().ok(), "server listening");
+    axum::serve(listener, router(state)).await?;
+    Ok(())
+}
+
+/// Streams published agent events, such as todo list updates, as JSON
+/// server-sent events. A client that falls behind skips the events it missed.
+async fn stream_events(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let stream = stream::unfold(state.events.subscribe(), |mut events| async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => match Event::default().json_data(&event) {
+                    Ok(event) => return Some((Ok(event), events)),
+                    Err(err) => warn!("failed to encode agent event: {err}"),
+                },
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!(skipped, "event stream client fell behind");
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+async fn list_sessions(State(state): State<AppState>) -> ApiResult<Vec<StoredSession>> {
+    Ok(Json(state.store.list().await?))
+}
+
+async fn get_session(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> ApiResult<StoredSession> {
+    Ok(Json(state.store.get(id).await?))
+}
+
+async fn list_messages(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> ApiResult<Vec<MessageRecord>> {
+    let session = state.store.get(id).await?;
+    Ok(Json(session.visible_messages().to_vec()))
+}
+
+async fn create_session(
+    State(state): State<AppState>,
+    Json(body): Json<PromptBody>,
+) -> ApiResult<StoredSession> {
+    Ok(Json(run_prompt(&state, None, body).await?))
+}
+
+async fn prompt_session(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(body): Json<PromptBody>,
+) -> ApiResult<StoredSession> {
+    let _claim = state.claim(id)?;
+    let session = state.store.get(id).await?;
+    Ok(Json(run_prompt(&state, Some(session), body).await?))
+}
+
+/// Runs `body` as the next turn of `session`, or of a new session, and saves
+/// the result. Progress is streamed through `GET /event` while it runs.
+async fn run_prompt(
+    state: &AppState,
+    session: Option<StoredSession>,
+    body: PromptBody,
+) -> anyhow::Result<StoredSession> {
+    let runtime = state
+        .runtime
+        .as_ref()
+        .ok_or_else(|| anyhow!("the session runtime is not configured"))?;
+    let mut request = SessionRequest::new(body.prompt.clone());
+    request.agent = body.agent;
+    if let Some(session) = &session {
+        state.todos.set(session.id, session.todos.clone());
+        request = request
+            .with_history(session.context_messages().to_vec())
+            .with_session_id(session.id);
+    }
+    let result = runtime.execute(request).await?;
+    let mut session = StoredSession::with_turn(session, &body.prompt, &result);
+    session.todos = state.todos.get(session.id);
+    state.store.save(&session).await?;
+    Ok(session)
+}
+
+async fn list_todos(State(state): State<AppState>, Path(id): Path<Uuid>) -> ApiResult<Vec<Todo>> {
+    Ok(Json(state.store.get(id).await?.todos))
+}
+
+async fn revert_session(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(body): Json<RevertBody>,
+) -> ApiResult<StoredSession> {
+    let _claim = state.claim(id)?;
+    Ok(Json(state.revert.revert(id, body.message_id).await?))
+}
+
+async fn unrevert_session(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> ApiResult<StoredSession> {
+    let _claim = state.claim(id)?;
+    Ok(Json(state.revert.unrevert(id).await?))
+}
+
+async fn compact_session(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> ApiResult<StoredSession> {
+    let compactor = state
+        .compactor
+        .as_ref()
+        .ok_or_else(|| anyhow!("session compaction is not configured"))?;
+    let _claim = state.claim(id)?;
+    let (session, _) = compactor.compact_session(&state.store, id).await?;
+    Ok(Json(session))
+}
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+/// Maps runtime errors to JSON error responses; unknown sessions and
+/// messages are reported as 404, and busy sessions as 409.
+pub struct ApiError(anyhow::Error);
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        Self(err)
+    }
+}
+
+impl From<SessionBusy> for ApiError {
+    fn from(err: SessionBusy) -> Self {
+        Self(err.into())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = if self.0.is::<StoreError>() {
+            StatusCode::NOT_FOUND
+        } else if self.0.is::<SessionBusy>() {
+            StatusCode::CONFLICT
+        } else {
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+        let body = json!({ "error": format!("{:#}", self.0) });
+        (status, Json(body)).into_response()
+    }
+}
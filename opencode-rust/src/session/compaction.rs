@@ -0,0 +1,257 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use uuid::Uuid;
+
+use crate::agent::spec::{AgentBudgets, ModelHandle};
+use crate::session::prompts::SessionPrompts;
+use crate::session::runtime::{CompletionRequest, LanguageModel, PartialOutput};
+use crate::session::store::{MessageRecord, Role, SessionStore, StoredSession};
+use crate::session::tokens;
+use crate::util::config::Info;
+
+/// Used when the configuration does not give the model's window.
+pub const DEFAULT_CONTEXT_WINDOW: u32 = 128_000;
+const AGENT: &str = "compaction";
+const PRUNED_OUTPUT: &str = "[Old tool output pruned]";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactionConfig {
+    /// The model's context window, in tokens.
+    pub context_window: u32,
+    /// Share of the window the conversation may fill before it is compacted.
+    pub threshold: f64,
+    /// Messages at the end of the conversation kept verbatim.
+    pub keep_recent: usize,
+    /// Tool outputs among the kept messages that are not pruned, counted
+    /// from the most recent.
+    pub keep_tool_outputs: usize,
+}
+
+impl Default for CompactionConfig {
+    fn default() -> Self {
+        Self {
+            context_window: DEFAULT_CONTEXT_WINDOW,
+            threshold: 0.8,
+            keep_recent: 4,
+            keep_tool_outputs: 1,
+        }
+    }
+}
+
+impl CompactionConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_context_window(mut self, tokens: u32) -> Self {
+        self.context_window = tokens;
+        self
+    }
+
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn with_keep_recent(mut self, messages: usize) -> Self {
+        self.keep_recent = messages;
+        self
+    }
+
+    pub fn with_keep_tool_outputs(mut self, outputs: usize) -> Self {
+        self.keep_tool_outputs = outputs;
+        self
+    }
+
+    /// Token count above which the conversation is compacted.
+    pub fn limit(&self) -> u32 {
+        (f64::from(self.context_window) * self.threshold.clamp(0.0, 1.0)) as u32
+    }
+}
+
+/// What a compaction did, for the session store to record.
+#[derive(Debug, Clone)]
+pub struct Compaction {
+    /// Stands in for every message before `first_kept`.
+    pub summary: MessageRecord,
+    /// The oldest message kept verbatim, if any were.
+    pub first_kept: Option<Uuid>,
+    /// Kept tool messages whose output no longer goes to the model.
+    pub pruned: Vec<Uuid>,
+    pub tokens_before: u32,
+    pub tokens_after: u32,
+}
+
+impl Compaction {
+    /// The conversation as the model sees it after this compaction.
+    pub fn apply(&self, history: &[MessageRecord]) -> Vec<MessageRecord> {
+        let start = self
+            .first_kept
+            .and_then(|id| history.iter().position(|message| message.id == id))
+            .unwrap_or(history.len());
+        let mut compacted = vec![self.summary.clone()];
+        compacted.extend(history[start..].iter().cloned().map(|mut message| {
+            message.pruned |= self.pruned.contains(&message.id);
+            message
+        }));
+        compacted
+    }
+}
+
+/// Summarizes older turns with the small model so a conversation keeps
+/// fitting in the context window.
+pub struct Compactor {
+    model: Arc<dyn LanguageModel>,
+    small_model: ModelHandle,
+    config: CompactionConfig,
+}
+
+impl Compactor {
+    pub fn new(model: Arc<dyn LanguageModel>, small_model: ModelHandle) -> Self {
+        Self {
+            model,
+            small_model,
+            config: CompactionConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: CompactionConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Summarizes with `small_model`, falling back to `model`, and sizes the
+    /// window from `model`'s `limit.context` in its provider's `models`.
+    pub fn from_info(info: &Info, llm: Arc<dyn LanguageModel>, model: &ModelHandle) -> Self {
+        let small_model = info
+            .small_model
+            .as_deref()
+            .map(ModelHandle::from)
+            .unwrap_or_else(|| model.clone());
+        let window = context_window(info, model).unwrap_or(DEFAULT_CONTEXT_WINDOW);
+        Self::new(llm, small_model).with_config(CompactionConfig::new().with_context_window(window))
+    }
+
+    pub fn config(&self) -> &CompactionConfig {
+        &self.config
+    }
+
+    /// Whether `history` plus `pending` more tokens goes over the limit.
+    pub fn needs_compaction(&self, history: &[MessageRecord], pending: u32) -> bool {
+        estimate_history(history).saturating_add(pending) > self.config.limit()
+    }
+
+    /// Summarizes all but the last `keep_recent` messages of `history` and
+    /// prunes older tool outputs among the rest. Returns `None` when there
+    /// is nothing to summarize.
+    pub async fn compact(
+        &self,
+        session_id: Uuid,
+        history: &[MessageRecord],
+    ) -> Result<Option<Compaction>> {
+        let split = history.len().saturating_sub(self.config.keep_recent);
+        let (older, kept) = history.split_at(split);
+        if older.is_empty() || matches!(older, [only] if only.summary) {
+            return Ok(None);
+        }
+
+        let objective = "Summarize the conversation so far.".to_string();
+        let prompt = format!(
+            "{}\n\n<CONVERSATION>\n{}\n</CONVERSATION>\n",
+            SessionPrompts::compaction().trim(),
+            transcript(older)
+        );
+        let request = CompletionRequest {
+            session_id,
+            agent: AGENT.to_string(),
+            model: self.small_model.clone(),
+            prompt,
+            objective,
+            budgets: AgentBudgets::default(),
+            tool_names: Vec::new(),
+            partial: PartialOutput::new(),
+        };
+        let response = self.model.complete(request).await?;
+
+        let pruned = kept
+            .iter()
+            .filter(|message| message.role == Role::Tool && !message.pruned)
+            .rev()
+            .skip(self.config.keep_tool_outputs)
+            .map(|message| message.id)
+            .collect();
+        let mut compaction = Compaction {
+            summary: MessageRecord::summary(AGENT, response.summary),
+            first_kept: kept.first().map(|message| message.id),
+            pruned,
+            tokens_before: estimate_history(history),
+            tokens_after: 0,
+        };
+        compaction.tokens_after = estimate_history(&compaction.apply(history));
+        Ok(Some(compaction))
+    }
+
+    /// Compacts a stored session regardless of its size and saves it.
+    pub async fn compact_session(
+        &self,
+        store: &SessionStore,
+        id: Uuid,
+    ) -> Result<(StoredSession, Option<Compaction>)> {
+        let mut session = store.get(id).await?;
+        let compaction = self.compact(id, session.context_messages()).await?;
+        if let Some(compaction) = &compaction {
+            session.apply_compaction(compaction);
+            store.save(&session).await?;
+        }
+        Ok((session, compaction))
+    }
+}
+
+/// Renders messages for a prompt, one block per message, with pruned tool
+/// outputs replaced by a marker.
+pub fn transcript(messages: &[MessageRecord]) -> String {
+    messages
+        .iter()
+        .map(|message| {
+            let (label, content) = match message.role {
+                Role::User => ("user".to_string(), message.content.as_str()),
+                Role::Assistant if message.summary => {
+                    ("summary".to_string(), message.content.as_str())
+                }
+                Role::Assistant => (
+                    format!("assistant ({})", message.agent),
+                    message.content.as_str(),
+                ),
+                Role::Tool if message.pruned => {
+                    (format!("tool ({})", message.agent), PRUNED_OUTPUT)
+                }
+                Role::Tool => (
+                    format!("tool ({})", message.agent),
+                    message.content.as_str(),
+                ),
+            };
+            format!("[{label}]\n{}", content.trim())
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Estimated tokens the messages take up in a prompt.
+pub fn estimate_history(messages: &[MessageRecord]) -> u32 {
+    tokens::estimate(&transcript(messages))
+}
+
+/// `limit.context` of the model's entry under its provider's `models`.
+fn context_window(info: &Info, model: &ModelHandle) -> Option<u32> {
+    let (provider, name) = model.id().split_once('/')?;
+    let entry = info
+        .provider
+        .as_ref()?
+        .get(provider)?
+        .models
+        .as_ref()?
+        .get(name)?;
+    let tokens = entry.get("limit")?.get("context")?.as_u64()?;
+    u32::try_from(tokens).ok()
+}
@@ -1,10 +1,27 @@
+pub mod compaction;
 pub mod prompt_builder;
 pub mod prompts;
+pub mod rate_limit;
+pub mod retry;
+pub mod revert;
 pub mod runtime;
+pub mod store;
+pub mod subtasks;
+pub mod todo;
+pub mod tokens;
 
+pub use compaction::{Compaction, CompactionConfig, Compactor};
 pub use prompt_builder::{ProjectContext, PromptBuilder};
 pub use prompts::SessionPrompts;
+pub use rate_limit::{RateLimitedModel, RateLimiter, RateLimits};
+pub use retry::{ErrorClass, ProviderError, RetryPolicy, RetryingModel};
+pub use revert::SessionRevert;
 pub use runtime::{
-    AgentEvent, CompletionRequest, CompletionResponse, LanguageModel, LocalModel, SessionRequest,
-    SessionResult, SessionRuntime, SubagentInvocation, SubagentOutcome,
+    AgentEvent, CompletionRequest, CompletionResponse, FailurePolicy, LanguageModel, LocalModel,
+    PartialOutput, SessionRequest, SessionResult, SessionRuntime, SubagentInvocation,
+    SubagentOutcome, SubtaskFailure, SubtaskFailureKind,
 };
+pub use store::{MessageRecord, Role, SessionStore, StoredSession};
+pub use subtasks::SubtaskGraph;
+pub use todo::{Todo, TodoLists, TodoPriority, TodoStatus};
+pub use tokens::{ModelFamily, TokenEstimator, TokenUsage, UsageLedger};
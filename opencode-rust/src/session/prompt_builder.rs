@@ -2,9 +2,9 @@ use std::fs;
 use std::path::{Path, PathBuf};
 
 use anyhow::Result;
-use walkdir::WalkDir;
 
 use crate::agent::spec::{AgentBudgets, AgentSpec};
+use crate::search::{self, SearchOptions};
 use crate::util::config::Info;
 
 const DEFAULT_REPORT_FORMAT: &str =
@@ -22,16 +22,16 @@ impl ProjectContext {
         let root_path = root.into();
         let mut sections = Vec::new();
 
-        if let Some(instructions) = &info.instructions {
-            if !instructions.is_empty() {
-                sections.push(format!(
-                    "# Project instructions\n{}",
-                    instructions.join("\n")
-                ));
-            }
+        if let Some(instructions) = &info.instructions
+            && !instructions.is_empty()
+        {
+            sections.push(format!(
+                "# Project instructions\n{}",
+                instructions.join("\n")
+            ));
         }
 
-        for file in collect_rule_sources(&root_path) {
+        for file in collect_rule_sources(&root_path)? {
             if let Ok(content) = fs::read_to_string(&file) {
                 let relative = file
                     .strip_prefix(&root_path)
@@ -67,6 +67,8 @@ pub struct PromptBuilder<'a> {
     spec: &'a AgentSpec,
     context: &'a ProjectContext,
     objective: &'a str,
+    history: Option<&'a str>,
+    subagent_results: Option<&'a str>,
 }
 
 impl<'a> PromptBuilder<'a> {
@@ -75,18 +77,33 @@ impl<'a> PromptBuilder<'a> {
             spec,
             context,
             objective,
+            history: None,
+            subagent_results: None,
         }
     }
 
+    /// Adds a `<CONVERSATION>` section with earlier turns before the
+    /// objective.
+    pub fn with_history(mut self, history: &'a str) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Adds a `<SUBAGENT_RESULTS>` section after the objective.
+    pub fn with_subagent_results(mut self, results: &'a str) -> Self {
+        self.subagent_results = Some(results);
+        self
+    }
+
     pub fn build(&self) -> String {
         let mut role_section = self.spec.prompt_sections.join("\n\n");
-        if let Some(description) = &self.spec.description {
-            if !description.trim().is_empty() {
-                if !role_section.is_empty() {
-                    role_section.push_str("\n\n");
-                }
-                role_section.push_str(description.trim());
+        if let Some(description) = &self.spec.description
+            && !description.trim().is_empty()
+        {
+            if !role_section.is_empty() {
+                role_section.push_str("\n\n");
             }
+            role_section.push_str(description.trim());
         }
         if role_section.is_empty() {
             role_section.push_str("You are a focussed coding agent.");
@@ -99,8 +116,22 @@ impl<'a> PromptBuilder<'a> {
             .as_deref()
             .unwrap_or(DEFAULT_REPORT_FORMAT);
 
+        let history = self
+            .history
+            .map(|history| format!("<CONVERSATION>\n{}\n</CONVERSATION>\n\n", history.trim()))
+            .unwrap_or_default();
+        let results = self
+            .subagent_results
+            .map(|results| {
+                format!(
+                    "<SUBAGENT_RESULTS>\n{}\n</SUBAGENT_RESULTS>\n\n",
+                    results.trim()
+                )
+            })
+            .unwrap_or_default();
+
         format!(
-            "<ROLE>\n{role_section}\n</ROLE>\n\n<OBJECTIVE>\n{}\n</OBJECTIVE>\n\n<PROJECT_RULES>\n{}\n</PROJECT_RULES>\n\n<CONSTRAINTS>\n{}\n</CONSTRAINTS>\n\n<REPORT_FORMAT>\n{}\n</REPORT_FORMAT>\n",
+            "<ROLE>\n{role_section}\n</ROLE>\n\n{history}<OBJECTIVE>\n{}\n</OBJECTIVE>\n\n{results}<PROJECT_RULES>\n{}\n</PROJECT_RULES>\n\n<CONSTRAINTS>\n{}\n</CONSTRAINTS>\n\n<REPORT_FORMAT>\n{}\n</REPORT_FORMAT>\n",
             self.objective.trim(),
             self.context.rules(),
             constraints,
@@ -122,31 +153,25 @@ fn compose_constraints(budgets: &AgentBudgets) -> String {
         .join("\n")
 }
 
-fn collect_rule_sources(root: &Path) -> Vec<PathBuf> {
-    let mut files = Vec::new();
-    for entry in WalkDir::new(root)
-        .follow_links(false)
+/// Finds `AGENTS.md`/`CLAUDE.md` files and markdown under `migration`
+/// folders, skipping gitignored and dependency folders.
+fn collect_rule_sources(root: &Path) -> Result<Vec<PathBuf>> {
+    let options = SearchOptions::new().skip_ignored_folders();
+    let files = search::files(root, &options)?
         .into_iter()
-        .filter_map(Result::ok)
-    {
-        if !entry.file_type().is_file() {
-            continue;
-        }
-        let file_name = entry.file_name().to_string_lossy().to_lowercase();
-        if file_name == "claude.md" || file_name == "agents.md" {
-            files.push(entry.path().to_path_buf());
-            continue;
-        }
-        if entry
-            .path()
-            .components()
-            .any(|component| component.as_os_str() == "migration")
-        {
-            if entry.path().extension().and_then(|ext| ext.to_str()) == Some("md") {
-                files.push(entry.path().to_path_buf());
-            }
-        }
-    }
-    files.sort();
-    files
+        .filter(|file| {
+            let file_name = file
+                .file_name()
+                .map(|name| name.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            file_name == "claude.md"
+                || file_name == "agents.md"
+                || (file
+                    .components()
+                    .any(|component| component.as_os_str() == "migration")
+                    && file.extension().and_then(|ext| ext.to_str()) == Some("md"))
+        })
+        .map(|file| root.join(file))
+        .collect();
+    Ok(files)
 }
@@ -8,4 +8,15 @@ impl SessionPrompts {
     pub fn build_switch() -> &'static str {
         include_str!("prompts/build-switch.txt")
     }
+
+    /// Instructions for summarizing older turns when compacting a session.
+    pub fn compaction() -> &'static str {
+        include_str!("prompts/compaction.txt")
+    }
+
+    /// Instructions for the parent's follow-up turn over its subagents'
+    /// results.
+    pub fn synthesize() -> &'static str {
+        include_str!("prompts/synthesize.txt")
+    }
 }
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::info;
+
+use crate::session::runtime::{AgentEvent, CompletionRequest, CompletionResponse, LanguageModel};
+use crate::session::tokens;
+use crate::util::config::Info;
+
+/// Per-minute limits for one provider.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimits {
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u32>,
+}
+
+impl RateLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_requests_per_minute(mut self, requests: u32) -> Self {
+        self.requests_per_minute = Some(requests);
+        self
+    }
+
+    pub fn with_tokens_per_minute(mut self, tokens: u32) -> Self {
+        self.tokens_per_minute = Some(tokens);
+        self
+    }
+
+    fn is_unlimited(&self) -> bool {
+        self.requests_per_minute.is_none() && self.tokens_per_minute.is_none()
+    }
+}
+
+/// Holds up to a minute's allowance and refills continuously. Reservations
+/// may overdraw it; the caller then waits until the debt is repaid, so
+/// callers are served in the order they reserved.
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    available: f64,
+    per_second: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(per_minute: u32, now: Instant) -> Self {
+        let capacity = f64::from(per_minute);
+        Self {
+            capacity,
+            available: capacity,
+            per_second: capacity / 60.0,
+            updated: now,
+        }
+    }
+
+    fn reserve(&mut self, amount: f64, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.available = (self.available + elapsed * self.per_second).min(self.capacity);
+        self.updated = now;
+        self.available -= amount;
+        if self.available >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.available / self.per_second)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ProviderBuckets {
+    requests: Option<TokenBucket>,
+    tokens: Option<TokenBucket>,
+}
+
+/// Request and token budgets shared by every agent in the process, keyed by
+/// provider ID. Providers without configured limits are never held back.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    limits: HashMap<String, RateLimits>,
+    buckets: Mutex<HashMap<String, ProviderBuckets>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_limits(mut self, provider: impl Into<String>, limits: RateLimits) -> Self {
+        if !limits.is_unlimited() {
+            self.limits.insert(provider.into(), limits);
+        }
+        self
+    }
+
+    /// Reads `requestsPerMinute` and `tokensPerMinute` from each provider's
+    /// options.
+    pub fn from_info(info: &Info) -> Self {
+        let mut limiter = Self::new();
+        for (name, config) in info.provider.iter().flatten() {
+            if let Some(options) = &config.options {
+                let limits = RateLimits {
+                    requests_per_minute: options.requests_per_minute,
+                    tokens_per_minute: options.tokens_per_minute,
+                };
+                limiter = limiter.with_limits(name.clone(), limits);
+            }
+        }
+        limiter
+    }
+
+    pub fn limits(&self, provider: &str) -> Option<RateLimits> {
+        self.limits.get(provider).copied()
+    }
+
+    /// Takes one request and `tokens` tokens from the provider's budget and
+    /// returns how long the caller must wait before sending the request.
+    pub fn reserve(&self, provider: &str, tokens: u32) -> Duration {
+        let Some(limits) = self.limits.get(provider) else {
+            return Duration::ZERO;
+        };
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|err| err.into_inner());
+        let buckets = buckets
+            .entry(provider.to_string())
+            .or_insert_with(|| ProviderBuckets {
+                requests: limits
+                    .requests_per_minute
+                    .map(|limit| TokenBucket::new(limit, now)),
+                tokens: limits
+                    .tokens_per_minute
+                    .map(|limit| TokenBucket::new(limit, now)),
+            });
+        let requests = buckets
+            .requests
+            .as_mut()
+            .map_or(Duration::ZERO, |bucket| bucket.reserve(1.0, now));
+        let tokens = buckets.tokens.as_mut().map_or(Duration::ZERO, |bucket| {
+            bucket.reserve(f64::from(tokens), now)
+        });
+        requests.max(tokens)
+    }
+}
+
+/// Holds each request to a model until its provider's budget allows it,
+/// charging the prompt and the request's `max_tokens` of output. Placed
+/// under `RetryingModel`, so every retried attempt is charged too; the wait
+/// counts against the agent's wall clock budget.
+pub struct RateLimitedModel {
+    inner: Arc<dyn LanguageModel>,
+    limiter: Arc<RateLimiter>,
+    events: Option<mpsc::Sender<AgentEvent>>,
+}
+
+impl RateLimitedModel {
+    pub fn new(inner: Arc<dyn LanguageModel>, limiter: Arc<RateLimiter>) -> Self {
+        Self {
+            inner,
+            limiter,
+            events: None,
+        }
+    }
+
+    /// Emits `AgentEvent::Queued` whenever a request has to wait.
+    pub fn with_events(mut self, events: mpsc::Sender<AgentEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+}
+
+#[async_trait]
+impl LanguageModel for RateLimitedModel {
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        if let Some(provider) = request.model.provider() {
+            let tokens = tokens::estimate_for(&request.model, &request.prompt)
+                .saturating_add(request.budgets.max_tokens.unwrap_or_default());
+            let wait = self.limiter.reserve(provider, tokens);
+            if !wait.is_zero() {
+                info!(agent = %request.agent, %provider, ?wait, "waiting for provider rate limit");
+                if let Some(events) = &self.events {
+                    let _ = events
+                        .send(AgentEvent::Queued {
+                            session_id: request.session_id,
+                            agent: request.agent.clone(),
+                            provider: provider.to_string(),
+                            wait_ms: wait.as_millis() as u64,
+                        })
+                        .await;
+                }
+                tokio::time::sleep(wait).await;
+            }
+        }
+        self.inner.complete(request).await
+    }
+}
@@ -0,0 +1,322 @@
+use std::error::Error as StdError;
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use crate::session::runtime::{AgentEvent, CompletionRequest, CompletionResponse, LanguageModel};
+
+/// A failed provider response. Models return this, wrapped in
+/// `anyhow::Error`, so the retry layer can tell throttling and outages from
+/// bad requests.
+#[derive(Debug, Clone, Error)]
+#[error("provider returned {status}: {message}")]
+pub struct ProviderError {
+    pub status: StatusCode,
+    pub message: String,
+    /// How long the provider asked us to wait before trying again.
+    pub retry_after: Option<Duration>,
+}
+
+impl ProviderError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(retry_after);
+        self
+    }
+
+    /// Builds the error from an unsuccessful response, reading its body as
+    /// the message and its `Retry-After` header.
+    pub async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        let retry_after = retry_after(response.headers());
+        let message = response.text().await.unwrap_or_default();
+        let message = match message.trim() {
+            "" => status
+                .canonical_reason()
+                .unwrap_or("no details")
+                .to_string(),
+            trimmed => trimmed.to_string(),
+        };
+        Self {
+            status,
+            message,
+            retry_after,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        self.status == StatusCode::TOO_MANY_REQUESTS
+            || self.status == StatusCode::REQUEST_TIMEOUT
+            || self.status.is_server_error()
+    }
+}
+
+/// How a failed completion should be handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Worth another attempt, after `retry_after` when the provider said so.
+    Retryable {
+        retry_after: Option<Duration>,
+    },
+    Fatal,
+}
+
+/// Classifies an error by the first provider, HTTP or I/O error in its
+/// chain. Throttling, server errors, timeouts and dropped connections are
+/// retryable; anything else is not.
+pub fn classify(err: &anyhow::Error) -> ErrorClass {
+    for cause in err.chain() {
+        if let Some(class) = classify_cause(cause) {
+            return class;
+        }
+    }
+    ErrorClass::Fatal
+}
+
+fn classify_cause(cause: &(dyn StdError + 'static)) -> Option<ErrorClass> {
+    let retryable = |retry_after| ErrorClass::Retryable { retry_after };
+    if let Some(err) = cause.downcast_ref::<ProviderError>() {
+        return Some(if err.is_retryable() {
+            retryable(err.retry_after)
+        } else {
+            ErrorClass::Fatal
+        });
+    }
+    if let Some(err) = cause.downcast_ref::<reqwest::Error>() {
+        if let Some(status) = err.status() {
+            return Some(if ProviderError::new(status, "").is_retryable() {
+                retryable(None)
+            } else {
+                ErrorClass::Fatal
+            });
+        }
+        if err.is_connect() || err.is_timeout() {
+            return Some(retryable(None));
+        }
+        // Resets surface as I/O errors further down the source chain.
+        return None;
+    }
+    if let Some(err) = cause.downcast_ref::<io::Error>() {
+        return Some(match err.kind() {
+            io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::UnexpectedEof => retryable(None),
+            _ => ErrorClass::Fatal,
+        });
+    }
+    None
+}
+
+/// Reads `Retry-After` as either delay seconds or an HTTP date.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = httpdate::parse_http_date(value).ok()?;
+    Some(at.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+/// Exponential backoff with jitter between attempts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts after the first; zero disables retries.
+    pub max_retries: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 4,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn with_initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    pub fn with_max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// The delay before retry number `retry` (starting at 1): the
+    /// exponential step capped at `max_delay`, of which the upper half is
+    /// randomized so clients that failed together do not retry together.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(32) as i32;
+        let step = self
+            .initial_delay
+            .mul_f64(self.multiplier.max(1.0).powi(exponent))
+            .min(self.max_delay);
+        let half = step / 2;
+        half + half.mul_f64(fastrand::f64())
+    }
+
+    /// The delay before retry number `retry`, deferring to the provider's
+    /// `Retry-After` when it sent one.
+    pub fn delay(&self, retry: u32, retry_after: Option<Duration>) -> Duration {
+        retry_after.unwrap_or_else(|| self.backoff(retry))
+    }
+}
+
+/// Retries a model's retryable failures according to a `RetryPolicy`,
+/// emitting `AgentEvent::Retrying` before each new attempt. It does not
+/// bound the total time spent: the runtime's wall clock budget for the
+/// agent covers every attempt and the waits between them.
+pub struct RetryingModel {
+    inner: Arc<dyn LanguageModel>,
+    policy: RetryPolicy,
+    events: Option<mpsc::Sender<AgentEvent>>,
+}
+
+impl RetryingModel {
+    pub fn new(inner: Arc<dyn LanguageModel>, policy: RetryPolicy) -> Self {
+        Self {
+            inner,
+            policy,
+            events: None,
+        }
+    }
+
+    pub fn with_events(mut self, events: mpsc::Sender<AgentEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+}
+
+#[async_trait]
+impl LanguageModel for RetryingModel {
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        let mut retry = 0;
+        loop {
+            let err = match self.inner.complete(request.clone()).await {
+                Ok(response) => return Ok(response),
+                Err(err) => err,
+            };
+            let ErrorClass::Retryable { retry_after } = classify(&err) else {
+                return Err(err);
+            };
+            if retry >= self.policy.max_retries {
+                return Err(err.context(format!("gave up after {} attempts", retry + 1)));
+            }
+            retry += 1;
+            let delay = self.policy.delay(retry, retry_after);
+            let error = format!("{err:#}");
+            debug!(agent = %request.agent, retry, ?delay, %error, "retrying completion");
+            if let Some(events) = &self.events {
+                let _ = events
+                    .send(AgentEvent::Retrying {
+                        session_id: request.session_id,
+                        agent: request.agent.clone(),
+                        attempt: retry + 1,
+                        delay_ms: delay.as_millis() as u64,
+                        error,
+                    })
+                    .await;
+            }
+            tokio::time::sleep(delay).await;
+            request.partial.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn backoff_grows_with_jitter_up_to_the_cap() {
+        let policy = RetryPolicy::new()
+            .with_initial_delay(Duration::from_millis(100))
+            .with_max_delay(Duration::from_millis(1000));
+        for _ in 0..50 {
+            let first = policy.backoff(1);
+            assert!((Duration::from_millis(50)..=Duration::from_millis(100)).contains(&first));
+            let third = policy.backoff(3);
+            assert!((Duration::from_millis(200)..=Duration::from_millis(400)).contains(&third));
+            let capped = policy.backoff(20);
+            assert!((Duration::from_millis(500)..=Duration::from_millis(1000)).contains(&capped));
+        }
+    }
+
+    #[test]
+    fn parses_retry_after_seconds_and_dates() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("7"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+
+        let later = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(120));
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(&later).unwrap());
+        let delay = retry_after(&headers).unwrap();
+        assert!(delay > Duration::from_secs(100) && delay <= Duration::from_secs(120));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[test]
+    fn classifies_errors() {
+        let throttled = anyhow::Error::new(
+            ProviderError::new(StatusCode::TOO_MANY_REQUESTS, "slow down")
+                .with_retry_after(Duration::from_secs(3)),
+        )
+        .context("completion failed");
+        assert_eq!(
+            classify(&throttled),
+            ErrorClass::Retryable {
+                retry_after: Some(Duration::from_secs(3))
+            }
+        );
+        let unavailable = anyhow::Error::new(ProviderError::new(StatusCode::BAD_GATEWAY, ""));
+        assert_eq!(
+            classify(&unavailable),
+            ErrorClass::Retryable { retry_after: None }
+        );
+        let bad_request = anyhow::Error::new(ProviderError::new(StatusCode::BAD_REQUEST, ""));
+        assert_eq!(classify(&bad_request), ErrorClass::Fatal);
+        let reset = anyhow::Error::new(io::Error::from(io::ErrorKind::ConnectionReset));
+        assert_eq!(
+            classify(&reset),
+            ErrorClass::Retryable { retry_after: None }
+        );
+        assert_eq!(classify(&anyhow::anyhow!("bad prompt")), ErrorClass::Fatal);
+    }
+}
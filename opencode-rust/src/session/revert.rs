@@ -0,0 +1,97 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::Mutex;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::session::store::{RevertState, Role, SessionStore, StoredSession};
+use crate::snapshot::Snapshot;
+
+/// Rolls a session's files and history back to before a message, and
+/// re-applies them on `unrevert`.
+pub struct SessionRevert {
+    store: Arc<SessionStore>,
+    snapshot: Arc<Snapshot>,
+    lock: Mutex<()>,
+}
+
+impl SessionRevert {
+    pub fn new(store: Arc<SessionStore>, snapshot: Arc<Snapshot>) -> Self {
+        Self {
+            store,
+            snapshot,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn store(&self) -> &Arc<SessionStore> {
+        &self.store
+    }
+
+    /// Hides `message_id` and everything after it. Reverting an assistant
+    /// message reverts the whole turn, starting at the user prompt that led
+    /// to it; a tool run is its own step. Reverting again while a revert is
+    /// pending keeps the original pre-revert snapshot so `unrevert` still
+    /// returns to the latest state.
+    pub async fn revert(&self, session_id: Uuid, message_id: Uuid) -> Result<StoredSession> {
+        let _lock = self.lock.lock().await;
+        let mut session = self.store.get(session_id).await?;
+        let target = session.message_index(message_id)?;
+        let start = session.messages[..=target]
+            .iter()
+            .rposition(|message| matches!(message.role, Role::User | Role::Tool))
+            .unwrap_or(target);
+
+        let snapshot = match session.revert.as_ref() {
+            Some(revert) => {
+                // Undo the pending revert first so the patches roll back
+                // from the latest state.
+                if let Some(hash) = &revert.snapshot {
+                    self.snapshot.restore(hash).await?;
+                }
+                revert.snapshot.clone()
+            }
+            None => self.snapshot.track().await?,
+        };
+        let patches: Vec<_> = session.messages[start..]
+            .iter()
+            .filter_map(|message| message.patch.clone())
+            .collect();
+        self.snapshot.revert(&patches).await?;
+        let diff = match &snapshot {
+            Some(hash) => Some(self.snapshot.diff(hash).await?),
+            None => None,
+        };
+
+        info!(
+            %session_id,
+            message_id = %session.messages[start].id,
+            files = patches.len(),
+            "reverted session"
+        );
+        session.revert = Some(RevertState {
+            message_id: session.messages[start].id,
+            snapshot,
+            diff,
+        });
+        self.store.save(&session).await?;
+        Ok(session)
+    }
+
+    /// Restores the files captured before the pending revert and shows the
+    /// hidden messages again.
+    pub async fn unrevert(&self, session_id: Uuid) -> Result<StoredSession> {
+        let _lock = self.lock.lock().await;
+        let mut session = self.store.get(session_id).await?;
+        let Some(revert) = session.revert.take() else {
+            return Ok(session);
+        };
+        if let Some(hash) = &revert.snapshot {
+            self.snapshot.restore(hash).await?;
+        }
+        info!(%session_id, "unreverted session");
+        self.store.save(&session).await?;
+        Ok(session)
+    }
+}
@@ -1,21 +1,38 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use async_trait::async_trait;
-use tokio::sync::mpsc;
+use serde::Serialize;
+use tokio::sync::{Notify, mpsc};
 use tokio::task::JoinSet;
 use tokio::time;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
 use crate::agent::registry::AgentRegistry;
 use crate::agent::session::Session;
 use crate::agent::spec::{AgentBudgets, AgentSpec, ModelHandle, resolve_model, resolve_tools};
+use crate::hook::{HookContext, HookRunner};
+use crate::session::compaction::{self, Compaction, Compactor};
 use crate::session::prompt_builder::{ProjectContext, PromptBuilder};
+use crate::session::prompts::SessionPrompts;
+use crate::session::store::MessageRecord;
+use crate::session::subtasks::{self, SubtaskGraph};
+use crate::session::todo::Todo;
+use crate::session::tokens::{self, TokenEstimator, TokenUsage, UsageLedger};
+use crate::snapshot::{Patch, Snapshot};
 use crate::tool::core::Tool;
+use crate::tool::task::TaskTool;
 
-#[derive(Debug, Clone)]
+/// Tools a `task` child does not inherit unless its allow-list names them: it
+/// should neither delegate further nor share the parent's todo list.
+const DELEGATION_TOOLS: &[&str] = &["task", "todowrite", "todoread"];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum AgentEvent {
     Started {
         session_id: Uuid,
@@ -26,23 +43,123 @@ pub enum AgentEvent {
         session_id: Uuid,
         agent: String,
         summary: String,
+        /// The agent ran out of its wall clock budget; `summary` is what it
+        /// produced until then.
+        timed_out: bool,
+        /// The agent used up its `max_tokens` budget and was stopped;
+        /// `summary` is cut off at the budget.
+        budget_exhausted: bool,
+        usage: TokenUsage,
+    },
+    Failed {
+        session_id: Uuid,
+        agent: String,
+        error: String,
+    },
+    /// A subtask was stopped or never started because a sibling or one of
+    /// its dependencies failed.
+    Cancelled {
+        session_id: Uuid,
+        agent: String,
+        reason: String,
+    },
+    /// Older turns of the conversation were summarized to make room in the
+    /// model's context window.
+    Compacted {
+        session_id: Uuid,
+        agent: String,
+        tokens_before: u32,
+        tokens_after: u32,
+    },
+    /// The provider's rate limits are used up, so the agent waits `wait_ms`
+    /// before sending its next request.
+    Queued {
+        session_id: Uuid,
+        agent: String,
+        provider: String,
+        wait_ms: u64,
+    },
+    /// A completion failed with a retryable error and will be attempted
+    /// again after `delay_ms`.
+    Retrying {
+        session_id: Uuid,
+        agent: String,
+        /// The upcoming attempt, counting the first as 1.
+        attempt: u32,
+        delay_ms: u64,
+        error: String,
+    },
+    /// An agent replaced its session's todo list.
+    TodoUpdated {
+        session_id: Uuid,
+        agent: String,
+        todos: Vec<Todo>,
     },
 }
 
 #[derive(Debug, Clone)]
 pub struct CompletionRequest {
+    pub session_id: Uuid,
     pub agent: String,
     pub model: ModelHandle,
     pub prompt: String,
     pub objective: String,
     pub budgets: AgentBudgets,
     pub tool_names: Vec<String>,
+    /// Where the model records output as it goes, so it survives a timeout.
+    pub partial: PartialOutput,
+}
+
+/// Output a model has produced so far. If the agent runs out of wall clock
+/// time or token budget, this becomes its result instead of being lost.
+#[derive(Debug, Clone, Default)]
+pub struct PartialOutput {
+    text: Arc<Mutex<String>>,
+    changed: Arc<Notify>,
+}
+
+impl PartialOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&self, text: &str) {
+        self.lock().push_str(text);
+        self.changed.notify_one();
+    }
+
+    /// Waits until more output is pushed.
+    pub async fn changed(&self) {
+        self.changed.notified().await;
+    }
+
+    pub fn get(&self) -> String {
+        self.lock().clone()
+    }
+
+    /// Output pushed after its first `offset` bytes, or `None` once it has
+    /// been cleared below that.
+    pub fn since(&self, offset: usize) -> Option<String> {
+        self.lock().get(offset..).map(str::to_string)
+    }
+
+    /// Drops what a failed attempt produced before the model tries again.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, String> {
+        self.text.lock().unwrap_or_else(|err| err.into_inner())
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct CompletionResponse {
     pub summary: String,
     pub raw_output: String,
+    /// Tokens the provider reports; estimated from the prompt and summary
+    /// when `None`.
+    pub usage: Option<TokenUsage>,
 }
 
 #[async_trait]
@@ -70,6 +187,7 @@ impl LanguageModel for LocalModel {
         Ok(CompletionResponse {
             summary,
             raw_output: request.prompt,
+            usage: None,
         })
     }
 }
@@ -77,7 +195,13 @@ impl LanguageModel for LocalModel {
 #[derive(Debug, Clone)]
 pub struct SubagentInvocation {
     pub agent: String,
+    /// May reference the outcomes of other subtasks as `{{id.summary}}`.
     pub objective: String,
+    pub model: Option<ModelHandle>,
+    /// Names the subtask so others can depend on or reference it.
+    pub id: Option<String>,
+    /// IDs of subtasks that must finish before this one starts.
+    pub depends_on: Vec<String>,
 }
 
 impl SubagentInvocation {
@@ -85,41 +209,192 @@ impl SubagentInvocation {
         Self {
             agent: agent.into(),
             objective: objective.into(),
+            model: None,
+            id: None,
+            depends_on: Vec::new(),
         }
     }
+
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn with_depends_on<I, S>(mut self, ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.depends_on.extend(ids.into_iter().map(Into::into));
+        self
+    }
+
+    /// Runs the subagent on `model` regardless of the agent's own setting.
+    pub fn with_model(mut self, model: ModelHandle) -> Self {
+        self.model = Some(model);
+        self
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct SubagentOutcome {
+    /// The invocation's ID, for subtasks that declared one.
+    pub id: Option<String>,
     pub agent: String,
     pub objective: String,
     pub session_id: Uuid,
     pub summary: String,
     pub model: ModelHandle,
     pub raw_output: String,
+    /// The agent hit its wall clock budget and `summary` holds its partial
+    /// output.
+    pub timed_out: bool,
+    /// The agent hit its `max_tokens` budget and `summary` holds its output
+    /// up to the budget.
+    pub budget_exhausted: bool,
+    /// Tokens this step consumed.
+    pub usage: TokenUsage,
+    /// Worktree snapshot recorded before the agent ran.
+    pub snapshot: Option<String>,
+    /// Files changed while the agent ran.
+    pub patch: Option<Patch>,
+}
+
+/// What happens to the other subtasks when one fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Cancel the remaining subtasks and fail the request.
+    #[default]
+    FailFast,
+    /// Keep running independent subtasks, cancel only the dependents of the
+    /// failed one, and report failures in `SessionResult::failures`.
+    CollectAll,
 }
 
 #[derive(Debug, Clone)]
 pub struct SessionRequest {
     pub agent: Option<String>,
+    pub model: Option<ModelHandle>,
     pub objective: String,
     pub subtasks: Vec<SubagentInvocation>,
+    /// How many subtasks may run at once; unbounded when `None`.
+    pub max_parallel: Option<usize>,
+    pub failure_policy: FailurePolicy,
+    /// Gives the primary agent a follow-up turn over the subtask results so
+    /// it can answer with one synthesized reply.
+    pub synthesize: bool,
+    /// Earlier messages of the conversation, shown to the primary agent.
+    pub history: Vec<MessageRecord>,
+    /// Continues this agent session, and with it the session's todo list,
+    /// instead of starting a new one.
+    pub session_id: Option<Uuid>,
 }
 
 impl SessionRequest {
     pub fn new(objective: impl Into<String>) -> Self {
         Self {
             agent: None,
+            model: None,
             objective: objective.into(),
             subtasks: Vec::new(),
+            max_parallel: None,
+            failure_policy: FailurePolicy::default(),
+            synthesize: false,
+            history: Vec::new(),
+            session_id: None,
+        }
+    }
+
+    pub fn with_max_parallel(mut self, max_parallel: usize) -> Self {
+        self.max_parallel = Some(max_parallel);
+        self
+    }
+
+    pub fn with_failure_policy(mut self, policy: FailurePolicy) -> Self {
+        self.failure_policy = policy;
+        self
+    }
+
+    pub fn with_synthesis(mut self) -> Self {
+        self.synthesize = true;
+        self
+    }
+
+    pub fn with_history(mut self, history: Vec<MessageRecord>) -> Self {
+        self.history = history;
+        self
+    }
+
+    pub fn with_session_id(mut self, session_id: Uuid) -> Self {
+        self.session_id = Some(session_id);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubtaskFailureKind {
+    Failed,
+    Cancelled,
+}
+
+impl SubtaskFailureKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
         }
     }
 }
 
+/// A subtask that produced no outcome under `FailurePolicy::CollectAll`.
+#[derive(Debug, Clone)]
+pub struct SubtaskFailure {
+    pub id: Option<String>,
+    pub agent: String,
+    pub objective: String,
+    pub session_id: Uuid,
+    pub kind: SubtaskFailureKind,
+    pub error: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct SessionResult {
     pub primary: SubagentOutcome,
+    /// Subtask outcomes in dependency order.
     pub subtasks: Vec<SubagentOutcome>,
+    /// Failed and cancelled subtasks, in dependency order.
+    pub failures: Vec<SubtaskFailure>,
+    /// The primary agent's follow-up turn over the subtask results, for
+    /// requests that asked for synthesis and ran subtasks.
+    pub synthesis: Option<SubagentOutcome>,
+    /// The history was compacted before the primary agent ran; the store
+    /// should record it with `StoredSession::apply_compaction`.
+    pub compaction: Option<Compaction>,
+}
+
+impl SessionResult {
+    /// The reply to show the user: the synthesis when there is one,
+    /// otherwise the primary agent's own summary.
+    pub fn answer(&self) -> &SubagentOutcome {
+        self.synthesis.as_ref().unwrap_or(&self.primary)
+    }
+
+    /// Tokens consumed by every agent step of the request.
+    pub fn usage(&self) -> TokenUsage {
+        std::iter::once(&self.primary)
+            .chain(&self.subtasks)
+            .chain(&self.synthesis)
+            .fold(TokenUsage::default(), |total, outcome| {
+                total + outcome.usage
+            })
+    }
+}
+
+/// Optional prompt sections for an agent step.
+#[derive(Debug, Clone, Default)]
+struct PromptExtras {
+    history: Option<String>,
+    subagent_results: Option<String>,
 }
 
 struct SpawnArtifacts {
@@ -134,6 +409,10 @@ pub struct SessionRuntime {
     tools: Arc<Vec<Arc<dyn Tool>>>,
     event_tx: mpsc::Sender<AgentEvent>,
     default_model: ModelHandle,
+    hooks: Option<Arc<HookRunner>>,
+    snapshot: Option<Arc<Snapshot>>,
+    compactor: Option<Arc<Compactor>>,
+    usage: Arc<UsageLedger>,
 }
 
 impl SessionRuntime {
@@ -152,70 +431,473 @@ impl SessionRuntime {
             tools: Arc::new(tools),
             event_tx,
             default_model,
+            hooks: None,
+            snapshot: None,
+            compactor: None,
+            usage: Arc::new(UsageLedger::new()),
         }
     }
 
+    /// Runs the `session_completed` hooks once `execute` or `delegate`
+    /// finishes, whether or not it succeeded.
+    pub fn with_hooks(mut self, hooks: Arc<HookRunner>) -> Self {
+        self.hooks = Some(hooks);
+        self
+    }
+
+    /// Compacts the request history before the primary agent runs when it
+    /// nears the model's context window.
+    pub fn with_compactor(mut self, compactor: Arc<Compactor>) -> Self {
+        self.compactor = Some(compactor);
+        self
+    }
+
+    /// Records a worktree snapshot before each agent step.
+    pub fn with_snapshot(mut self, snapshot: Arc<Snapshot>) -> Self {
+        self.snapshot = Some(snapshot);
+        self
+    }
+
+    /// Shares the runtime and registers the `task` tool, through which agents
+    /// delegate work to subagents. The tool refers back to the runtime
+    /// weakly, so dropping the returned handle releases both.
+    pub fn into_shared_with_task(mut self) -> Arc<Self> {
+        Arc::new_cyclic(|runtime| {
+            let mut tools = (*self.tools).clone();
+            tools.push(Arc::new(TaskTool::new(
+                runtime.clone(),
+                self.registry.clone(),
+            )));
+            self.tools = Arc::new(tools);
+            self
+        })
+    }
+
+    pub fn tools(&self) -> &[Arc<dyn Tool>] {
+        &self.tools
+    }
+
+    /// Tokens consumed so far by the agent session `session_id`.
+    pub fn usage(&self, session_id: Uuid) -> TokenUsage {
+        self.usage.get(session_id)
+    }
+
+    /// Runs the request and then the `session_completed` hooks, whether or
+    /// not it succeeded.
     pub async fn execute(&self, request: SessionRequest) -> Result<SessionResult> {
         let agent_name = request
             .agent
-            .as_deref()
-            .unwrap_or(self.registry.default_agent_name());
+            .clone()
+            .unwrap_or_else(|| self.registry.default_agent_name().to_string());
+        let session_id = request.session_id.unwrap_or_else(|| Session::new().id());
+        let result = self.execute_in(session_id, &agent_name, request).await;
+        self.session_completed(session_id, agent_name).await;
+        result
+    }
+
+    /// Runs a single invocation as a child session of `parent` (the default
+    /// agent when `None`) without a turn of the parent's own, then the
+    /// `session_completed` hooks. The child's outcome is the result's
+    /// primary.
+    pub async fn delegate(
+        &self,
+        parent: Option<&str>,
+        invocation: SubagentInvocation,
+    ) -> Result<SessionResult> {
+        let parent = self
+            .registry
+            .require_spec(parent.unwrap_or(self.registry.default_agent_name()))?;
+        let spec = self.registry.require_spec(&invocation.agent)?;
+        let session_id = Session::new().id();
+        let model = invocation
+            .model
+            .clone()
+            .unwrap_or_else(|| resolve_model(&spec, &resolve_model(&parent, &self.default_model)));
+        let tools = self.child_tools(&parent, &spec);
+        let result = self
+            .spawn_agent(
+                session_id,
+                spec,
+                invocation.objective,
+                model,
+                tools,
+                PromptExtras::default(),
+            )
+            .await;
+        self.session_completed(session_id, invocation.agent).await;
+        let mut outcome = result?.outcome;
+        outcome.id = invocation.id;
+        Ok(SessionResult {
+            primary: outcome,
+            subtasks: Vec::new(),
+            failures: Vec::new(),
+            synthesis: None,
+            compaction: None,
+        })
+    }
+
+    async fn session_completed(&self, session_id: Uuid, agent: String) {
+        if let Some(hooks) = &self.hooks {
+            let ctx = HookContext {
+                session_id: Some(session_id),
+                agent: Some(agent),
+            };
+            hooks.session_completed(&ctx).await;
+        }
+    }
+
+    async fn execute_in(
+        &self,
+        session_id: Uuid,
+        agent_name: &str,
+        request: SessionRequest,
+    ) -> Result<SessionResult> {
         let spec = self.registry.require_spec(agent_name)?;
+        let graph = SubtaskGraph::build(&request.subtasks)?;
+        for invocation in &request.subtasks {
+            self.registry.require_spec(&invocation.agent)?;
+        }
+        let model = request
+            .model
+            .clone()
+            .unwrap_or_else(|| resolve_model(&spec, &self.default_model));
+        let (history, compaction) = self.compact_history(session_id, &spec.name, &request).await;
+        let extras = PromptExtras {
+            history: (!history.is_empty()).then(|| compaction::transcript(&history)),
+            subagent_results: None,
+        };
         let spawn = self
             .spawn_agent(
-                spec,
+                session_id,
+                spec.clone(),
                 request.objective.clone(),
-                self.default_model.clone(),
+                model,
                 (*self.tools).clone(),
+                extras.clone(),
             )
             .await?;
         let parent_model = spawn.outcome.model.clone();
         let parent_tools = spawn.tools.clone();
-        let mut subtasks = Vec::new();
+        let (subtasks, failures) = self
+            .run_subtasks(&request, &graph, parent_model.clone(), parent_tools)
+            .await?;
+
+        let synthesis = if request.synthesize && !(subtasks.is_empty() && failures.is_empty()) {
+            let results = subagent_results(&subtasks, &failures);
+            let artifacts = self
+                .spawn_agent(
+                    spawn.outcome.session_id,
+                    spec,
+                    request.objective.clone(),
+                    parent_model,
+                    (*self.tools).clone(),
+                    PromptExtras {
+                        subagent_results: Some(results),
+                        ..extras
+                    },
+                )
+                .await?;
+            Some(artifacts.outcome)
+        } else {
+            None
+        };
+
+        Ok(SessionResult {
+            primary: spawn.outcome,
+            subtasks,
+            failures,
+            synthesis,
+            compaction,
+        })
+    }
+
+    /// The request history to show the primary agent, compacted first when
+    /// it would crowd the context window. A failed compaction is logged and
+    /// the full history used.
+    async fn compact_history(
+        &self,
+        session_id: Uuid,
+        agent: &str,
+        request: &SessionRequest,
+    ) -> (Vec<MessageRecord>, Option<Compaction>) {
+        let history = request.history.clone();
+        let Some(compactor) = &self.compactor else {
+            return (history, None);
+        };
+        if !compactor.needs_compaction(&history, tokens::estimate(&request.objective)) {
+            return (history, None);
+        }
+        match compactor.compact(session_id, &history).await {
+            Ok(Some(compaction)) => {
+                info!(
+                    %agent,
+                    tokens_before = compaction.tokens_before,
+                    tokens_after = compaction.tokens_after,
+                    "compacted session history"
+                );
+                self.emit(AgentEvent::Compacted {
+                    session_id,
+                    agent: agent.to_string(),
+                    tokens_before: compaction.tokens_before,
+                    tokens_after: compaction.tokens_after,
+                })
+                .await;
+                (compaction.apply(&history), Some(compaction))
+            }
+            Ok(None) => (history, None),
+            Err(err) => {
+                warn!(%agent, error = %format!("{err:#}"), "compaction failed; keeping the full history");
+                (history, None)
+            }
+        }
+    }
 
-        if !request.subtasks.is_empty() {
-            let mut set = JoinSet::new();
-            for invocation in request.subtasks {
+    /// Runs the subtasks as a dependency graph: each starts once its
+    /// dependencies have finished, with their outcomes substituted into its
+    /// objective, and up to `max_parallel` independent subtasks run at once.
+    /// A failure is handled according to the request's `failure_policy`.
+    async fn run_subtasks(
+        &self,
+        request: &SessionRequest,
+        graph: &SubtaskGraph,
+        parent_model: ModelHandle,
+        parent_tools: Vec<Arc<dyn Tool>>,
+    ) -> Result<(Vec<SubagentOutcome>, Vec<SubtaskFailure>)> {
+        let invocations = &request.subtasks;
+        let limit = request.max_parallel.unwrap_or(usize::MAX).max(1);
+        let session_ids: Vec<Uuid> = invocations.iter().map(|_| Session::new().id()).collect();
+        let mut waiting: Vec<usize> = (0..invocations.len())
+            .map(|index| graph.dependencies(index).len())
+            .collect();
+        let mut ready: VecDeque<usize> = graph
+            .order()
+            .iter()
+            .copied()
+            .filter(|&index| waiting[index] == 0)
+            .collect();
+        let mut finished: Vec<Option<SubagentOutcome>> = vec![None; invocations.len()];
+        let mut settled = vec![false; invocations.len()];
+        let mut failures = Vec::new();
+        let mut by_id: HashMap<String, SubagentOutcome> = HashMap::new();
+        let mut running = HashMap::new();
+        let mut set = JoinSet::new();
+
+        loop {
+            while running.len() < limit
+                && let Some(index) = ready.pop_front()
+            {
+                let invocation = &invocations[index];
                 let spec = self.registry.require_spec(&invocation.agent)?;
                 let runtime = self.clone();
-                let objective = invocation.objective.clone();
-                let model = parent_model.clone();
+                let session_id = session_ids[index];
+                let objective = subtasks::render_objective(&invocation.objective, &by_id);
+                let model = invocation
+                    .model
+                    .clone()
+                    .unwrap_or_else(|| resolve_model(&spec, &parent_model));
                 let tools = parent_tools.clone();
-                set.spawn(async move {
+                let handle = set.spawn(async move {
                     runtime
-                        .spawn_agent(spec, objective, model, tools)
+                        .spawn_agent(
+                            session_id,
+                            spec,
+                            objective,
+                            model,
+                            tools,
+                            PromptExtras::default(),
+                        )
                         .await
                         .map(|artifacts| artifacts.outcome)
                 });
+                running.insert(handle.id(), index);
             }
 
-            while let Some(result) = set.join_next().await {
-                subtasks.push(result??);
+            let Some(joined) = set.join_next_with_id().await else {
+                break;
+            };
+            let (index, result) = match joined {
+                Ok((task, result)) => (running.remove(&task), result),
+                Err(err) => {
+                    let index = running.remove(&err.id());
+                    let error = anyhow!("subagent task failed: {err}");
+                    if let Some(index) = index {
+                        self.emit(AgentEvent::Failed {
+                            session_id: session_ids[index],
+                            agent: invocations[index].agent.clone(),
+                            error: error.to_string(),
+                        })
+                        .await;
+                    }
+                    (index, Err(error))
+                }
+            };
+            let Some(index) = index else {
+                continue;
+            };
+            settled[index] = true;
+
+            let err = match result {
+                Ok(mut outcome) => {
+                    outcome.id = invocations[index].id.clone();
+                    if let Some(id) = &outcome.id {
+                        by_id.insert(id.clone(), outcome.clone());
+                    }
+                    finished[index] = Some(outcome);
+                    for &dependent in graph.dependents(index) {
+                        waiting[dependent] -= 1;
+                        if waiting[dependent] == 0 {
+                            ready.push_back(dependent);
+                        }
+                    }
+                    continue;
+                }
+                Err(err) => err,
+            };
+            let name = subtasks::label(&invocations[index], index);
+
+            if request.failure_policy == FailurePolicy::FailFast {
+                set.abort_all();
+                while let Some(joined) = set.join_next_with_id().await {
+                    if let Ok((task, _)) = joined
+                        && let Some(index) = running.remove(&task)
+                    {
+                        settled[index] = true;
+                    }
+                }
+                let reason = format!("subtask {name} failed");
+                for &other in graph.order() {
+                    if !settled[other] {
+                        self.cancel(invocations, &session_ids, other, &reason).await;
+                    }
+                }
+                return Err(err.context(reason));
+            }
+
+            failures.push(failure(
+                invocations,
+                &session_ids,
+                index,
+                SubtaskFailureKind::Failed,
+                format!("{err:#}"),
+            ));
+            let reason = format!("dependency {name} failed");
+            let mut blocked = graph.dependents(index).to_vec();
+            while let Some(dependent) = blocked.pop() {
+                if std::mem::replace(&mut settled[dependent], true) {
+                    continue;
+                }
+                self.cancel(invocations, &session_ids, dependent, &reason)
+                    .await;
+                failures.push(failure(
+                    invocations,
+                    &session_ids,
+                    dependent,
+                    SubtaskFailureKind::Cancelled,
+                    reason.clone(),
+                ));
+                blocked.extend_from_slice(graph.dependents(dependent));
             }
         }
 
-        Ok(SessionResult {
-            primary: spawn.outcome,
-            subtasks,
+        let position: HashMap<usize, usize> = graph
+            .order()
+            .iter()
+            .enumerate()
+            .map(|(position, &index)| (index, position))
+            .collect();
+        failures.sort_by_key(|failure: &(usize, SubtaskFailure)| position[&failure.0]);
+        Ok((
+            graph
+                .order()
+                .iter()
+                .filter_map(|&index| finished[index].take())
+                .collect(),
+            failures.into_iter().map(|(_, failure)| failure).collect(),
+        ))
+    }
+
+    async fn cancel(
+        &self,
+        invocations: &[SubagentInvocation],
+        session_ids: &[Uuid],
+        index: usize,
+        reason: &str,
+    ) {
+        info!(agent = %invocations[index].agent, reason, "subtask cancelled");
+        self.emit(AgentEvent::Cancelled {
+            session_id: session_ids[index],
+            agent: invocations[index].agent.clone(),
+            reason: reason.to_string(),
         })
+        .await;
+    }
+
+    async fn emit(&self, event: AgentEvent) {
+        let _ = self.event_tx.send(event).await;
+    }
+
+    /// Runs `spec` in a child session on behalf of an agent running as
+    /// `parent`, inheriting the parent's model and tools.
+    pub(crate) async fn spawn_child(
+        &self,
+        spec: Arc<AgentSpec>,
+        objective: String,
+        parent: &AgentSpec,
+    ) -> Result<SubagentOutcome> {
+        let model = resolve_model(&spec, &resolve_model(parent, &self.default_model));
+        let tools = self.child_tools(parent, &spec);
+        self.spawn_agent(
+            Session::new().id(),
+            spec,
+            objective,
+            model,
+            tools,
+            PromptExtras::default(),
+        )
+        .await
+        .map(|artifacts| artifacts.outcome)
+    }
+
+    /// The parent's tools, less the delegation tools the child's allow-list
+    /// does not name.
+    fn child_tools(&self, parent: &AgentSpec, spec: &AgentSpec) -> Vec<Arc<dyn Tool>> {
+        let allow = spec.tool_rules.allow_list().unwrap_or_default();
+        resolve_tools(parent, &self.tools)
+            .into_iter()
+            .filter(|tool| {
+                !DELEGATION_TOOLS.contains(&tool.name())
+                    || allow.iter().any(|name| name == tool.name())
+            })
+            .collect()
     }
 
     async fn spawn_agent(
         &self,
+        session_id: Uuid,
         spec: Arc<AgentSpec>,
         objective: String,
-        parent_model: ModelHandle,
+        model: ModelHandle,
         parent_tools: Vec<Arc<dyn Tool>>,
+        extras: PromptExtras,
     ) -> Result<SpawnArtifacts> {
-        let session = Session::new();
-        let session_id = session.id();
-        let model = resolve_model(&spec, &parent_model);
         let tools = resolve_tools(&spec, &parent_tools);
         let tool_names = tools.iter().map(|tool| tool.name().to_string()).collect();
-        let builder = PromptBuilder::new(&spec, &self.context, &objective);
+        let mut builder = PromptBuilder::new(&spec, &self.context, &objective);
+        if let Some(history) = &extras.history {
+            builder = builder.with_history(history);
+        }
+        if let Some(results) = &extras.subagent_results {
+            builder = builder.with_subagent_results(results);
+        }
         let prompt = builder.build();
         let budgets = spec.budgets.clone();
         let timeout = budgets.wall_clock_or(Duration::from_secs(60));
+        // The budget caps what the session generates; prompts are resent
+        // every turn and would use it up on history alone.
+        let remaining = budgets
+            .max_tokens
+            .map(|max| max.saturating_sub(self.usage.get(session_id).output_tokens));
+        let input_tokens = tokens::estimate_for(&model, &prompt);
 
         debug!(
             agent = %spec.name,
@@ -225,48 +907,255 @@ impl SessionRuntime {
             "spawning agent"
         );
 
-        let _ = self
-            .event_tx
-            .send(AgentEvent::Started {
-                session_id,
-                agent: spec.name.clone(),
-                objective: objective.clone(),
-            })
-            .await;
+        let snapshot = self.track_snapshot().await;
+
+        self.emit(AgentEvent::Started {
+            session_id,
+            agent: spec.name.clone(),
+            objective: objective.clone(),
+        })
+        .await;
 
         let request = CompletionRequest {
+            session_id,
             agent: spec.name.clone(),
             model: model.clone(),
             prompt,
             objective: objective.clone(),
             budgets: budgets.clone(),
             tool_names,
+            partial: PartialOutput::new(),
         };
+        let partial = request.partial.clone();
 
-        let response = time::timeout(timeout, self.model.complete(request)).await??;
+        let completion = self.complete_within_budget(request, remaining);
+        let (response, timed_out, budget_exhausted) = match time::timeout(timeout, completion).await
+        {
+            Ok(Ok((response, budget_exhausted))) => (response, false, budget_exhausted),
+            Ok(Err(err)) => {
+                self.emit(AgentEvent::Failed {
+                    session_id,
+                    agent: spec.name.clone(),
+                    error: format!("{err:#}"),
+                })
+                .await;
+                return Err(err);
+            }
+            Err(_) => {
+                warn!(agent = %spec.name, ?timeout, "agent ran out of wall clock time");
+                let output = partial.get();
+                let summary = if output.trim().is_empty() {
+                    format!(
+                        "{} produced no output before its {timeout:?} wall clock budget ran out.",
+                        spec.name
+                    )
+                } else {
+                    output.clone()
+                };
+                let response = CompletionResponse {
+                    summary,
+                    raw_output: output,
+                    usage: None,
+                };
+                (response, true, false)
+            }
+        };
+        let usage = response.usage.unwrap_or_else(|| {
+            TokenUsage::new(
+                input_tokens,
+                tokens::estimate_for(&model, &response.summary),
+            )
+        });
+        let total = self.usage.record(session_id, usage);
+        if budget_exhausted {
+            warn!(
+                agent = %spec.name,
+                max_tokens = budgets.max_tokens,
+                output_tokens = total.output_tokens,
+                "agent ran out of token budget"
+            );
+        }
+        let patch = self.snapshot_patch(snapshot.as_deref()).await;
 
         let outcome = SubagentOutcome {
+            id: None,
             agent: spec.name.clone(),
             objective,
             session_id,
             summary: response.summary.clone(),
             model: model.clone(),
             raw_output: response.raw_output,
+            timed_out,
+            budget_exhausted,
+            usage,
+            snapshot,
+            patch,
         };
 
-        let _ = self
-            .event_tx
-            .send(AgentEvent::Completed {
-                session_id,
-                agent: spec.name.clone(),
-                summary: outcome.summary.clone(),
-            })
-            .await;
+        self.emit(AgentEvent::Completed {
+            session_id,
+            agent: spec.name.clone(),
+            summary: outcome.summary.clone(),
+            timed_out,
+            budget_exhausted,
+            usage,
+        })
+        .await;
 
         info!(agent = %spec.name, session_id = %session_id, "agent completed");
 
         Ok(SpawnArtifacts { outcome, tools })
     }
+
+    /// Runs the completion, stopping the model once its output reaches
+    /// `remaining` tokens. A response over the budget is cut off at it. The
+    /// flag tells whether the budget cut the output short.
+    async fn complete_within_budget(
+        &self,
+        request: CompletionRequest,
+        remaining: Option<u32>,
+    ) -> Result<(CompletionResponse, bool)> {
+        let Some(remaining) = remaining else {
+            let response = self.model.complete(request).await?;
+            return Ok((response, false));
+        };
+        let model = request.model.clone();
+        if remaining == 0 {
+            let response = CompletionResponse {
+                summary: format!(
+                    "{} has no token budget left (max {} tokens).",
+                    request.agent,
+                    request.budgets.max_tokens.unwrap_or_default()
+                ),
+                raw_output: String::new(),
+                usage: Some(TokenUsage::default()),
+            };
+            return Ok((response, true));
+        }
+        let partial = request.partial.clone();
+        let exhausted = async {
+            let mut estimator = TokenEstimator::new(&model);
+            let mut seen = 0;
+            loop {
+                match partial.since(seen) {
+                    Some(added) => {
+                        seen += added.len();
+                        estimator.push(&added);
+                    }
+                    None => {
+                        estimator = TokenEstimator::new(&model);
+                        seen = 0;
+                        continue;
+                    }
+                }
+                if estimator.total() >= remaining {
+                    break;
+                }
+                partial.changed().await;
+            }
+        };
+
+        tokio::select! {
+            biased;
+            response = self.model.complete(request) => {
+                let mut response = response?;
+                let output = response.usage.map_or_else(
+                    || tokens::estimate_for(&model, &response.summary),
+                    |usage| usage.output_tokens,
+                );
+                if output <= remaining {
+                    return Ok((response, false));
+                }
+                response.summary =
+                    tokens::truncate(&model, &response.summary, remaining).to_string();
+                Ok((response, true))
+            }
+            () = exhausted => {
+                let output = partial.get();
+                let response = CompletionResponse {
+                    summary: tokens::truncate(&model, &output, remaining).to_string(),
+                    raw_output: output,
+                    usage: None,
+                };
+                Ok((response, true))
+            }
+        }
+    }
+
+    /// A failed snapshot is logged but never stops the agent.
+    async fn track_snapshot(&self) -> Option<String> {
+        let snapshot = self.snapshot.as_ref()?;
+        match snapshot.track().await {
+            Ok(hash) => hash,
+            Err(err) => {
+                warn!("failed to track snapshot: {err:#}");
+                None
+            }
+        }
+    }
+
+    async fn snapshot_patch(&self, hash: Option<&str>) -> Option<Patch> {
+        let (snapshot, hash) = (self.snapshot.as_ref()?, hash?);
+        match snapshot.patch(hash).await {
+            Ok(patch) => Some(patch),
+            Err(err) => {
+                warn!("failed to compute snapshot patch: {err:#}");
+                None
+            }
+        }
+    }
+}
+
+/// Renders subtask outcomes and failures for the parent's synthesis turn,
+/// after the instructions for that turn.
+fn subagent_results(outcomes: &[SubagentOutcome], failures: &[SubtaskFailure]) -> String {
+    let mut out = SessionPrompts::synthesize().trim().to_string();
+    for outcome in outcomes {
+        let name = outcome.id.as_deref().unwrap_or(&outcome.agent);
+        let status = if outcome.timed_out {
+            "timed out (partial output)"
+        } else if outcome.budget_exhausted {
+            "out of token budget (partial output)"
+        } else {
+            "completed"
+        };
+        out.push_str(&format!(
+            "\n\n## {name} ({}, {status})\nObjective: {}\n\n{}",
+            outcome.agent,
+            outcome.objective.trim(),
+            outcome.summary.trim()
+        ));
+    }
+    for failure in failures {
+        let name = failure.id.as_deref().unwrap_or(&failure.agent);
+        out.push_str(&format!(
+            "\n\n## {name} ({}, {})\nObjective: {}\n\n{}",
+            failure.agent,
+            failure.kind.as_str(),
+            failure.objective.trim(),
+            failure.error.trim()
+        ));
+    }
+    out
+}
+
+fn failure(
+    invocations: &[SubagentInvocation],
+    session_ids: &[Uuid],
+    index: usize,
+    kind: SubtaskFailureKind,
+    error: String,
+) -> (usize, SubtaskFailure) {
+    let invocation = &invocations[index];
+    let failure = SubtaskFailure {
+        id: invocation.id.clone(),
+        agent: invocation.agent.clone(),
+        objective: invocation.objective.clone(),
+        session_id: session_ids[index],
+        kind,
+        error,
+    };
+    (index, failure)
 }
 
 impl Clone for SessionRuntime {
@@ -278,6 +1167,10 @@ impl Clone for SessionRuntime {
             tools: self.tools.clone(),
             event_tx: self.event_tx.clone(),
             default_model: self.default_model.clone(),
+            hooks: self.hooks.clone(),
+            snapshot: self.snapshot.clone(),
+            compactor: self.compactor.clone(),
+            usage: self.usage.clone(),
         }
     }
 }
@@ -0,0 +1,367 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::session::compaction::Compaction;
+use crate::session::runtime::{SessionResult, SubagentOutcome};
+use crate::session::todo::Todo;
+use crate::session::tokens::TokenUsage;
+use crate::snapshot::{self, Patch};
+use crate::util::paths;
+
+const TITLE_LENGTH: usize = 50;
+
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error("session {0} not found")]
+    SessionNotFound(Uuid),
+    #[error("message {message} not found in session {session}")]
+    MessageNotFound { session: Uuid, message: Uuid },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Assistant,
+    /// Output of a tool run in the session; `agent` holds the tool name.
+    Tool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageRecord {
+    pub id: Uuid,
+    pub role: Role,
+    pub agent: String,
+    pub content: String,
+    /// Worktree snapshot recorded before the step that produced the message.
+    #[serde(default)]
+    pub snapshot: Option<String>,
+    /// Files the step changed, relative to `snapshot`.
+    #[serde(default)]
+    pub patch: Option<Patch>,
+    /// Summarizes the messages before it, which are left out of the model's
+    /// context from then on.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub summary: bool,
+    /// A tool output dropped from the model's context by compaction. The
+    /// content is kept for the record.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pruned: bool,
+    pub created_at: u64,
+}
+
+impl MessageRecord {
+    pub fn user(agent: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            role: Role::User,
+            agent: agent.into(),
+            content: content.into(),
+            snapshot: None,
+            patch: None,
+            summary: false,
+            pruned: false,
+            created_at: now_millis(),
+        }
+    }
+
+    pub fn tool(tool: impl Into<String>, output: impl Into<String>) -> Self {
+        Self {
+            role: Role::Tool,
+            ..Self::user(tool, output)
+        }
+    }
+
+    /// A compaction summary written by `agent`.
+    pub fn summary(agent: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            role: Role::Assistant,
+            summary: true,
+            ..Self::user(agent, content)
+        }
+    }
+
+    pub fn assistant(outcome: &SubagentOutcome) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            role: Role::Assistant,
+            agent: outcome.agent.clone(),
+            content: outcome.summary.clone(),
+            snapshot: outcome.snapshot.clone(),
+            patch: outcome.patch.clone(),
+            summary: false,
+            pruned: false,
+            created_at: now_millis(),
+        }
+    }
+}
+
+/// Pending revert of a session: messages from `message_id` on are hidden and
+/// their file changes rolled back until the session is unreverted or a new
+/// message is added.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevertState {
+    pub message_id: Uuid,
+    /// Worktree state before the revert, restored by `unrevert`.
+    #[serde(default)]
+    pub snapshot: Option<String>,
+    /// Unified diff of what the revert undid, from `snapshot` to the
+    /// reverted worktree.
+    #[serde(default)]
+    pub diff: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredSession {
+    pub id: Uuid,
+    pub title: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+    #[serde(default)]
+    pub revert: Option<RevertState>,
+    #[serde(default)]
+    pub messages: Vec<MessageRecord>,
+    /// The todo list the agent last wrote for this session.
+    #[serde(default)]
+    pub todos: Vec<Todo>,
+    /// Tokens consumed by every turn of the session.
+    #[serde(default)]
+    pub usage: TokenUsage,
+    /// Modification time of each file as the session last read or wrote it,
+    /// so edits in later runs can tell when a file changed in between.
+    #[serde(default)]
+    pub file_times: BTreeMap<PathBuf, SystemTime>,
+}
+
+impl StoredSession {
+    pub fn new(id: Uuid, title: impl Into<String>) -> Self {
+        let now = now_millis();
+        Self {
+            id,
+            title: title.into(),
+            created_at: now,
+            updated_at: now,
+            revert: None,
+            messages: Vec::new(),
+            todos: Vec::new(),
+            usage: TokenUsage::default(),
+            file_times: BTreeMap::new(),
+        }
+    }
+
+    pub fn message_index(&self, message_id: Uuid) -> Result<usize, StoreError> {
+        self.messages
+            .iter()
+            .position(|message| message.id == message_id)
+            .ok_or(StoreError::MessageNotFound {
+                session: self.id,
+                message: message_id,
+            })
+    }
+
+    /// Messages that are part of the conversation, excluding reverted ones.
+    pub fn visible_messages(&self) -> &[MessageRecord] {
+        let end = self
+            .revert
+            .as_ref()
+            .and_then(|revert| self.message_index(revert.message_id).ok())
+            .unwrap_or(self.messages.len());
+        &self.messages[..end]
+    }
+
+    /// The visible messages the model still sees: everything from the latest
+    /// compaction summary on.
+    pub fn context_messages(&self) -> &[MessageRecord] {
+        let visible = self.visible_messages();
+        let start = visible
+            .iter()
+            .rposition(|message| message.summary)
+            .unwrap_or(0);
+        &visible[start..]
+    }
+
+    /// Records a compaction: the summary goes in front of the first message
+    /// it kept, and pruned tool outputs are flagged. A pending revert is
+    /// committed first.
+    pub fn apply_compaction(&mut self, compaction: &Compaction) {
+        self.cleanup_revert();
+        let index = compaction
+            .first_kept
+            .and_then(|id| self.message_index(id).ok())
+            .unwrap_or(self.messages.len());
+        self.messages.insert(index, compaction.summary.clone());
+        for message in &mut self.messages {
+            if compaction.pruned.contains(&message.id) {
+                message.pruned = true;
+            }
+        }
+        self.updated_at = now_millis();
+    }
+
+    /// Drops the messages hidden by a pending revert, making it permanent.
+    pub fn cleanup_revert(&mut self) {
+        if let Some(revert) = self.revert.take()
+            && let Ok(index) = self.message_index(revert.message_id)
+        {
+            self.messages.truncate(index);
+        }
+    }
+
+    /// Appends the output of a tool run directly in the session, with the
+    /// snapshot taken before it and the files it changed so it can be
+    /// reverted. A pending revert is committed first.
+    pub fn record_tool(
+        &mut self,
+        tool: &str,
+        output: &str,
+        snapshot: Option<String>,
+        patch: Option<Patch>,
+    ) {
+        self.cleanup_revert();
+        self.messages.push(MessageRecord {
+            snapshot,
+            patch,
+            ..MessageRecord::tool(tool, output)
+        });
+        self.updated_at = now_millis();
+    }
+
+    /// Records a finished turn on `session`, or on a new session titled after
+    /// `prompt`, applying the turn's compaction first.
+    pub fn with_turn(session: Option<Self>, prompt: &str, result: &SessionResult) -> Self {
+        let mut session =
+            session.unwrap_or_else(|| Self::new(result.primary.session_id, title_for(prompt)));
+        if let Some(compaction) = &result.compaction {
+            session.apply_compaction(compaction);
+        }
+        session.record_turn(prompt, result);
+        session
+    }
+
+    /// Appends a user prompt and the agent outcomes that answered it. A
+    /// pending revert is committed first.
+    pub fn record_turn(&mut self, prompt: &str, result: &SessionResult) {
+        self.cleanup_revert();
+        let mut user = MessageRecord::user(&result.primary.agent, prompt);
+        user.snapshot = result.primary.snapshot.clone();
+        self.messages.push(user);
+        self.messages
+            .push(MessageRecord::assistant(&result.primary));
+        self.messages
+            .extend(result.subtasks.iter().map(MessageRecord::assistant));
+        if let Some(synthesis) = &result.synthesis {
+            self.messages.push(MessageRecord::assistant(synthesis));
+        }
+        self.usage += result.usage();
+        self.updated_at = now_millis();
+    }
+}
+
+/// Persists sessions as one JSON document per session.
+pub struct SessionStore {
+    root: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Stores sessions under `<data dir>/storage/session/<project id>`.
+    pub fn for_project(worktree: &Path) -> Self {
+        Self::new(
+            paths::data_dir()
+                .join("storage")
+                .join("session")
+                .join(snapshot::project_id(worktree)),
+        )
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<StoredSession> {
+        let path = self.path(id);
+        let data = match tokio::fs::read_to_string(&path).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Err(StoreError::SessionNotFound(id).into());
+            }
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to read {}", path.display()));
+            }
+        };
+        serde_json::from_str(&data).with_context(|| format!("invalid session {}", path.display()))
+    }
+
+    /// Writes the session through a temporary file so readers never observe
+    /// a partial document.
+    pub async fn save(&self, session: &StoredSession) -> Result<()> {
+        tokio::fs::create_dir_all(&self.root)
+            .await
+            .with_context(|| format!("failed to create {}", self.root.display()))?;
+        let path = self.path(session.id);
+        let temp = path.with_extension("json.tmp");
+        tokio::fs::write(&temp, serde_json::to_vec_pretty(session)?)
+            .await
+            .with_context(|| format!("failed to write {}", temp.display()))?;
+        tokio::fs::rename(&temp, &path)
+            .await
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    /// All sessions, most recently updated first.
+    pub async fn list(&self) -> Result<Vec<StoredSession>> {
+        let mut sessions = Vec::new();
+        let mut entries = match tokio::fs::read_dir(&self.root).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(sessions),
+            Err(err) => return Err(err.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let Some(id) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".json"))
+                .and_then(|id| Uuid::parse_str(id).ok())
+            else {
+                continue;
+            };
+            sessions.push(self.get(id).await?);
+        }
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.updated_at));
+        Ok(sessions)
+    }
+
+    pub async fn latest(&self) -> Result<Option<StoredSession>> {
+        Ok(self.list().await?.into_iter().next())
+    }
+
+    fn path(&self, id: Uuid) -> PathBuf {
+        self.root.join(format!("{id}.json"))
+    }
+}
+
+pub fn title_for(prompt: &str) -> String {
+    let line = prompt.lines().next().unwrap_or_default().trim();
+    match line.char_indices().nth(TITLE_LENGTH) {
+        Some((idx, _)) => format!("{}...", &line[..idx]),
+        None => line.to_string(),
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,179 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::LazyLock;
+
+use anyhow::{Result, bail};
+use regex::{Captures, Regex};
+
+use crate::session::runtime::{SubagentInvocation, SubagentOutcome};
+
+/// `{{id.field}}` placeholders in subtask objectives.
+static REFERENCE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\{\{\s*([A-Za-z0-9_-]+)\.([A-Za-z_]+)\s*\}\}").expect("valid reference pattern")
+});
+
+/// Outcome fields an objective can reference.
+const FIELDS: &[&str] = &["summary", "raw_output", "agent", "objective"];
+
+/// Dependencies between the subtasks of a request. An invocation depends on
+/// the IDs in its `depends_on` and on every ID its objective references.
+#[derive(Debug, Clone)]
+pub struct SubtaskGraph {
+    dependencies: Vec<Vec<usize>>,
+    dependents: Vec<Vec<usize>>,
+    order: Vec<usize>,
+}
+
+impl SubtaskGraph {
+    /// Validates IDs and references and orders the subtasks so each comes
+    /// after its dependencies, keeping declaration order among independent
+    /// ones.
+    pub fn build(invocations: &[SubagentInvocation]) -> Result<Self> {
+        let mut ids = HashMap::new();
+        for (index, invocation) in invocations.iter().enumerate() {
+            if let Some(id) = &invocation.id
+                && ids.insert(id.as_str(), index).is_some()
+            {
+                bail!("duplicate subtask id '{id}'");
+            }
+        }
+
+        let mut dependencies = Vec::with_capacity(invocations.len());
+        let mut dependents = vec![Vec::new(); invocations.len()];
+        for (index, invocation) in invocations.iter().enumerate() {
+            let name = label(invocation, index);
+            let mut needs = BTreeSet::new();
+            for id in &invocation.depends_on {
+                let Some(&dependency) = ids.get(id.as_str()) else {
+                    bail!("subtask {name} depends on unknown subtask '{id}'");
+                };
+                needs.insert(dependency);
+            }
+            for (id, field) in references(&invocation.objective) {
+                let Some(&dependency) = ids.get(id.as_str()) else {
+                    bail!("subtask {name} references unknown subtask '{id}'");
+                };
+                if !FIELDS.contains(&field.as_str()) {
+                    bail!(
+                        "subtask {name} references unknown field '{id}.{field}'; expected one of {}",
+                        FIELDS.join(", ")
+                    );
+                }
+                needs.insert(dependency);
+            }
+            if needs.contains(&index) {
+                bail!("subtask {name} depends on itself");
+            }
+            for &dependency in &needs {
+                dependents[dependency].push(index);
+            }
+            dependencies.push(needs.into_iter().collect::<Vec<_>>());
+        }
+
+        let mut waiting: Vec<usize> = dependencies.iter().map(Vec::len).collect();
+        let mut ready: BTreeSet<usize> = (0..invocations.len())
+            .filter(|&index| waiting[index] == 0)
+            .collect();
+        let mut order = Vec::with_capacity(invocations.len());
+        while let Some(index) = ready.pop_first() {
+            order.push(index);
+            for &dependent in &dependents[index] {
+                waiting[dependent] -= 1;
+                if waiting[dependent] == 0 {
+                    ready.insert(dependent);
+                }
+            }
+        }
+        if order.len() < invocations.len() {
+            let cycle = find_cycle(&dependencies, &waiting)
+                .into_iter()
+                .map(|index| label(&invocations[index], index))
+                .collect::<Vec<_>>();
+            bail!("subtask dependency cycle: {}", cycle.join(" -> "));
+        }
+
+        Ok(Self {
+            dependencies,
+            dependents,
+            order,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Subtask indices in topological order.
+    pub fn order(&self) -> &[usize] {
+        &self.order
+    }
+
+    pub fn dependencies(&self, index: usize) -> &[usize] {
+        &self.dependencies[index]
+    }
+
+    pub fn dependents(&self, index: usize) -> &[usize] {
+        &self.dependents[index]
+    }
+}
+
+/// Replaces `{{id.field}}` placeholders with fields of finished outcomes,
+/// keyed by subtask ID.
+pub fn render_objective(objective: &str, outcomes: &HashMap<String, SubagentOutcome>) -> String {
+    REFERENCE
+        .replace_all(objective, |captures: &Captures| {
+            let Some(outcome) = outcomes.get(&captures[1]) else {
+                return captures[0].to_string();
+            };
+            match &captures[2] {
+                "summary" => outcome.summary.clone(),
+                "raw_output" => outcome.raw_output.clone(),
+                "agent" => outcome.agent.clone(),
+                "objective" => outcome.objective.clone(),
+                _ => captures[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+fn references(objective: &str) -> Vec<(String, String)> {
+    REFERENCE
+        .captures_iter(objective)
+        .map(|captures| (captures[1].to_string(), captures[2].to_string()))
+        .collect()
+}
+
+pub(crate) fn label(invocation: &SubagentInvocation, index: usize) -> String {
+    match &invocation.id {
+        Some(id) => format!("'{id}'"),
+        None => format!("#{} ({})", index + 1, invocation.agent),
+    }
+}
+
+/// Follows unresolved dependencies from a node left over by the topological
+/// sort until one repeats, returning the loop with its start repeated.
+fn find_cycle(dependencies: &[Vec<usize>], waiting: &[usize]) -> Vec<usize> {
+    let Some(start) = (0..waiting.len()).find(|&index| waiting[index] > 0) else {
+        return Vec::new();
+    };
+    let mut path = vec![start];
+    let mut current = start;
+    loop {
+        let Some(&next) = dependencies[current]
+            .iter()
+            .find(|&&dependency| waiting[dependency] > 0)
+        else {
+            return path;
+        };
+        if let Some(position) = path.iter().position(|&index| index == next) {
+            let mut cycle = path.split_off(position);
+            cycle.push(next);
+            return cycle;
+        }
+        path.push(next);
+        current = next;
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoStatus {
+    #[default]
+    Pending,
+    InProgress,
+    Completed,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TodoPriority {
+    High,
+    #[default]
+    Medium,
+    Low,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Todo {
+    pub id: String,
+    pub content: String,
+    #[serde(default)]
+    pub status: TodoStatus,
+    #[serde(default)]
+    pub priority: TodoPriority,
+}
+
+impl Todo {
+    pub fn new(id: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            content: content.into(),
+            status: TodoStatus::default(),
+            priority: TodoPriority::default(),
+        }
+    }
+
+    pub fn with_status(mut self, status: TodoStatus) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: TodoPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+/// The current todo list of each session. Writes replace the whole list.
+#[derive(Debug, Default)]
+pub struct TodoLists {
+    lists: Mutex<HashMap<Uuid, Vec<Todo>>>,
+}
+
+impl TodoLists {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, session_id: Uuid) -> Vec<Todo> {
+        self.lock().get(&session_id).cloned().unwrap_or_default()
+    }
+
+    pub fn set(&self, session_id: Uuid, todos: Vec<Todo>) {
+        self.lock().insert(session_id, todos);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, Vec<Todo>>> {
+        self.lists.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Renders the list as a checklist headed by the number of open items.
+pub fn render(todos: &[Todo]) -> String {
+    if todos.is_empty() {
+        return "No todos.".to_string();
+    }
+    let open = todos
+        .iter()
+        .filter(|todo| todo.status != TodoStatus::Completed)
+        .count();
+    let mut out = format!("{open} of {} todos remaining", todos.len());
+    for todo in todos {
+        let mark = match todo.status {
+            TodoStatus::Pending => ' ',
+            TodoStatus::InProgress => '>',
+            TodoStatus::Completed => 'x',
+        };
+        let priority = match todo.priority {
+            TodoPriority::High => "high",
+            TodoPriority::Medium => "medium",
+            TodoPriority::Low => "low",
+        };
+        out.push_str(&format!(
+            "\n[{mark}] {} ({priority}) {}",
+            todo.id, todo.content
+        ));
+    }
+    out
+}
@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::{Add, AddAssign};
+use std::sync::{LazyLock, Mutex};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tiktoken_rs::tokenizer::{Tokenizer, get_tokenizer};
+use tiktoken_rs::{CoreBPE, cl100k_base_singleton, o200k_base_singleton};
+use uuid::Uuid;
+
+use crate::agent::spec::ModelHandle;
+
+/// Splits text the way byte-pair tokenizers do before merging: contractions,
+/// words with their leading space, short digit runs, punctuation runs and
+/// whitespace.
+static PIECES: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"'(?:s|t|re|ve|m|ll|d)| ?\p{L}+| ?\p{N}{1,3}| ?[^\s\p{L}\p{N}]+|\s+")
+        .expect("valid pre-tokenizer pattern")
+});
+
+/// Model families token counts tell apart. GPT models are counted with
+/// their real byte-pair vocabulary; the other families use a heuristic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelFamily {
+    Gpt,
+    Claude,
+    Gemini,
+    Llama,
+    Other,
+}
+
+impl ModelFamily {
+    pub fn of(model: &ModelHandle) -> Self {
+        let name = model_name(model);
+        if name.starts_with("gpt")
+            || name.starts_with('o') && name[1..].starts_with(char::is_numeric)
+        {
+            Self::Gpt
+        } else if name.starts_with("claude") {
+            Self::Claude
+        } else if name.starts_with("gemini") || name.starts_with("gemma") {
+            Self::Gemini
+        } else if name.starts_with("llama")
+            || name.starts_with("mistral")
+            || name.starts_with("qwen")
+        {
+            Self::Llama
+        } else {
+            Self::Other
+        }
+    }
+
+    /// Characters the heuristic charges one token for within a piece. These
+    /// are rough guesses, a little wider for the families with larger
+    /// vocabularies, not figures measured against the real tokenizers;
+    /// `None` falls back to `estimate`.
+    fn chars_per_token(self) -> Option<usize> {
+        match self {
+            Self::Gpt => Some(6),
+            Self::Gemini => Some(6),
+            Self::Claude => Some(5),
+            Self::Llama => Some(5),
+            Self::Other => None,
+        }
+    }
+}
+
+/// The model id without its provider, lowercased.
+fn model_name(model: &ModelHandle) -> String {
+    model
+        .id()
+        .rsplit_once('/')
+        .map_or(model.id(), |(_, name)| name)
+        .to_ascii_lowercase()
+}
+
+/// How the tokens of a model are counted.
+#[derive(Clone, Copy)]
+enum Counter {
+    /// The model's own byte-pair vocabulary.
+    Bpe(&'static CoreBPE),
+    /// One token per this many characters of each pre-tokenizer piece.
+    Pieces(usize),
+    /// About four characters per token, as `estimate` counts.
+    Chars,
+}
+
+impl Counter {
+    fn of(model: &ModelHandle) -> Self {
+        let family = ModelFamily::of(model);
+        if family == ModelFamily::Gpt {
+            // Models tiktoken does not know yet are newer than the ones it
+            // maps to cl100k, so they get the newer vocabulary.
+            return Self::Bpe(match get_tokenizer(&model_name(model)) {
+                Some(Tokenizer::Cl100kBase) => cl100k_base_singleton(),
+                _ => o200k_base_singleton(),
+            });
+        }
+        family.chars_per_token().map_or(Self::Chars, Self::Pieces)
+    }
+
+    fn count(self, text: &str) -> usize {
+        match self {
+            Self::Bpe(bpe) => bpe.encode_ordinary(text).len(),
+            Self::Pieces(width) => PIECES
+                .find_iter(text)
+                .map(|piece| piece.as_str().chars().count().div_ceil(width))
+                .sum(),
+            Self::Chars => text.chars().count().div_ceil(4),
+        }
+    }
+}
+
+impl fmt::Debug for Counter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bpe(_) => f.write_str("Bpe"),
+            Self::Pieces(width) => f.debug_tuple("Pieces").field(width).finish(),
+            Self::Chars => f.write_str("Chars"),
+        }
+    }
+}
+
+/// A rough token count for any model: about four characters per token.
+pub fn estimate(text: &str) -> u32 {
+    saturate(Counter::Chars.count(text))
+}
+
+/// Counts the tokens `text` takes for `model`: exactly for GPT models, with
+/// the per-family heuristic of `TokenEstimator` otherwise.
+pub fn estimate_for(model: &ModelHandle, text: &str) -> u32 {
+    saturate(Counter::of(model).count(text))
+}
+
+/// The longest prefix of `text` whose `estimate_for` is at most `max`.
+pub fn truncate<'a>(model: &ModelHandle, text: &'a str, max: u32) -> &'a str {
+    let max = max as usize;
+    match Counter::of(model) {
+        Counter::Bpe(bpe) => {
+            let tokens = bpe.encode_ordinary(text);
+            if tokens.len() <= max {
+                return text;
+            }
+            // Ordinary tokens decode back to the text they came from, but a
+            // character can span tokens, so the cut moves back until it
+            // lands on a character boundary.
+            let end = (0..=max)
+                .rev()
+                .find_map(|keep| bpe.decode(tokens[..keep].to_vec()).ok())
+                .map_or(0, |prefix| prefix.len());
+            &text[..end]
+        }
+        Counter::Pieces(width) => {
+            let mut used = 0;
+            for piece in PIECES.find_iter(text) {
+                let cost = piece.as_str().chars().count().div_ceil(width);
+                if used + cost <= max {
+                    used += cost;
+                    continue;
+                }
+                // Part of a piece may split differently from the whole, so
+                // the prefix that still fits is counted on its own.
+                let fits =
+                    |end: usize| Counter::Pieces(width).count(&piece.as_str()[..end]) <= max - used;
+                let end = piece
+                    .as_str()
+                    .char_indices()
+                    .map(|(index, _)| index)
+                    .skip(1)
+                    .take_while(|&end| fits(end))
+                    .last()
+                    .unwrap_or(0);
+                return &text[..piece.start() + end];
+            }
+            text
+        }
+        Counter::Chars => {
+            let end = text
+                .char_indices()
+                .nth(max.saturating_mul(4))
+                .map_or(text.len(), |(index, _)| index);
+            &text[..end]
+        }
+    }
+}
+
+/// Counts the tokens of text that arrives in parts, such as streamed model
+/// output, looking only at the newly pushed text each time.
+///
+/// Text is counted the way `estimate_for` counts it for the model. GPT text
+/// is held back from its last word break on, and heuristic text from its
+/// last two pre-tokenizer pieces, because more text can still extend or
+/// merge them; everything before is counted once and settled.
+#[derive(Debug, Clone)]
+pub struct TokenEstimator {
+    counter: Counter,
+    /// Tokens of the settled pieces, or characters seen when falling back
+    /// to `estimate`.
+    counted: usize,
+    pending: String,
+}
+
+impl TokenEstimator {
+    pub fn new(model: &ModelHandle) -> Self {
+        Self {
+            counter: Counter::of(model),
+            counted: 0,
+            pending: String::new(),
+        }
+    }
+
+    pub fn push(&mut self, text: &str) {
+        if let Counter::Chars = self.counter {
+            self.counted += text.chars().count();
+            return;
+        }
+        self.pending.push_str(text);
+        let keep = match self.counter {
+            Counter::Bpe(_) => word_break(&self.pending),
+            _ => {
+                let starts: Vec<usize> = PIECES
+                    .find_iter(&self.pending)
+                    .map(|piece| piece.start())
+                    .collect();
+                starts
+                    .len()
+                    .checked_sub(2)
+                    .and_then(|index| starts.get(index))
+                    .copied()
+            }
+        };
+        if let Some(keep) = keep
+            && keep > 0
+        {
+            self.counted += self.counter.count(&self.pending[..keep]);
+            self.pending.drain(..keep);
+        }
+    }
+
+    /// Tokens of everything pushed so far.
+    pub fn total(&self) -> u32 {
+        match self.counter {
+            Counter::Chars => saturate(self.counted.div_ceil(4)),
+            counter => saturate(self.counted + counter.count(&self.pending)),
+        }
+    }
+}
+
+/// The last space that follows a non-space character. The byte-pair
+/// pre-tokenizers always start a new piece there, whatever comes after it,
+/// so the text before it can be encoded on its own.
+fn word_break(text: &str) -> Option<usize> {
+    text.rmatch_indices(' ')
+        .map(|(index, _)| index)
+        .find(|&index| !text[..index].ends_with(char::is_whitespace))
+}
+
+fn saturate(tokens: usize) -> u32 {
+    u32::try_from(tokens).unwrap_or(u32::MAX)
+}
+
+/// Tokens a completion consumed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+}
+
+impl TokenUsage {
+    pub fn new(input_tokens: u32, output_tokens: u32) -> Self {
+        Self {
+            input_tokens,
+            output_tokens,
+        }
+    }
+
+    pub fn total(&self) -> u32 {
+        self.input_tokens.saturating_add(self.output_tokens)
+    }
+}
+
+impl Add for TokenUsage {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            input_tokens: self.input_tokens.saturating_add(other.input_tokens),
+            output_tokens: self.output_tokens.saturating_add(other.output_tokens),
+        }
+    }
+}
+
+impl AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+/// Cumulative token usage of each session.
+#[derive(Debug, Default)]
+pub struct UsageLedger {
+    sessions: Mutex<HashMap<Uuid, TokenUsage>>,
+}
+
+impl UsageLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, session_id: Uuid) -> TokenUsage {
+        self.lock().get(&session_id).copied().unwrap_or_default()
+    }
+
+    /// Adds `usage` to the session's total and returns the new total.
+    pub fn record(&self, session_id: Uuid, usage: TokenUsage) -> TokenUsage {
+        let mut sessions = self.lock();
+        let total = sessions.entry(session_id).or_default();
+        *total += usage;
+        *total
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, TokenUsage>> {
+        self.sessions.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_by_model_family() {
+        let gpt = ModelHandle::new("openai/gpt-4o");
+        assert_eq!(ModelFamily::of(&gpt), ModelFamily::Gpt);
+        assert_eq!(
+            ModelFamily::of(&ModelHandle::new("openai/o3-mini")),
+            ModelFamily::Gpt
+        );
+        assert_eq!(
+            ModelFamily::of(&ModelHandle::new("anthropic/claude-sonnet-4")),
+            ModelFamily::Claude
+        );
+        assert_eq!(
+            ModelFamily::of(&ModelHandle::new("local/olmo")),
+            ModelFamily::Other
+        );
+
+        assert_eq!(estimate_for(&gpt, "Hello, world!"), 4);
+        assert_eq!(estimate_for(&gpt, "internationalization"), 2);
+        assert_eq!(estimate_for(&gpt, "12345"), 2);
+        let claude = ModelHandle::new("anthropic/claude-sonnet-4");
+        assert_eq!(estimate_for(&claude, "internationalization"), 4);
+        let other = ModelHandle::new("local/model");
+        assert_eq!(
+            estimate_for(&other, "Hello, world!"),
+            estimate("Hello, world!")
+        );
+        assert_eq!(estimate(""), 0);
+    }
+
+    #[test]
+    fn truncates_to_a_token_budget() {
+        let model = ModelHandle::new("openai/gpt-4o");
+        let text = "one two three four five";
+        assert_eq!(truncate(&model, text, 3), "one two three");
+        assert_eq!(truncate(&model, text, 100), text);
+        assert_eq!(truncate(&model, text, 0), "");
+
+        let text = "They're international, aren't they? 1234567 -- done.";
+        for max in 0..=estimate_for(&model, text) {
+            let prefix = truncate(&model, text, max);
+            assert!(estimate_for(&model, prefix) <= max);
+            assert!(
+                prefix.len() == text.len() || estimate_for(&model, &text[..=prefix.len()]) > max
+            );
+        }
+    }
+
+    #[test]
+    fn estimates_pushed_parts_like_the_whole_text() {
+        let text = "We're streaming internationalization output: 12345 tokens, they'll see.";
+        for model in ["openai/gpt-4o", "anthropic/claude-sonnet-4", "local/model"] {
+            let model = ModelHandle::new(model);
+            for size in 1..8 {
+                let mut estimator = TokenEstimator::new(&model);
+                for part in text.as_bytes().chunks(size) {
+                    estimator.push(std::str::from_utf8(part).unwrap());
+                }
+                assert_eq!(
+                    estimator.total(),
+                    estimate_for(&model, text),
+                    "parts of {size}"
+                );
+            }
+        }
+    }
+}
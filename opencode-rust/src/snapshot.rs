@@ -0,0 +1,245 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+use crate::util::config::Info;
+use crate::util::paths;
+
+/// Files that differ between a snapshot and the current worktree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Patch {
+    pub hash: String,
+    pub files: Vec<PathBuf>,
+}
+
+/// Records worktree states as tree objects in a shadow git repository kept
+/// outside the project, so agent edits can be inspected and rolled back
+/// without touching the project's own history.
+pub struct Snapshot {
+    worktree: PathBuf,
+    git_dir: PathBuf,
+    enabled: bool,
+    // Every operation goes through the shadow index; concurrent agents must
+    // not race on its lock file.
+    index: Mutex<()>,
+}
+
+impl Snapshot {
+    pub fn new(worktree: impl Into<PathBuf>, git_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            worktree: worktree.into(),
+            git_dir: git_dir.into(),
+            enabled: true,
+            index: Mutex::new(()),
+        }
+    }
+
+    /// Stores snapshots under `<data dir>/snapshot/<project id>`. Setting
+    /// `snapshot: false` in config disables tracking.
+    pub fn from_info(worktree: impl Into<PathBuf>, info: &Info) -> Self {
+        let worktree = worktree.into();
+        let git_dir = paths::data_dir()
+            .join("snapshot")
+            .join(project_id(&worktree));
+        Self::new(worktree, git_dir).with_enabled(info.snapshot != Some(false))
+    }
+
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn worktree(&self) -> &Path {
+        &self.worktree
+    }
+
+    pub fn git_dir(&self) -> &Path {
+        &self.git_dir
+    }
+
+    /// Records the current worktree and returns the snapshot hash, or `None`
+    /// when snapshots are disabled.
+    pub async fn track(&self) -> Result<Option<String>> {
+        if !self.enabled {
+            return Ok(None);
+        }
+        let _index = self.index.lock().await;
+        self.init().await?;
+        self.stage().await?;
+        let output = self.git(&["write-tree"]).await?;
+        let hash = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        info!(hash, worktree = %self.worktree.display(), "tracked snapshot");
+        Ok(Some(hash))
+    }
+
+    /// Lists the files that changed since the snapshot `hash`.
+    pub async fn patch(&self, hash: &str) -> Result<Patch> {
+        let _index = self.index.lock().await;
+        self.init().await?;
+        self.stage().await?;
+        let files = self.changed_files(hash).await?;
+        Ok(Patch {
+            hash: hash.to_string(),
+            files: files.iter().map(|file| self.worktree.join(file)).collect(),
+        })
+    }
+
+    /// Renders a unified diff from the snapshot `hash` to the current worktree.
+    pub async fn diff(&self, hash: &str) -> Result<String> {
+        let _index = self.index.lock().await;
+        self.init().await?;
+        self.stage().await?;
+        let output = self
+            .git(&["diff", "--no-color", "--no-ext-diff", hash, "--", "."])
+            .await?;
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .trim_end()
+            .to_string())
+    }
+
+    /// Resets the whole worktree to the snapshot `hash`. Files created since
+    /// the snapshot are removed; ignored files are left alone.
+    pub async fn restore(&self, hash: &str) -> Result<()> {
+        let _index = self.index.lock().await;
+        self.init().await?;
+        self.stage().await?;
+        let changed = self.changed_files(hash).await?;
+        let tracked = self.tree_files(hash).await?;
+        self.git(&["read-tree", hash]).await?;
+        self.git(&["checkout-index", "-a", "-f"]).await?;
+        for file in changed.iter().filter(|file| !tracked.contains(*file)) {
+            remove_file(&self.worktree.join(file)).await?;
+        }
+        info!(hash, "restored snapshot");
+        Ok(())
+    }
+
+    /// Rolls back only the files named in `patches`. When several patches
+    /// touch the same file the first one wins, so passing patches oldest
+    /// first restores the earliest recorded content.
+    pub async fn revert(&self, patches: &[Patch]) -> Result<()> {
+        let _index = self.index.lock().await;
+        self.init().await?;
+        let mut seen = HashSet::new();
+        for patch in patches {
+            let tracked = self.tree_files(&patch.hash).await?;
+            for file in &patch.files {
+                if !seen.insert(file.clone()) {
+                    continue;
+                }
+                let relative = self.relative(file);
+                if tracked.contains(&relative) {
+                    debug!(file = %file.display(), hash = %patch.hash, "reverting file");
+                    self.git(&["checkout", &patch.hash, "--", &relative])
+                        .await?;
+                } else {
+                    debug!(file = %file.display(), "file did not exist in snapshot, deleting");
+                    remove_file(file).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn init(&self) -> Result<()> {
+        if self.git_dir.join("HEAD").exists() {
+            return Ok(());
+        }
+        tokio::fs::create_dir_all(&self.git_dir)
+            .await
+            .with_context(|| format!("failed to create {}", self.git_dir.display()))?;
+        self.git(&["init", "--quiet"]).await?;
+        info!(git_dir = %self.git_dir.display(), "initialized snapshot store");
+        Ok(())
+    }
+
+    async fn stage(&self) -> Result<()> {
+        self.git(&["add", "--all", "."]).await.map(|_| ())
+    }
+
+    async fn changed_files(&self, hash: &str) -> Result<Vec<String>> {
+        let output = self
+            .git(&["diff", "--name-only", "-z", "--no-renames", hash, "--", "."])
+            .await?;
+        Ok(split_nul(&output.stdout))
+    }
+
+    async fn tree_files(&self, hash: &str) -> Result<HashSet<String>> {
+        let output = self
+            .git(&["ls-tree", "-r", "-z", "--name-only", hash])
+            .await?;
+        Ok(split_nul(&output.stdout).into_iter().collect())
+    }
+
+    fn relative(&self, file: &Path) -> String {
+        let relative = file.strip_prefix(&self.worktree).unwrap_or(file);
+        relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    async fn git(&self, args: &[&str]) -> Result<Output> {
+        let output = Command::new("git")
+            .args(["-c", "core.autocrlf=false", "-c", "core.quotepath=false"])
+            .args(args)
+            .current_dir(&self.worktree)
+            .env("GIT_DIR", &self.git_dir)
+            .env("GIT_WORK_TREE", &self.worktree)
+            .env_remove("GIT_INDEX_FILE")
+            .output()
+            .await
+            .context("failed to run git")?;
+        if !output.status.success() {
+            bail!(
+                "git {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(output)
+    }
+}
+
+/// Stable identifier for a worktree path, used to keep one snapshot store
+/// per project.
+pub fn project_id(worktree: &Path) -> String {
+    let path = worktree
+        .canonicalize()
+        .unwrap_or_else(|_| worktree.to_path_buf());
+    // FNV-1a: small, dependency free and stable across builds.
+    let hash = path
+        .to_string_lossy()
+        .bytes()
+        .fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        });
+    format!("{hash:016x}")
+}
+
+fn split_nul(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .split(|byte| *byte == 0)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| String::from_utf8_lossy(entry).to_string())
+        .collect()
+}
+
+async fn remove_file(path: &Path) -> Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err).with_context(|| format!("failed to remove {}", path.display())),
+    }
+}
@@ -1,36 +1,238 @@
-use crate::tool::core::Tool;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::tool::core::{Tool, ToolContext};
+use crate::tool::pty::PtySessions;
 use crate::util::error::Result;
 use async_trait::async_trait;
-use tokio::process::Command;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::{Child, Command};
+use tokio::task::JoinHandle;
+use tokio::time;
+use uuid::Uuid;
 
-pub struct BashTool;
+/// Used when the agent has no `tool_timeout` budget.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
+/// Output beyond this many bytes is cut from the middle.
+pub const MAX_OUTPUT_BYTES: usize = 30_000;
+/// Background jobs that may run at once.
+pub const MAX_JOBS: usize = 16;
+const USAGE: &str = "Usage: bash [--background|--pty] <command...> | bash --poll|--kill <job_id> | bash --screen|--terminate <pty_id> | bash --send <pty_id> <keys...>";
+/// Variables used to hand the command to the shell and read its state back;
+/// never persisted into the session environment.
+const INTERNAL_PREFIX: &str = "__OPENCODE_";
+const FOREGROUND_SCRIPT: &str = r#"trap 'pwd > "$__OPENCODE_CWD_FILE"; env -0 > "$__OPENCODE_ENV_FILE"' EXIT
+eval "$__OPENCODE_COMMAND""#;
+const BACKGROUND_SCRIPT: &str = r#"eval "$__OPENCODE_COMMAND""#;
 
-#[async_trait]
-impl Tool for BashTool {
-    fn name(&self) -> &str {
-        "bash"
+/// Working directory and exported environment carried from one command to
+/// the next.
+#[derive(Debug, Clone)]
+struct ShellState {
+    cwd: PathBuf,
+    env: HashMap<String, String>,
+}
+
+impl ShellState {
+    fn inherit() -> Self {
+        Self {
+            cwd: std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+            env: std::env::vars().collect(),
+        }
     }
 
-    fn description(&self) -> &str {
-        "Executes a shell command and returns its output. The first argument is the command to execute, and the following are its arguments."
+    fn command(&self, script: &str, command: &str) -> Command {
+        let mut cmd = Command::new("bash");
+        cmd.args(["--noprofile", "--norc", "-c", script])
+            .env_clear()
+            .envs(&self.env)
+            .env("__OPENCODE_COMMAND", command)
+            .current_dir(&self.cwd)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        // Each command leads its own process group so a timeout can kill
+        // everything it started.
+        #[cfg(unix)]
+        cmd.process_group(0);
+        cmd
     }
 
-    async fn execute(&self, args: &[String]) -> Result<String> {
-        if args.is_empty() {
-            return Ok("Usage: bash <command> [args...]".to_string());
+    /// Reads back the state the shell dumped on exit.
+    fn update(&mut self, cwd_file: &Path, env_file: &Path) {
+        if let Ok(cwd) = std::fs::read_to_string(cwd_file) {
+            let cwd = cwd.trim_end_matches('\n');
+            if !cwd.is_empty() {
+                self.cwd = PathBuf::from(cwd);
+            }
+        }
+        if let Ok(env) = std::fs::read(env_file) {
+            self.env = String::from_utf8_lossy(&env)
+                .split('\0')
+                .filter_map(|entry| entry.split_once('='))
+                .filter(|(key, _)| {
+                    !key.starts_with(INTERNAL_PREFIX) && *key != "SHLVL" && *key != "_"
+                })
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
         }
+    }
+}
 
-        let command = &args[0];
-        let command_args = &args[1..];
+type Shell = Arc<tokio::sync::Mutex<ShellState>>;
 
-        let output = Command::new(command)
-            .args(command_args)
-            .output()
-            .await?;
+type Buffer = Arc<Mutex<Captured>>;
 
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
+/// Bytes read from a child's pipes. A capped buffer keeps only the most
+/// recent `limit` bytes and counts the ones it dropped; it can also keep the
+/// first `head_limit` bytes, dropping only what falls between the two.
+#[derive(Default)]
+struct Captured {
+    head: Vec<u8>,
+    head_limit: usize,
+    bytes: Vec<u8>,
+    limit: Option<usize>,
+    dropped: usize,
+}
 
+impl Captured {
+    fn capped(limit: usize) -> Self {
+        Self {
+            limit: Some(limit),
+            ..Self::default()
+        }
+    }
+
+    fn head_and_tail(limit: usize) -> Self {
+        Self {
+            head_limit: limit,
+            ..Self::capped(limit)
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8]) {
+        let split = chunk.len().min(self.head_limit - self.head.len());
+        self.head.extend_from_slice(&chunk[..split]);
+        self.bytes.extend_from_slice(&chunk[split..]);
+        if let Some(limit) = self.limit
+            && self.bytes.len() > limit
+        {
+            let excess = self.bytes.len() - limit;
+            self.bytes.drain(..excess);
+            self.dropped += excess;
+        }
+    }
+}
+
+struct Job {
+    /// Process group of the job, cleared when its shell is reaped so a
+    /// reused ID is never signalled.
+    group: Arc<Mutex<Option<u32>>>,
+    /// Output not yet returned by a poll, capped at `MAX_OUTPUT_BYTES`.
+    output: Buffer,
+    /// Set once the job exits and its output is read; the inner value is
+    /// `None` when it was killed by a signal.
+    status: Arc<Mutex<Option<Option<i32>>>>,
+}
+
+impl Job {
+    fn running(&self) -> bool {
+        self.group
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .is_some()
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        kill_group(*self.group.lock().unwrap_or_else(|err| err.into_inner()));
+    }
+}
+
+/// Runs commands through `bash`, keeping a working directory and exported
+/// environment per session and agent, so `cd` and `export` carry over
+/// between calls. Commands are killed with their whole process group when
+/// they exceed the agent's `tool_timeout`; long-running commands can be
+/// started in the background and polled by job ID, and interactive ones run
+/// on a pseudo-terminal driven with keystrokes. Background jobs and terminals
+/// still running when the tool is dropped are killed.
+#[derive(Default)]
+pub struct BashTool {
+    shells: Mutex<HashMap<(Uuid, String), Shell>>,
+    jobs: Mutex<HashMap<u64, Job>>,
+    next_job: AtomicU64,
+    pty: PtySessions,
+}
+
+impl BashTool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn shell(&self, ctx: &ToolContext) -> Shell {
+        let mut shells = self.shells.lock().unwrap_or_else(|err| err.into_inner());
+        shells
+            .entry((ctx.session_id, ctx.agent.name.clone()))
+            .or_insert_with(|| Arc::new(tokio::sync::Mutex::new(ShellState::inherit())))
+            .clone()
+    }
+
+    async fn run(&self, ctx: &ToolContext, command: &str) -> Result<String> {
+        let shell = self.shell(ctx);
+        let mut state = shell.lock().await;
+        let id = Uuid::new_v4();
+        let cwd_file = std::env::temp_dir().join(format!("opencode-shell-{id}.cwd"));
+        let env_file = std::env::temp_dir().join(format!("opencode-shell-{id}.env"));
+
+        let mut child = state
+            .command(FOREGROUND_SCRIPT, command)
+            .env("__OPENCODE_CWD_FILE", &cwd_file)
+            .env("__OPENCODE_ENV_FILE", &env_file)
+            .spawn()?;
+        let pid = child.id();
+        // Each stream keeps as much of its start and end as the result can
+        // show, so whatever it drops falls in the truncated middle.
+        let stdout = Arc::new(Mutex::new(Captured::head_and_tail(MAX_OUTPUT_BYTES)));
+        let stderr = Arc::new(Mutex::new(Captured::head_and_tail(MAX_OUTPUT_BYTES)));
+        let readers = [
+            capture(child.stdout.take(), stdout.clone()),
+            capture(child.stderr.take(), stderr.clone()),
+        ];
+
+        let timeout = ctx.agent.budgets.tool_timeout.unwrap_or(DEFAULT_TIMEOUT);
+        let exit = time::timeout(timeout, exited(&mut child)).await;
+        // Bash is not reaped yet, so its group ID cannot have been reused.
+        // Also kills anything the command left running in the background,
+        // which would otherwise hold the output pipes open.
+        kill_group(pid);
+        let status = child.wait().await?;
+        let footer = match exit {
+            Ok(exit) => {
+                exit?;
+                state.update(&cwd_file, &env_file);
+                match status.code() {
+                    Some(code) => format!("Exit code: {code}"),
+                    None => "Exit code: none (terminated by signal)".to_string(),
+                }
+            }
+            Err(_) => {
+                format!("Command timed out after {timeout:?}; its process group was killed.")
+            }
+        };
+        for reader in readers {
+            let _ = reader.await;
+        }
+        let _ = std::fs::remove_file(&cwd_file);
+        let _ = std::fs::remove_file(&env_file);
+
+        let (stdout, stdout_dropped) = take(&stdout);
+        let (stderr, stderr_dropped) = take(&stderr);
         let mut result = String::new();
         if !stdout.is_empty() {
             result.push_str("---STDOUT---\n");
@@ -43,11 +245,334 @@ impl Tool for BashTool {
             result.push_str("---STDERR---\n");
             result.push_str(&stderr);
         }
-
-        if result.is_empty() {
+        if result.is_empty() && footer == "Exit code: 0" {
             result.push_str("[Command executed successfully with no output]");
         }
-
+        let mut result =
+            truncate_dropped(&result, MAX_OUTPUT_BYTES, stdout_dropped + stderr_dropped);
+        if !result.ends_with('\n') {
+            result.push('\n');
+        }
+        result.push_str(&footer);
         Ok(result)
     }
+
+    async fn start_job(&self, ctx: &ToolContext, command: &str) -> Result<String> {
+        let state = self.shell(ctx).lock().await.clone();
+        let mut jobs = self.jobs.lock().unwrap_or_else(|err| err.into_inner());
+        if jobs.values().filter(|job| job.running()).count() >= MAX_JOBS {
+            return Ok(format!(
+                "{MAX_JOBS} background jobs are already running. Use `bash --kill <job_id>` to stop one first."
+            ));
+        }
+        let mut child = state.command(BACKGROUND_SCRIPT, command).spawn()?;
+        let group = Arc::new(Mutex::new(child.id()));
+        let output = Arc::new(Mutex::new(Captured::capped(MAX_OUTPUT_BYTES)));
+        let readers = [
+            capture(child.stdout.take(), output.clone()),
+            capture(child.stderr.take(), output.clone()),
+        ];
+        let status = Arc::new(Mutex::new(None));
+        tokio::spawn(wait_job(child, group.clone(), readers, status.clone()));
+
+        let id = self.next_job.fetch_add(1, Ordering::Relaxed) + 1;
+        jobs.insert(
+            id,
+            Job {
+                group,
+                output,
+                status,
+            },
+        );
+        Ok(format!(
+            "Started background job {id}. Use `bash --poll {id}` for its output or `bash --kill {id}` to stop it."
+        ))
+    }
+
+    /// Returns the job's status and the output since the last poll. A
+    /// finished job is forgotten once its output has been returned.
+    fn poll(&self, id: u64) -> String {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|err| err.into_inner());
+        let Some(job) = jobs.get(&id) else {
+            return format!("No background job with ID {id}");
+        };
+        let exit = *job.status.lock().unwrap_or_else(|err| err.into_inner());
+        let status = match exit {
+            None => format!("Job {id} is still running."),
+            Some(Some(code)) => format!("Job {id} exited with code {code}."),
+            Some(None) => format!("Job {id} was terminated by a signal."),
+        };
+        let (output, dropped) = {
+            let mut captured = job.output.lock().unwrap_or_else(|err| err.into_inner());
+            let bytes = std::mem::take(&mut captured.bytes);
+            let dropped = std::mem::take(&mut captured.dropped);
+            (String::from_utf8_lossy(&bytes).into_owned(), dropped)
+        };
+        if exit.is_some() {
+            jobs.remove(&id);
+        }
+        match (output.is_empty(), dropped) {
+            (true, _) => format!("{status}\n[No output yet]"),
+            (false, 0) => format!("{status}\n{output}"),
+            (false, dropped) => {
+                format!("{status}\n... [{dropped} earlier bytes dropped] ...\n\n{output}")
+            }
+        }
+    }
+
+    fn kill(&self, id: u64) -> String {
+        let jobs = self.jobs.lock().unwrap_or_else(|err| err.into_inner());
+        let Some(job) = jobs.get(&id) else {
+            return format!("No background job with ID {id}");
+        };
+        let group = job.group.lock().unwrap_or_else(|err| err.into_inner());
+        if group.is_none() {
+            return format!("Job {id} has already exited.");
+        }
+        kill_group(*group);
+        format!("Killed job {id}.")
+    }
+
+    async fn start_pty(&self, ctx: &ToolContext, command: &str) -> Result<String> {
+        let state = self.shell(ctx).lock().await.clone();
+        let id = self.pty.start(command, &state.cwd, &state.env)?;
+        self.pty.settle(id).await;
+        Ok(format!(
+            "Started PTY session {id}. Use `bash --send {id} <keys>` to type and `bash --screen {id}` to read the screen.\n{}",
+            self.screen(id)
+        ))
+    }
+
+    async fn send(&self, id: u64, keys: &str) -> Result<String> {
+        if !self.pty.send(id, keys).await? {
+            return Ok(format!("No PTY session with ID {id}"));
+        }
+        self.pty.settle(id).await;
+        Ok(self.screen(id))
+    }
+
+    fn screen(&self, id: u64) -> String {
+        self.pty
+            .screen(id)
+            .unwrap_or_else(|| format!("No PTY session with ID {id}"))
+    }
+}
+
+#[async_trait]
+impl Tool for BashTool {
+    fn name(&self) -> &str {
+        "bash"
+    }
+
+    fn description(&self) -> &str {
+        "Executes a command in a persistent bash session: the working directory and exported variables carry over between calls. A single argument is run as a command line, so pipes and redirects work; several arguments are quoted and run as one command with one word each. Returns stdout, stderr and the exit code. Prefix with --background to start a long-running command and get a job ID, then use --poll <job_id> to read the output produced since the last poll or --kill <job_id> to stop it. Prefix with --pty to run an interactive command (REPLs, prompts, git rebase) on a terminal: --screen <pty_id> shows the screen, --send <pty_id> <keys> types text where <enter>, <tab>, <esc>, <up>, <ctrl-c> and similar name special keys, and --terminate <pty_id> kills it."
+    }
+
+    async fn execute(&self, args: &[String]) -> Result<String> {
+        self.execute_with_context(&ToolContext::default(), args)
+            .await
+    }
+
+    async fn execute_with_context(&self, ctx: &ToolContext, args: &[String]) -> Result<String> {
+        let job_id = || args.get(1).and_then(|id| id.parse::<u64>().ok());
+        match args.first().map(String::as_str) {
+            None => Ok(USAGE.to_string()),
+            Some("--poll") => Ok(job_id().map_or(USAGE.to_string(), |id| self.poll(id))),
+            Some("--kill") => Ok(job_id().map_or(USAGE.to_string(), |id| self.kill(id))),
+            Some("--background") if args.len() > 1 => {
+                self.start_job(ctx, &command_line(&args[1..])).await
+            }
+            Some("--background") => Ok(USAGE.to_string()),
+            Some("--pty") if args.len() > 1 => self.start_pty(ctx, &command_line(&args[1..])).await,
+            Some("--screen") => Ok(job_id().map_or(USAGE.to_string(), |id| self.screen(id))),
+            Some("--send") if args.len() > 2 => match job_id() {
+                Some(id) => self.send(id, &args[2..].join(" ")).await,
+                None => Ok(USAGE.to_string()),
+            },
+            Some("--terminate") => Ok(job_id().map_or(USAGE.to_string(), |id| {
+                if self.pty.terminate(id) {
+                    format!("Terminated PTY session {id}.")
+                } else {
+                    format!("No PTY session with ID {id}")
+                }
+            })),
+            Some(flag) if flag.starts_with("--") => Ok(USAGE.to_string()),
+            Some(_) => self.run(ctx, &command_line(args)).await,
+        }
+    }
+}
+
+/// A single argument is the command line itself; several are the words of
+/// one command, quoted so each stays a single word.
+fn command_line(args: &[String]) -> String {
+    match args {
+        [command] => command.clone(),
+        words => shell_words::join(words),
+    }
+}
+
+fn capture(
+    reader: Option<impl AsyncRead + Unpin + Send + 'static>,
+    buffer: Buffer,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let Some(mut reader) = reader else {
+            return;
+        };
+        let mut chunk = [0u8; 8192];
+        while let Ok(read) = reader.read(&mut chunk).await {
+            if read == 0 {
+                break;
+            }
+            buffer
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .push(&chunk[..read]);
+        }
+    })
+}
+
+/// Waits for the job and its output. The shell stays unreaped, keeping its
+/// group ID taken, until the output is done and the group is cleared.
+async fn wait_job(
+    mut child: Child,
+    group: Arc<Mutex<Option<u32>>>,
+    readers: [JoinHandle<()>; 2],
+    status: Arc<Mutex<Option<Option<i32>>>>,
+) {
+    let _ = exited(&mut child).await;
+    for reader in readers {
+        let _ = reader.await;
+    }
+    let code = {
+        let mut group = group.lock().unwrap_or_else(|err| err.into_inner());
+        *group = None;
+        child
+            .try_wait()
+            .ok()
+            .flatten()
+            .and_then(|status| status.code())
+    };
+    *status.lock().unwrap_or_else(|err| err.into_inner()) = Some(code);
+}
+
+/// Waits for the process to exit without reaping it, so its ID stays taken
+/// until `Child::wait` collects it.
+#[cfg(unix)]
+async fn exited(child: &mut Child) -> std::io::Result<()> {
+    let Some(pid) = child.id() else {
+        return Ok(());
+    };
+    tokio::task::spawn_blocking(move || {
+        loop {
+            // SAFETY: `info` is a valid out-pointer for waitid, and WNOWAIT
+            // leaves the child for `Child::wait` to reap.
+            let result = unsafe {
+                let mut info: libc::siginfo_t = std::mem::zeroed();
+                libc::waitid(
+                    libc::P_PID,
+                    pid as libc::id_t,
+                    &mut info,
+                    libc::WEXITED | libc::WNOWAIT,
+                )
+            };
+            if result == 0 {
+                return Ok(());
+            }
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
+
+#[cfg(not(unix))]
+async fn exited(child: &mut Child) -> std::io::Result<()> {
+    child.wait().await.map(drop)
+}
+
+/// The kept start and end of a stream, joined, and how many bytes were
+/// dropped between them.
+fn take(buffer: &Mutex<Captured>) -> (String, usize) {
+    let captured = buffer.lock().unwrap_or_else(|err| err.into_inner());
+    let bytes = [captured.head.as_slice(), &captured.bytes].concat();
+    (
+        String::from_utf8_lossy(&bytes).into_owned(),
+        captured.dropped,
+    )
+}
+
+pub(crate) fn kill_group(pid: Option<u32>) {
+    #[cfg(unix)]
+    if let Some(pid) = pid {
+        // SAFETY: killpg has no memory-safety preconditions; a stale group
+        // only makes it fail with ESRCH.
+        unsafe {
+            libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = pid;
+}
+
+/// Keeps the start and end of `text`, replacing the middle with a marker
+/// when it is longer than `limit` bytes.
+pub fn truncate_middle(text: &str, limit: usize) -> String {
+    truncate_dropped(text, limit, 0)
+}
+
+/// Like `truncate_middle`, for text that already lost `dropped` bytes from
+/// the part the marker replaces.
+fn truncate_dropped(text: &str, limit: usize, dropped: usize) -> String {
+    if text.len() <= limit {
+        return text.to_string();
+    }
+    let mut head = limit / 2;
+    while !text.is_char_boundary(head) {
+        head -= 1;
+    }
+    let mut tail = text.len() - limit / 2;
+    while !text.is_char_boundary(tail) {
+        tail += 1;
+    }
+    format!(
+        "{}\n\n... [{} bytes truncated] ...\n\n{}",
+        &text[..head],
+        tail - head + dropped,
+        &text[tail..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Captured, take};
+    use std::sync::Mutex;
+
+    #[test]
+    fn head_and_tail_capture_stays_bounded() {
+        let mut captured = Captured::head_and_tail(4);
+        for chunk in [&b"abc"[..], b"defgh", b"ijklmnop", b"qr"] {
+            captured.push(chunk);
+        }
+        assert_eq!(captured.head, b"abcd");
+        assert_eq!(captured.bytes, b"opqr");
+        assert_eq!(take(&Mutex::new(captured)), ("abcdopqr".to_string(), 10));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn exited_leaves_the_child_for_wait_to_reap() {
+        let mut child = tokio::process::Command::new("sh")
+            .args(["-c", "exit 7"])
+            .spawn()
+            .unwrap();
+        super::exited(&mut child).await.unwrap();
+        let pid = child.id().expect("not reaped yet") as libc::pid_t;
+        // SAFETY: signal 0 only checks that the process still exists.
+        assert_eq!(unsafe { libc::kill(pid, 0) }, 0);
+        assert_eq!(child.wait().await.unwrap().code(), Some(7));
+    }
 }
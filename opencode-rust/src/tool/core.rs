@@ -1,9 +1,50 @@
+use std::sync::Arc;
+
+use crate::agent::session::Session;
+use crate::agent::spec::AgentSpec;
+use crate::tool::file_time::FileTimes;
 use crate::util::error::Result;
 use async_trait::async_trait;
+use uuid::Uuid;
+
+/// Identifies the session and agent a tool call is made on behalf of.
+#[derive(Debug, Clone)]
+pub struct ToolContext {
+    pub session_id: Uuid,
+    pub agent: Arc<AgentSpec>,
+    /// When the session last saw each file; shared across calls so edits can
+    /// detect files changed on disk behind the agent's back.
+    pub file_times: Arc<FileTimes>,
+}
+
+impl ToolContext {
+    pub fn new(session_id: Uuid, agent: Arc<AgentSpec>) -> Self {
+        Self {
+            session_id,
+            agent,
+            file_times: Arc::new(FileTimes::new()),
+        }
+    }
+
+    pub fn with_file_times(mut self, file_times: Arc<FileTimes>) -> Self {
+        self.file_times = file_times;
+        self
+    }
+}
+
+impl Default for ToolContext {
+    fn default() -> Self {
+        Self::new(Session::new().id(), Arc::new(AgentSpec::new("primary")))
+    }
+}
 
 #[async_trait]
 pub trait Tool: Send + Sync {
     fn name(&self) -> &str;
     fn description(&self) -> &str;
     async fn execute(&self, args: &[String]) -> Result<String>;
+
+    async fn execute_with_context(&self, _ctx: &ToolContext, args: &[String]) -> Result<String> {
+        self.execute(args).await
+    }
 }
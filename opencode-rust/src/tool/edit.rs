@@ -0,0 +1,268 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::hook::{HookContext, HookRunner};
+use crate::lsp::LspManager;
+use crate::tool::core::{Tool, ToolContext};
+use crate::tool::fs::WriteEffects;
+use crate::util::error::{OpenCodeError, Result};
+use async_trait::async_trait;
+use similar::TextDiff;
+use tokio::fs;
+
+const USAGE: &str = "Usage: edit <path> <old_string> <new_string> [replace_all]";
+
+/// Replaces an exact snippet of a file and reports the change as a unified
+/// diff. Line endings are normalized before matching, and a snippet that only
+/// differs from the file in indentation is matched line by line, with the
+/// replacement re-indented to fit.
+#[derive(Default)]
+pub struct EditTool {
+    effects: WriteEffects,
+}
+
+impl EditTool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends error diagnostics for the edited file, like `WriteFileTool`.
+    pub fn with_lsp(mut self, lsp: Arc<LspManager>) -> Self {
+        self.effects.set_lsp(lsp);
+        self
+    }
+
+    /// Fires the `file_edited` hooks after each edit.
+    pub fn with_hooks(mut self, hooks: Arc<HookRunner>) -> Self {
+        self.effects.set_hooks(hooks);
+        self
+    }
+
+    async fn edit(&self, args: &[String], ctx: Option<&ToolContext>) -> Result<String> {
+        let (path, old, new, replace_all) = match args {
+            [path, old, new] => (path, old, new, false),
+            [path, old, new, flag] => match flag.as_str() {
+                "true" | "replace_all" => (path, old, new, true),
+                "false" => (path, old, new, false),
+                _ => return Ok(USAGE.to_string()),
+            },
+            _ => return Ok(USAGE.to_string()),
+        };
+        if old == new {
+            return Err(edit_error("old_string and new_string must be different"));
+        }
+        let file = Path::new(path);
+
+        let before = match fs::metadata(file).await {
+            Ok(meta) if meta.is_dir() => {
+                return Err(edit_error(format!("{path} is a directory, not a file")));
+            }
+            Ok(_) => {
+                if let Some(ctx) = ctx {
+                    ctx.file_times
+                        .check(ctx.session_id, file)
+                        .map_err(OpenCodeError::Edit)?;
+                }
+                fs::read_to_string(file).await?
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound && old.is_empty() => {
+                String::new()
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Err(edit_error(format!("File {path} not found")));
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let after = if old.is_empty() {
+            if !before.is_empty() {
+                return Err(edit_error(format!(
+                    "old_string is empty but {path} already has content; use write_file to overwrite it"
+                )));
+            }
+            new.clone()
+        } else {
+            replace(&before, old, new, replace_all).map_err(OpenCodeError::Edit)?
+        };
+
+        if let Some(parent) = file
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(file, &after).await?;
+
+        let mut output = format!(
+            "File {path} edited successfully.\n\n{}",
+            unified_diff(path, &before, &after)
+        );
+        let (hook_ctx, diagnostics) = match ctx {
+            Some(ctx) => (HookContext::from(ctx), ctx.agent.diagnostics),
+            None => (HookContext::default(), true),
+        };
+        let report = self.effects.after_write(file, &hook_ctx, diagnostics).await;
+        // Hooks such as formatters may rewrite the file, so its state is
+        // recorded only once they have run.
+        if let Some(ctx) = ctx {
+            ctx.file_times.record(ctx.session_id, file);
+        }
+        if let Some(report) = report {
+            output.push_str("\n\n");
+            output.push_str(&report);
+        }
+        Ok(output)
+    }
+}
+
+#[async_trait]
+impl Tool for EditTool {
+    fn name(&self) -> &str {
+        "edit"
+    }
+
+    fn description(&self) -> &str {
+        "Replaces old_string with new_string in a file and returns a unified diff. old_string must match exactly one place in the file unless replace_all is 'true'. An empty old_string creates a new file. Arguments: <path> <old_string> <new_string> [replace_all]."
+    }
+
+    async fn execute(&self, args: &[String]) -> Result<String> {
+        self.edit(args, None).await
+    }
+
+    async fn execute_with_context(&self, ctx: &ToolContext, args: &[String]) -> Result<String> {
+        self.edit(args, Some(ctx)).await
+    }
+}
+
+fn edit_error(message: impl Into<String>) -> OpenCodeError {
+    OpenCodeError::Edit(message.into())
+}
+
+/// Unified diff between two versions of `path`, with three lines of context.
+pub fn unified_diff(path: &str, before: &str, after: &str) -> String {
+    TextDiff::from_lines(before, after)
+        .unified_diff()
+        .context_radius(3)
+        .header(path, path)
+        .to_string()
+}
+
+/// Replaces `old` with `new` in `content`. An exact match is tried first;
+/// failing that, lines are compared with surrounding whitespace trimmed. The
+/// file's line endings are preserved.
+pub fn replace(
+    content: &str,
+    old: &str,
+    new: &str,
+    replace_all: bool,
+) -> std::result::Result<String, String> {
+    let crlf = content.contains("\r\n");
+    let content = content.replace("\r\n", "\n");
+    let old = old.replace("\r\n", "\n");
+    let new = new.replace("\r\n", "\n");
+
+    let replaced = match content.matches(old.as_str()).count() {
+        0 => replace_trimmed_lines(&content, &old, &new, replace_all)?,
+        1 => content.replacen(old.as_str(), &new, 1),
+        _ if replace_all => content.replace(old.as_str(), &new),
+        count => return Err(ambiguous(count)),
+    };
+    Ok(if crlf {
+        replaced.replace('\n', "\r\n")
+    } else {
+        replaced
+    })
+}
+
+fn ambiguous(count: usize) -> String {
+    format!(
+        "Found {count} matches for old_string. Provide more surrounding lines to identify the correct match, or set replace_all to replace every occurrence."
+    )
+}
+
+/// Matches `old` against windows of whole lines, ignoring leading and
+/// trailing whitespace on each line.
+fn replace_trimmed_lines(
+    content: &str,
+    old: &str,
+    new: &str,
+    replace_all: bool,
+) -> std::result::Result<String, String> {
+    let not_found = || {
+        "old_string not found in file. It must match the file contents, including line breaks."
+            .to_string()
+    };
+    let old = old.strip_suffix('\n').unwrap_or(old);
+    let new = new.strip_suffix('\n').unwrap_or(new);
+    let old_lines: Vec<&str> = old.split('\n').collect();
+    if old_lines.iter().all(|line| line.trim().is_empty()) {
+        return Err(not_found());
+    }
+
+    // Byte span of every line, excluding its newline.
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for line in content.split('\n') {
+        lines.push((start, start + line.len()));
+        start += line.len() + 1;
+    }
+    let text = |(start, end): (usize, usize)| &content[start..end];
+
+    let mut spans = Vec::new();
+    let mut idx = 0;
+    while idx + old_lines.len() <= lines.len() {
+        let window = &lines[idx..idx + old_lines.len()];
+        let matches = window
+            .iter()
+            .zip(&old_lines)
+            .all(|(&span, old)| text(span).trim() == old.trim());
+        if matches {
+            spans.push(idx);
+            idx += old_lines.len();
+        } else {
+            idx += 1;
+        }
+    }
+    match spans.len() {
+        0 => return Err(not_found()),
+        1 => {}
+        _ if replace_all => {}
+        count => return Err(ambiguous(count)),
+    }
+
+    let mut result = String::with_capacity(content.len());
+    let mut cursor = 0;
+    for first in spans {
+        let window = &lines[first..first + old_lines.len()];
+        let (start, end) = (window[0].0, window[window.len() - 1].1);
+        result.push_str(&content[cursor..start]);
+        result.push_str(&reindent(new, &old_lines, |idx| text(window[idx])));
+        cursor = end;
+    }
+    result.push_str(&content[cursor..]);
+    Ok(result)
+}
+
+/// Shifts `new` from the indentation used in `old_lines` to the one found in
+/// the file, based on the first non-blank line of the snippet.
+fn reindent<'a>(new: &str, old_lines: &[&str], file_line: impl Fn(usize) -> &'a str) -> String {
+    let Some(first) = old_lines.iter().position(|line| !line.trim().is_empty()) else {
+        return new.to_string();
+    };
+    let old_indent = indentation(old_lines[first]);
+    let file_indent = indentation(file_line(first));
+    if old_indent == file_indent {
+        return new.to_string();
+    }
+    new.split('\n')
+        .map(|line| match line.strip_prefix(old_indent) {
+            Some(rest) if !line.trim().is_empty() => format!("{file_indent}{rest}"),
+            _ => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn indentation(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
@@ -0,0 +1,80 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use uuid::Uuid;
+
+/// Remembers, per session, the modification time of each file as it was when
+/// the agent last read or wrote it, so edits against stale contents can be
+/// refused.
+#[derive(Debug, Default)]
+pub struct FileTimes {
+    seen: Mutex<HashMap<(Uuid, PathBuf), SystemTime>>,
+}
+
+impl FileTimes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the current on-disk state of `path` as seen by the session.
+    pub fn record(&self, session_id: Uuid, path: &Path) {
+        let Some(modified) = modified(path) else {
+            return;
+        };
+        if let Ok(mut seen) = self.seen.lock() {
+            seen.insert((session_id, key(path)), modified);
+        }
+    }
+
+    pub fn get(&self, session_id: Uuid, path: &Path) -> Option<SystemTime> {
+        let seen = self.seen.lock().ok()?;
+        seen.get(&(session_id, key(path))).copied()
+    }
+
+    /// Every time recorded for the session, to be saved with it.
+    pub fn session(&self, session_id: Uuid) -> BTreeMap<PathBuf, SystemTime> {
+        let Ok(seen) = self.seen.lock() else {
+            return BTreeMap::new();
+        };
+        seen.iter()
+            .filter(|((id, _), _)| *id == session_id)
+            .map(|((_, path), modified)| (path.clone(), *modified))
+            .collect()
+    }
+
+    /// Loads times saved with the session by an earlier run.
+    pub fn restore(&self, session_id: Uuid, times: &BTreeMap<PathBuf, SystemTime>) {
+        if let Ok(mut seen) = self.seen.lock() {
+            for (path, modified) in times {
+                seen.insert((session_id, path.clone()), *modified);
+            }
+        }
+    }
+
+    /// Fails when the session has seen `path` and the file was modified on
+    /// disk since. Files the session never read are not checked.
+    pub fn check(&self, session_id: Uuid, path: &Path) -> Result<(), String> {
+        let Some(seen) = self.get(session_id, path) else {
+            return Ok(());
+        };
+        match modified(path) {
+            Some(current) if current != seen => Err(format!(
+                "File {} has been modified since it was last read. Please read the file again before modifying it.",
+                path.display()
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn key(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
@@ -1,11 +1,127 @@
-use crate::tool::core::Tool;
-use crate::util::error::Result;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::hook::{HookContext, HookRunner};
+use crate::lsp::{self, LspManager};
+use crate::tool::core::{Tool, ToolContext};
+use crate::util::error::{OpenCodeError, Result};
+use crate::watcher::IgnoreMatcher;
 use async_trait::async_trait;
+use ignore::WalkBuilder;
 use tokio::fs;
-use walkdir::WalkDir;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tracing::warn;
+
+const DEFAULT_READ_LIMIT: usize = 2000;
+const MAX_LINE_LENGTH: usize = 2000;
+/// Output stops once this many bytes of file content have been returned.
+const MAX_READ_BYTES: usize = 50 * 1024;
+const BINARY_PROBE: usize = 8192;
+const MAX_SUGGESTIONS: usize = 3;
+const BINARY_EXTENSIONS: &[&str] = &[
+    "7z", "a", "bin", "bmp", "class", "dll", "doc", "docx", "dylib", "exe", "gif", "gz", "ico",
+    "jar", "jpeg", "jpg", "o", "pdf", "png", "pyc", "so", "tar", "wasm", "webp", "xls", "xlsx",
+    "zip",
+];
 
+/// Reads a window of lines from a text file, numbered like `cat -n`.
 pub struct ReadFileTool;
 
+impl ReadFileTool {
+    /// Returns the output and whether it shows the file's text, rather than
+    /// usage or a refusal.
+    async fn read(&self, args: &[String]) -> Result<(String, bool)> {
+        let usage = || {
+            Ok((
+                "Usage: read_file <path> [offset] [limit]".to_string(),
+                false,
+            ))
+        };
+        let (path, offset, limit) = match args {
+            [path] => (path, None, None),
+            [path, offset] => (path, Some(offset), None),
+            [path, offset, limit] => (path, Some(offset), Some(limit)),
+            _ => return usage(),
+        };
+        let Ok(offset) = offset.map_or(Ok(0), |offset| offset.parse::<usize>()) else {
+            return usage();
+        };
+        let Ok(limit) = limit.map_or(Ok(DEFAULT_READ_LIMIT), |limit| limit.parse::<usize>()) else {
+            return usage();
+        };
+        let path = Path::new(path);
+
+        let metadata = match fs::metadata(path).await {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Err(OpenCodeError::Read(not_found(path).await));
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if metadata.is_dir() {
+            return Err(OpenCodeError::Read(format!(
+                "{} is a directory; use list_files to see its contents",
+                path.display()
+            )));
+        }
+
+        let mut reader = BufReader::new(fs::File::open(path).await?);
+        if is_binary(path, reader.fill_buf().await?) {
+            let refusal = format!(
+                "Cannot read binary file: {}. Only text files can be read.",
+                path.display()
+            );
+            return Ok((refusal, false));
+        }
+
+        let mut output = Vec::new();
+        let mut line_number = 0;
+        let mut bytes = 0;
+        let mut more = false;
+        let mut buffer = Vec::new();
+        loop {
+            buffer.clear();
+            if reader.read_until(b'\n', &mut buffer).await? == 0 {
+                break;
+            }
+            line_number += 1;
+            if line_number <= offset {
+                continue;
+            }
+            if output.len() >= limit || bytes >= MAX_READ_BYTES {
+                more = true;
+                break;
+            }
+            let text = String::from_utf8_lossy(&buffer);
+            let text = text.trim_end_matches(['\n', '\r']);
+            let text = match text.char_indices().nth(MAX_LINE_LENGTH) {
+                Some((idx, _)) => format!("{}...", &text[..idx]),
+                None => text.to_string(),
+            };
+            bytes += text.len();
+            output.push(format!("{line_number:>6}\t{text}"));
+        }
+
+        let last = offset + output.len();
+        let shown = !output.is_empty() || line_number == 0;
+        if more {
+            output.push(String::new());
+            output.push(format!(
+                "(File has more lines. Use offset {last} to read beyond line {last})"
+            ));
+        } else if output.is_empty() && line_number > 0 {
+            output.push(format!(
+                "(Offset {offset} is past the end of the file, which has {line_number} lines)"
+            ));
+        } else {
+            output.push(String::new());
+            output.push(format!("(End of file - total {line_number} lines)"));
+        }
+        Ok((output.join("\n"), shown))
+    }
+}
+
 #[async_trait]
 impl Tool for ReadFileTool {
     fn name(&self) -> &str {
@@ -13,20 +129,162 @@ impl Tool for ReadFileTool {
     }
 
     fn description(&self) -> &str {
-        "Reads the entire content of a file and returns it as a string."
+        "Reads a text file and returns its lines numbered like `cat -n`. The first argument is the file path; the optional second is the 0-based line offset to start from and the third the number of lines to read (defaults to 2000). Very long lines are truncated and binary files are refused."
     }
 
     async fn execute(&self, args: &[String]) -> Result<String> {
-        if args.len() != 1 {
-            return Ok("Usage: read_file <path>".to_string());
+        Ok(self.read(args).await?.0)
+    }
+
+    async fn execute_with_context(&self, ctx: &ToolContext, args: &[String]) -> Result<String> {
+        let (content, shown) = self.read(args).await?;
+        if shown && let Some(path) = args.first() {
+            ctx.file_times.record(ctx.session_id, Path::new(path));
         }
-        let path = &args[0];
-        let content = fs::read_to_string(path).await?;
         Ok(content)
     }
 }
 
-pub struct WriteFileTool;
+/// Files are binary when their extension says so, their first bytes contain
+/// a NUL, or more than 30% of those bytes are non-text control characters.
+fn is_binary(path: &Path, probe: &[u8]) -> bool {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    if extension.is_some_and(|extension| BINARY_EXTENSIONS.contains(&extension.as_str())) {
+        return true;
+    }
+    let probe = &probe[..probe.len().min(BINARY_PROBE)];
+    if probe.contains(&0) {
+        return true;
+    }
+    let control = probe
+        .iter()
+        .filter(|&&byte| byte < 9 || (byte > 13 && byte < 32))
+        .count();
+    !probe.is_empty() && control * 10 > probe.len() * 3
+}
+
+/// Error message for a missing file, listing similarly named siblings.
+async fn not_found(path: &Path) -> String {
+    let message = format!("File not found: {}", path.display());
+    let Some(name) = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+    else {
+        return message;
+    };
+    let dir = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let Ok(mut entries) = fs::read_dir(dir).await else {
+        return message;
+    };
+    let mut suggestions = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let entry_name = entry.file_name().to_string_lossy().to_lowercase();
+        if entry_name.contains(&name) || name.contains(&entry_name) {
+            suggestions.push(path.with_file_name(entry.file_name()).display().to_string());
+        }
+    }
+    if suggestions.is_empty() {
+        return message;
+    }
+    suggestions.sort();
+    suggestions.truncate(MAX_SUGGESTIONS);
+    format!(
+        "{message}\n\nDid you mean one of these?\n{}",
+        suggestions.join("\n")
+    )
+}
+
+/// Follow-up work shared by the tools that modify files: `file_edited` hooks
+/// first, so formatters run before language servers are asked for errors.
+#[derive(Default, Clone)]
+pub(crate) struct WriteEffects {
+    lsp: Option<Arc<LspManager>>,
+    hooks: Option<Arc<HookRunner>>,
+}
+
+impl WriteEffects {
+    pub(crate) fn set_lsp(&mut self, lsp: Arc<LspManager>) {
+        self.lsp = Some(lsp);
+    }
+
+    pub(crate) fn set_hooks(&mut self, hooks: Arc<HookRunner>) {
+        self.hooks = Some(hooks);
+    }
+
+    /// Runs the hooks for `path` and returns the error diagnostics report,
+    /// if any, to append to the tool output.
+    pub(crate) async fn after_write(
+        &self,
+        path: &Path,
+        ctx: &HookContext,
+        diagnostics: bool,
+    ) -> Option<String> {
+        if let Some(hooks) = &self.hooks {
+            hooks.file_edited(path, ctx).await;
+        }
+        if !diagnostics {
+            return None;
+        }
+        let lsp = self.lsp.as_ref().filter(|lsp| lsp.handles(path))?;
+        match lsp.diagnostics(path).await {
+            Ok(diagnostics) => lsp::report_errors(&diagnostics),
+            Err(err) => {
+                warn!(path = %path.display(), "failed to collect diagnostics: {err}");
+                None
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct WriteFileTool {
+    effects: WriteEffects,
+}
+
+impl WriteFileTool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends error diagnostics from the configured language servers to the
+    /// result of every write made by an agent that has diagnostics enabled.
+    pub fn with_lsp(mut self, lsp: Arc<LspManager>) -> Self {
+        self.effects.set_lsp(lsp);
+        self
+    }
+
+    /// Fires the `file_edited` hooks after each write, before diagnostics are
+    /// collected, so formatter hooks are reflected in the reported errors.
+    pub fn with_hooks(mut self, hooks: Arc<HookRunner>) -> Self {
+        self.effects.set_hooks(hooks);
+        self
+    }
+
+    async fn write(&self, args: &[String], ctx: &HookContext, diagnostics: bool) -> Result<String> {
+        if args.len() != 2 {
+            return Ok("Usage: write_file <path> <content>".to_string());
+        }
+        let path = &args[0];
+        let content = &args[1];
+        fs::write(path, content).await?;
+        let mut output = format!("File {} written successfully.", path);
+        if let Some(report) = self
+            .effects
+            .after_write(Path::new(path), ctx, diagnostics)
+            .await
+        {
+            output.push_str("\n\n");
+            output.push_str(&report);
+        }
+        Ok(output)
+    }
+}
 
 #[async_trait]
 impl Tool for WriteFileTool {
@@ -39,16 +297,27 @@ impl Tool for WriteFileTool {
     }
 
     async fn execute(&self, args: &[String]) -> Result<String> {
-        if args.len() != 2 {
-            return Ok("Usage: write_file <path> <content>".to_string());
+        self.write(args, &HookContext::default(), true).await
+    }
+
+    async fn execute_with_context(&self, ctx: &ToolContext, args: &[String]) -> Result<String> {
+        let output = self
+            .write(args, &HookContext::from(ctx), ctx.agent.diagnostics)
+            .await?;
+        if let [path, _] = args {
+            ctx.file_times.record(ctx.session_id, Path::new(path));
         }
-        let path = &args[0];
-        let content = &args[1];
-        fs::write(path, content).await?;
-        Ok(format!("File {} written successfully.", path))
+        Ok(output)
     }
 }
 
+const DEFAULT_LIST_DEPTH: usize = 3;
+/// Entries shown in the whole listing and within a single directory.
+const MAX_LIST_ENTRIES: usize = 200;
+const MAX_DIR_ENTRIES: usize = 50;
+
+/// Lists a directory recursively as an indented tree, honoring `.gitignore`
+/// and the watcher's ignore rules.
 pub struct ListFilesTool;
 
 #[async_trait]
@@ -58,21 +327,108 @@ impl Tool for ListFilesTool {
     }
 
     fn description(&self) -> &str {
-        "Lists all files and directories under the given directory (defaults to current directory). Directories in the output will have a trailing slash."
+        "Lists files and directories under the given directory (defaults to the current directory) as an indented tree, directories first and marked with a trailing slash. The optional second argument is the depth to descend (defaults to 3); further arguments are globs to ignore. Gitignored files and build or dependency folders are skipped."
     }
 
     async fn execute(&self, args: &[String]) -> Result<String> {
-        let path = args.get(0).map_or(".", |s| s.as_str());
-        let mut result = String::new();
-        for entry in WalkDir::new(path).min_depth(1).max_depth(1) {
-            let entry = entry?;
-            let file_name = entry.file_name().to_string_lossy();
-            if entry.file_type().is_dir() {
-                result.push_str(&format!("{}/\n", file_name));
-            } else {
-                result.push_str(&format!("{}\n", file_name));
-            }
+        let root = PathBuf::from(args.first().map_or(".", |s| s.as_str()));
+        let depth = match args.get(1) {
+            Some(depth) => match depth.parse::<usize>() {
+                Ok(depth) if depth > 0 => depth,
+                _ => return Ok("Usage: list_files [path] [depth] [ignore_glob...]".to_string()),
+            },
+            None => DEFAULT_LIST_DEPTH,
+        };
+        // Bare globs such as `*.md` apply at any depth.
+        let ignore: Vec<String> = args
+            .iter()
+            .skip(2)
+            .map(|glob| {
+                if glob.contains('/') {
+                    glob.clone()
+                } else {
+                    format!("**/{glob}")
+                }
+            })
+            .collect();
+        if !fs::metadata(&root).await?.is_dir() {
+            return Err(OpenCodeError::Read(format!(
+                "{} is not a directory",
+                root.display()
+            )));
         }
-        Ok(result)
+
+        tokio::task::spawn_blocking(move || list_tree(&root, depth, &ignore))
+            .await
+            .map_err(std::io::Error::other)?
+    }
+}
+
+#[derive(Default)]
+struct TreeEntry {
+    dir: bool,
+    children: BTreeMap<String, TreeEntry>,
+}
+
+fn list_tree(root: &Path, depth: usize, ignore: &[String]) -> Result<String> {
+    let matcher = IgnoreMatcher::new(root.to_path_buf(), ignore);
+    let mut walker = WalkBuilder::new(root);
+    walker
+        .hidden(false)
+        .require_git(false)
+        .max_depth(Some(depth))
+        .filter_entry(move |entry| entry.file_name() != ".git" && !matcher.matches(entry.path()));
+
+    let mut tree = TreeEntry {
+        dir: true,
+        ..TreeEntry::default()
+    };
+    for entry in walker.build().flatten() {
+        let Ok(relative) = entry.path().strip_prefix(root) else {
+            continue;
+        };
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        let mut node = &mut tree;
+        for part in relative.iter() {
+            node = node
+                .children
+                .entry(part.to_string_lossy().to_string())
+                .or_default();
+        }
+        node.dir = entry.file_type().is_some_and(|kind| kind.is_dir());
+    }
+
+    let mut lines = vec![format!("{}/", root.display())];
+    let mut budget = MAX_LIST_ENTRIES;
+    render_tree(&tree, 1, &mut budget, &mut lines);
+    Ok(lines.join("\n"))
+}
+
+/// Renders children directories first, stopping at the per-directory and
+/// overall caps and noting how many entries of each directory were left out.
+fn render_tree(node: &TreeEntry, depth: usize, budget: &mut usize, lines: &mut Vec<String>) {
+    let indent = "  ".repeat(depth);
+    let mut children: Vec<_> = node.children.iter().collect();
+    children.sort_by_key(|(_, child)| !child.dir);
+
+    let mut shown = 0;
+    for (name, child) in &children {
+        if *budget == 0 || shown == MAX_DIR_ENTRIES {
+            break;
+        }
+        *budget -= 1;
+        shown += 1;
+        if child.dir {
+            lines.push(format!("{indent}{name}/"));
+            render_tree(child, depth + 1, budget, lines);
+        } else {
+            lines.push(format!("{indent}{name}"));
+        }
+    }
+    let hidden = children.len() - shown;
+    if hidden > 0 {
+        lines.push(format!("{indent}[{hidden} truncated]"));
     }
 }
@@ -1,5 +1,13 @@
+pub mod bash;
 pub mod core;
 pub mod echo;
-pub mod bash;
+pub mod edit;
+pub mod file_time;
 pub mod fs;
+pub mod patch;
+pub mod pty;
+pub mod search;
+pub mod task;
+pub mod todo;
 pub mod web;
+pub mod web_search;
@@ -0,0 +1,606 @@
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use crate::hook::{HookContext, HookRunner};
+use crate::lsp::LspManager;
+use crate::tool::core::{Tool, ToolContext};
+use crate::tool::fs::WriteEffects;
+use crate::util::error::{OpenCodeError, Result};
+use async_trait::async_trait;
+use tokio::fs;
+use tracing::warn;
+
+/// Leading and trailing context lines a hunk may drop to find a match, like
+/// `patch --fuzz`.
+const MAX_FUZZ: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineKind {
+    Context,
+    Remove,
+    Add,
+}
+
+#[derive(Debug, Clone)]
+struct Hunk {
+    header: String,
+    old_start: usize,
+    lines: Vec<(LineKind, String)>,
+    /// `\ No newline at end of file` seen for the old or new side.
+    old_no_newline: bool,
+    new_no_newline: bool,
+}
+
+impl Hunk {
+    /// Handles `\ No newline at end of file`, which refers to the line
+    /// before it.
+    fn mark_no_newline(&mut self) {
+        match self.lines.last().map(|(kind, _)| *kind) {
+            Some(LineKind::Remove) => self.old_no_newline = true,
+            Some(LineKind::Add) => self.new_no_newline = true,
+            _ => {
+                self.old_no_newline = true;
+                self.new_no_newline = true;
+            }
+        }
+    }
+
+    fn count(&self, kind: LineKind) -> usize {
+        self.lines.iter().filter(|(line, _)| *line == kind).count()
+    }
+}
+
+/// One file section of a unified diff. `None` paths stand for `/dev/null`.
+#[derive(Debug, Clone, Default)]
+pub struct FilePatch {
+    pub old_path: Option<PathBuf>,
+    pub new_path: Option<PathBuf>,
+    hunks: Vec<Hunk>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileChange {
+    Add,
+    Delete,
+    Rename,
+    Modify,
+}
+
+impl FilePatch {
+    pub fn change(&self) -> FileChange {
+        match (&self.old_path, &self.new_path) {
+            (None, _) => FileChange::Add,
+            (_, None) => FileChange::Delete,
+            (Some(old), Some(new)) if old != new => FileChange::Rename,
+            _ => FileChange::Modify,
+        }
+    }
+
+    fn display(&self) -> String {
+        let show = |path: &Option<PathBuf>| {
+            path.as_deref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default()
+        };
+        match self.change() {
+            FileChange::Add => format!("A {}", show(&self.new_path)),
+            FileChange::Delete => format!("D {}", show(&self.old_path)),
+            FileChange::Rename => format!("R {} -> {}", show(&self.old_path), show(&self.new_path)),
+            FileChange::Modify => format!("M {}", show(&self.new_path)),
+        }
+    }
+}
+
+/// Parses a (possibly git-style) multi-file unified diff.
+pub fn parse(patch: &str) -> std::result::Result<Vec<FilePatch>, String> {
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut current: Option<FilePatch> = None;
+    // Whether the current section already had its `---` line.
+    let mut has_header = false;
+    let mut lines = patch.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        if let Some(rest) = line.strip_prefix("diff --git ") {
+            files.extend(current.take());
+            let (old, new) = git_paths(rest)?;
+            current = Some(FilePatch {
+                old_path: Some(old),
+                new_path: Some(new),
+                hunks: Vec::new(),
+            });
+            has_header = false;
+        } else if line.starts_with("new file mode") {
+            if let Some(file) = current.as_mut() {
+                file.old_path = None;
+            }
+        } else if line.starts_with("deleted file mode") {
+            if let Some(file) = current.as_mut() {
+                file.new_path = None;
+            }
+        } else if let Some(path) = line.strip_prefix("rename from ") {
+            if let Some(file) = current.as_mut() {
+                file.old_path = Some(PathBuf::from(path));
+            }
+        } else if let Some(path) = line.strip_prefix("rename to ") {
+            if let Some(file) = current.as_mut() {
+                file.new_path = Some(PathBuf::from(path));
+            }
+        } else if line.starts_with("GIT binary patch") || line.starts_with("Binary files ") {
+            return Err("binary patches are not supported".to_string());
+        } else if let Some(path) = line.strip_prefix("--- ") {
+            if current.is_none() || has_header {
+                files.extend(current.take());
+                current = Some(FilePatch::default());
+            }
+            has_header = true;
+            if let Some(file) = current.as_mut() {
+                file.old_path = header_path(path, "a/");
+            }
+        } else if let Some(path) = line.strip_prefix("+++ ") {
+            let Some(file) = current.as_mut() else {
+                return Err(format!("'+++' line without a '---' line: {line}"));
+            };
+            file.new_path = header_path(path, "b/");
+        } else if line.starts_with("@@") {
+            let Some(file) = current.as_mut() else {
+                return Err(format!("hunk without a file header: {line}"));
+            };
+            file.hunks.push(parse_hunk(line, &mut lines)?);
+        }
+    }
+    files.extend(current);
+
+    if files.is_empty() {
+        return Err("no file changes found in patch".to_string());
+    }
+    for file in &files {
+        if file.old_path.is_none() && file.new_path.is_none() {
+            return Err("file section without a path".to_string());
+        }
+    }
+    Ok(files)
+}
+
+fn git_paths(rest: &str) -> std::result::Result<(PathBuf, PathBuf), String> {
+    let invalid = || format!("invalid diff header: diff --git {rest}");
+    let old = rest.strip_prefix("a/").ok_or_else(invalid)?;
+    let (old, new) = old.split_once(" b/").ok_or_else(invalid)?;
+    Ok((PathBuf::from(old), PathBuf::from(new)))
+}
+
+fn header_path(value: &str, prefix: &str) -> Option<PathBuf> {
+    // Timestamps follow the path after a tab in `diff -u` output.
+    let value = value.split('\t').next().unwrap_or(value).trim_end();
+    if value == "/dev/null" {
+        return None;
+    }
+    Some(PathBuf::from(value.strip_prefix(prefix).unwrap_or(value)))
+}
+
+fn parse_hunk<'a>(
+    header: &str,
+    lines: &mut std::iter::Peekable<impl Iterator<Item = &'a str>>,
+) -> std::result::Result<Hunk, String> {
+    let invalid = || format!("invalid hunk header: {header}");
+    let ranges = header
+        .strip_prefix("@@ ")
+        .and_then(|rest| rest.split_once(" @@").map(|(ranges, _)| ranges))
+        .ok_or_else(invalid)?;
+    let (old, new) = ranges.split_once(' ').ok_or_else(invalid)?;
+    let (old_start, mut old_left) =
+        parse_range(old.strip_prefix('-').ok_or_else(invalid)?).ok_or_else(invalid)?;
+    let (_, mut new_left) =
+        parse_range(new.strip_prefix('+').ok_or_else(invalid)?).ok_or_else(invalid)?;
+
+    let mut hunk = Hunk {
+        header: header.to_string(),
+        old_start,
+        lines: Vec::new(),
+        old_no_newline: false,
+        new_no_newline: false,
+    };
+    while old_left > 0 || new_left > 0 {
+        let Some(line) = lines.next() else {
+            return Err(format!("hunk {header} ends early"));
+        };
+        // Editors often strip the single space of blank context lines.
+        let (kind, text) = match line.chars().next() {
+            Some(' ') => (LineKind::Context, &line[1..]),
+            None => (LineKind::Context, ""),
+            Some('-') => (LineKind::Remove, &line[1..]),
+            Some('+') => (LineKind::Add, &line[1..]),
+            Some('\\') => {
+                hunk.mark_no_newline();
+                continue;
+            }
+            _ => return Err(format!("unexpected line in hunk {header}: {line}")),
+        };
+        match kind {
+            LineKind::Context if old_left > 0 && new_left > 0 => {
+                old_left -= 1;
+                new_left -= 1;
+            }
+            LineKind::Remove if old_left > 0 => old_left -= 1,
+            LineKind::Add if new_left > 0 => new_left -= 1,
+            _ => return Err(format!("hunk {header} does not match its line counts")),
+        }
+        hunk.lines.push((kind, text.to_string()));
+    }
+    if lines.next_if(|line| line.starts_with('\\')).is_some() {
+        hunk.mark_no_newline();
+    }
+    Ok(hunk)
+}
+
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+/// Applies `hunks` to `content`, tolerating shifted line numbers, whitespace
+/// differences and up to `MAX_FUZZ` mismatched context lines per hunk edge.
+fn apply_hunks(content: &str, hunks: &[Hunk]) -> std::result::Result<String, String> {
+    let crlf = content.contains("\r\n");
+    let normalized = content.replace("\r\n", "\n");
+    let had_newline = normalized.is_empty() || normalized.ends_with('\n');
+    let lines: Vec<&str> = match normalized.strip_suffix('\n').unwrap_or(&normalized) {
+        "" if normalized.is_empty() => Vec::new(),
+        body => body.split('\n').collect(),
+    };
+
+    let mut result: Vec<String> = Vec::new();
+    let mut cursor = 0;
+    let mut offset: isize = 0;
+    let mut ends_with_newline = had_newline;
+    for (idx, hunk) in hunks.iter().enumerate() {
+        let expected = hunk.old_start.saturating_sub(1) as isize + offset;
+        let (position, body) = locate(&lines, cursor, expected, hunk).ok_or_else(|| {
+            format!(
+                "hunk {} ({}) does not match the file contents",
+                idx + 1,
+                hunk.header
+            )
+        })?;
+        result.extend(lines[cursor..position].iter().map(|line| line.to_string()));
+        let mut file_line = position;
+        for (kind, text) in body {
+            match kind {
+                // Keep the file's own version of context lines.
+                LineKind::Context => {
+                    result.push(lines[file_line].to_string());
+                    file_line += 1;
+                }
+                LineKind::Remove => file_line += 1,
+                LineKind::Add => result.push(text.clone()),
+            }
+        }
+        offset = position as isize - expected;
+        cursor = file_line;
+        if hunk.new_no_newline {
+            ends_with_newline = false;
+        } else if hunk.old_no_newline {
+            ends_with_newline = true;
+        }
+    }
+    result.extend(lines[cursor..].iter().map(|line| line.to_string()));
+
+    let mut output = result.join("\n");
+    if ends_with_newline && !result.is_empty() {
+        output.push('\n');
+    }
+    Ok(if crlf {
+        output.replace('\n', "\r\n")
+    } else {
+        output
+    })
+}
+
+/// Finds where the old side of `hunk` occurs at or after `cursor`, nearest to
+/// `expected` first. Returns the position and the hunk lines actually used
+/// once fuzz has dropped edge context.
+fn locate<'h>(
+    lines: &[&str],
+    cursor: usize,
+    expected: isize,
+    hunk: &'h Hunk,
+) -> Option<(usize, &'h [(LineKind, String)])> {
+    let leading = hunk
+        .lines
+        .iter()
+        .take_while(|(kind, _)| *kind == LineKind::Context)
+        .count();
+    let trailing = hunk
+        .lines
+        .iter()
+        .rev()
+        .take_while(|(kind, _)| *kind == LineKind::Context)
+        .count();
+
+    for fuzz in 0..=MAX_FUZZ {
+        let front = fuzz.min(leading);
+        let back = fuzz.min(trailing).min(hunk.lines.len() - front);
+        if fuzz > 0 && front + back == 0 {
+            break;
+        }
+        let body = &hunk.lines[front..hunk.lines.len() - back];
+        let old: Vec<&str> = body
+            .iter()
+            .filter(|(kind, _)| *kind != LineKind::Add)
+            .map(|(_, text)| text.as_str())
+            .collect();
+        let target = expected + front as isize;
+        if old.is_empty() {
+            let position = target.clamp(cursor as isize, lines.len() as isize) as usize;
+            return Some((position, body));
+        }
+        if lines.len() < old.len() + cursor {
+            continue;
+        }
+        let mut candidates: Vec<usize> = (cursor..=lines.len() - old.len()).collect();
+        candidates.sort_by_key(|&position| (position as isize - target).unsigned_abs());
+        let exact = |a: &str, b: &str| a == b;
+        let loose = |a: &str, b: &str| a.trim() == b.trim();
+        for same in [&exact as &dyn Fn(&str, &str) -> bool, &loose] {
+            if let Some(&position) = candidates.iter().find(|&&position| {
+                old.iter()
+                    .zip(&lines[position..])
+                    .all(|(old, line)| same(old, line))
+            }) {
+                return Some((position, body));
+            }
+        }
+    }
+    None
+}
+
+/// Content a file ends up with once the whole patch is applied; `None`
+/// removes the file.
+struct Planned {
+    path: PathBuf,
+    content: Option<String>,
+}
+
+/// Applies multi-file unified diffs. Every hunk is validated in memory first
+/// and files are only written when all of them apply; a failed write rolls
+/// back the files already changed.
+#[derive(Default)]
+pub struct ApplyPatchTool {
+    effects: WriteEffects,
+}
+
+impl ApplyPatchTool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends error diagnostics for each written file, like `WriteFileTool`.
+    pub fn with_lsp(mut self, lsp: Arc<LspManager>) -> Self {
+        self.effects.set_lsp(lsp);
+        self
+    }
+
+    /// Fires the `file_edited` hooks for each written file.
+    pub fn with_hooks(mut self, hooks: Arc<HookRunner>) -> Self {
+        self.effects.set_hooks(hooks);
+        self
+    }
+
+    async fn apply(&self, args: &[String], ctx: Option<&ToolContext>) -> Result<String> {
+        let (patch, root) = match args {
+            [patch] => (patch, Path::new(".")),
+            [patch, root] => (patch, Path::new(root)),
+            _ => return Ok("Usage: apply_patch <patch> [directory]".to_string()),
+        };
+        let files = parse(patch).map_err(patch_error)?;
+
+        let mut failures = Vec::new();
+        let mut planned = Vec::new();
+        let mut summary = Vec::new();
+        let mut touched = HashSet::new();
+        for file in &files {
+            match self.plan(root, file, ctx, &mut touched).await {
+                Ok(changes) => {
+                    let added: usize = file.hunks.iter().map(|h| h.count(LineKind::Add)).sum();
+                    let removed: usize = file.hunks.iter().map(|h| h.count(LineKind::Remove)).sum();
+                    summary.push(format!("{} (+{added} -{removed})", file.display()));
+                    planned.extend(changes);
+                }
+                Err(err) => failures.push(format!("{}: {err}", file.display())),
+            }
+        }
+        if !failures.is_empty() {
+            return Err(patch_error(format!(
+                "no files were changed.\n{}",
+                failures.join("\n")
+            )));
+        }
+
+        write_all(&planned).await?;
+
+        let (hook_ctx, diagnostics) = match ctx {
+            Some(ctx) => (HookContext::from(ctx), ctx.agent.diagnostics),
+            None => (HookContext::default(), true),
+        };
+        let mut reports = Vec::new();
+        for change in &planned {
+            if change.content.is_none() {
+                continue;
+            }
+            let report = self
+                .effects
+                .after_write(&change.path, &hook_ctx, diagnostics)
+                .await;
+            // Recorded after the hooks, which may reformat the file.
+            if let Some(ctx) = ctx {
+                ctx.file_times.record(ctx.session_id, &change.path);
+            }
+            if let Some(report) = report {
+                reports.push(format!("{}:\n{report}", change.path.display()));
+            }
+        }
+
+        let mut output = format!(
+            "Patch applied to {} file(s):\n{}",
+            files.len(),
+            summary.join("\n")
+        );
+        for report in reports {
+            output.push_str("\n\n");
+            output.push_str(&report);
+        }
+        Ok(output)
+    }
+
+    async fn plan(
+        &self,
+        root: &Path,
+        file: &FilePatch,
+        ctx: Option<&ToolContext>,
+        touched: &mut HashSet<PathBuf>,
+    ) -> std::result::Result<Vec<Planned>, String> {
+        let source = file
+            .old_path
+            .as_deref()
+            .map(|path| resolve(root, path))
+            .transpose()?;
+        let target = file
+            .new_path
+            .as_deref()
+            .map(|path| resolve(root, path))
+            .transpose()?;
+        let mut paths: Vec<&PathBuf> = source.iter().chain(&target).collect();
+        paths.dedup();
+        for path in paths {
+            if !touched.insert(path.clone()) {
+                return Err("file is changed more than once in the patch".to_string());
+            }
+        }
+
+        let before = match &source {
+            Some(path) => {
+                if let Some(ctx) = ctx {
+                    ctx.file_times.check(ctx.session_id, path)?;
+                }
+                fs::read_to_string(path)
+                    .await
+                    .map_err(|err| format!("cannot read {}: {err}", path.display()))?
+            }
+            None => String::new(),
+        };
+        if let Some(path) = &target
+            && source.as_ref() != Some(path)
+            && fs::try_exists(path).await.unwrap_or(false)
+        {
+            return Err(format!("{} already exists", path.display()));
+        }
+        let after = apply_hunks(&before, &file.hunks)?;
+        if target.is_none() && !after.is_empty() {
+            return Err(format!(
+                "deleting the file would drop {} byte(s) the patch does not remove",
+                after.len()
+            ));
+        }
+
+        let mut changes = Vec::new();
+        if let Some(source) = source
+            && target.as_ref() != Some(&source)
+        {
+            changes.push(Planned {
+                path: source,
+                content: None,
+            });
+        }
+        if let Some(target) = target {
+            changes.push(Planned {
+                path: target,
+                content: Some(after),
+            });
+        }
+        Ok(changes)
+    }
+}
+
+#[async_trait]
+impl Tool for ApplyPatchTool {
+    fn name(&self) -> &str {
+        "apply_patch"
+    }
+
+    fn description(&self) -> &str {
+        "Applies a unified diff that may add, delete, rename and modify several files. The first argument is the patch, the optional second is the directory its paths are relative to (defaults to the current directory). Either every file is changed or none is."
+    }
+
+    async fn execute(&self, args: &[String]) -> Result<String> {
+        self.apply(args, None).await
+    }
+
+    async fn execute_with_context(&self, ctx: &ToolContext, args: &[String]) -> Result<String> {
+        self.apply(args, Some(ctx)).await
+    }
+}
+
+fn patch_error(message: impl Into<String>) -> OpenCodeError {
+    OpenCodeError::Patch(message.into())
+}
+
+/// Joins a patch path onto `root`, refusing paths that escape it.
+fn resolve(root: &Path, path: &Path) -> std::result::Result<PathBuf, String> {
+    let escapes = path
+        .components()
+        .any(|part| !matches!(part, Component::Normal(_) | Component::CurDir));
+    if escapes {
+        return Err(format!(
+            "path {} must be relative and stay inside the patch directory",
+            path.display()
+        ));
+    }
+    Ok(root.join(path))
+}
+
+/// Writes every planned change, restoring the previous contents of files
+/// already written when one of them fails.
+async fn write_all(planned: &[Planned]) -> Result<()> {
+    let mut originals = Vec::new();
+    for change in planned {
+        let original = match fs::read(&change.path).await {
+            Ok(bytes) => Some(bytes),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        originals.push(original);
+    }
+
+    for (done, change) in planned.iter().enumerate() {
+        if let Err(err) = write_one(change).await {
+            for (change, original) in planned[..done].iter().zip(&originals).rev() {
+                let restored = match original {
+                    Some(bytes) => fs::write(&change.path, bytes).await,
+                    None => fs::remove_file(&change.path).await,
+                };
+                if let Err(err) = restored {
+                    warn!(path = %change.path.display(), "failed to roll back patch: {err}");
+                }
+            }
+            return Err(patch_error(format!(
+                "failed to write {}, no files were changed: {err}",
+                change.path.display()
+            )));
+        }
+    }
+    Ok(())
+}
+
+async fn write_one(change: &Planned) -> std::io::Result<()> {
+    match &change.content {
+        Some(content) => {
+            if let Some(parent) = change.path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            fs::write(&change.path, content).await
+        }
+        None => fs::remove_file(&change.path).await,
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::tool::bash::kill_group;
+use crate::util::error::Result;
+use portable_pty::{Child, CommandBuilder, MasterPty, PtySize, native_pty_system};
+
+pub const PTY_ROWS: u16 = 40;
+pub const PTY_COLS: u16 = 120;
+/// Output is considered settled once the terminal has been quiet this long.
+const SETTLE_QUIET: Duration = Duration::from_millis(150);
+const SETTLE_MAX: Duration = Duration::from_secs(2);
+
+struct Terminal {
+    parser: vt100::Parser,
+    last_output: Instant,
+}
+
+type Writer = Arc<Mutex<Box<dyn Write + Send>>>;
+
+struct PtyProcess {
+    child: Box<dyn Child + Send + Sync>,
+    /// Locked on its own so a blocked write holds up only this session.
+    writer: Writer,
+    terminal: Arc<Mutex<Terminal>>,
+    _master: Box<dyn MasterPty + Send>,
+}
+
+impl Drop for PtyProcess {
+    fn drop(&mut self) {
+        kill_group(self.child.process_id());
+        let _ = self.child.kill();
+    }
+}
+
+/// Interactive processes attached to pseudo-terminals, addressed by ID. Their
+/// output is fed through a terminal emulator so the agent reads the screen as
+/// a user would see it.
+#[derive(Default)]
+pub struct PtySessions {
+    processes: Mutex<HashMap<u64, PtyProcess>>,
+    next_id: AtomicU64,
+}
+
+impl PtySessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts `command` under `bash` on a new terminal and returns its ID.
+    pub fn start(&self, command: &str, cwd: &Path, env: &HashMap<String, String>) -> Result<u64> {
+        let pair = native_pty_system()
+            .openpty(PtySize {
+                rows: PTY_ROWS,
+                cols: PTY_COLS,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .map_err(pty_error)?;
+        let mut cmd = CommandBuilder::new("bash");
+        cmd.args(["--noprofile", "--norc", "-c", command]);
+        cmd.cwd(cwd);
+        cmd.env_clear();
+        for (key, value) in env {
+            cmd.env(key, value);
+        }
+        cmd.env("TERM", "xterm");
+        let child = pair.slave.spawn_command(cmd).map_err(pty_error)?;
+        drop(pair.slave);
+
+        let mut reader = pair.master.try_clone_reader().map_err(pty_error)?;
+        let writer = pair.master.take_writer().map_err(pty_error)?;
+        let terminal = Arc::new(Mutex::new(Terminal {
+            parser: vt100::Parser::new(PTY_ROWS, PTY_COLS, 0),
+            last_output: Instant::now(),
+        }));
+        let feed = terminal.clone();
+        std::thread::spawn(move || {
+            let mut chunk = [0u8; 4096];
+            while let Ok(read) = reader.read(&mut chunk) {
+                if read == 0 {
+                    break;
+                }
+                let mut terminal = feed.lock().unwrap_or_else(|err| err.into_inner());
+                terminal.parser.process(&chunk[..read]);
+                terminal.last_output = Instant::now();
+            }
+        });
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.lock().insert(
+            id,
+            PtyProcess {
+                child,
+                writer: Arc::new(Mutex::new(writer)),
+                terminal,
+                _master: pair.master,
+            },
+        );
+        Ok(id)
+    }
+
+    /// The process status followed by the visible screen, or `None` for an
+    /// unknown ID.
+    pub fn screen(&self, id: u64) -> Option<String> {
+        let mut processes = self.lock();
+        let process = processes.get_mut(&id)?;
+        let status = match process.child.try_wait() {
+            Ok(None) => format!("PTY session {id} is running."),
+            Ok(Some(status)) if status.signal().is_some() => {
+                format!("PTY session {id} was terminated by a signal.")
+            }
+            Ok(Some(status)) => {
+                format!("PTY session {id} exited with code {}.", status.exit_code())
+            }
+            Err(err) => format!("PTY session {id} status unknown: {err}"),
+        };
+        let terminal = process
+            .terminal
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let contents = terminal.parser.screen().contents();
+        Some(format!("{status}\n{}", contents.trim_end()))
+    }
+
+    /// Writes `keys` to the terminal; `<enter>`, `<ctrl-c>` and similar
+    /// names are translated to their control sequences. The write runs on
+    /// the blocking pool, since it stalls while the process is not reading.
+    pub async fn send(&self, id: u64, keys: &str) -> Result<bool> {
+        let Some(writer) = self.lock().get(&id).map(|process| process.writer.clone()) else {
+            return Ok(false);
+        };
+        let bytes = encode_keys(keys);
+        tokio::task::spawn_blocking(move || {
+            let mut writer = writer.lock().unwrap_or_else(|err| err.into_inner());
+            writer.write_all(&bytes)?;
+            writer.flush()
+        })
+        .await
+        .map_err(std::io::Error::other)??;
+        Ok(true)
+    }
+
+    /// Kills the process group and forgets the session.
+    pub fn terminate(&self, id: u64) -> bool {
+        self.lock().remove(&id).is_some()
+    }
+
+    /// Waits until the terminal has stopped producing output, so a screen
+    /// read right after starting or sending keys shows the response.
+    pub async fn settle(&self, id: u64) {
+        let Some(terminal) = self.lock().get(&id).map(|process| process.terminal.clone()) else {
+            return;
+        };
+        let started = Instant::now();
+        while started.elapsed() < SETTLE_MAX {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let quiet = terminal
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .last_output
+                .elapsed();
+            if quiet >= SETTLE_QUIET {
+                break;
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, PtyProcess>> {
+        self.processes.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+fn pty_error(err: anyhow::Error) -> std::io::Error {
+    std::io::Error::other(format!("{err:#}"))
+}
+
+/// Translates `<name>` key tokens into terminal input; other text is sent
+/// as typed.
+pub fn encode_keys(keys: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut rest = keys;
+    while let Some(start) = rest.find('<') {
+        bytes.extend_from_slice(&rest.as_bytes()[..start]);
+        let token = &rest[start..];
+        let Some(end) = token.find('>') else {
+            rest = token;
+            break;
+        };
+        match key_sequence(&token[1..end]) {
+            Some(sequence) => bytes.extend_from_slice(&sequence),
+            None => bytes.extend_from_slice(&token.as_bytes()[..=end]),
+        }
+        rest = &token[end + 1..];
+    }
+    bytes.extend_from_slice(rest.as_bytes());
+    bytes
+}
+
+fn key_sequence(name: &str) -> Option<Vec<u8>> {
+    let name = name.to_ascii_lowercase();
+    let sequence: &[u8] = match name.as_str() {
+        "enter" | "return" => b"\r",
+        "tab" => b"\t",
+        "esc" | "escape" => b"\x1b",
+        "backspace" => b"\x7f",
+        "space" => b" ",
+        "up" => b"\x1b[A",
+        "down" => b"\x1b[B",
+        "right" => b"\x1b[C",
+        "left" => b"\x1b[D",
+        _ => {
+            let letter = name.strip_prefix("ctrl-")?;
+            let &[byte] = letter.as_bytes() else {
+                return None;
+            };
+            if !byte.is_ascii_lowercase() {
+                return None;
+            }
+            return Some(vec![byte & 0x1f]);
+        }
+    };
+    Some(sequence.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::encode_keys;
+
+    #[test]
+    fn encodes_named_keys() {
+        assert_eq!(encode_keys("ls<enter>"), b"ls\r");
+        assert_eq!(encode_keys("<ctrl-c><UP>x"), b"\x03\x1b[Ax");
+        assert_eq!(encode_keys("a < b <nope> <c"), b"a < b <nope> <c");
+    }
+}
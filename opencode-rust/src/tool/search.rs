@@ -0,0 +1,157 @@
+use std::cmp::Reverse;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::search::{self, SearchOptions};
+use crate::tool::core::Tool;
+use crate::util::error::{OpenCodeError, Result};
+use async_trait::async_trait;
+
+/// Results beyond this many are dropped with a truncation notice.
+pub const MAX_RESULTS: usize = 100;
+/// The search stops after finding this many, so huge result sets are never
+/// collected; the newest of them are shown.
+pub const MAX_MATCHES: usize = 10 * MAX_RESULTS;
+const MAX_LINE_LENGTH: usize = 2000;
+const TRUNCATED_NOTICE: &str =
+    "(Results are truncated. Consider using a more specific path or pattern.)";
+const STOPPED_NOTICE: &str = "(Results are truncated and the search stopped early, so newer matches may be missing. Consider using a more specific path or pattern.)";
+
+pub struct GrepTool;
+
+#[async_trait]
+impl Tool for GrepTool {
+    fn name(&self) -> &str {
+        "grep"
+    }
+
+    fn description(&self) -> &str {
+        "Searches file contents with a regular expression. The first argument is the pattern, the optional second is the directory to search (defaults to the current directory), and any further arguments are globs restricting which files are searched (e.g. \"*.rs\"). Returns path:line:text matches, most recently modified files first."
+    }
+
+    async fn execute(&self, args: &[String]) -> Result<String> {
+        let Some(pattern) = args.first().cloned() else {
+            return Ok("Usage: grep <pattern> [path] [include_glob...]".to_string());
+        };
+        let base = args.get(1).map(PathBuf::from);
+        let root = base.clone().unwrap_or_else(|| PathBuf::from("."));
+        let options = SearchOptions::new()
+            .with_globs(args.iter().skip(2).cloned())
+            .with_limit(Some(MAX_MATCHES))
+            .skip_ignored_folders();
+
+        let mut matches = blocking(move || search::search(&root, &pattern, &options)).await?;
+        if matches.is_empty() {
+            return Ok("No files found".to_string());
+        }
+        let stopped = matches.len() >= MAX_MATCHES;
+        matches.sort_by_key(|found| Reverse(found.modified));
+
+        let truncated = matches.len() > MAX_RESULTS;
+        let mut lines: Vec<String> = matches
+            .iter()
+            .take(MAX_RESULTS)
+            .map(|found| {
+                format!(
+                    "{}:{}:{}",
+                    display_path(base.as_deref(), &found.path),
+                    found.line_number,
+                    truncate_line(&found.line)
+                )
+            })
+            .collect();
+        if truncated {
+            lines.push(String::new());
+            lines.push(notice(stopped).to_string());
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+pub struct GlobTool;
+
+#[async_trait]
+impl Tool for GlobTool {
+    fn name(&self) -> &str {
+        "glob"
+    }
+
+    fn description(&self) -> &str {
+        "Finds files matching a glob pattern such as \"**/*.rs\" or \"src/**/mod.rs\". The first argument is the pattern and the optional second is the directory to search (defaults to the current directory). Returns matching paths, most recently modified first."
+    }
+
+    async fn execute(&self, args: &[String]) -> Result<String> {
+        let Some(pattern) = args.first().cloned() else {
+            return Ok("Usage: glob <pattern> [path]".to_string());
+        };
+        let base = args.get(1).map(PathBuf::from);
+        let root = base.clone().unwrap_or_else(|| PathBuf::from("."));
+        let options = SearchOptions::new()
+            .with_globs([pattern])
+            .with_limit(Some(MAX_MATCHES))
+            .skip_ignored_folders();
+
+        let walk_root = root.clone();
+        let files = blocking(move || search::files(&walk_root, &options)).await?;
+        if files.is_empty() {
+            return Ok("No files found".to_string());
+        }
+        let stopped = files.len() >= MAX_MATCHES;
+        let mut files: Vec<(PathBuf, Option<SystemTime>)> = files
+            .into_iter()
+            .map(|file| {
+                let modified = std::fs::metadata(root.join(&file))
+                    .and_then(|meta| meta.modified())
+                    .ok();
+                (file, modified)
+            })
+            .collect();
+        files.sort_by_key(|(_, modified)| Reverse(*modified));
+
+        let truncated = files.len() > MAX_RESULTS;
+        let mut lines: Vec<String> = files
+            .iter()
+            .take(MAX_RESULTS)
+            .map(|(file, _)| display_path(base.as_deref(), file))
+            .collect();
+        if truncated {
+            lines.push(String::new());
+            lines.push(notice(stopped).to_string());
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Runs a search off the async runtime; the walker does blocking I/O.
+async fn blocking<T, F>(search: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> anyhow::Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(search)
+        .await
+        .map_err(|err| OpenCodeError::Search(err.to_string()))?
+        .map_err(|err| OpenCodeError::Search(format!("{err:#}")))
+}
+
+fn notice(stopped: bool) -> &'static str {
+    if stopped {
+        STOPPED_NOTICE
+    } else {
+        TRUNCATED_NOTICE
+    }
+}
+
+fn display_path(base: Option<&Path>, path: &Path) -> String {
+    match base {
+        Some(base) => base.join(path).display().to_string(),
+        None => path.display().to_string(),
+    }
+}
+
+fn truncate_line(line: &str) -> String {
+    match line.char_indices().nth(MAX_LINE_LENGTH) {
+        Some((idx, _)) => format!("{}...", &line[..idx]),
+        None => line.to_string(),
+    }
+}
@@ -0,0 +1,107 @@
+use std::sync::{Arc, Weak};
+
+use crate::agent::registry::AgentRegistry;
+use crate::agent::spec::{AgentMode, AgentSpec};
+use crate::session::runtime::SessionRuntime;
+use crate::tool::core::{Tool, ToolContext};
+use crate::util::error::{OpenCodeError, Result};
+use async_trait::async_trait;
+
+const USAGE: &str = "Usage: task <agent> <prompt>";
+
+/// Delegates a prompt to a subagent running in a child session and returns
+/// the child's summary. The runtime is held weakly because it owns the tool
+/// list this tool is part of.
+pub struct TaskTool {
+    runtime: Weak<SessionRuntime>,
+    registry: Arc<AgentRegistry>,
+    description: String,
+}
+
+impl TaskTool {
+    pub fn new(runtime: Weak<SessionRuntime>, registry: Arc<AgentRegistry>) -> Self {
+        let description = describe(&subagents(&registry));
+        Self {
+            runtime,
+            registry,
+            description,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for TaskTool {
+    fn name(&self) -> &str {
+        "task"
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    async fn execute(&self, args: &[String]) -> Result<String> {
+        self.execute_with_context(&ToolContext::default(), args)
+            .await
+    }
+
+    async fn execute_with_context(&self, ctx: &ToolContext, args: &[String]) -> Result<String> {
+        let [agent, prompt] = args else {
+            return Ok(USAGE.to_string());
+        };
+        let prompt = prompt.trim();
+        if prompt.is_empty() {
+            return Ok(USAGE.to_string());
+        }
+        let Some(spec) = self
+            .registry
+            .spec(agent)
+            .filter(|spec| spec.mode != AgentMode::Primary)
+        else {
+            let available: Vec<String> = subagents(&self.registry)
+                .iter()
+                .map(|spec| spec.name.clone())
+                .collect();
+            return Err(task_error(format!(
+                "unknown agent type {agent}; available subagents: {}",
+                available.join(", ")
+            )));
+        };
+        let runtime = self
+            .runtime
+            .upgrade()
+            .ok_or_else(|| task_error("the session runtime has shut down"))?;
+        let outcome = runtime
+            .spawn_child(spec, prompt.to_string(), &ctx.agent)
+            .await
+            .map_err(|err| task_error(format!("{agent} subagent failed: {err:#}")))?;
+        Ok(outcome.summary)
+    }
+}
+
+/// Subagent-capable agents, by name.
+fn subagents(registry: &AgentRegistry) -> Vec<Arc<AgentSpec>> {
+    let mut agents = registry.agents_in_mode(AgentMode::Subagent);
+    agents.sort_by(|a, b| a.name.cmp(&b.name));
+    agents
+}
+
+fn describe(agents: &[Arc<AgentSpec>]) -> String {
+    let list: Vec<String> = agents
+        .iter()
+        .map(|spec| {
+            let description = spec
+                .description
+                .as_deref()
+                .unwrap_or("This subagent should only be called manually by the user.");
+            format!("- {}: {description}", spec.name)
+        })
+        .collect();
+    format!(
+        "Launches a subagent to handle a focused, multi-step task autonomously in its own session. Takes the agent name and a detailed prompt; the agent's final summary is returned. Each invocation is stateless, so the prompt must say exactly what to do and what to report back.\n\nAvailable agents:\n{}",
+        list.join("\n")
+    )
+}
+
+fn task_error(message: impl Into<String>) -> OpenCodeError {
+    OpenCodeError::Task(message.into())
+}
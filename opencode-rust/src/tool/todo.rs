@@ -0,0 +1,150 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::session::runtime::AgentEvent;
+use crate::session::todo::{self, Todo, TodoLists, TodoPriority, TodoStatus};
+use crate::tool::core::{Tool, ToolContext};
+use crate::util::error::{OpenCodeError, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+const WRITE_USAGE: &str = "Usage: todowrite <json array of {content, status, priority, id}>";
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum WriteInput {
+    List(Vec<TodoInput>),
+    Object { todos: Vec<TodoInput> },
+}
+
+#[derive(Debug, Deserialize)]
+struct TodoInput {
+    #[serde(default)]
+    id: Option<String>,
+    content: String,
+    #[serde(default)]
+    status: TodoStatus,
+    #[serde(default)]
+    priority: TodoPriority,
+}
+
+/// Replaces the session's todo list and announces the new list with an
+/// `AgentEvent::TodoUpdated`.
+pub struct TodoWriteTool {
+    todos: Arc<TodoLists>,
+    events: Option<mpsc::Sender<AgentEvent>>,
+}
+
+impl TodoWriteTool {
+    pub fn new(todos: Arc<TodoLists>) -> Self {
+        Self {
+            todos,
+            events: None,
+        }
+    }
+
+    pub fn with_events(mut self, events: mpsc::Sender<AgentEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+}
+
+#[async_trait]
+impl Tool for TodoWriteTool {
+    fn name(&self) -> &str {
+        "todowrite"
+    }
+
+    fn description(&self) -> &str {
+        "Replaces the session's todo list. Takes one JSON array argument whose items have content, status (pending, in_progress or completed; default pending), priority (high, medium or low; default medium) and an optional id. Use it to plan multi-step work and keep one item in_progress at a time, marking items completed as soon as they are done."
+    }
+
+    async fn execute(&self, args: &[String]) -> Result<String> {
+        self.execute_with_context(&ToolContext::default(), args)
+            .await
+    }
+
+    async fn execute_with_context(&self, ctx: &ToolContext, args: &[String]) -> Result<String> {
+        let [input] = args else {
+            return Ok(WRITE_USAGE.to_string());
+        };
+        let input: WriteInput = serde_json::from_str(input)
+            .map_err(|err| todo_error(format!("invalid todo list: {err}")))?;
+        let todos = match input {
+            WriteInput::List(todos) | WriteInput::Object { todos } => todos,
+        };
+
+        let mut ids = HashSet::new();
+        let mut list = Vec::with_capacity(todos.len());
+        for (index, input) in todos.into_iter().enumerate() {
+            let content = input.content.trim();
+            if content.is_empty() {
+                return Err(todo_error(format!("todo {} has no content", index + 1)));
+            }
+            let id = input
+                .id
+                .filter(|id| !id.trim().is_empty())
+                .unwrap_or_else(|| (index + 1).to_string());
+            if !ids.insert(id.clone()) {
+                return Err(todo_error(format!("duplicate todo id {id}")));
+            }
+            list.push(
+                Todo::new(id, content)
+                    .with_status(input.status)
+                    .with_priority(input.priority),
+            );
+        }
+
+        self.todos.set(ctx.session_id, list.clone());
+        let output = todo::render(&list);
+        if let Some(events) = &self.events {
+            let _ = events
+                .send(AgentEvent::TodoUpdated {
+                    session_id: ctx.session_id,
+                    agent: ctx.agent.name.clone(),
+                    todos: list,
+                })
+                .await;
+        }
+        Ok(output)
+    }
+}
+
+/// Shows the session's current todo list.
+pub struct TodoReadTool {
+    todos: Arc<TodoLists>,
+}
+
+impl TodoReadTool {
+    pub fn new(todos: Arc<TodoLists>) -> Self {
+        Self { todos }
+    }
+}
+
+#[async_trait]
+impl Tool for TodoReadTool {
+    fn name(&self) -> &str {
+        "todoread"
+    }
+
+    fn description(&self) -> &str {
+        "Shows the session's todo list with the status and priority of each item. Takes no arguments."
+    }
+
+    async fn execute(&self, args: &[String]) -> Result<String> {
+        self.execute_with_context(&ToolContext::default(), args)
+            .await
+    }
+
+    async fn execute_with_context(&self, ctx: &ToolContext, args: &[String]) -> Result<String> {
+        if !args.is_empty() {
+            return Ok("Usage: todoread".to_string());
+        }
+        Ok(todo::render(&self.todos.get(ctx.session_id)))
+    }
+}
+
+fn todo_error(message: impl Into<String>) -> OpenCodeError {
+    OpenCodeError::Todo(message.into())
+}
@@ -1,8 +1,50 @@
+use std::time::Duration;
+
 use crate::tool::core::Tool;
-use crate::util::error::Result;
+use crate::util::error::{OpenCodeError, Result};
+use crate::util::html;
 use async_trait::async_trait;
-use scraper::{Html, Selector};
+use reqwest::header::{ACCEPT, CONTENT_TYPE, USER_AGENT};
+
+pub const MAX_RESPONSE_BYTES: usize = 5 * 1024 * 1024;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_TIMEOUT: Duration = Duration::from_secs(120);
+const USAGE: &str = "Usage: web_fetch <url> [markdown|text|html] [timeout_secs]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Markdown,
+    Text,
+    Html,
+}
 
+impl Format {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "markdown" | "md" => Some(Self::Markdown),
+            "text" => Some(Self::Text),
+            "html" => Some(Self::Html),
+            _ => None,
+        }
+    }
+
+    /// Prefers the requested representation while accepting anything.
+    fn accept(self) -> &'static str {
+        match self {
+            Self::Markdown => {
+                "text/markdown;q=1.0, text/x-markdown;q=0.9, text/plain;q=0.8, text/html;q=0.7, */*;q=0.1"
+            }
+            Self::Text => "text/plain;q=1.0, text/markdown;q=0.9, text/html;q=0.8, */*;q=0.1",
+            Self::Html => {
+                "text/html;q=1.0, application/xhtml+xml;q=0.9, text/plain;q=0.8, text/markdown;q=0.7, */*;q=0.1"
+            }
+        }
+    }
+}
+
+/// Fetches an http(s) URL and returns its content as markdown (the
+/// default), plain text or raw HTML. Responses are bounded by a timeout and
+/// `MAX_RESPONSE_BYTES`, and only textual content types are accepted.
 pub struct WebFetchTool;
 
 #[async_trait]
@@ -12,19 +54,126 @@ impl Tool for WebFetchTool {
     }
 
     fn description(&self) -> &str {
-        "Fetches the content of a URL and returns the text content of the body."
+        "Fetches an http(s) URL and returns its content. The optional second argument is the format: markdown (default, HTML converted with headings, links, code blocks and tables kept), text, or html for the raw page. The optional third is a timeout in seconds (default 30, 1 to 120). Responses over 5MB and binary content are rejected."
     }
 
     async fn execute(&self, args: &[String]) -> Result<String> {
-        if args.len() != 1 {
-            return Ok("Usage: web_fetch <url>".to_string());
+        let (url, format, timeout) = match args {
+            [url] => (url, Some(Format::Markdown), None),
+            [url, format] => (url, Format::parse(format), None),
+            [url, format, timeout] => (url, Format::parse(format), Some(timeout)),
+            _ => return Ok(USAGE.to_string()),
+        };
+        let Some(format) = format else {
+            return Ok(USAGE.to_string());
+        };
+        let timeout = match timeout.map(|secs| secs.parse::<u64>()) {
+            None => DEFAULT_TIMEOUT,
+            Some(Ok(secs)) if secs > 0 => Duration::from_secs(secs).min(MAX_TIMEOUT),
+            Some(_) => return Ok(USAGE.to_string()),
+        };
+
+        let url = reqwest::Url::parse(url)
+            .map_err(|err| fetch_error(format!("invalid URL {url}: {err}")))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(fetch_error("URL must start with http:// or https://"));
+        }
+
+        let client = reqwest::Client::builder().timeout(timeout).build()?;
+        let mut response = client
+            .get(url)
+            .header(USER_AGENT, concat!("opencode/", env!("CARGO_PKG_VERSION")))
+            .header(ACCEPT, format.accept())
+            .send()
+            .await
+            .map_err(|err| request_error(err, timeout))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(fetch_error(format!("request failed with status {status}")));
+        }
+        if response
+            .content_length()
+            .is_some_and(|length| length > MAX_RESPONSE_BYTES as u64)
+        {
+            return Err(too_large());
         }
-        let url = &args[0];
-        let response = reqwest::get(url).await?.text().await?;
-        let document = Html::parse_document(&response);
-        let selector = Selector::parse("body").unwrap();
-        let body = document.select(&selector).next().unwrap();
-        let text = body.text().collect::<Vec<_>>().join("\n");
-        Ok(text)
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if !is_textual(&content_type) {
+            return Err(fetch_error(format!(
+                "unsupported content type {content_type}; only text content can be fetched"
+            )));
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|err| request_error(err, timeout))?
+        {
+            body.extend_from_slice(&chunk);
+            if body.len() > MAX_RESPONSE_BYTES {
+                return Err(too_large());
+            }
+        }
+        let content = String::from_utf8_lossy(&body);
+
+        let is_html =
+            content_type.contains("html") || (content_type.is_empty() && looks_like_html(&content));
+        Ok(match format {
+            Format::Markdown if is_html => html::to_markdown(&content),
+            Format::Text if is_html => html::to_text(&content),
+            _ => content.into_owned(),
+        })
     }
 }
+
+fn fetch_error(message: impl Into<String>) -> OpenCodeError {
+    OpenCodeError::WebFetch(message.into())
+}
+
+fn too_large() -> OpenCodeError {
+    fetch_error(format!(
+        "response too large (exceeds {} MB limit)",
+        MAX_RESPONSE_BYTES / (1024 * 1024)
+    ))
+}
+
+fn request_error(err: reqwest::Error, timeout: Duration) -> OpenCodeError {
+    if err.is_timeout() {
+        fetch_error(format!("request timed out after {timeout:?}"))
+    } else {
+        err.into()
+    }
+}
+
+/// Text, markup and structured data are accepted; a missing content type is
+/// given the benefit of the doubt.
+fn is_textual(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    mime.is_empty()
+        || mime.starts_with("text/")
+        || mime.ends_with("+xml")
+        || mime.ends_with("+json")
+        || matches!(
+            mime,
+            "application/json"
+                | "application/xml"
+                | "application/javascript"
+                | "application/x-javascript"
+                | "application/ecmascript"
+                | "application/x-yaml"
+                | "application/yaml"
+                | "application/toml"
+        )
+}
+
+fn looks_like_html(content: &str) -> bool {
+    let start = content.trim_start().as_bytes();
+    let start = start[..start.len().min(16)].to_ascii_lowercase();
+    start.starts_with(b"<!doctype html") || start.starts_with(b"<html")
+}
@@ -0,0 +1,402 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::tool::core::Tool;
+use crate::util::config::{Info, ProviderOptions, SearchBackendKind, SearchConfig, Timeout};
+use crate::util::error::{OpenCodeError, Result};
+use crate::util::html;
+use async_trait::async_trait;
+use reqwest::header::{ACCEPT, AUTHORIZATION, USER_AGENT};
+use serde_json::Value as JsonValue;
+
+pub const DEFAULT_RESULTS: usize = 8;
+pub const MAX_RESULTS: usize = 20;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_SNIPPET_CHARS: usize = 300;
+const USAGE: &str = "Usage: web_search <query> [limit]";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    pub snippet: String,
+}
+
+impl SearchResult {
+    pub fn new(
+        title: impl Into<String>,
+        url: impl Into<String>,
+        snippet: impl Into<String>,
+    ) -> Self {
+        Self {
+            title: title.into(),
+            url: url.into(),
+            snippet: snippet.into(),
+        }
+    }
+}
+
+/// A search engine the `web_search` tool can query. Results are returned
+/// best match first.
+#[async_trait]
+pub trait SearchBackend: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>>;
+}
+
+/// Builds the backend selected by a `search` config section.
+pub fn backend_from_config(config: &SearchConfig) -> Result<Arc<dyn SearchBackend>> {
+    let options = config.options.clone().unwrap_or_default();
+    Ok(match config.backend {
+        SearchBackendKind::Json => Arc::new(JsonSearchBackend::from_options(&options)?),
+        SearchBackendKind::Searxng => Arc::new(SearxngBackend::from_options(&options)?),
+    })
+}
+
+/// A generic JSON search API. The request and response layout come from the
+/// extra keys of its options:
+///
+/// - `queryParam` (default `q`) and `limitParam` (default `count`, `null` to
+///   omit) name the query string parameters;
+/// - `params` is an object of additional fixed query parameters;
+/// - `resultsPath` (default `results`) is the dot-separated path to the
+///   result array, and `titleField`, `urlField` and `snippetField` (default
+///   `title`, `url` and `snippet`) name the fields of each result;
+/// - `apiKeyHeader` sends `apiKey` as-is in that header instead of as an
+///   `Authorization: Bearer` token.
+pub struct JsonSearchBackend {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    api_key_header: Option<String>,
+    query_param: String,
+    limit_param: Option<String>,
+    params: Vec<(String, String)>,
+    results_path: String,
+    title_field: String,
+    url_field: String,
+    snippet_field: String,
+}
+
+impl JsonSearchBackend {
+    pub fn from_options(options: &ProviderOptions) -> Result<Self> {
+        let base_url = required_base_url(options, "json")?;
+        let extra = &options.extra;
+        let string = |key: &str, default: &str| match extra.get(key) {
+            None => Ok(default.to_string()),
+            Some(JsonValue::String(value)) if !value.is_empty() => Ok(value.clone()),
+            Some(_) => Err(config_error(format!(
+                "search option {key} must be a non-empty string"
+            ))),
+        };
+        let limit_param = match extra.get("limitParam") {
+            Some(JsonValue::Null) => None,
+            _ => Some(string("limitParam", "count")?),
+        };
+        let api_key_header = match extra.get("apiKeyHeader") {
+            None => None,
+            Some(_) => Some(string("apiKeyHeader", "")?),
+        };
+        let params = match extra.get("params") {
+            None => Vec::new(),
+            Some(JsonValue::Object(params)) => params
+                .iter()
+                .map(|(key, value)| {
+                    let value = match value {
+                        JsonValue::String(value) => value.clone(),
+                        other => other.to_string(),
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+            Some(_) => return Err(config_error("search option params must be an object")),
+        };
+        Ok(Self {
+            client: client(options)?,
+            base_url,
+            api_key: options.api_key.clone(),
+            api_key_header,
+            query_param: string("queryParam", "q")?,
+            limit_param,
+            params,
+            results_path: string("resultsPath", "results")?,
+            title_field: string("titleField", "title")?,
+            url_field: string("urlField", "url")?,
+            snippet_field: string("snippetField", "snippet")?,
+        })
+    }
+}
+
+#[async_trait]
+impl SearchBackend for JsonSearchBackend {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
+        let mut params = self.params.clone();
+        params.push((self.query_param.clone(), query.to_string()));
+        if let Some(limit_param) = &self.limit_param {
+            params.push((limit_param.clone(), limit.to_string()));
+        }
+        let mut request = self.client.get(&self.base_url).query(&params);
+        if let Some(api_key) = &self.api_key {
+            request = match &self.api_key_header {
+                Some(header) => request.header(header.as_str(), api_key),
+                None => request.header(AUTHORIZATION, format!("Bearer {api_key}")),
+            };
+        }
+        let body = send(request).await?;
+        let results = self
+            .results_path
+            .split('.')
+            .try_fold(&body, |value, key| value.get(key))
+            .and_then(JsonValue::as_array)
+            .ok_or_else(|| {
+                search_error(format!(
+                    "response has no result array at {}",
+                    self.results_path
+                ))
+            })?;
+        Ok(results
+            .iter()
+            .filter_map(|item| {
+                let field = |name: &str| item.get(name).and_then(JsonValue::as_str);
+                Some(SearchResult::new(
+                    field(&self.title_field).unwrap_or_default(),
+                    field(&self.url_field)?,
+                    field(&self.snippet_field).unwrap_or_default(),
+                ))
+            })
+            .collect())
+    }
+}
+
+/// A SearxNG instance, or any endpoint speaking its `/search?format=json`
+/// API. The JSON output format must be enabled on the instance.
+pub struct SearxngBackend {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl SearxngBackend {
+    pub fn from_options(options: &ProviderOptions) -> Result<Self> {
+        Ok(Self {
+            client: client(options)?,
+            base_url: required_base_url(options, "searxng")?,
+        })
+    }
+}
+
+#[async_trait]
+impl SearchBackend for SearxngBackend {
+    fn name(&self) -> &str {
+        "searxng"
+    }
+
+    async fn search(&self, query: &str, _limit: usize) -> Result<Vec<SearchResult>> {
+        let url = format!("{}/search", self.base_url.trim_end_matches('/'));
+        let request = self
+            .client
+            .get(url)
+            .query(&[("q", query), ("format", "json")]);
+        let body = send(request).await?;
+        let results = body
+            .get("results")
+            .and_then(JsonValue::as_array)
+            .ok_or_else(|| search_error("response has no results array"))?;
+        Ok(results
+            .iter()
+            .filter_map(|item| {
+                let field = |name: &str| item.get(name).and_then(JsonValue::as_str);
+                Some(SearchResult::new(
+                    field("title").unwrap_or_default(),
+                    field("url")?,
+                    field("content").unwrap_or_default(),
+                ))
+            })
+            .collect())
+    }
+}
+
+/// Returns canned results and remembers the queries it was asked.
+#[derive(Default)]
+pub struct MockSearchBackend {
+    results: Vec<SearchResult>,
+    queries: Mutex<Vec<String>>,
+}
+
+impl MockSearchBackend {
+    pub fn new(results: Vec<SearchResult>) -> Self {
+        Self {
+            results,
+            queries: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn queries(&self) -> Vec<String> {
+        self.queries
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+}
+
+#[async_trait]
+impl SearchBackend for MockSearchBackend {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
+        self.queries
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(query.to_string());
+        Ok(self.results.iter().take(limit).cloned().collect())
+    }
+}
+
+/// Searches the web through the configured backend and lists the results
+/// as a ranked list of titles, URLs and snippets.
+pub struct WebSearchTool {
+    backend: Arc<dyn SearchBackend>,
+}
+
+impl WebSearchTool {
+    pub fn new(backend: Arc<dyn SearchBackend>) -> Self {
+        Self { backend }
+    }
+
+    /// The tool for the `search` config section, or `None` when search is
+    /// not configured.
+    pub fn from_info(info: &Info) -> Result<Option<Self>> {
+        info.search
+            .as_ref()
+            .map(|config| backend_from_config(config).map(Self::new))
+            .transpose()
+    }
+}
+
+#[async_trait]
+impl Tool for WebSearchTool {
+    fn name(&self) -> &str {
+        "web_search"
+    }
+
+    fn description(&self) -> &str {
+        "Searches the web and returns a ranked list of results with title, URL and snippet. The optional second argument is the number of results (default 8, max 20). Use web_fetch to read a result."
+    }
+
+    async fn execute(&self, args: &[String]) -> Result<String> {
+        let (query, limit) = match args {
+            [query] => (query.trim(), Some(DEFAULT_RESULTS)),
+            [query, limit] => (query.trim(), limit.parse::<usize>().ok()),
+            _ => return Ok(USAGE.to_string()),
+        };
+        let Some(limit) = limit.filter(|limit| *limit > 0) else {
+            return Ok(USAGE.to_string());
+        };
+        if query.is_empty() {
+            return Ok(USAGE.to_string());
+        }
+        let limit = limit.min(MAX_RESULTS);
+
+        let mut seen = HashSet::new();
+        let results: Vec<SearchResult> = self
+            .backend
+            .search(query, limit)
+            .await?
+            .into_iter()
+            .filter(|result| seen.insert(result.url.clone()))
+            .take(limit)
+            .collect();
+        if results.is_empty() {
+            return Ok(format!("No results found for \"{query}\"."));
+        }
+
+        let mut out = format!("Search results for \"{query}\":");
+        for (rank, result) in results.iter().enumerate() {
+            let title = clean(&result.title);
+            let title = if title.is_empty() {
+                result.url.as_str()
+            } else {
+                &title
+            };
+            out.push_str(&format!("\n\n{}. {title}\n   {}", rank + 1, result.url));
+            let snippet = clean(&result.snippet);
+            if !snippet.is_empty() {
+                out.push_str(&format!("\n   {}", truncate(&snippet)));
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Strips markup some engines put in titles and snippets and collapses
+/// whitespace.
+fn clean(text: &str) -> String {
+    let text = if text.contains('<') {
+        html::to_text(text)
+    } else {
+        text.to_string()
+    };
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn truncate(snippet: &str) -> String {
+    match snippet.char_indices().nth(MAX_SNIPPET_CHARS) {
+        Some((end, _)) => format!("{}...", snippet[..end].trim_end()),
+        None => snippet.to_string(),
+    }
+}
+
+fn client(options: &ProviderOptions) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder();
+    match options.timeout {
+        Some(Timeout::Millis(ms)) => builder = builder.timeout(Duration::from_millis(ms)),
+        Some(Timeout::Disabled) => {}
+        None => builder = builder.timeout(DEFAULT_TIMEOUT),
+    }
+    Ok(builder.build()?)
+}
+
+fn required_base_url(options: &ProviderOptions, backend: &str) -> Result<String> {
+    options
+        .base_url
+        .clone()
+        .ok_or_else(|| config_error(format!("the {backend} search backend requires baseURL")))
+}
+
+async fn send(request: reqwest::RequestBuilder) -> Result<JsonValue> {
+    let response = request
+        .header(USER_AGENT, concat!("opencode/", env!("CARGO_PKG_VERSION")))
+        .header(ACCEPT, "application/json")
+        .send()
+        .await
+        .map_err(|err| {
+            if err.is_timeout() {
+                search_error("request timed out")
+            } else {
+                err.into()
+            }
+        })?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(search_error(format!("request failed with status {status}")));
+    }
+    response
+        .json()
+        .await
+        .map_err(|err| search_error(format!("invalid JSON response: {err}")))
+}
+
+fn search_error(message: impl Into<String>) -> OpenCodeError {
+    OpenCodeError::WebSearch(message.into())
+}
+
+fn config_error(message: impl Into<String>) -> OpenCodeError {
+    OpenCodeError::Config(message.into())
+}
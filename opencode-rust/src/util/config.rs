@@ -1,13 +1,16 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result, bail};
 use serde::de::{DeserializeOwned, Error as DeError};
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::{self, Map as JsonMap, Number, Value as JsonValue};
+use tracing::warn;
 use validator::{Validate, ValidationError, ValidationErrors, ValidationErrorsKind};
 
+use crate::util::paths;
+
 #[derive(Debug, Clone, Default, Deserialize)]
 pub struct Info {
     #[serde(rename = "$schema")]
@@ -76,6 +79,9 @@ pub struct Info {
     #[serde(default)]
     pub tools: Option<HashMap<String, bool>>,
 
+    #[serde(default)]
+    pub search: Option<SearchConfig>,
+
     #[serde(default)]
     pub experimental: Option<ExperimentalConfig>,
 }
@@ -116,23 +122,18 @@ impl Info {
         overwrite_if_some(&mut self.username, other.username);
         overwrite_if_some(&mut self.layout, other.layout);
         overwrite_if_some(&mut self.permission, other.permission);
+        overwrite_if_some(&mut self.search, other.search);
         overwrite_if_some(&mut self.experimental, other.experimental);
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Validate)]
+#[derive(Debug, Clone, Deserialize, Validate, Default)]
 pub struct WatcherSettings {
     #[serde(default)]
     #[validate(custom(function = "validate_glob_patterns"))]
     pub ignore: Vec<String>,
 }
 
-impl Default for WatcherSettings {
-    fn default() -> Self {
-        Self { ignore: Vec::new() }
-    }
-}
-
 #[derive(Debug, Clone, Default, Deserialize, Validate)]
 pub struct Keybinds {
     #[serde(default)]
@@ -355,6 +356,18 @@ pub struct ProviderOptions {
     #[validate(custom(function = "validate_timeout_option"))]
     pub timeout: Option<Timeout>,
 
+    /// Completions started per minute, shared by every agent on the
+    /// provider.
+    #[serde(rename = "requestsPerMinute", default)]
+    #[validate(range(min = 1))]
+    pub requests_per_minute: Option<u32>,
+
+    /// Estimated prompt tokens sent per minute, shared by every agent on the
+    /// provider.
+    #[serde(rename = "tokensPerMinute", default)]
+    #[validate(range(min = 1))]
+    pub tokens_per_minute: Option<u32>,
+
     #[serde(flatten)]
     pub extra: HashMap<String, JsonValue>,
 }
@@ -388,12 +401,12 @@ where
 }
 
 fn validate_timeout_option(value: &Timeout) -> std::result::Result<(), ValidationError> {
-    if let Timeout::Millis(ms) = value {
-        if *ms == 0 {
-            let mut error = ValidationError::new("timeout");
-            error.message = Some("timeout must be greater than zero".into());
-            return Err(error);
-        }
+    if let Timeout::Millis(ms) = value
+        && *ms == 0
+    {
+        let mut error = ValidationError::new("timeout");
+        error.message = Some("timeout must be greater than zero".into());
+        return Err(error);
     }
     Ok(())
 }
@@ -524,6 +537,26 @@ pub struct LspServerConfig {
     pub initialization: Option<HashMap<String, JsonValue>>,
 }
 
+/// Settings for the `web_search` tool. `options` takes the same `apiKey`,
+/// `baseURL` and `timeout` keys as a provider; the generic JSON backend reads
+/// its request and response layout from the remaining keys.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchConfig {
+    #[serde(default)]
+    pub backend: SearchBackendKind,
+
+    #[serde(default)]
+    pub options: Option<ProviderOptions>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchBackendKind {
+    #[default]
+    Json,
+    Searxng,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct ExperimentalConfig {
     #[serde(default)]
@@ -533,7 +566,7 @@ pub struct ExperimentalConfig {
     pub disable_paste_summary: Option<bool>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct HookConfig {
     #[serde(default, rename = "file_edited")]
     pub file_edited: Option<HashMap<String, Vec<HookCommand>>>,
@@ -580,6 +613,110 @@ pub async fn load_info_from_path(path: &Path) -> Result<Info> {
     parse_info(&data)
 }
 
+#[derive(Debug, Default, Deserialize)]
+struct CommandFrontMatter {
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    agent: Option<String>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    subtask: Option<bool>,
+}
+
+/// Parses a markdown command file: front matter carries the metadata and the
+/// body becomes the template.
+pub fn parse_markdown_command(input: &str) -> Result<Command> {
+    let parsed = parse_front_matter::<Option<CommandFrontMatter>>(input)?;
+    let meta = parsed.data.unwrap_or_default();
+    let command = Command {
+        template: parsed.content,
+        description: meta.description,
+        agent: meta.agent,
+        model: meta.model,
+        subtask: meta.subtask,
+    };
+    command.validate()?;
+    Ok(command)
+}
+
+/// Loads every `*.md` file below `dir` as a command named after its path
+/// relative to `dir` without the extension (`git/commit.md` -> `git/commit`).
+/// A missing directory yields no commands, and files that cannot be read or
+/// parsed are logged and skipped.
+pub async fn load_markdown_commands(dir: &Path) -> Result<HashMap<String, Command>> {
+    let mut commands = HashMap::new();
+    if !dir.is_dir() {
+        return Ok(commands);
+    }
+    let mut files: Vec<_> = walkdir::WalkDir::new(dir)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry.file_type().is_file()
+                && entry.path().extension().and_then(|ext| ext.to_str()) == Some("md")
+        })
+        .map(|entry| entry.into_path())
+        .collect();
+    files.sort();
+
+    for path in files {
+        let Ok(relative) = path
+            .with_extension("")
+            .strip_prefix(dir)
+            .map(Path::to_path_buf)
+        else {
+            continue;
+        };
+        let name = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let command = match tokio::fs::read_to_string(&path).await {
+            Ok(data) => parse_markdown_command(&data),
+            Err(err) => Err(err.into()),
+        };
+        match command {
+            Ok(command) => {
+                commands.insert(name, command);
+            }
+            Err(err) => {
+                warn!(path = %path.display(), "skipping invalid command file: {err:#}");
+            }
+        }
+    }
+    Ok(commands)
+}
+
+/// Directories searched for markdown commands, lowest precedence first: the
+/// global config directory, then the project's `.opencode` directory.
+pub fn command_directories(project_root: &Path) -> Vec<PathBuf> {
+    vec![
+        paths::config_dir().join("command"),
+        project_root.join(".opencode").join("command"),
+    ]
+}
+
+/// Merges markdown commands from `directories` into `info.command`. Later
+/// directories override earlier ones, and markdown files override commands
+/// of the same name from JSON config.
+pub async fn load_commands(info: &mut Info, directories: &[PathBuf]) -> Result<()> {
+    for dir in directories {
+        let commands = load_markdown_commands(dir).await?;
+        if commands.is_empty() {
+            continue;
+        }
+        info.merge(Info {
+            command: Some(commands),
+            ..Default::default()
+        });
+    }
+    Ok(())
+}
+
 pub fn parse_front_matter<T>(input: &str) -> Result<FrontMatter<T>>
 where
     T: DeserializeOwned,
@@ -659,11 +796,9 @@ fn strip_json_comments(input: &str) -> String {
         }
 
         if in_multi_comment {
-            if ch == '*' {
-                if matches!(chars.peek(), Some('/')) {
-                    chars.next();
-                    in_multi_comment = false;
-                }
+            if ch == '*' && matches!(chars.peek(), Some('/')) {
+                chars.next();
+                in_multi_comment = false;
             }
             if ch == '\n' || ch == '\r' {
                 output.push(ch);
@@ -900,10 +1035,11 @@ fn parse_scalar(value: &str) -> JsonValue {
         return JsonValue::Null;
     }
 
-    if trimmed.starts_with('"') && trimmed.ends_with('"') {
-        if let Ok(parsed) = serde_json::from_str::<String>(trimmed) {
-            return JsonValue::String(parsed);
-        }
+    if trimmed.starts_with('"')
+        && trimmed.ends_with('"')
+        && let Ok(parsed) = serde_json::from_str::<String>(trimmed)
+    {
+        return JsonValue::String(parsed);
     }
 
     let apostrophe = '\u{27}';
@@ -925,20 +1061,21 @@ fn parse_scalar(value: &str) -> JsonValue {
         return parse_array_literal(trimmed);
     }
 
-    if trimmed.starts_with('{') && trimmed.ends_with('}') {
-        if let Ok(value) = serde_json::from_str::<JsonValue>(trimmed) {
-            return value;
-        }
+    if trimmed.starts_with('{')
+        && trimmed.ends_with('}')
+        && let Ok(value) = serde_json::from_str::<JsonValue>(trimmed)
+    {
+        return value;
     }
 
     if let Ok(int) = trimmed.parse::<i64>() {
         return JsonValue::Number(Number::from(int));
     }
 
-    if let Ok(float) = trimmed.parse::<f64>() {
-        if let Some(number) = Number::from_f64(float) {
-            return JsonValue::Number(number);
-        }
+    if let Ok(float) = trimmed.parse::<f64>()
+        && let Some(number) = Number::from_f64(float)
+    {
+        return JsonValue::Number(number);
     }
 
     JsonValue::String(trimmed.to_string())
@@ -966,51 +1103,51 @@ impl Validate for Info {
         let mut errors = ValidationErrors::new();
         let mut has_error = false;
 
-        if let Some(schema) = &self.schema {
-            if schema.trim().is_empty() {
-                push_field_error(
-                    &mut errors,
-                    "$schema",
-                    "length",
-                    "$schema must not be empty",
-                );
-                has_error = true;
-            }
+        if let Some(schema) = &self.schema
+            && schema.trim().is_empty()
+        {
+            push_field_error(
+                &mut errors,
+                "$schema",
+                "length",
+                "$schema must not be empty",
+            );
+            has_error = true;
         }
-        if let Some(theme) = &self.theme {
-            if theme.trim().is_empty() {
-                push_field_error(&mut errors, "theme", "length", "theme must not be empty");
-                has_error = true;
-            }
+        if let Some(theme) = &self.theme
+            && theme.trim().is_empty()
+        {
+            push_field_error(&mut errors, "theme", "length", "theme must not be empty");
+            has_error = true;
         }
-        if let Some(username) = &self.username {
-            if username.trim().is_empty() {
-                push_field_error(
-                    &mut errors,
-                    "username",
-                    "length",
-                    "username must not be empty",
-                );
-                has_error = true;
-            }
+        if let Some(username) = &self.username
+            && username.trim().is_empty()
+        {
+            push_field_error(
+                &mut errors,
+                "username",
+                "length",
+                "username must not be empty",
+            );
+            has_error = true;
         }
-        if let Some(keybinds) = &self.keybinds {
-            if let Err(err) = keybinds.validate() {
-                push_struct_error(&mut errors, "keybinds", err);
-                has_error = true;
-            }
+        if let Some(keybinds) = &self.keybinds
+            && let Err(err) = keybinds.validate()
+        {
+            push_struct_error(&mut errors, "keybinds", err);
+            has_error = true;
         }
-        if let Some(tui) = &self.tui {
-            if let Err(err) = tui.validate() {
-                push_struct_error(&mut errors, "tui", err);
-                has_error = true;
-            }
+        if let Some(tui) = &self.tui
+            && let Err(err) = tui.validate()
+        {
+            push_struct_error(&mut errors, "tui", err);
+            has_error = true;
         }
-        if let Some(watcher) = &self.watcher {
-            if let Err(err) = watcher.validate() {
-                push_struct_error(&mut errors, "watcher", err);
-                has_error = true;
-            }
+        if let Some(watcher) = &self.watcher
+            && let Err(err) = watcher.validate()
+        {
+            push_struct_error(&mut errors, "watcher", err);
+            has_error = true;
         }
         if let Some(providers) = &self.provider {
             for (name, config) in providers {
@@ -1044,11 +1181,18 @@ impl Validate for Info {
                 }
             }
         }
-        if let Some(experimental) = &self.experimental {
-            if let Err(err) = experimental.validate() {
-                push_struct_error(&mut errors, "experimental", err);
-                has_error = true;
-            }
+        if let Some(search) = &self.search
+            && let Some(options) = &search.options
+            && let Err(err) = options.validate()
+        {
+            push_struct_error(&mut errors, "search.options", err);
+            has_error = true;
+        }
+        if let Some(experimental) = &self.experimental
+            && let Err(err) = experimental.validate()
+        {
+            push_struct_error(&mut errors, "experimental", err);
+            has_error = true;
         }
 
         if has_error { Err(errors) } else { Ok(()) }
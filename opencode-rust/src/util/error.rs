@@ -14,6 +14,30 @@ pub enum OpenCodeError {
     #[error("WalkDir error: {0}")]
     WalkDir(#[from] walkdir::Error),
 
+    #[error("Read error: {0}")]
+    Read(String),
+
+    #[error("Edit error: {0}")]
+    Edit(String),
+
+    #[error("Patch error: {0}")]
+    Patch(String),
+
+    #[error("Search error: {0}")]
+    Search(String),
+
+    #[error("Web fetch error: {0}")]
+    WebFetch(String),
+
+    #[error("Web search error: {0}")]
+    WebSearch(String),
+
+    #[error("Todo error: {0}")]
+    Todo(String),
+
+    #[error("Task error: {0}")]
+    Task(String),
+
     #[error("Reqwest error: {0}")]
     Reqwest(#[from] reqwest::Error),
 }
@@ -0,0 +1,511 @@
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use ego_tree::NodeRef;
+use ego_tree::iter::Edge;
+use scraper::{Html, Node};
+
+/// Deepest element nesting that keeps its structure. The parser slows down
+/// quadratically with nesting and the converter recurses, so deeper
+/// elements are flattened into the text of their ancestors.
+const MAX_DEPTH: usize = 128;
+
+/// Elements whose content is never shown.
+const SKIPPED: &[&str] = &[
+    "head", "script", "style", "noscript", "template", "svg", "iframe", "object", "button",
+    "select", "form", "meta", "link",
+];
+
+/// Converts an HTML document to markdown, keeping headings, emphasis, links,
+/// images, lists, quotes, code blocks and tables.
+pub fn to_markdown(html: &str) -> String {
+    render(html, false)
+}
+
+/// Extracts the readable text of an HTML document, one block per line.
+pub fn to_text(html: &str) -> String {
+    render(html, true)
+}
+
+fn render(html: &str, plain: bool) -> String {
+    let document = Html::parse_document(&limit_nesting(html));
+    let converter = Converter {
+        plain,
+        depth: Cell::new(0),
+    };
+    let mut out = String::new();
+    converter.children(document.tree.root(), &mut out);
+    normalize(&out)
+}
+
+struct Converter {
+    plain: bool,
+    depth: Cell<usize>,
+}
+
+impl Converter {
+    fn children(&self, node: NodeRef<'_, Node>, out: &mut String) {
+        let depth = self.depth.get();
+        if depth >= MAX_DEPTH {
+            self.flatten(node, out);
+            return;
+        }
+        self.depth.set(depth + 1);
+        for child in node.children() {
+            self.node(child, out);
+        }
+        self.depth.set(depth);
+    }
+
+    /// Appends the text below `node` without its structure, walking the
+    /// tree without recursion.
+    fn flatten(&self, node: NodeRef<'_, Node>, out: &mut String) {
+        let mut skipped = 0;
+        for edge in node.traverse().skip(1) {
+            match edge {
+                Edge::Open(child) => match child.value() {
+                    Node::Element(element) if skipped > 0 || SKIPPED.contains(&element.name()) => {
+                        skipped += 1;
+                    }
+                    Node::Text(text) if skipped == 0 => push_text(out, &text.text),
+                    _ => {}
+                },
+                Edge::Close(child) => {
+                    if skipped > 0 && child.value().is_element() {
+                        skipped -= 1;
+                    }
+                }
+            }
+        }
+    }
+
+    fn node(&self, node: NodeRef<'_, Node>, out: &mut String) {
+        match node.value() {
+            Node::Text(text) => push_text(out, &text.text),
+            Node::Element(element) => self.element(node, element.name(), out),
+            Node::Document | Node::Fragment => self.children(node, out),
+            _ => {}
+        }
+    }
+
+    fn element(&self, node: NodeRef<'_, Node>, name: &str, out: &mut String) {
+        if SKIPPED.contains(&name) {
+            return;
+        }
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let text = self.inline(node);
+                if !text.is_empty() {
+                    let level = usize::from(name.as_bytes()[1] - b'0');
+                    let prefix = if self.plain {
+                        String::new()
+                    } else {
+                        format!("{} ", "#".repeat(level))
+                    };
+                    push_block(out, &format!("{prefix}{text}"));
+                }
+            }
+            "p" | "div" | "section" | "article" | "main" | "header" | "footer" | "aside"
+            | "nav" | "figure" | "figcaption" | "dl" | "dt" | "dd" | "details" | "summary"
+            | "address" => {
+                let mut inner = String::new();
+                self.children(node, &mut inner);
+                push_block(out, inner.trim());
+            }
+            "br" => out.push('\n'),
+            "hr" => {
+                if !self.plain {
+                    push_block(out, "---");
+                }
+            }
+            "pre" => push_block(out, &self.code_block(node)),
+            "blockquote" => {
+                let mut inner = String::new();
+                self.children(node, &mut inner);
+                let inner = normalize(&inner);
+                if self.plain {
+                    push_block(out, &inner);
+                } else {
+                    let quoted: Vec<String> = inner
+                        .lines()
+                        .map(|line| format!("> {line}").trim_end().to_string())
+                        .collect();
+                    push_block(out, &quoted.join("\n"));
+                }
+            }
+            "ul" | "ol" => push_block(out, &self.list(node, name == "ol")),
+            "table" => push_block(out, &self.table(node)),
+            "a" => {
+                let text = self.inline(node);
+                let href = attr(node, "href").unwrap_or_default();
+                if self.plain || href.is_empty() || href.starts_with("javascript:") {
+                    push_inline(out, &text);
+                } else if !text.is_empty() {
+                    push_inline(out, &format!("[{text}]({href})"));
+                }
+            }
+            "img" => {
+                if !self.plain
+                    && let Some(src) = attr(node, "src").filter(|src| !src.is_empty())
+                {
+                    let alt = attr(node, "alt").unwrap_or_default();
+                    push_inline(out, &format!("![{alt}]({src})"));
+                }
+            }
+            "strong" | "b" => self.wrap(node, "**", out),
+            "em" | "i" => self.wrap(node, "*", out),
+            "del" | "s" | "strike" => self.wrap(node, "~~", out),
+            "code" | "kbd" | "samp" => {
+                let code = self.inline(node);
+                if self.plain {
+                    push_inline(out, &code);
+                } else if !code.is_empty() {
+                    let fence = if code.contains('`') { "``" } else { "`" };
+                    push_inline(out, &format!("{fence}{code}{fence}"));
+                }
+            }
+            _ => self.children(node, out),
+        }
+    }
+
+    /// Renders the children of `node` on a single line.
+    fn inline(&self, node: NodeRef<'_, Node>) -> String {
+        let mut inner = String::new();
+        self.children(node, &mut inner);
+        inner.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    fn wrap(&self, node: NodeRef<'_, Node>, marker: &str, out: &mut String) {
+        let mut inner = String::new();
+        self.children(node, &mut inner);
+        let text = inner.trim();
+        if text.is_empty() {
+            return;
+        }
+        if inner.starts_with(char::is_whitespace) {
+            push_inline(out, " ");
+        }
+        if self.plain {
+            push_inline(out, text);
+        } else {
+            push_inline(out, &format!("{marker}{text}{marker}"));
+        }
+        if inner.ends_with(char::is_whitespace) {
+            out.push(' ');
+        }
+    }
+
+    fn code_block(&self, node: NodeRef<'_, Node>) -> String {
+        let code: String = node
+            .descendants()
+            .filter_map(|node| match node.value() {
+                Node::Text(text) => Some(&*text.text),
+                _ => None,
+            })
+            .collect();
+        let code = code.trim_matches('\n');
+        if self.plain {
+            return code.to_string();
+        }
+        let language = std::iter::once(node)
+            .chain(node.children())
+            .filter_map(|node| attr(node, "class"))
+            .flat_map(|class| {
+                class
+                    .split_whitespace()
+                    .filter_map(|name| {
+                        name.strip_prefix("language-")
+                            .or_else(|| name.strip_prefix("lang-"))
+                    })
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .next()
+            .unwrap_or_default();
+        let fence = if code.contains("```") { "~~~" } else { "```" };
+        format!("{fence}{language}\n{code}\n{fence}")
+    }
+
+    fn list(&self, node: NodeRef<'_, Node>, ordered: bool) -> String {
+        let start: usize = attr(node, "start")
+            .and_then(|start| start.parse().ok())
+            .unwrap_or(1);
+        let mut items = Vec::new();
+        for item in node.children().filter(|child| is_element(*child, "li")) {
+            let mut inner = String::new();
+            self.children(item, &mut inner);
+            let inner = normalize(&inner);
+            let marker = match (self.plain, ordered) {
+                (true, _) => String::new(),
+                (false, true) => format!("{}. ", start + items.len()),
+                (false, false) => "- ".to_string(),
+            };
+            let indent = " ".repeat(marker.len());
+            let mut lines = inner.lines().filter(|line| !line.trim().is_empty());
+            let first = lines.next().unwrap_or_default();
+            let mut rendered = format!("{marker}{first}");
+            for line in lines {
+                rendered.push('\n');
+                rendered.push_str(&indent);
+                rendered.push_str(line);
+            }
+            items.push(rendered);
+        }
+        items.join("\n")
+    }
+
+    fn table(&self, node: NodeRef<'_, Node>) -> String {
+        // Only the table's own rows; nested tables are rendered inside their
+        // cells rather than once for every enclosing table.
+        let rows: Vec<Vec<String>> = node
+            .children()
+            .flat_map(|child| {
+                let section = ["thead", "tbody", "tfoot"]
+                    .iter()
+                    .any(|name| is_element(child, name));
+                if section {
+                    child.children().collect()
+                } else {
+                    vec![child]
+                }
+            })
+            .filter(|row| is_element(*row, "tr"))
+            .map(|row| {
+                row.children()
+                    .filter(|cell| is_element(*cell, "td") || is_element(*cell, "th"))
+                    .map(|cell| self.inline(cell).replace('|', "\\|"))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|cells| !cells.is_empty())
+            .collect();
+        let Some(columns) = rows.iter().map(Vec::len).max() else {
+            return String::new();
+        };
+        if self.plain {
+            return rows
+                .iter()
+                .map(|cells| cells.join("\t"))
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+        let line = |cells: &[String]| {
+            let mut padded = cells.to_vec();
+            padded.resize(columns, String::new());
+            format!("| {} |", padded.join(" | "))
+        };
+        let mut lines = vec![line(&rows[0]), line(&vec!["---".to_string(); columns])];
+        lines.extend(rows[1..].iter().map(|cells| line(cells)));
+        lines.join("\n")
+    }
+}
+
+/// Elements without content or end tag.
+const VOID: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements the parser closes by itself when their end tag is left out. They
+/// are not counted towards the nesting depth, so pages that never close them
+/// keep their structure.
+const OPTIONAL_END: &[&str] = &[
+    "html", "head", "body", "p", "li", "dt", "dd", "tr", "td", "th", "thead", "tbody", "tfoot",
+    "caption", "colgroup", "option", "optgroup", "rb", "rt", "rp", "rtc",
+];
+
+/// Elements whose content is text rather than markup.
+const RAW_TEXT: &[&str] = &[
+    "script",
+    "style",
+    "textarea",
+    "title",
+    "xmp",
+    "iframe",
+    "noembed",
+    "noframes",
+    "noscript",
+    "plaintext",
+];
+
+/// Drops the start and end tags of elements nested more than `MAX_DEPTH`
+/// deep, keeping their content. A linear scan over the tags is enough here;
+/// the parser still does the real work on what is left.
+fn limit_nesting(html: &str) -> Cow<'_, str> {
+    let mut out = String::new();
+    let mut copied = 0;
+    let mut open: Vec<String> = Vec::new();
+    let mut dropped: HashMap<String, usize> = HashMap::new();
+    let mut pos = 0;
+    while let Some(offset) = html[pos..].find('<') {
+        let start = pos + offset;
+        let rest = &html[start..];
+        let mut drop = false;
+        let end = if rest.starts_with("<!--") {
+            rest.find("-->").map_or(html.len(), |end| start + end + 3)
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest.find('>').map_or(html.len(), |end| start + end + 1)
+        } else if let Some(name) = rest.strip_prefix("</").and_then(tag_name) {
+            match dropped.get_mut(&name) {
+                Some(count) if *count > 0 => {
+                    *count -= 1;
+                    drop = true;
+                }
+                _ => {
+                    if let Some(index) = open.iter().rposition(|tag| *tag == name) {
+                        open.truncate(index);
+                    }
+                }
+            }
+            tag_end(html, start)
+        } else if let Some(name) = tag_name(&rest[1..]) {
+            let end = tag_end(html, start);
+            if RAW_TEXT.contains(&name.as_str()) {
+                find_end_tag(html, end, &name)
+            } else {
+                let counted = !html[..end].ends_with("/>")
+                    && !VOID.contains(&name.as_str())
+                    && !OPTIONAL_END.contains(&name.as_str());
+                if counted && open.len() >= MAX_DEPTH {
+                    *dropped.entry(name).or_default() += 1;
+                    drop = true;
+                } else if counted {
+                    open.push(name);
+                }
+                end
+            }
+        } else {
+            start + 1
+        };
+        if drop {
+            out.push_str(&html[copied..start]);
+            copied = end;
+        }
+        pos = end;
+    }
+    if copied == 0 {
+        return Cow::Borrowed(html);
+    }
+    out.push_str(&html[copied..]);
+    Cow::Owned(out)
+}
+
+/// The lowercased name of a tag whose `<` or `</` has been stripped.
+fn tag_name(text: &str) -> Option<String> {
+    if !text.starts_with(|ch: char| ch.is_ascii_alphabetic()) {
+        return None;
+    }
+    let end = text
+        .find(|ch: char| !ch.is_ascii_alphanumeric() && ch != '-')
+        .unwrap_or(text.len());
+    Some(text[..end].to_ascii_lowercase())
+}
+
+/// The offset just past the `>` closing the tag at `start`, skipping quoted
+/// attribute values.
+fn tag_end(html: &str, start: usize) -> usize {
+    let mut quote = None;
+    for (offset, byte) in html.as_bytes()[start..].iter().enumerate() {
+        match (quote, byte) {
+            (None, b'"' | b'\'') => quote = Some(*byte),
+            (Some(open), _) if open == *byte => quote = None,
+            (None, b'>') => return start + offset + 1,
+            _ => {}
+        }
+    }
+    html.len()
+}
+
+/// The offset just past the end tag of the raw text element `name` whose
+/// content starts at `from`.
+fn find_end_tag(html: &str, from: usize, name: &str) -> usize {
+    let closing = format!("</{name}");
+    let haystack = html.as_bytes();
+    (from..haystack.len())
+        .find(|&index| {
+            haystack[index..]
+                .get(..closing.len())
+                .is_some_and(|candidate| candidate.eq_ignore_ascii_case(closing.as_bytes()))
+        })
+        .map_or(html.len(), |index| tag_end(html, index))
+}
+
+fn attr<'a>(node: NodeRef<'a, Node>, name: &str) -> Option<&'a str> {
+    match node.value() {
+        Node::Element(element) => element.attr(name),
+        _ => None,
+    }
+}
+
+fn is_element(node: NodeRef<'_, Node>, name: &str) -> bool {
+    matches!(node.value(), Node::Element(element) if element.name() == name)
+}
+
+/// Appends text with runs of whitespace collapsed, dropping leading space at
+/// the start of a line.
+fn push_text(out: &mut String, text: &str) {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut space = false;
+    for ch in text.chars() {
+        if ch.is_whitespace() {
+            space = true;
+        } else {
+            if space {
+                collapsed.push(' ');
+            }
+            space = false;
+            collapsed.push(ch);
+        }
+    }
+    if space {
+        collapsed.push(' ');
+    }
+    push_inline(out, &collapsed);
+}
+
+fn push_inline(out: &mut String, text: &str) {
+    if out.is_empty() || out.ends_with(char::is_whitespace) {
+        out.push_str(text.trim_start());
+    } else {
+        out.push_str(text);
+    }
+}
+
+fn push_block(out: &mut String, block: &str) {
+    if block.is_empty() {
+        return;
+    }
+    if !out.is_empty() {
+        out.push_str("\n\n");
+    }
+    out.push_str(block);
+    out.push_str("\n\n");
+}
+
+/// Trims trailing spaces and collapses blank lines, leaving fenced code
+/// blocks untouched.
+fn normalize(text: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    let mut fence: Option<&str> = None;
+    for line in text.lines() {
+        if let Some(open) = fence {
+            lines.push(line);
+            if line == open {
+                fence = None;
+            }
+            continue;
+        }
+        let line = line.trim_end();
+        if line.starts_with("```") || line.starts_with("~~~") {
+            fence = Some(&line[..3]);
+        }
+        if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
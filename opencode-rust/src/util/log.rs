@@ -45,7 +45,7 @@ pub fn init(config: LogConfig) -> Result<()> {
 
     let make_writer: BoxMakeWriter = match config.print_logs {
         true => BoxMakeWriter::new(std::io::stderr),
-        false => BoxMakeWriter::new(|| std::io::sink()),
+        false => BoxMakeWriter::new(std::io::sink),
     };
 
     let subscriber = base.with_writer(make_writer).finish();
@@ -1,3 +1,5 @@
 pub mod config;
 pub mod error;
+pub mod html;
 pub mod log;
+pub mod paths;
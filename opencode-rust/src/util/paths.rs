@@ -0,0 +1,30 @@
+use std::env;
+use std::path::PathBuf;
+
+const APP_DIR: &str = "opencode";
+
+/// Global configuration directory: `$XDG_CONFIG_HOME/opencode`, falling back
+/// to `~/.config/opencode`.
+pub fn config_dir() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// Global data directory: `$XDG_DATA_HOME/opencode`, falling back to
+/// `~/.local/share/opencode`.
+pub fn data_dir() -> PathBuf {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+fn xdg_dir(variable: &str, fallback: &str) -> PathBuf {
+    if let Some(base) = env::var_os(variable).filter(|value| !value.is_empty()) {
+        return PathBuf::from(base).join(APP_DIR);
+    }
+    home_dir().join(fallback).join(APP_DIR)
+}
+
+pub fn home_dir() -> PathBuf {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir)
+}
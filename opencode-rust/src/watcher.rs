@@ -11,7 +11,8 @@ use tracing::{debug, error};
 
 use crate::util::config::Info;
 
-const IGNORED_FOLDERS: &[&str] = &[
+/// Folders skipped by the watcher and by searches that opt into it.
+pub const IGNORED_FOLDERS: &[&str] = &[
     "node_modules",
     "bower_components",
     ".pnpm-store",
@@ -138,14 +139,16 @@ pub async fn watch(path: &Path, options: WatchOptions) -> Result<FileWatcher> {
     })
 }
 
-struct IgnoreMatcher {
+/// Matches paths against the ignored folders, the built-in ignore globs and
+/// any extra globs, relative to `root`.
+pub struct IgnoreMatcher {
     root: PathBuf,
     folders: HashSet<String>,
     patterns: Vec<String>,
 }
 
 impl IgnoreMatcher {
-    fn new(root: PathBuf, extra: &[String]) -> Self {
+    pub fn new(root: PathBuf, extra: &[String]) -> Self {
         let mut patterns = IGNORED_GLOBS
             .iter()
             .map(|p| p.to_string())
@@ -162,7 +165,7 @@ impl IgnoreMatcher {
         }
     }
 
-    fn matches(&self, path: &Path) -> bool {
+    pub fn matches(&self, path: &Path) -> bool {
         let relative = path
             .strip_prefix(&self.root)
             .ok()
@@ -170,10 +173,10 @@ impl IgnoreMatcher {
             .unwrap_or(path);
 
         for component in relative.iter() {
-            if let Some(name) = component.to_str() {
-                if self.folders.contains(name) {
-                    return true;
-                }
+            if let Some(name) = component.to_str()
+                && self.folders.contains(name)
+            {
+                return true;
             }
         }
         if let Some(path_str) = normalize_path(relative) {
@@ -204,11 +207,10 @@ async fn process_events(
                 };
                 match event {
                     Ok(event) => {
-                        if let Some(mapped) = map_event(event, &matcher) {
-                            if event_tx.send(mapped).await.is_err() {
+                        if let Some(mapped) = map_event(event, &matcher)
+                            && event_tx.send(mapped).await.is_err() {
                                 break;
                             }
-                        }
                     }
                     Err(err) => {
                         error!("watch error: {err}");
@@ -251,90 +253,6 @@ fn select_path(event: &Event, matcher: &IgnoreMatcher) -> Option<PathBuf> {
     None
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
-    use std::path::PathBuf;
-    use tokio::time::{sleep, timeout};
-    use uuid::Uuid;
-
-    #[tokio::test]
-    async fn emits_events_for_changes() {
-        let path = temp_dir();
-        let mut watcher = watch(path.as_path(), WatchOptions::default())
-            .await
-            .unwrap();
-
-        let file = path.join("test.txt");
-        fs::write(&file, "hello").unwrap();
-
-        let event = timeout(Duration::from_secs(5), watcher.next())
-            .await
-            .unwrap()
-            .unwrap();
-        assert_eq!(event.kind, FileEventKind::Created);
-        assert_eq!(event.path, file);
-
-        fs::write(&file, "world").unwrap();
-        let event = timeout(Duration::from_secs(5), watcher.next())
-            .await
-            .unwrap()
-            .unwrap();
-        assert_eq!(event.kind, FileEventKind::Modified);
-
-        fs::remove_file(&file).unwrap();
-        let event = timeout(Duration::from_secs(5), watcher.next())
-            .await
-            .unwrap()
-            .unwrap();
-        assert_eq!(event.kind, FileEventKind::Deleted);
-
-        watcher.shutdown().await;
-        let _ = fs::remove_dir_all(&path);
-    }
-
-    #[tokio::test]
-    async fn applies_ignore_patterns() {
-        let path = temp_dir();
-        let mut options = WatchOptions::default();
-        options.ignore.push("**/*.log".to_string());
-        let mut watcher = watch(path.as_path(), options).await.unwrap();
-
-        let ignored_dir = path.join("node_modules");
-        fs::create_dir_all(&ignored_dir).unwrap();
-        let ignored_file = ignored_dir.join("ignored.txt");
-        fs::write(&ignored_file, "ignored").unwrap();
-
-        let log_file = path.join("debug.log");
-        fs::write(&log_file, "ignored").unwrap();
-
-        sleep(Duration::from_millis(200)).await;
-        let maybe_event = timeout(Duration::from_millis(500), watcher.next()).await;
-        assert!(
-            maybe_event.is_err(),
-            "no events should be emitted for ignored files"
-        );
-
-        let tracked = path.join("tracked.txt");
-        fs::write(&tracked, "data").unwrap();
-        let event = timeout(Duration::from_secs(5), watcher.next())
-            .await
-            .unwrap()
-            .unwrap();
-        assert_eq!(event.path, tracked);
-
-        watcher.shutdown().await;
-        let _ = fs::remove_dir_all(&path);
-    }
-
-    fn temp_dir() -> PathBuf {
-        let dir = std::env::temp_dir().join(format!("opencode-watcher-{}", Uuid::new_v4()));
-        fs::create_dir_all(&dir).unwrap();
-        dir
-    }
-}
-
 fn normalize_path(path: &Path) -> Option<String> {
     let mut parts = Vec::new();
     for component in path.iter() {
@@ -419,3 +337,87 @@ fn segment_matches(pattern: &str, text: &str) -> bool {
 
     pi == pat.len()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use tokio::time::{sleep, timeout};
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn emits_events_for_changes() {
+        let path = temp_dir();
+        let mut watcher = watch(path.as_path(), WatchOptions::default())
+            .await
+            .unwrap();
+
+        let file = path.join("test.txt");
+        fs::write(&file, "hello").unwrap();
+
+        let event = timeout(Duration::from_secs(5), watcher.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.kind, FileEventKind::Created);
+        assert_eq!(event.path, file);
+
+        fs::write(&file, "world").unwrap();
+        let event = timeout(Duration::from_secs(5), watcher.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.kind, FileEventKind::Modified);
+
+        fs::remove_file(&file).unwrap();
+        let event = timeout(Duration::from_secs(5), watcher.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.kind, FileEventKind::Deleted);
+
+        watcher.shutdown().await;
+        let _ = fs::remove_dir_all(&path);
+    }
+
+    #[tokio::test]
+    async fn applies_ignore_patterns() {
+        let path = temp_dir();
+        let mut options = WatchOptions::default();
+        options.ignore.push("**/*.log".to_string());
+        let mut watcher = watch(path.as_path(), options).await.unwrap();
+
+        let ignored_dir = path.join("node_modules");
+        fs::create_dir_all(&ignored_dir).unwrap();
+        let ignored_file = ignored_dir.join("ignored.txt");
+        fs::write(&ignored_file, "ignored").unwrap();
+
+        let log_file = path.join("debug.log");
+        fs::write(&log_file, "ignored").unwrap();
+
+        sleep(Duration::from_millis(200)).await;
+        let maybe_event = timeout(Duration::from_millis(500), watcher.next()).await;
+        assert!(
+            maybe_event.is_err(),
+            "no events should be emitted for ignored files"
+        );
+
+        let tracked = path.join("tracked.txt");
+        fs::write(&tracked, "data").unwrap();
+        let event = timeout(Duration::from_secs(5), watcher.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.path, tracked);
+
+        watcher.shutdown().await;
+        let _ = fs::remove_dir_all(&path);
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("opencode-watcher-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+}
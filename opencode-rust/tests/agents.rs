@@ -50,8 +50,10 @@ fn merges_runtime_overrides() {
         .extra
         .insert("reportFormat".into(), Value::String("Summaries".into()));
 
-    let mut info = Info::default();
-    info.agent = Some(HashMap::from([("primary".to_string(), agent_config)]));
+    let info = Info {
+        agent: Some(HashMap::from([("primary".to_string(), agent_config)])),
+        ..Default::default()
+    };
 
     let mut registry = AgentRegistry::from_info(&info);
     let overrides = parse_agents_json(
@@ -101,8 +103,10 @@ fn prompt_builder_collects_rules() -> Result<()> {
     std::fs::create_dir_all(temp.path().join("migration"))?;
     std::fs::write(temp.path().join("migration/task.md"), "Migration steps")?;
 
-    let mut info = Info::default();
-    info.instructions = Some(vec!["Stay focused".to_string()]);
+    let info = Info {
+        instructions: Some(vec!["Stay focused".to_string()]),
+        ..Default::default()
+    };
 
     let context = ProjectContext::gather(temp.path(), &info)?;
 
@@ -5,7 +5,7 @@ mod tests {
 
     #[test]
     fn test_run_command() {
-        let opts = Opts::parse_from(&[
+        let opts = Opts::parse_from([
             "opencode-rust",
             "run",
             "hello",
@@ -23,9 +23,38 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_command_list_command() {
+        let opts = Opts::parse_from(["opencode-rust", "command", "ls"]);
+        match opts.command {
+            Command::Command(command_cmd) => {
+                assert!(matches!(
+                    command_cmd.action,
+                    cmd::command::CommandAction::List
+                ));
+            }
+            _ => panic!("Expected Command command"),
+        }
+    }
+
+    #[test]
+    fn test_session_revert_command() {
+        let opts = Opts::parse_from(["opencode-rust", "session", "revert", "s1", "m1"]);
+        match opts.command {
+            Command::Session(session_cmd) => match session_cmd.action {
+                cmd::session::SessionAction::Revert(args) => {
+                    assert_eq!(args.session, "s1");
+                    assert_eq!(args.message, "m1");
+                }
+                _ => panic!("Expected session revert"),
+            },
+            _ => panic!("Expected Session command"),
+        }
+    }
+
     #[test]
     fn test_generate_command() {
-        let opts = Opts::parse_from(&["opencode-rust", "generate"]);
+        let opts = Opts::parse_from(["opencode-rust", "generate"]);
         match opts.command {
             Command::Generate => (),
             _ => panic!("Expected Generate command"),
@@ -34,7 +63,7 @@ mod tests {
 
     #[test]
     fn test_auth_login_command() {
-        let opts = Opts::parse_from(&["opencode-rust", "auth", "login", "https://example.com"]);
+        let opts = Opts::parse_from(["opencode-rust", "auth", "login", "https://example.com"]);
         match opts.command {
             Command::Auth(auth_cmd) => match auth_cmd.action {
                 cmd::auth::AuthAction::Login(login) => {
@@ -48,7 +77,7 @@ mod tests {
 
     #[test]
     fn test_upgrade_method() {
-        let opts = Opts::parse_from(&["opencode-rust", "upgrade", "1.2.3", "--method", "bun"]);
+        let opts = Opts::parse_from(["opencode-rust", "upgrade", "1.2.3", "--method", "bun"]);
         match opts.command {
             Command::Upgrade(upgrade_cmd) => {
                 assert_eq!(upgrade_cmd.target.as_deref(), Some("1.2.3"));
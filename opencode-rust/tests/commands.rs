@@ -0,0 +1,136 @@
+use anyhow::Result;
+use opencode_rust::command;
+use opencode_rust::util::config;
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn renders_command_templates() -> Result<()> {
+    let temp = tempdir()?;
+    std::fs::write(temp.path().join("notes.md"), "Remember the changelog")?;
+    let info = config::parse_info(
+        r#"{
+        "command": {
+            "review": {
+                "template": "Review $1 on !`echo main` with focus: $2. See @notes.md and @missing.md.",
+                "agent": "reviewer",
+                "model": "review/model",
+                "subtask": true
+            }
+        }
+    }"#,
+    )?;
+
+    let definition = command::lookup(&info, "/review")?;
+    let arguments = vec!["src/lib.rs".to_string(), "error".into(), "handling".into()];
+    let rendered = command::render("review", definition, &arguments, temp.path()).await;
+
+    assert_eq!(rendered.name, "review");
+    assert_eq!(rendered.agent.as_deref(), Some("reviewer"));
+    assert_eq!(rendered.model.as_deref(), Some("review/model"));
+    assert_eq!(rendered.subtask, Some(true));
+    assert!(rendered.prompt.starts_with(
+        "Review src/lib.rs on main with focus: error handling. See @notes.md and @missing.md."
+    ));
+    assert!(
+        rendered
+            .prompt
+            .contains("<file path=\"notes.md\">\nRemember the changelog\n</file>")
+    );
+    assert!(!rendered.prompt.contains("<file path=\"missing.md\">"));
+
+    assert!(command::lookup(&info, "deploy").is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn loads_markdown_commands_over_json_config() -> Result<()> {
+    let global = tempdir()?;
+    let project = tempdir()?;
+    let project_commands = project.path().join(".opencode").join("command");
+    std::fs::create_dir_all(project_commands.join("git"))?;
+    std::fs::write(
+        global.path().join("review.md"),
+        "---\ndescription: Global review\n---\nGlobal review of $ARGUMENTS",
+    )?;
+    std::fs::write(global.path().join("lint.md"), "Run the linter")?;
+    std::fs::write(
+        project_commands.join("review.md"),
+        "---\ndescription: Project review\nagent: reviewer\nmodel: review/model\nsubtask: true\n---\nReview $1 carefully\n",
+    )?;
+    std::fs::write(
+        project_commands.join("git").join("commit.md"),
+        "Commit staged work",
+    )?;
+    std::fs::write(project_commands.join("notes.txt"), "not a command")?;
+
+    let mut info = config::parse_info(
+        r#"{"command": {"review": {"template": "json"}, "deploy": {"template": "Deploy"}}}"#,
+    )?;
+    config::load_commands(&mut info, &[global.path().to_path_buf(), project_commands]).await?;
+
+    let commands = info.command.as_ref().unwrap();
+    let mut names: Vec<_> = commands.keys().map(String::as_str).collect();
+    names.sort();
+    assert_eq!(names, vec!["deploy", "git/commit", "lint", "review"]);
+
+    let review = command::lookup(&info, "/review")?;
+    assert_eq!(review.template, "Review $1 carefully");
+    assert_eq!(review.description.as_deref(), Some("Project review"));
+    assert_eq!(review.agent.as_deref(), Some("reviewer"));
+    assert_eq!(review.model.as_deref(), Some("review/model"));
+    assert_eq!(review.subtask, Some(true));
+    assert_eq!(command::lookup(&info, "lint")?.description, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn skips_invalid_markdown_commands() -> Result<()> {
+    let dir = tempdir()?;
+    std::fs::write(
+        dir.path().join("empty.md"),
+        "---\ndescription: Nothing\n---\n",
+    )?;
+    std::fs::write(
+        dir.path().join("broken.md"),
+        "---\ndescription: Unterminated\nBody",
+    )?;
+    std::fs::write(dir.path().join("lint.md"), "Run the linter")?;
+    let commands = config::load_markdown_commands(dir.path()).await?;
+    assert_eq!(commands.keys().collect::<Vec<_>>(), ["lint"]);
+
+    let mut info = config::Info::default();
+    config::load_commands(&mut info, &[dir.path().to_path_buf()]).await?;
+    assert_eq!(command::lookup(&info, "lint")?.template, "Run the linter");
+    assert!(
+        config::load_markdown_commands(&dir.path().join("missing"))
+            .await?
+            .is_empty()
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn shell_snippets_report_failures() -> Result<()> {
+    let temp = tempdir()?;
+    assert_eq!(
+        command::expand_shell("on !`echo main; echo noise >&2`.", temp.path()).await,
+        "on main."
+    );
+    assert_eq!(
+        command::expand_shell("[!`echo out; echo err >&2; exit 3`]", temp.path()).await,
+        "[out\nerr\n[command failed (exit 3)]]"
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test(start_paused = true)]
+async fn shell_snippets_time_out() -> Result<()> {
+    let temp = tempdir()?;
+    assert_eq!(
+        command::expand_shell("!`sleep 600`", temp.path()).await,
+        "[command timed out after 30s]"
+    );
+    Ok(())
+}
@@ -0,0 +1,230 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use opencode_rust::agent::registry::AgentRegistry;
+use opencode_rust::agent::spec::ModelHandle;
+use opencode_rust::server::{self, AppState};
+use opencode_rust::session::{
+    AgentEvent, CompactionConfig, Compactor, CompletionRequest, CompletionResponse, LanguageModel,
+    MessageRecord, ProjectContext, SessionRequest, SessionRevert, SessionRuntime, SessionStore,
+    StoredSession,
+};
+use opencode_rust::snapshot::Snapshot;
+use opencode_rust::util::config::Info;
+use serde_json::{Value, json};
+use tempfile::tempdir;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+/// Summarizes for the `compaction` agent and records the model and prompt
+/// of every request.
+#[derive(Default)]
+struct RecordingModel {
+    requests: Mutex<Vec<(String, String, String)>>,
+    fail_compaction: bool,
+}
+
+impl RecordingModel {
+    fn prompt_of(&self, agent: &str) -> Option<String> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|(name, _, _)| name == agent)
+            .map(|(_, _, prompt)| prompt.clone())
+    }
+}
+
+#[async_trait]
+impl LanguageModel for RecordingModel {
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        self.requests.lock().unwrap().push((
+            request.agent.clone(),
+            request.model.to_string(),
+            request.prompt.clone(),
+        ));
+        if request.agent == "compaction" {
+            if self.fail_compaction {
+                anyhow::bail!("small model unavailable");
+            }
+            return Ok(CompletionResponse {
+                summary: "The user is migrating the parser; lexer is done.".to_string(),
+                raw_output: String::new(),
+                usage: None,
+            });
+        }
+        Ok(CompletionResponse {
+            summary: format!("{} answered", request.agent),
+            raw_output: String::new(),
+            usage: None,
+        })
+    }
+}
+
+fn history() -> Vec<MessageRecord> {
+    let filler = "lorem ipsum ".repeat(40);
+    vec![
+        MessageRecord::user("build", format!("Migrate the lexer. {filler}")),
+        MessageRecord::summary("build", format!("Lexer migrated. {filler}")),
+        MessageRecord::tool("grep", format!("src/lexer.rs:1: old output {filler}")),
+        MessageRecord::user("build", "Now the parser."),
+        MessageRecord::tool("read", "fn parse() {}"),
+        MessageRecord::tool("grep", "src/parser.rs:10: newest output"),
+    ]
+}
+
+fn compacting_runtime(
+    model: Arc<RecordingModel>,
+    window: u32,
+) -> Result<(SessionRuntime, mpsc::Receiver<AgentEvent>)> {
+    let temp = tempdir()?;
+    let context = Arc::new(ProjectContext::gather(temp.path(), &Info::default())?);
+    let mut registry = AgentRegistry::new();
+    registry.ensure_primary();
+    let config = CompactionConfig::new()
+        .with_context_window(window)
+        .with_keep_recent(3);
+    let compactor =
+        Compactor::new(model.clone(), ModelHandle::new("acme/small")).with_config(config);
+    let (event_tx, event_rx) = mpsc::channel(16);
+    let runtime = SessionRuntime::new(
+        context,
+        Arc::new(registry),
+        model,
+        Vec::new(),
+        event_tx,
+        ModelHandle::new("acme/large"),
+    )
+    .with_compactor(Arc::new(compactor));
+    Ok((runtime, event_rx))
+}
+
+#[tokio::test]
+async fn compacts_history_near_the_context_window() -> Result<()> {
+    let model = Arc::new(RecordingModel::default());
+    let (runtime, mut events) = compacting_runtime(model.clone(), 400)?;
+    let history = history();
+
+    let request = SessionRequest::new("Finish the parser").with_history(history.clone());
+    let result = runtime.execute(request).await?;
+    let compaction = result.compaction.as_ref().expect("history was compacted");
+    assert_eq!(compaction.first_kept, Some(history[3].id));
+    assert_eq!(compaction.pruned, [history[4].id]);
+    assert!(compaction.tokens_after < compaction.tokens_before);
+
+    let requests = model.requests.lock().unwrap().clone();
+    let (_, small_model, summary_prompt) = &requests[0];
+    assert_eq!(small_model, "acme/small");
+    assert!(summary_prompt.contains("[summary]\nLexer migrated."));
+    assert!(summary_prompt.contains("src/lexer.rs:1: old output"));
+
+    let prompt = model.prompt_of(&result.primary.agent).unwrap();
+    let (_, conversation) = prompt.split_once("<CONVERSATION>\n").unwrap();
+    let (conversation, _) = conversation.split_once("\n</CONVERSATION>").unwrap();
+    assert_eq!(
+        conversation,
+        "[summary]\nThe user is migrating the parser; lexer is done.\n\n\
+         [user]\nNow the parser.\n\n\
+         [tool (read)]\n[Old tool output pruned]\n\n\
+         [tool (grep)]\nsrc/parser.rs:10: newest output"
+    );
+
+    let compacted = matches!(
+        events.recv().await,
+        Some(AgentEvent::Compacted { tokens_before, tokens_after, .. })
+            if tokens_before == compaction.tokens_before && tokens_after == compaction.tokens_after
+    );
+    assert!(compacted);
+
+    let mut session = StoredSession::new(result.primary.session_id, "parser");
+    session.messages = history.clone();
+    session.apply_compaction(compaction);
+    session.record_turn("Finish the parser", &result);
+    let context = session.context_messages();
+    assert!(context[0].summary);
+    assert_eq!(context[1].id, history[3].id);
+    assert!(context[2].pruned);
+    assert_eq!(session.messages.len(), history.len() + 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn keeps_history_that_fits_or_cannot_be_summarized() -> Result<()> {
+    let model = Arc::new(RecordingModel::default());
+    let (runtime, _events) = compacting_runtime(model.clone(), 100_000)?;
+    let request = SessionRequest::new("Finish the parser").with_history(history());
+    let result = runtime.execute(request).await?;
+    assert!(result.compaction.is_none());
+    assert!(model.prompt_of("compaction").is_none());
+    let prompt = model.prompt_of(&result.primary.agent).unwrap();
+    assert!(prompt.contains("src/lexer.rs:1: old output"));
+
+    let model = Arc::new(RecordingModel {
+        fail_compaction: true,
+        ..RecordingModel::default()
+    });
+    let (runtime, _events) = compacting_runtime(model.clone(), 400)?;
+    let request = SessionRequest::new("Finish the parser").with_history(history());
+    let result = runtime.execute(request).await?;
+    assert!(result.compaction.is_none());
+    assert!(model.prompt_of("compaction").is_some());
+    let prompt = model.prompt_of(&result.primary.agent).unwrap();
+    assert!(prompt.contains("src/lexer.rs:1: old output"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn server_compacts_stored_sessions() -> Result<()> {
+    let worktree = tempdir()?;
+    let data = tempdir()?;
+    let store = Arc::new(SessionStore::new(data.path().join("sessions")));
+    let snapshot = Arc::new(Snapshot::new(worktree.path(), data.path().join("snapshot")));
+    let model = Arc::new(RecordingModel::default());
+    let compactor = Compactor::new(model, ModelHandle::new("acme/small"))
+        .with_config(CompactionConfig::new().with_keep_recent(3));
+
+    let mut session = StoredSession::new(uuid::Uuid::new_v4(), "parser");
+    session.messages = history();
+    store.save(&session).await?;
+    let short = StoredSession::new(uuid::Uuid::new_v4(), "short");
+    store.save(&short).await?;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let base = format!("http://{}", listener.local_addr()?);
+    let state = AppState::new(Arc::new(SessionRevert::new(store.clone(), snapshot)))
+        .with_compactor(Arc::new(compactor));
+    let server = tokio::spawn(server::serve(listener, state));
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{base}/session/{}/compact", session.id))
+        .send()
+        .await?;
+    assert!(response.status().is_success());
+    let body: Value = response.json().await?;
+    let messages = body["messages"].as_array().unwrap();
+    assert_eq!(messages.len(), 7);
+    assert_eq!(messages[3]["summary"], json!(true));
+    assert_eq!(messages[5]["pruned"], json!(true));
+
+    let stored = store.get(session.id).await?;
+    assert_eq!(stored.context_messages().len(), 4);
+
+    let response = client
+        .post(format!("{base}/session/{}/compact", short.id))
+        .send()
+        .await?;
+    assert!(response.status().is_success());
+    assert!(store.get(short.id).await?.messages.is_empty());
+
+    let missing = client
+        .post(format!("{base}/session/{}/compact", uuid::Uuid::new_v4()))
+        .send()
+        .await?;
+    assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+
+    server.abort();
+    Ok(())
+}
@@ -0,0 +1,260 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use opencode_rust::agent::registry::AgentRegistry;
+use opencode_rust::agent::spec::{AgentSpec, ModelHandle};
+use opencode_rust::hook::{HookContext, HookRunner};
+use opencode_rust::session::{
+    LocalModel, ProjectContext, SessionRequest, SessionRuntime, SubagentInvocation,
+};
+use opencode_rust::tool::core::{Tool, ToolContext};
+use opencode_rust::tool::edit::EditTool;
+use opencode_rust::tool::fs::{ReadFileTool, WriteFileTool};
+use opencode_rust::tool::patch::ApplyPatchTool;
+use opencode_rust::util::config;
+use opencode_rust::watcher::{self, WatchOptions};
+use tempfile::tempdir;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, timeout};
+use uuid::Uuid;
+
+fn hook_config(body: &str) -> config::Info {
+    config::parse_info(&format!(r#"{{"experimental": {{"hook": {body}}}}}"#)).unwrap()
+}
+
+#[tokio::test]
+async fn write_tool_fires_matching_file_edited_hooks() -> Result<()> {
+    let temp = tempdir()?;
+    let info = hook_config(
+        r#"{
+        "file_edited": {
+            ".rs": [{
+                "command": ["sh", "-c", "echo \"$OPENCODE_FILE|$OPENCODE_AGENT|$OPENCODE_SESSION_ID|$LABEL\" >> hooks.log"],
+                "environment": {"LABEL": "fmt"}
+            }],
+            "md": [{"command": ["sh", "-c", "echo markdown >> hooks.log"]}]
+        }
+    }"#,
+    );
+    let hooks = Arc::new(HookRunner::from_info(temp.path(), &info));
+    let tool = WriteFileTool::new().with_hooks(hooks);
+
+    let session_id = Uuid::new_v4();
+    let ctx = ToolContext::new(session_id, Arc::new(AgentSpec::new("builder")));
+    let file = temp.path().join("lib.rs");
+    tool.execute_with_context(&ctx, &[file.display().to_string(), "fn main() {}".into()])
+        .await?;
+
+    let log = std::fs::read_to_string(temp.path().join("hooks.log"))?;
+    assert_eq!(
+        log.trim(),
+        format!("{}|builder|{session_id}|fmt", file.display())
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn formatting_hooks_do_not_make_edited_files_stale() -> Result<()> {
+    let temp = tempdir()?;
+    let info = hook_config(
+        r#"{
+        "file_edited": {
+            ".toml": [{"command": ["sed", "-i", "s/  */ /g", "$FILE"]}]
+        }
+    }"#,
+    );
+    let hooks = Arc::new(HookRunner::from_info(temp.path(), &info));
+    let tool = EditTool::new().with_hooks(hooks);
+    let ctx = ToolContext::default();
+    let file = temp.path().join("config.toml");
+    std::fs::write(&file, "a  =  1\n")?;
+    let path = file.display().to_string();
+
+    ReadFileTool
+        .execute_with_context(&ctx, std::slice::from_ref(&path))
+        .await?;
+    tool.execute_with_context(&ctx, &[path.clone(), "1".into(), "2".into()])
+        .await?;
+    assert_eq!(std::fs::read_to_string(&file)?, "a = 2\n");
+    tool.execute_with_context(&ctx, &[path.clone(), "a = 2".into(), "a  =  3".into()])
+        .await?;
+    assert_eq!(std::fs::read_to_string(&file)?, "a = 3\n");
+
+    let patch = "\
+--- a/config.toml
++++ b/config.toml
+@@ -1 +1 @@
+-a = 3
++a  =  4
+";
+    let root = temp.path().display().to_string();
+    let hooks = Arc::new(HookRunner::from_info(temp.path(), &info));
+    ApplyPatchTool::new()
+        .with_hooks(hooks)
+        .execute_with_context(&ctx, &[patch.to_string(), root])
+        .await?;
+    assert_eq!(std::fs::read_to_string(&file)?, "a = 4\n");
+    tool.execute_with_context(&ctx, &[path, "a = 4".into(), "a = 5".into()])
+        .await?;
+    assert_eq!(std::fs::read_to_string(&file)?, "a = 5\n");
+    Ok(())
+}
+
+#[tokio::test]
+async fn failing_hooks_are_reported_not_raised() -> Result<()> {
+    let temp = tempdir()?;
+    let info = hook_config(
+        r#"{
+        "file_edited": {
+            "*": [
+                {"command": ["sh", "-c", "echo broken >&2; exit 3"]},
+                {"command": ["definitely-not-a-real-hook-binary"]}
+            ]
+        }
+    }"#,
+    );
+    let hooks = HookRunner::from_info(temp.path(), &info);
+
+    let outcomes = hooks
+        .file_edited(&temp.path().join("notes.txt"), &HookContext::default())
+        .await;
+    assert_eq!(outcomes.len(), 2);
+    assert_eq!(outcomes[0].status, Some(3));
+    assert_eq!(outcomes[0].stderr.trim(), "broken");
+    assert!(outcomes[1].error.is_some());
+    assert!(outcomes.iter().all(|outcome| !outcome.success()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn session_completed_runs_after_execute() -> Result<()> {
+    let temp = tempdir()?;
+    let info = hook_config(
+        r#"{
+        "session_completed": [{
+            "command": ["sh", "-c", "echo \"$OPENCODE_AGENT $OPENCODE_SESSION_ID\" > done.txt; exit 1"]
+        }]
+    }"#,
+    );
+    let context = Arc::new(ProjectContext::gather(temp.path(), &info)?);
+    let (event_tx, _event_rx) = mpsc::channel(16);
+    let runtime = SessionRuntime::new(
+        context,
+        Arc::new(AgentRegistry::new()),
+        Arc::new(LocalModel),
+        Vec::<Arc<dyn Tool>>::new(),
+        event_tx,
+        ModelHandle::new("baseline/model"),
+    )
+    .with_hooks(Arc::new(HookRunner::from_info(temp.path(), &info)));
+
+    let result = runtime.execute(SessionRequest::new("Ship it")).await?;
+
+    let done = std::fs::read_to_string(temp.path().join("done.txt"))?;
+    assert_eq!(
+        done.trim(),
+        format!("primary {}", result.primary.session_id)
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn session_completed_runs_when_execute_fails() -> Result<()> {
+    let temp = tempdir()?;
+    let info = hook_config(
+        r#"{
+        "session_completed": [{"command": ["sh", "-c", "echo \"$OPENCODE_AGENT\" > done.txt"]}]
+    }"#,
+    );
+    let context = Arc::new(ProjectContext::gather(temp.path(), &info)?);
+    let (event_tx, _event_rx) = mpsc::channel(16);
+    let runtime = SessionRuntime::new(
+        context,
+        Arc::new(AgentRegistry::new()),
+        Arc::new(LocalModel),
+        Vec::<Arc<dyn Tool>>::new(),
+        event_tx,
+        ModelHandle::new("baseline/model"),
+    )
+    .with_hooks(Arc::new(HookRunner::from_info(temp.path(), &info)));
+
+    let mut request = SessionRequest::new("Ship it");
+    request.subtasks = vec![SubagentInvocation::new("missing", "Help")];
+    assert!(runtime.execute(request).await.is_err());
+
+    let done = std::fs::read_to_string(temp.path().join("done.txt"))?;
+    assert_eq!(done.trim(), "primary");
+    Ok(())
+}
+
+#[tokio::test]
+async fn watcher_changes_fire_file_edited_hooks() -> Result<()> {
+    let temp = tempdir()?;
+    let root = temp.path().canonicalize()?;
+    let watched = root.join("src");
+    std::fs::create_dir_all(&watched)?;
+    let info = hook_config(
+        r#"{
+        "file_edited": {
+            "txt": [{"command": ["sh", "-c", "basename \"$OPENCODE_FILE\" >> ../hooks.log"]}]
+        }
+    }"#,
+    );
+    let hooks = Arc::new(HookRunner::from_info(&watched, &info));
+    let handle = watcher::watch(&watched, WatchOptions::default()).await?;
+    let listener = hooks.listen(handle);
+
+    std::fs::write(watched.join("changed.txt"), "data")?;
+
+    let log = root.join("hooks.log");
+    timeout(Duration::from_secs(5), async {
+        while !std::fs::read_to_string(&log).is_ok_and(|log| log.contains("changed.txt")) {
+            sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await?;
+
+    listener.abort();
+
+    let idle = Arc::new(HookRunner::from_info(&watched, &hook_config("{}")));
+    assert!(idle.watch(WatchOptions::default()).await.is_none());
+    let watching = Arc::new(HookRunner::from_info(&watched, &info))
+        .watch(WatchOptions::default())
+        .await;
+    watching.expect("watcher started").abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn slow_formatting_hooks_do_not_refire_on_their_own_writes() -> Result<()> {
+    let temp = tempdir()?;
+    let root = temp.path().canonicalize()?;
+    let watched = root.join("src");
+    std::fs::create_dir_all(&watched)?;
+    let info = hook_config(
+        r#"{
+        "file_edited": {
+            "txt": [{"command": ["sh", "-c", "echo run >> ../hooks.log; sleep 2.5; echo formatted > \"$OPENCODE_FILE\""]}]
+        }
+    }"#,
+    );
+    let hooks = Arc::new(HookRunner::from_info(&watched, &info));
+    let handle = watcher::watch(&watched, WatchOptions::default()).await?;
+    let listener = hooks.listen(handle);
+
+    let file = watched.join("slow.txt");
+    std::fs::write(&file, "data")?;
+    timeout(Duration::from_secs(10), async {
+        while !std::fs::read_to_string(&file).is_ok_and(|text| text == "formatted\n") {
+            sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await?;
+    sleep(Duration::from_millis(1500)).await;
+
+    listener.abort();
+    let log = std::fs::read_to_string(root.join("hooks.log"))?;
+    assert_eq!(log.lines().count(), 1, "{log}");
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use opencode_rust::agent::registry::{AgentRegistry, parse_agents_json};
+use opencode_rust::lsp::{Diagnostic, LspClient, path_to_uri, report_errors};
+use serde_json::{Value, json};
+use tempfile::tempdir;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, DuplexStream};
+
+async fn read_frame(reader: &mut BufReader<DuplexStream>) -> Option<Value> {
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await.ok()? == 0 {
+            return None;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok()?;
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await.ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+async fn write_frame(writer: &mut DuplexStream, value: Value) {
+    let body = serde_json::to_vec(&value).unwrap();
+    let header = format!("Content-Length: {}\r\n\r\n", body.len());
+    writer.write_all(header.as_bytes()).await.unwrap();
+    writer.write_all(&body).await.unwrap();
+}
+
+/// Minimal language server that reports one error and one warning for every
+/// document it is told about.
+async fn fake_server(input: DuplexStream, mut output: DuplexStream) {
+    let mut reader = BufReader::new(input);
+    while let Some(message) = read_frame(&mut reader).await {
+        let method = message["method"].as_str().unwrap_or_default();
+        match method {
+            "initialize" | "shutdown" => {
+                let result = if method == "initialize" {
+                    json!({ "capabilities": {} })
+                } else {
+                    Value::Null
+                };
+                write_frame(
+                    &mut output,
+                    json!({ "jsonrpc": "2.0", "id": message["id"], "result": result }),
+                )
+                .await;
+            }
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                let uri = message["params"]["textDocument"]["uri"].clone();
+                let range = json!({
+                    "start": { "line": 2, "character": 4 },
+                    "end": { "line": 2, "character": 9 }
+                });
+                write_frame(
+                    &mut output,
+                    json!({
+                        "jsonrpc": "2.0",
+                        "method": "textDocument/publishDiagnostics",
+                        "params": {
+                            "uri": uri,
+                            "diagnostics": [
+                                { "range": range, "severity": 1, "message": "expected `;`" },
+                                { "range": range, "severity": 2, "message": "unused import" }
+                            ]
+                        }
+                    }),
+                )
+                .await;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[tokio::test]
+async fn client_collects_published_diagnostics() -> Result<()> {
+    let temp = tempdir()?;
+    let file = temp.path().join("main.rs");
+    std::fs::write(&file, "fn main() {\n    let x = 1\n    x\n}\n")?;
+
+    let (client_out, server_in) = tokio::io::duplex(64 * 1024);
+    let (server_out, client_in) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(fake_server(server_in, server_out));
+
+    let client = LspClient::connect(client_in, client_out, temp.path(), None).await?;
+    let diagnostics = client.diagnostics(&file, Duration::from_secs(5)).await?;
+    assert_eq!(diagnostics.len(), 2);
+    assert!(path_to_uri(&file).starts_with("file://"));
+
+    let report = report_errors(&diagnostics).expect("error diagnostics");
+    assert!(report.contains("<file_diagnostics>"));
+    assert!(report.contains("ERROR [3:5] expected `;`"));
+    assert!(!report.contains("unused import"));
+
+    let refreshed = client.diagnostics(&file, Duration::from_secs(5)).await?;
+    assert_eq!(refreshed, diagnostics);
+
+    client.shutdown().await;
+    Ok(())
+}
+
+#[test]
+fn diagnostics_without_severity_are_reported_as_errors() -> Result<()> {
+    let diagnostic = |severity: Option<u8>, message: &str| -> Result<Diagnostic> {
+        Ok(serde_json::from_value(json!({
+            "range": {
+                "start": { "line": 0, "character": 0 },
+                "end": { "line": 0, "character": 1 }
+            },
+            "severity": severity,
+            "message": message
+        }))?)
+    };
+    let diagnostics = [
+        diagnostic(None, "unresolved name")?,
+        diagnostic(Some(3), "consider renaming")?,
+    ];
+    assert!(diagnostics[0].is_error());
+    assert!(!diagnostics[1].is_error());
+
+    let report = report_errors(&diagnostics).expect("error diagnostics");
+    assert!(report.contains("ERROR [1:1] unresolved name"));
+    assert!(!report.contains("consider renaming"));
+    Ok(())
+}
+
+#[test]
+fn agents_can_disable_diagnostics() -> Result<()> {
+    let mut registry = AgentRegistry::new();
+    let overrides = parse_agents_json(
+        r#"{
+        "reviewer": { "prompt": "Review only", "diagnostics": false }
+    }"#,
+    )?;
+    registry.apply_runtime_map(&overrides);
+
+    assert!(registry.require_spec("primary")?.diagnostics);
+    assert!(!registry.require_spec("reviewer")?.diagnostics);
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use opencode_rust::agent::registry::{AgentRegistry, parse_agents_json};
+use opencode_rust::agent::spec::{AgentBudgets, ModelHandle};
+use opencode_rust::session::{
+    AgentEvent, CompletionRequest, CompletionResponse, FailurePolicy, LanguageModel, LocalModel,
+    PartialOutput, ProjectContext, ProviderError, RateLimitedModel, RateLimiter, RateLimits,
+    RetryPolicy, RetryingModel, SessionRequest, SessionRuntime, SubagentInvocation,
+};
+use opencode_rust::util::config::Info;
+use serde_json::json;
+use tempfile::tempdir;
+use tokio::sync::mpsc;
+use validator::Validate;
+
+#[tokio::test(start_paused = true)]
+async fn buckets_queue_callers_until_the_allowance_refills() {
+    let limiter = RateLimiter::new()
+        .with_limits("acme", RateLimits::new().with_requests_per_minute(2))
+        .with_limits("tokens", RateLimits::new().with_tokens_per_minute(1000));
+
+    let waits: Vec<Duration> = (0..4).map(|_| limiter.reserve("acme", 10)).collect();
+    assert_eq!(
+        waits,
+        [
+            Duration::ZERO,
+            Duration::ZERO,
+            Duration::from_secs(30),
+            Duration::from_secs(60)
+        ]
+    );
+    tokio::time::advance(Duration::from_secs(30)).await;
+    assert_eq!(limiter.reserve("acme", 10), Duration::from_secs(60));
+
+    assert_eq!(limiter.reserve("tokens", 600), Duration::ZERO);
+    assert_eq!(limiter.reserve("tokens", 600), Duration::from_secs(12));
+    assert_eq!(limiter.reserve("unlimited", 1_000_000), Duration::ZERO);
+}
+
+#[test]
+fn reads_limits_from_provider_options() -> Result<()> {
+    let info: Info = serde_json::from_value(json!({
+        "provider": {
+            "acme": { "options": { "requestsPerMinute": 60, "tokensPerMinute": 90000 } },
+            "open": { "options": { "baseURL": "http://localhost:8080" } }
+        }
+    }))?;
+    info.validate()?;
+    let limiter = RateLimiter::from_info(&info);
+    assert_eq!(
+        limiter.limits("acme"),
+        Some(
+            RateLimits::new()
+                .with_requests_per_minute(60)
+                .with_tokens_per_minute(90000)
+        )
+    );
+    assert_eq!(limiter.limits("open"), None);
+
+    let zero: Info = serde_json::from_value(json!({
+        "provider": { "acme": { "options": { "requestsPerMinute": 0 } } }
+    }))?;
+    assert!(zero.validate().is_err());
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn subagents_share_the_provider_budget() -> Result<()> {
+    let temp = tempdir()?;
+    let context = Arc::new(ProjectContext::gather(temp.path(), &Info::default())?);
+    let mut registry = AgentRegistry::new();
+    registry.apply_runtime_map(&parse_agents_json(
+        r#"{ "worker": { "mode": "subagent" } }"#,
+    )?);
+    let limiter =
+        RateLimiter::new().with_limits("acme", RateLimits::new().with_requests_per_minute(3));
+    let (event_tx, mut events) = mpsc::channel(64);
+    let model = RateLimitedModel::new(Arc::new(LocalModel), Arc::new(limiter))
+        .with_events(event_tx.clone());
+    let runtime = SessionRuntime::new(
+        context,
+        Arc::new(registry),
+        Arc::new(model),
+        Vec::new(),
+        event_tx,
+        ModelHandle::new("acme/model"),
+    );
+
+    let mut request = SessionRequest::new("Fan out").with_failure_policy(FailurePolicy::CollectAll);
+    request.subtasks = (0..4)
+        .map(|n| SubagentInvocation::new("worker", format!("part {n}")))
+        .collect();
+    request.subtasks.push(
+        SubagentInvocation::new("worker", "elsewhere").with_model(ModelHandle::new("other/model")),
+    );
+
+    let started = tokio::time::Instant::now();
+    let result = runtime.execute(request).await?;
+    assert_eq!(result.subtasks.len(), 5);
+    assert_eq!(started.elapsed(), Duration::from_secs(40));
+
+    drop(runtime);
+    let mut queued = Vec::new();
+    while let Some(event) = events.recv().await {
+        if let AgentEvent::Queued {
+            provider, wait_ms, ..
+        } = event
+        {
+            queued.push((provider, wait_ms));
+        }
+    }
+    assert_eq!(
+        queued,
+        [("acme".to_string(), 20_000), ("acme".to_string(), 40_000)]
+    );
+    Ok(())
+}
+
+/// Throttles its first request, then answers like `LocalModel`.
+#[derive(Default)]
+struct ThrottledOnce {
+    calls: AtomicU32,
+}
+
+#[async_trait]
+impl LanguageModel for ThrottledOnce {
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        if self.calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            return Err(ProviderError::new(StatusCode::TOO_MANY_REQUESTS, "slow down").into());
+        }
+        LocalModel.complete(request).await
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn retried_attempts_are_charged_with_their_output_budget() -> Result<()> {
+    let limiter =
+        RateLimiter::new().with_limits("acme", RateLimits::new().with_tokens_per_minute(1200));
+    let inner = Arc::new(ThrottledOnce::default());
+    let model = RetryingModel::new(
+        Arc::new(RateLimitedModel::new(inner.clone(), Arc::new(limiter))),
+        RetryPolicy::new().with_initial_delay(Duration::from_millis(1)),
+    );
+    let request = CompletionRequest {
+        session_id: uuid::Uuid::new_v4(),
+        agent: "build".to_string(),
+        model: ModelHandle::new("acme/model"),
+        prompt: "hi".to_string(),
+        objective: "hi".to_string(),
+        budgets: AgentBudgets {
+            max_tokens: Some(1000),
+            ..AgentBudgets::default()
+        },
+        tool_names: Vec::new(),
+        partial: PartialOutput::new(),
+    };
+
+    let started = tokio::time::Instant::now();
+    model.complete(request).await?;
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+    // Each attempt takes the prompt and 1000 tokens of output, so the retry
+    // waits for roughly 800 tokens at 20 per second.
+    assert!(
+        started.elapsed() >= Duration::from_secs(40),
+        "{:?}",
+        started.elapsed()
+    );
+    Ok(())
+}
@@ -0,0 +1,249 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::Router;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::http::header::RETRY_AFTER;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use opencode_rust::agent::registry::{AgentRegistry, parse_agents_json};
+use opencode_rust::agent::spec::ModelHandle;
+use opencode_rust::session::{
+    AgentEvent, CompletionRequest, CompletionResponse, LanguageModel, ProjectContext,
+    ProviderError, RetryPolicy, RetryingModel, SessionRequest, SessionRuntime,
+};
+use opencode_rust::util::config::Info;
+use tempfile::tempdir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::{Mutex, mpsc};
+
+/// A provider that answers with scripted failures before settling on
+/// `otherwise`.
+struct Flaky {
+    script: Mutex<VecDeque<(u16, Option<&'static str>)>>,
+    otherwise: (u16, Option<&'static str>),
+    hits: AtomicUsize,
+}
+
+async fn complete(State(flaky): State<Arc<Flaky>>, body: String) -> Response {
+    flaky.hits.fetch_add(1, Ordering::SeqCst);
+    let (status, retry_after) = flaky
+        .script
+        .lock()
+        .await
+        .pop_front()
+        .unwrap_or(flaky.otherwise);
+    let status = StatusCode::from_u16(status).unwrap();
+    if status.is_success() {
+        return format!("done: {body}").into_response();
+    }
+    match retry_after {
+        Some(value) => (status, [(RETRY_AFTER, value)], "overloaded").into_response(),
+        None => (status, "overloaded").into_response(),
+    }
+}
+
+async fn serve(
+    script: Vec<(u16, Option<&'static str>)>,
+    otherwise: u16,
+) -> Result<(String, Arc<Flaky>)> {
+    let flaky = Arc::new(Flaky {
+        script: Mutex::new(script.into()),
+        otherwise: (otherwise, None),
+        hits: AtomicUsize::new(0),
+    });
+    let router = Router::new()
+        .route("/complete", post(complete))
+        .with_state(flaky.clone());
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/complete", listener.local_addr()?);
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    Ok((url, flaky))
+}
+
+/// Posts the objective to a provider endpoint and answers with its body.
+struct HttpModel {
+    url: String,
+    client: reqwest::Client,
+}
+
+#[async_trait]
+impl LanguageModel for HttpModel {
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        let response = self
+            .client
+            .post(&self.url)
+            .body(request.objective.clone())
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ProviderError::from_response(response).await.into());
+        }
+        let summary = response.text().await?;
+        Ok(CompletionResponse {
+            summary,
+            raw_output: String::new(),
+            usage: None,
+        })
+    }
+}
+
+fn runtime(
+    url: String,
+    policy: RetryPolicy,
+) -> Result<(SessionRuntime, mpsc::Receiver<AgentEvent>)> {
+    let temp = tempdir()?;
+    let context = Arc::new(ProjectContext::gather(temp.path(), &Info::default())?);
+    let mut registry = AgentRegistry::new();
+    registry.apply_runtime_map(&parse_agents_json(
+        r#"{
+        "lead": { "mode": "primary" },
+        "hasty": { "mode": "primary", "budgets": { "wallClockLimitMs": 300 } }
+    }"#,
+    )?);
+    let (event_tx, event_rx) = mpsc::channel(64);
+    let model = HttpModel {
+        url,
+        client: reqwest::Client::new(),
+    };
+    let model = RetryingModel::new(Arc::new(model), policy).with_events(event_tx.clone());
+    let runtime = SessionRuntime::new(
+        context,
+        Arc::new(registry),
+        Arc::new(model),
+        Vec::new(),
+        event_tx,
+        ModelHandle::new("baseline/model"),
+    );
+    Ok((runtime, event_rx))
+}
+
+fn request(agent: &str) -> SessionRequest {
+    let mut request = SessionRequest::new("Summarize");
+    request.agent = Some(agent.to_string());
+    request
+}
+
+fn fast_policy() -> RetryPolicy {
+    RetryPolicy::new().with_initial_delay(Duration::from_millis(10))
+}
+
+/// `(attempt, delay_ms, error)` of each `Retrying` event.
+fn retries(mut events: mpsc::Receiver<AgentEvent>) -> Vec<(u32, u64, String)> {
+    let mut retries = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        if let AgentEvent::Retrying {
+            attempt,
+            delay_ms,
+            error,
+            ..
+        } = event
+        {
+            retries.push((attempt, delay_ms, error));
+        }
+    }
+    retries
+}
+
+#[tokio::test]
+async fn retries_throttling_and_server_errors() -> Result<()> {
+    let (url, flaky) = serve(vec![(503, None), (429, Some("1"))], 200).await?;
+    let (runtime, events) = runtime(url, fast_policy())?;
+
+    let result = runtime.execute(request("lead")).await?;
+    assert_eq!(result.primary.summary, "done: Summarize");
+    assert_eq!(flaky.hits.load(Ordering::SeqCst), 3);
+
+    drop(runtime);
+    let retries = retries(events);
+    assert_eq!(retries.len(), 2);
+    let (attempt, delay_ms, error) = &retries[0];
+    assert_eq!(*attempt, 2);
+    assert!((5..=10).contains(delay_ms), "{delay_ms}");
+    assert_eq!(
+        error,
+        "provider returned 503 Service Unavailable: overloaded"
+    );
+    let (attempt, delay_ms, error) = &retries[1];
+    assert_eq!((*attempt, *delay_ms), (3, 1000));
+    assert!(error.contains("429 Too Many Requests"), "{error}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn does_not_retry_client_errors() -> Result<()> {
+    let (url, flaky) = serve(Vec::new(), 400).await?;
+    let (runtime, events) = runtime(url, fast_policy())?;
+
+    let err = runtime.execute(request("lead")).await.unwrap_err();
+    assert_eq!(
+        format!("{err:#}"),
+        "provider returned 400 Bad Request: overloaded"
+    );
+    assert_eq!(flaky.hits.load(Ordering::SeqCst), 1);
+    drop(runtime);
+    assert!(retries(events).is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn gives_up_after_max_retries() -> Result<()> {
+    let (url, flaky) = serve(Vec::new(), 500).await?;
+    let (runtime, events) = runtime(url, fast_policy().with_max_retries(2))?;
+
+    let err = runtime.execute(request("lead")).await.unwrap_err();
+    assert_eq!(
+        format!("{err:#}"),
+        "gave up after 3 attempts: provider returned 500 Internal Server Error: overloaded"
+    );
+    assert_eq!(flaky.hits.load(Ordering::SeqCst), 3);
+    drop(runtime);
+    assert_eq!(retries(events).len(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn retries_count_against_the_wall_clock_budget() -> Result<()> {
+    let (url, flaky) = serve(vec![(503, Some("5"))], 200).await?;
+    let (runtime, _events) = runtime(url, fast_policy())?;
+
+    let started = std::time::Instant::now();
+    let result = runtime.execute(request("hasty")).await?;
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert!(result.primary.timed_out);
+    assert_eq!(flaky.hits.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn retries_connection_resets() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/complete", listener.local_addr()?);
+    tokio::spawn(async move {
+        let (first, _) = listener.accept().await.unwrap();
+        first.set_linger(Some(Duration::ZERO)).unwrap();
+        drop(first);
+        let (mut second, _) = listener.accept().await.unwrap();
+        let mut buffer = [0u8; 4096];
+        let _ = second.read(&mut buffer).await.unwrap();
+        let body = "recovered";
+        let response = format!(
+            "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        second.write_all(response.as_bytes()).await.unwrap();
+    });
+    let (runtime, events) = runtime(url, fast_policy())?;
+
+    let result = runtime.execute(request("lead")).await?;
+    assert_eq!(result.primary.summary, "recovered");
+    drop(runtime);
+    assert_eq!(retries(events).len(), 1);
+    Ok(())
+}
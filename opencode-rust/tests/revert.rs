@@ -0,0 +1,316 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use opencode_rust::agent::registry::AgentRegistry;
+use opencode_rust::agent::spec::ModelHandle;
+use opencode_rust::server::{self, AppState};
+use opencode_rust::session::{
+    AgentEvent, CompletionRequest, CompletionResponse, LanguageModel, ProjectContext, Role,
+    SessionRequest, SessionRevert, SessionRuntime, SessionStore, StoredSession, Todo, TodoLists,
+    TodoStatus,
+};
+use opencode_rust::snapshot::Snapshot;
+use opencode_rust::tool::core::Tool;
+use opencode_rust::util::config;
+use serde_json::{Value, json};
+use tempfile::{TempDir, tempdir};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+/// Treats objectives of the form `path=content` as file edits; editing
+/// `sleep` first waits that many milliseconds.
+struct EditingModel {
+    root: PathBuf,
+}
+
+#[async_trait]
+impl LanguageModel for EditingModel {
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        let (path, content) = request.objective.split_once('=').unwrap();
+        if path == "sleep" {
+            tokio::time::sleep(std::time::Duration::from_millis(content.parse()?)).await;
+        }
+        fs::write(self.root.join(path), content)?;
+        Ok(CompletionResponse {
+            summary: format!("wrote {path}"),
+            raw_output: String::new(),
+            usage: None,
+        })
+    }
+}
+
+struct Fixture {
+    worktree: TempDir,
+    _data: TempDir,
+    runtime: SessionRuntime,
+    store: Arc<SessionStore>,
+    revert: Arc<SessionRevert>,
+}
+
+impl Fixture {
+    fn new() -> Result<Self> {
+        let worktree = tempdir()?;
+        let data = tempdir()?;
+        fs::write(worktree.path().join("a.txt"), "a1")?;
+        let info = config::Info::default();
+        let context = Arc::new(ProjectContext::gather(worktree.path(), &info)?);
+        let snapshot = Arc::new(Snapshot::new(worktree.path(), data.path().join("snapshot")));
+        let store = Arc::new(SessionStore::new(data.path().join("sessions")));
+        let (event_tx, _event_rx) = mpsc::channel(16);
+        let runtime = SessionRuntime::new(
+            context,
+            Arc::new(AgentRegistry::new()),
+            Arc::new(EditingModel {
+                root: worktree.path().to_path_buf(),
+            }),
+            Vec::<Arc<dyn Tool>>::new(),
+            event_tx,
+            ModelHandle::new("baseline/model"),
+        )
+        .with_snapshot(snapshot.clone());
+        let revert = Arc::new(SessionRevert::new(store.clone(), snapshot));
+        Ok(Self {
+            worktree,
+            _data: data,
+            runtime,
+            store,
+            revert,
+        })
+    }
+
+    async fn turn(&self, session: Option<StoredSession>, prompt: &str) -> Result<StoredSession> {
+        let result = self.runtime.execute(SessionRequest::new(prompt)).await?;
+        let mut session =
+            session.unwrap_or_else(|| StoredSession::new(result.primary.session_id, "test"));
+        session.record_turn(prompt, &result);
+        self.store.save(&session).await?;
+        Ok(session)
+    }
+
+    fn read(&self, path: &str) -> Option<String> {
+        fs::read_to_string(self.worktree.path().join(path)).ok()
+    }
+}
+
+#[tokio::test]
+async fn reverts_and_unreverts_files_and_history() -> Result<()> {
+    let fixture = Fixture::new()?;
+    let session = fixture.turn(None, "a.txt=a2").await?;
+    let session = fixture.turn(Some(session), "b.txt=b2").await?;
+    assert_eq!(session.messages.len(), 4);
+    let second_reply = session.messages[3].id;
+    assert_eq!(session.messages[3].role, Role::Assistant);
+
+    let reverted = fixture.revert.revert(session.id, second_reply).await?;
+    assert_eq!(fixture.read("a.txt").as_deref(), Some("a2"));
+    assert_eq!(fixture.read("b.txt"), None);
+    assert_eq!(reverted.visible_messages().len(), 2);
+    assert!(
+        reverted
+            .revert
+            .as_ref()
+            .unwrap()
+            .diff
+            .as_deref()
+            .unwrap()
+            .contains("-b2")
+    );
+
+    // Reverting further back while a revert is pending still unreverts to
+    // the latest state.
+    let reverted = fixture
+        .revert
+        .revert(session.id, session.messages[0].id)
+        .await?;
+    assert_eq!(fixture.read("a.txt").as_deref(), Some("a1"));
+    assert!(reverted.visible_messages().is_empty());
+
+    let restored = fixture.revert.unrevert(session.id).await?;
+    assert!(restored.revert.is_none());
+    assert_eq!(restored.visible_messages().len(), 4);
+    assert_eq!(fixture.read("a.txt").as_deref(), Some("a2"));
+    assert_eq!(fixture.read("b.txt").as_deref(), Some("b2"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn new_turn_discards_reverted_messages() -> Result<()> {
+    let fixture = Fixture::new()?;
+    let session = fixture.turn(None, "a.txt=a2").await?;
+    let session = fixture.turn(Some(session), "a.txt=a3").await?;
+    fixture
+        .revert
+        .revert(session.id, session.messages[2].id)
+        .await?;
+    assert_eq!(fixture.read("a.txt").as_deref(), Some("a2"));
+
+    let session = fixture.store.get(session.id).await?;
+    let session = fixture.turn(Some(session), "c.txt=c1").await?;
+    let contents: Vec<_> = session
+        .messages
+        .iter()
+        .map(|m| m.content.as_str())
+        .collect();
+    assert_eq!(
+        contents,
+        vec!["a.txt=a2", "wrote a.txt", "c.txt=c1", "wrote c.txt"]
+    );
+    assert!(session.revert.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn server_exposes_revert_and_unrevert() -> Result<()> {
+    let fixture = Fixture::new()?;
+    let mut session = fixture.turn(None, "a.txt=a2").await?;
+    session.todos = vec![Todo::new("1", "Review").with_status(TodoStatus::InProgress)];
+    fixture.store.save(&session).await?;
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let base = format!("http://{}", listener.local_addr()?);
+    let server = tokio::spawn(server::serve(
+        listener,
+        AppState::new(fixture.revert.clone()),
+    ));
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{base}/session/{}/revert", session.id))
+        .json(&json!({ "messageID": session.messages[1].id }))
+        .send()
+        .await?;
+    assert!(response.status().is_success());
+    let body: Value = response.json().await?;
+    assert_eq!(
+        body["revert"]["message_id"],
+        session.messages[0].id.to_string()
+    );
+    assert_eq!(fixture.read("a.txt").as_deref(), Some("a1"));
+
+    let messages: Vec<Value> = client
+        .get(format!("{base}/session/{}/message", session.id))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert!(messages.is_empty());
+
+    let todos: Value = client
+        .get(format!("{base}/session/{}/todo", session.id))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(
+        todos,
+        json!([{ "id": "1", "content": "Review", "status": "in_progress", "priority": "medium" }])
+    );
+
+    let response = client
+        .post(format!("{base}/session/{}/unrevert", session.id))
+        .send()
+        .await?;
+    assert!(response.status().is_success());
+    assert_eq!(fixture.read("a.txt").as_deref(), Some("a2"));
+
+    let missing = client
+        .post(format!("{base}/session/{}/unrevert", uuid::Uuid::new_v4()))
+        .send()
+        .await?;
+    assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+
+    server.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn server_rejects_prompts_while_the_session_is_busy() -> Result<()> {
+    let fixture = Fixture::new()?;
+    let session = fixture.turn(None, "a.txt=a2").await?;
+    let Fixture {
+        _data,
+        worktree,
+        runtime,
+        revert,
+        ..
+    } = fixture;
+    let todos = Arc::new(TodoLists::new());
+    let state = AppState::new(revert).with_runtime(Arc::new(runtime), todos);
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let base = format!("http://{}", listener.local_addr()?);
+    let server = tokio::spawn(server::serve(listener, state));
+    let client = reqwest::Client::new();
+    let url = format!("{base}/session/{}/message", session.id);
+
+    let slow = tokio::spawn(
+        client
+            .post(&url)
+            .json(&json!({ "prompt": "sleep=500" }))
+            .send(),
+    );
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    let busy = client
+        .post(&url)
+        .json(&json!({ "prompt": "a.txt=a3" }))
+        .send()
+        .await?;
+    assert_eq!(busy.status(), reqwest::StatusCode::CONFLICT);
+    let body: Value = busy.json().await?;
+    assert!(body["error"].as_str().unwrap().contains("busy"), "{body}");
+    let revert = client
+        .post(format!("{base}/session/{}/unrevert", session.id))
+        .send()
+        .await?;
+    assert_eq!(revert.status(), reqwest::StatusCode::CONFLICT);
+
+    assert!(slow.await??.status().is_success());
+    let response = client
+        .post(&url)
+        .json(&json!({ "prompt": "a.txt=a3" }))
+        .send()
+        .await?;
+    assert!(response.status().is_success());
+    let saved: StoredSession = response.json().await?;
+    assert_eq!(saved.messages.len(), 6);
+    assert_eq!(fs::read_to_string(worktree.path().join("a.txt"))?, "a3");
+
+    server.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn server_streams_published_agent_events() -> Result<()> {
+    let fixture = Fixture::new()?;
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let base = format!("http://{}", listener.local_addr()?);
+    let state = AppState::new(fixture.revert.clone());
+    let server = tokio::spawn(server::serve(listener, state.clone()));
+
+    let mut response = reqwest::get(format!("{base}/event")).await?;
+    assert!(response.status().is_success());
+    let session_id = uuid::Uuid::new_v4();
+    state.publish(AgentEvent::TodoUpdated {
+        session_id,
+        agent: "build".to_string(),
+        todos: vec![Todo::new("1", "Review").with_status(TodoStatus::InProgress)],
+    });
+
+    let mut body = String::new();
+    while !body.contains("\n\n") {
+        let chunk = response.chunk().await?.expect("event stream ended");
+        body.push_str(std::str::from_utf8(&chunk)?);
+    }
+    let data = body
+        .lines()
+        .find_map(|line| line.strip_prefix("data: "))
+        .expect("event data");
+    let event: Value = serde_json::from_str(data)?;
+    assert_eq!(event["type"], "todo_updated");
+    assert_eq!(event["session_id"], session_id.to_string());
+    assert_eq!(event["todos"][0]["status"], "in_progress");
+
+    server.abort();
+    Ok(())
+}
@@ -0,0 +1,225 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use serde_json::{Value, json};
+use tempfile::{TempDir, tempdir};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+/// A project directory run through the `opencode-rust` binary, with its own
+/// home so sessions and snapshots stay out of the real data directory.
+struct Project {
+    root: TempDir,
+    home: TempDir,
+}
+
+impl Project {
+    fn new() -> Result<Self> {
+        Ok(Self {
+            root: tempdir()?,
+            home: tempdir()?,
+        })
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.root.path().join(name)
+    }
+
+    fn read(&self, name: &str) -> String {
+        fs::read_to_string(self.path(name)).unwrap()
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_opencode-rust"));
+        command
+            .args(args)
+            .current_dir(self.root.path())
+            .env("HOME", self.home.path())
+            .env("XDG_DATA_HOME", self.home.path().join("data"))
+            .env("XDG_CONFIG_HOME", self.home.path().join("config"))
+            .env("RUST_BACKTRACE", "0");
+        command
+    }
+
+    fn run(&self, args: &[&str]) -> Result<Output> {
+        Ok(self.command(args).output()?)
+    }
+
+    fn run_with_input(&self, args: &[&str], input: &str) -> Result<Output> {
+        let mut child = self
+            .command(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        child
+            .stdin
+            .take()
+            .expect("piped stdin")
+            .write_all(input.as_bytes())?;
+        Ok(child.wait_with_output()?)
+    }
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn reads_then_edits_through_the_cli() -> Result<()> {
+    let project = Project::new()?;
+    fs::write(project.path("a.txt"), "hello\n")?;
+
+    let read = project.run(&["run", "read_file", "a.txt"])?;
+    assert!(read.status.success(), "{}", stderr(&read));
+    let edit = project.run(&["run", "edit", "a.txt", "hello", "bye"])?;
+    assert!(edit.status.success(), "{}", stderr(&edit));
+    assert!(stdout(&edit).contains("+bye"));
+    assert_eq!(project.read("a.txt"), "bye\n");
+    Ok(())
+}
+
+#[test]
+fn continued_sessions_refuse_edits_to_files_changed_since_read() -> Result<()> {
+    let project = Project::new()?;
+    fs::write(project.path("a.txt"), "one\n")?;
+    assert!(project.run(&["run", "Start"])?.status.success());
+
+    let read = project.run(&["run", "--continue", "read_file", "a.txt"])?;
+    assert!(read.status.success(), "{}", stderr(&read));
+    fs::write(project.path("a.txt"), "two\n")?;
+    File::options()
+        .write(true)
+        .open(project.path("a.txt"))?
+        .set_modified(SystemTime::now() - Duration::from_secs(60))?;
+
+    let edit = project.run(&["run", "--continue", "edit", "a.txt", "two", "three"])?;
+    assert!(!edit.status.success());
+    assert!(stderr(&edit).contains("modified since it was last read"));
+    assert_eq!(project.read("a.txt"), "two\n");
+
+    assert!(
+        project
+            .run(&["run", "--continue", "read_file", "a.txt"])?
+            .status
+            .success()
+    );
+    let edit = project.run(&["run", "--continue", "edit", "a.txt", "two", "three"])?;
+    assert!(edit.status.success(), "{}", stderr(&edit));
+    assert_eq!(project.read("a.txt"), "three\n");
+    Ok(())
+}
+
+#[test]
+fn applies_patches_given_after_dashes_or_on_stdin() -> Result<()> {
+    let project = Project::new()?;
+    fs::write(project.path("config.toml"), "a = 1\n")?;
+    let patch = |from: u32, to: u32| {
+        format!("--- a/config.toml\n+++ b/config.toml\n@@ -1 +1 @@\n-a = {from}\n+a = {to}\n")
+    };
+
+    let rejected = project.run(&["run", "apply_patch", &patch(1, 2)])?;
+    assert!(!rejected.status.success());
+
+    let applied = project.run(&["run", "apply_patch", "--", &patch(1, 2)])?;
+    assert!(applied.status.success(), "{}", stderr(&applied));
+    assert!(stdout(&applied).contains("M config.toml"));
+    assert_eq!(project.read("config.toml"), "a = 2\n");
+
+    let piped = project.run_with_input(&["run", "apply_patch", "-"], &patch(2, 3))?;
+    assert!(piped.status.success(), "{}", stderr(&piped));
+    assert_eq!(project.read("config.toml"), "a = 3\n");
+    Ok(())
+}
+
+#[test]
+fn reverts_and_unreverts_tool_edits() -> Result<()> {
+    let project = Project::new()?;
+    fs::write(project.path("a.txt"), "one\n")?;
+
+    let edit = project.run(&["run", "edit", "a.txt", "one", "two"])?;
+    assert!(edit.status.success(), "{}", stderr(&edit));
+    assert_eq!(project.read("a.txt"), "two\n");
+
+    let sessions = stdout(&project.run(&["session", "list"])?);
+    let session = sessions.split_whitespace().next().expect("a session");
+    let messages = stdout(&project.run(&["session", "messages", session])?);
+    let message = messages
+        .lines()
+        .find(|line| line.contains("tool      [edit]"))
+        .and_then(|line| line.split_whitespace().next())
+        .expect("the edit is recorded");
+
+    let revert = project.run(&["session", "revert", session, message])?;
+    assert!(revert.status.success(), "{}", stderr(&revert));
+    assert_eq!(project.read("a.txt"), "one\n");
+
+    let unrevert = project.run(&["session", "unrevert", session])?;
+    assert!(unrevert.status.success(), "{}", stderr(&unrevert));
+    assert_eq!(project.read("a.txt"), "two\n");
+    Ok(())
+}
+
+#[test]
+fn runs_without_web_search_when_its_config_is_broken() -> Result<()> {
+    let project = Project::new()?;
+    fs::write(
+        project.path("opencode.json"),
+        r#"{ "search": { "backend": "json" } }"#,
+    )?;
+
+    let output = project.run(&["--print-logs", "run", "Hello"])?;
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("skipping web_search"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn serve_runs_prompts_and_streams_their_events() -> Result<()> {
+    let project = Project::new()?;
+    let mut server = tokio::process::Command::from(project.command(&["serve"]))
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut banner = String::new();
+    BufReader::new(server.stdout.take().expect("piped stdout"))
+        .read_line(&mut banner)
+        .await?;
+    let base = banner
+        .trim()
+        .strip_prefix("opencode server listening on ")
+        .expect("listening banner")
+        .to_string();
+
+    let mut events = reqwest::get(format!("{base}/event")).await?;
+    let session: Value = reqwest::Client::new()
+        .post(format!("{base}/session"))
+        .json(&json!({ "prompt": "Hello" }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(session["messages"].as_array().map(Vec::len), Some(2));
+
+    let mut body = String::new();
+    while !body.contains("\n\n") {
+        let chunk = events.chunk().await?.expect("event stream ended");
+        body.push_str(std::str::from_utf8(&chunk)?);
+    }
+    let data = body
+        .lines()
+        .find_map(|line| line.strip_prefix("data: "))
+        .expect("event data");
+    let event: Value = serde_json::from_str(data)?;
+    assert_eq!(event["type"], "started");
+    assert_eq!(event["session_id"], session["id"]);
+    Ok(())
+}
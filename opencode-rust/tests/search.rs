@@ -0,0 +1,104 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use opencode_rust::search::{self, SearchOptions};
+use tempfile::tempdir;
+
+fn write(root: &Path, path: &str, content: &str) {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+fn paths(values: &[&str]) -> Vec<PathBuf> {
+    values.iter().map(PathBuf::from).collect()
+}
+
+#[test]
+fn lists_files_respecting_gitignore_and_globs() -> Result<()> {
+    let temp = tempdir()?;
+    let root = temp.path();
+    write(root, ".gitignore", "*.log\ngenerated/\n");
+    write(root, "src/main.rs", "fn main() {}");
+    write(root, "src/lib.rs", "");
+    write(root, "README.md", "# readme");
+    write(root, "debug.log", "noise");
+    write(root, "generated/out.rs", "");
+    write(root, ".git/HEAD", "ref: refs/heads/main");
+    write(root, "node_modules/pkg/index.js", "");
+
+    let all = search::files(root, &SearchOptions::new())?;
+    assert_eq!(
+        all,
+        paths(&[
+            ".gitignore",
+            "README.md",
+            "node_modules/pkg/index.js",
+            "src/lib.rs",
+            "src/main.rs"
+        ])
+    );
+
+    let options = SearchOptions::new()
+        .with_globs(["*.rs", "!main.rs"])
+        .skip_ignored_folders();
+    assert_eq!(search::files(root, &options)?, paths(&["src/lib.rs"]));
+
+    let limited = search::files(root, &SearchOptions::new().with_limit(Some(2)))?;
+    assert_eq!(limited.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn searches_contents_with_limit_and_skips_binaries() -> Result<()> {
+    let temp = tempdir()?;
+    let root = temp.path();
+    write(root, "a.rs", "fn alpha() {}\nlet x = 1;\nfn beta() {}\n");
+    write(root, "b.txt", "fn gamma\n");
+    fs::write(root.join("blob.bin"), b"fn binary\0data")?;
+
+    let found = search::search(root, r"fn \w+", &SearchOptions::new())?;
+    let lines: Vec<_> = found
+        .iter()
+        .map(|m| format!("{}:{}:{}", m.path.display(), m.line_number, m.line))
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            "a.rs:1:fn alpha() {}",
+            "a.rs:3:fn beta() {}",
+            "b.txt:1:fn gamma"
+        ]
+    );
+    assert!(found.iter().all(|m| m.modified.is_some()));
+
+    let rust_only = SearchOptions::new()
+        .with_globs(["*.rs"])
+        .with_limit(Some(1));
+    assert_eq!(search::search(root, "fn", &rust_only)?.len(), 1);
+    assert!(search::search(root, "(unclosed", &SearchOptions::new()).is_err());
+    Ok(())
+}
+
+#[test]
+fn renders_breadth_first_tree_with_truncation() -> Result<()> {
+    let temp = tempdir()?;
+    let root = temp.path();
+    write(root, "src/a.rs", "");
+    write(root, "src/b.rs", "");
+    write(root, "src/nested/c.rs", "");
+    write(root, "docs/guide.md", "");
+    write(root, "Cargo.toml", "");
+    write(root, ".opencode/command/x.md", "");
+
+    assert_eq!(
+        search::tree(root, 50)?,
+        "docs/\n\tguide.md\nsrc/\n\tnested/\n\t\tc.rs\n\ta.rs\n\tb.rs\nCargo.toml"
+    );
+    assert_eq!(
+        search::tree(root, 4)?,
+        "docs/\n\tguide.md\nsrc/\n\t[3 truncated]\nCargo.toml"
+    );
+    Ok(())
+}
@@ -1,13 +1,19 @@
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
 use anyhow::Result;
+use async_trait::async_trait;
 use opencode_rust::agent::registry::{AgentRegistry, parse_agents_json};
 use opencode_rust::agent::spec::ModelHandle;
 use opencode_rust::session::{
-    AgentEvent, LocalModel, ProjectContext, SessionRequest, SessionRuntime, SubagentInvocation,
+    AgentEvent, CompletionRequest, CompletionResponse, FailurePolicy, LanguageModel, LocalModel,
+    ProjectContext, SessionRequest, SessionRuntime, SubagentInvocation, SubtaskFailureKind,
+    SubtaskGraph, TodoLists,
 };
-use opencode_rust::tool::core::Tool;
+use opencode_rust::tool::core::{Tool, ToolContext};
 use opencode_rust::tool::echo::EchoTool;
+use opencode_rust::tool::todo::TodoWriteTool;
 use opencode_rust::util::config::Info;
 use tempfile::tempdir;
 use tokio::sync::{Mutex, mpsc};
@@ -40,7 +46,7 @@ async fn executes_subagents_and_emits_events() -> Result<()> {
     let runtime = SessionRuntime::new(
         context,
         registry,
-        Arc::new(LocalModel::default()),
+        Arc::new(LocalModel),
         tools,
         event_tx,
         ModelHandle::new("baseline/model"),
@@ -57,11 +63,17 @@ async fn executes_subagents_and_emits_events() -> Result<()> {
 
     let request = SessionRequest {
         agent: Some("primary".to_string()),
+        model: None,
         objective: "Coordinate build".to_string(),
         subtasks: vec![
             SubagentInvocation::new("builder", "Compile artifacts"),
             SubagentInvocation::new("builder", "Write report"),
         ],
+        max_parallel: None,
+        failure_policy: FailurePolicy::FailFast,
+        synthesize: false,
+        history: Vec::new(),
+        session_id: None,
     };
 
     let result = runtime.execute(request).await?;
@@ -83,3 +95,615 @@ async fn executes_subagents_and_emits_events() -> Result<()> {
 
     Ok(())
 }
+
+/// Reports the model and tools each agent was given.
+struct ToolListingModel;
+
+#[async_trait]
+impl LanguageModel for ToolListingModel {
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        Ok(CompletionResponse {
+            summary: format!(
+                "{} on {} with [{}]: {}",
+                request.agent,
+                request.model,
+                request.tool_names.join(", "),
+                request.objective
+            ),
+            raw_output: String::new(),
+            usage: None,
+        })
+    }
+}
+
+#[tokio::test]
+async fn task_tool_runs_subagents_in_child_sessions() -> Result<()> {
+    let temp = tempdir()?;
+    let context = Arc::new(ProjectContext::gather(temp.path(), &Info::default())?);
+    let mut registry = AgentRegistry::new();
+    registry.apply_runtime_map(&parse_agents_json(
+        r#"{
+        "primary": { "mode": "primary", "model": "lead/model" },
+        "explorer": { "mode": "subagent", "description": "Searches the codebase" },
+        "planner": { "mode": "subagent", "tools": { "allow": ["todowrite", "echo"] } },
+        "reviewer": { "mode": "subagent", "model": "review/model", "tools": { "deny": ["echo"] } }
+    }"#,
+    )?);
+    let registry = Arc::new(registry);
+    let (event_tx, mut event_rx) = mpsc::channel(16);
+    let tools: Vec<Arc<dyn Tool>> = vec![
+        Arc::new(EchoTool),
+        Arc::new(TodoWriteTool::new(Arc::new(TodoLists::new()))),
+    ];
+    let runtime = SessionRuntime::new(
+        context,
+        registry.clone(),
+        Arc::new(ToolListingModel),
+        tools,
+        event_tx,
+        ModelHandle::new("baseline/model"),
+    )
+    .into_shared_with_task();
+
+    let task = runtime
+        .tools()
+        .iter()
+        .find(|tool| tool.name() == "task")
+        .cloned()
+        .expect("task tool is registered");
+    let description = task.description();
+    assert!(description.contains("- explorer: Searches the codebase"));
+    assert!(description.contains("- planner: This subagent should only be called manually"));
+    assert!(!description.contains("- primary:"));
+
+    let ctx = ToolContext::new(uuid::Uuid::new_v4(), registry.require_spec("primary")?);
+    let args = |agent: &str, prompt: &str| vec![agent.to_string(), prompt.to_string()];
+    assert_eq!(
+        task.execute_with_context(&ctx, &args("explorer", "find the parser"))
+            .await?,
+        "explorer on lead/model with [echo]: find the parser"
+    );
+    assert_eq!(
+        task.execute_with_context(&ctx, &args("planner", "plan it"))
+            .await?,
+        "planner on lead/model with [echo, todowrite]: plan it"
+    );
+    assert_eq!(
+        task.execute_with_context(&ctx, &args("reviewer", "review"))
+            .await?,
+        "reviewer on review/model with []: review"
+    );
+
+    let Some(AgentEvent::Started {
+        session_id, agent, ..
+    }) = event_rx.recv().await
+    else {
+        panic!("expected the child to start");
+    };
+    assert_eq!(agent, "explorer");
+    assert_ne!(session_id, ctx.session_id);
+
+    for agent in ["primary", "missing"] {
+        let err = task
+            .execute_with_context(&ctx, &args(agent, "work"))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains(&format!("unknown agent type {agent}")),
+            "{err}"
+        );
+    }
+    assert!(task.execute(&[]).await?.starts_with("Usage:"));
+
+    drop(runtime);
+    let err = task
+        .execute_with_context(&ctx, &args("explorer", "again"))
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("runtime has shut down"), "{err}");
+    Ok(())
+}
+
+/// Answers with the objective after a delay set by the agent name, so
+/// independent subtasks finish out of declaration order.
+struct EchoObjectiveModel;
+
+#[async_trait]
+impl LanguageModel for EchoObjectiveModel {
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        let delay = if request.agent == "slow" { 50 } else { 0 };
+        tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+        Ok(CompletionResponse {
+            summary: format!("<{}>", request.objective),
+            raw_output: format!("raw {}", request.agent),
+            usage: None,
+        })
+    }
+}
+
+#[tokio::test]
+async fn delegate_runs_one_child_turn_without_the_parent() -> Result<()> {
+    let temp = tempdir()?;
+    let context = Arc::new(ProjectContext::gather(temp.path(), &Info::default())?);
+    let mut registry = AgentRegistry::new();
+    registry.apply_runtime_map(&parse_agents_json(
+        r#"{
+        "primary": { "mode": "primary", "model": "lead/model" },
+        "reviewer": { "mode": "subagent" }
+    }"#,
+    )?);
+    let (event_tx, mut event_rx) = mpsc::channel(16);
+    let tools: Vec<Arc<dyn Tool>> = vec![
+        Arc::new(EchoTool),
+        Arc::new(TodoWriteTool::new(Arc::new(TodoLists::new()))),
+    ];
+    let runtime = SessionRuntime::new(
+        context,
+        Arc::new(registry),
+        Arc::new(ToolListingModel),
+        tools,
+        event_tx,
+        ModelHandle::new("baseline/model"),
+    )
+    .into_shared_with_task();
+
+    let result = runtime
+        .delegate(None, SubagentInvocation::new("reviewer", "review src"))
+        .await?;
+    assert_eq!(
+        result.primary.summary,
+        "reviewer on lead/model with [echo]: review src"
+    );
+    assert!(result.subtasks.is_empty() && result.synthesis.is_none());
+
+    let invocation =
+        SubagentInvocation::new("reviewer", "again").with_model(ModelHandle::new("review/model"));
+    let result = runtime.delegate(Some("primary"), invocation).await?;
+    assert_eq!(result.answer().model.id(), "review/model");
+
+    drop(runtime);
+    let mut started = Vec::new();
+    while let Some(event) = event_rx.recv().await {
+        if let AgentEvent::Started { agent, .. } = event {
+            started.push(agent);
+        }
+    }
+    assert_eq!(started, ["reviewer", "reviewer"]);
+    Ok(())
+}
+
+fn dag_runtime() -> Result<SessionRuntime> {
+    let temp = tempdir()?;
+    let context = Arc::new(ProjectContext::gather(temp.path(), &Info::default())?);
+    let mut registry = AgentRegistry::new();
+    registry.apply_runtime_map(&parse_agents_json(
+        r#"{ "slow": { "mode": "subagent" }, "fast": { "mode": "subagent" } }"#,
+    )?);
+    let (event_tx, _event_rx) = mpsc::channel(64);
+    Ok(SessionRuntime::new(
+        context,
+        Arc::new(registry),
+        Arc::new(EchoObjectiveModel),
+        Vec::new(),
+        event_tx,
+        ModelHandle::new("baseline/model"),
+    ))
+}
+
+#[tokio::test]
+async fn subtasks_run_as_a_dependency_graph() -> Result<()> {
+    let runtime = dag_runtime()?;
+    let mut request = SessionRequest::new("Coordinate");
+    request.subtasks = vec![
+        SubagentInvocation::new(
+            "fast",
+            "Write report from {{research.summary}} and {{ lint.raw_output }}",
+        )
+        .with_id("report"),
+        SubagentInvocation::new("slow", "Research").with_id("research"),
+        SubagentInvocation::new("fast", "Lint")
+            .with_id("lint")
+            .with_depends_on(["research"]),
+        SubagentInvocation::new("fast", "Unrelated"),
+    ];
+
+    let result = runtime.execute(request).await?;
+    let summaries: Vec<(Option<&str>, &str)> = result
+        .subtasks
+        .iter()
+        .map(|outcome| (outcome.id.as_deref(), outcome.summary.as_str()))
+        .collect();
+    assert_eq!(
+        summaries,
+        [
+            (Some("research"), "<Research>"),
+            (Some("lint"), "<Lint>"),
+            (
+                Some("report"),
+                "<Write report from <Research> and raw fast>"
+            ),
+            (None, "<Unrelated>"),
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_invalid_subtask_graphs() -> Result<()> {
+    let error =
+        |subtasks: Vec<SubagentInvocation>| SubtaskGraph::build(&subtasks).unwrap_err().to_string();
+    let task = |id: &str, objective: &str| SubagentInvocation::new("fast", objective).with_id(id);
+
+    assert_eq!(
+        error(vec![
+            task("a", "{{c.summary}}"),
+            task("b", "x").with_depends_on(["a"]),
+            task("c", "x").with_depends_on(["b"]),
+        ]),
+        "subtask dependency cycle: 'a' -> 'c' -> 'b' -> 'a'"
+    );
+    assert_eq!(
+        error(vec![task("a", "x").with_depends_on(["a"])]),
+        "subtask 'a' depends on itself"
+    );
+    assert_eq!(
+        error(vec![task("a", "x"), task("a", "y")]),
+        "duplicate subtask id 'a'"
+    );
+    assert_eq!(
+        error(vec![
+            SubagentInvocation::new("fast", "x").with_depends_on(["missing"])
+        ]),
+        "subtask #1 (fast) depends on unknown subtask 'missing'"
+    );
+    assert!(
+        error(vec![task("a", "x"), task("b", "{{a.tokens}}")])
+            .starts_with("subtask 'b' references unknown field 'a.tokens'")
+    );
+
+    let runtime = dag_runtime()?;
+    let mut request = SessionRequest::new("Coordinate");
+    request.subtasks = vec![task("a", "{{b.summary}}"), task("b", "{{a.summary}}")];
+    let err = runtime.execute(request).await.unwrap_err().to_string();
+    assert!(err.starts_with("subtask dependency cycle"), "{err}");
+    Ok(())
+}
+
+/// Tracks how many agents run at once. `broken` fails, `stuck` streams some
+/// output and then never finishes, everyone else works for a moment.
+#[derive(Default)]
+struct ScriptedModel {
+    running: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+#[async_trait]
+impl LanguageModel for ScriptedModel {
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(running, Ordering::SeqCst);
+        let result = match request.agent.as_str() {
+            "broken" => {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                Err(anyhow::anyhow!("model unavailable"))
+            }
+            "stuck" => {
+                request.partial.push("found two of three issues");
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Err(anyhow::anyhow!("unreachable"))
+            }
+            agent => {
+                let delay = if agent == "slow" { 300 } else { 30 };
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                Ok(CompletionResponse {
+                    summary: format!("{agent}: {}", request.objective),
+                    raw_output: String::new(),
+                    usage: None,
+                })
+            }
+        };
+        self.running.fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+}
+
+fn scripted_runtime(
+    model: Arc<ScriptedModel>,
+) -> Result<(SessionRuntime, mpsc::Receiver<AgentEvent>)> {
+    let temp = tempdir()?;
+    let context = Arc::new(ProjectContext::gather(temp.path(), &Info::default())?);
+    let mut registry = AgentRegistry::new();
+    registry.apply_runtime_map(&parse_agents_json(
+        r#"{
+        "worker": { "mode": "subagent" },
+        "slow": { "mode": "subagent" },
+        "broken": { "mode": "subagent" },
+        "stuck": { "mode": "subagent", "budgets": { "wallClockLimitMs": 100 } }
+    }"#,
+    )?);
+    let (event_tx, event_rx) = mpsc::channel(64);
+    let runtime = SessionRuntime::new(
+        context,
+        Arc::new(registry),
+        model,
+        Vec::new(),
+        event_tx,
+        ModelHandle::new("baseline/model"),
+    );
+    Ok((runtime, event_rx))
+}
+
+fn drain(mut events: mpsc::Receiver<AgentEvent>) -> Vec<AgentEvent> {
+    let mut drained = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        drained.push(event);
+    }
+    drained
+}
+
+#[tokio::test]
+async fn limits_subtask_parallelism() -> Result<()> {
+    let model = Arc::new(ScriptedModel::default());
+    let (runtime, _events) = scripted_runtime(model.clone())?;
+    let mut request = SessionRequest::new("Coordinate").with_max_parallel(2);
+    request.subtasks = (0..5)
+        .map(|n| SubagentInvocation::new("worker", format!("part {n}")))
+        .collect();
+
+    let result = runtime.execute(request).await?;
+    assert_eq!(result.subtasks.len(), 5);
+    assert_eq!(model.peak.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn fail_fast_cancels_remaining_subtasks() -> Result<()> {
+    let (runtime, events) = scripted_runtime(Arc::new(ScriptedModel::default()))?;
+    let mut request = SessionRequest::new("Coordinate");
+    request.subtasks = vec![
+        SubagentInvocation::new("slow", "long job").with_id("long"),
+        SubagentInvocation::new("broken", "fragile job").with_id("fragile"),
+        SubagentInvocation::new("worker", "follow up").with_depends_on(["long"]),
+    ];
+
+    let err = runtime.execute(request).await.unwrap_err();
+    assert_eq!(
+        format!("{err:#}"),
+        "subtask 'fragile' failed: model unavailable"
+    );
+    drop(runtime);
+    let events = drain(events);
+    assert!(events.iter().any(|event| matches!(
+        event,
+        AgentEvent::Failed { agent, error, .. } if agent == "broken" && error == "model unavailable"
+    )));
+    let cancelled: Vec<&str> = events
+        .iter()
+        .filter_map(|event| match event {
+            AgentEvent::Cancelled { agent, reason, .. } => {
+                assert_eq!(reason, "subtask 'fragile' failed");
+                Some(agent.as_str())
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(cancelled, ["slow", "worker"]);
+    assert!(
+        !events
+            .iter()
+            .any(|event| matches!(event, AgentEvent::Completed { agent, .. } if agent == "slow"))
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn collect_all_reports_failures_and_keeps_partial_results() -> Result<()> {
+    let (runtime, events) = scripted_runtime(Arc::new(ScriptedModel::default()))?;
+    let mut request =
+        SessionRequest::new("Coordinate").with_failure_policy(FailurePolicy::CollectAll);
+    request.subtasks = vec![
+        SubagentInvocation::new("broken", "fragile job").with_id("fragile"),
+        SubagentInvocation::new("worker", "use {{fragile.summary}}").with_id("next"),
+        SubagentInvocation::new("worker", "after next").with_depends_on(["next"]),
+        SubagentInvocation::new("stuck", "audit"),
+        SubagentInvocation::new("worker", "independent"),
+    ];
+
+    let result = runtime.execute(request).await?;
+    let outcomes: Vec<(&str, bool)> = result
+        .subtasks
+        .iter()
+        .map(|outcome| (outcome.summary.as_str(), outcome.timed_out))
+        .collect();
+    assert_eq!(
+        outcomes,
+        [
+            ("found two of three issues", true),
+            ("worker: independent", false)
+        ]
+    );
+    let failures: Vec<(Option<&str>, SubtaskFailureKind, &str)> = result
+        .failures
+        .iter()
+        .map(|failure| (failure.id.as_deref(), failure.kind, failure.error.as_str()))
+        .collect();
+    assert_eq!(
+        failures,
+        [
+            (
+                Some("fragile"),
+                SubtaskFailureKind::Failed,
+                "model unavailable"
+            ),
+            (
+                Some("next"),
+                SubtaskFailureKind::Cancelled,
+                "dependency 'fragile' failed"
+            ),
+            (
+                None,
+                SubtaskFailureKind::Cancelled,
+                "dependency 'fragile' failed"
+            ),
+        ]
+    );
+
+    drop(runtime);
+    let events = drain(events);
+    assert!(events.iter().any(|event| matches!(
+        event,
+        AgentEvent::Completed { agent, timed_out: true, .. } if agent == "stuck"
+    )));
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| matches!(event, AgentEvent::Cancelled { .. }))
+            .count(),
+        2
+    );
+    Ok(())
+}
+
+/// Answers the synthesis turn by quoting its results section and records
+/// every prompt it sees.
+#[derive(Default)]
+struct SynthesizingModel {
+    prompts: std::sync::Mutex<Vec<String>>,
+}
+
+#[async_trait]
+impl LanguageModel for SynthesizingModel {
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        self.prompts.lock().unwrap().push(request.prompt.clone());
+        if request.agent == "broken" {
+            return Err(anyhow::anyhow!("model unavailable"));
+        }
+        let summary = match request.prompt.split_once("<SUBAGENT_RESULTS>") {
+            Some(_) => format!("synthesized answer to {}", request.objective),
+            None => format!("{}: {}", request.agent, request.objective),
+        };
+        Ok(CompletionResponse {
+            summary,
+            raw_output: String::new(),
+            usage: None,
+        })
+    }
+}
+
+#[tokio::test]
+async fn synthesis_turn_sees_every_subtask_result() -> Result<()> {
+    let temp = tempdir()?;
+    let context = Arc::new(ProjectContext::gather(temp.path(), &Info::default())?);
+    let mut registry = AgentRegistry::new();
+    registry.apply_runtime_map(&parse_agents_json(
+        r#"{
+        "lead": { "mode": "primary" },
+        "worker": { "mode": "subagent" },
+        "broken": { "mode": "subagent" }
+    }"#,
+    )?);
+    let model = Arc::new(SynthesizingModel::default());
+    let (event_tx, events) = mpsc::channel(64);
+    let runtime = SessionRuntime::new(
+        context,
+        Arc::new(registry),
+        model.clone(),
+        Vec::new(),
+        event_tx,
+        ModelHandle::new("baseline/model"),
+    );
+
+    let mut request = SessionRequest::new("Audit the crate")
+        .with_failure_policy(FailurePolicy::CollectAll)
+        .with_synthesis();
+    request.agent = Some("lead".to_string());
+    request.subtasks = vec![
+        SubagentInvocation::new("worker", "Check the docs").with_id("docs"),
+        SubagentInvocation::new("worker", "Check the tests"),
+        SubagentInvocation::new("broken", "Check the benches"),
+    ];
+    let result = runtime.execute(request).await?;
+
+    let synthesis = result.synthesis.as_ref().expect("synthesis turn ran");
+    assert_eq!(synthesis.agent, "lead");
+    assert_eq!(synthesis.session_id, result.primary.session_id);
+    assert_eq!(synthesis.summary, "synthesized answer to Audit the crate");
+    assert_eq!(result.answer().summary, synthesis.summary);
+    assert_eq!(result.subtasks.len(), 2);
+    assert_eq!(result.failures.len(), 1);
+
+    let prompts = model.prompts.lock().unwrap();
+    let prompt = prompts.last().unwrap();
+    let (_, results) = prompt.split_once("<SUBAGENT_RESULTS>").unwrap();
+    let (results, _) = results.split_once("</SUBAGENT_RESULTS>").unwrap();
+    assert!(results.contains(
+        "## docs (worker, completed)\nObjective: Check the docs\n\nworker: Check the docs"
+    ));
+    assert!(results.contains("## worker (worker, completed)\nObjective: Check the tests"));
+    assert!(
+        results.contains(
+            "## broken (broken, failed)\nObjective: Check the benches\n\nmodel unavailable"
+        )
+    );
+    assert_eq!(
+        prompts
+            .iter()
+            .filter(|prompt| prompt.contains("<SUBAGENT_RESULTS>"))
+            .count(),
+        1
+    );
+
+    drop(runtime);
+    let completed = drain(events)
+        .into_iter()
+        .filter(|event| matches!(event, AgentEvent::Completed { agent, .. } if agent == "lead"))
+        .count();
+    assert_eq!(completed, 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn synthesis_is_skipped_without_subtasks() -> Result<()> {
+    let runtime = dag_runtime()?;
+    let result = runtime
+        .execute(SessionRequest::new("Coordinate").with_synthesis())
+        .await?;
+    assert!(result.synthesis.is_none());
+    assert_eq!(result.answer().summary, "<Coordinate>");
+    Ok(())
+}
+
+#[tokio::test]
+async fn execute_continues_the_requested_session() -> Result<()> {
+    let temp = tempdir()?;
+    let context = Arc::new(ProjectContext::gather(temp.path(), &Info::default())?);
+    let (event_tx, mut event_rx) = mpsc::channel(16);
+    let runtime = SessionRuntime::new(
+        context,
+        Arc::new(AgentRegistry::new()),
+        Arc::new(LocalModel),
+        Vec::<Arc<dyn Tool>>::new(),
+        event_tx,
+        ModelHandle::new("baseline/model"),
+    );
+
+    let session_id = uuid::Uuid::new_v4();
+    let result = runtime
+        .execute(SessionRequest::new("Resume").with_session_id(session_id))
+        .await?;
+    assert_eq!(result.primary.session_id, session_id);
+    let Some(AgentEvent::Started {
+        session_id: started,
+        ..
+    }) = event_rx.recv().await
+    else {
+        panic!("expected the primary agent to start");
+    };
+    assert_eq!(started, session_id);
+
+    let fresh = runtime.execute(SessionRequest::new("Start")).await?;
+    assert_ne!(fresh.primary.session_id, session_id);
+    Ok(())
+}
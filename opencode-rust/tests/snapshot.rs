@@ -0,0 +1,124 @@
+use std::fs;
+use std::sync::Arc;
+
+use anyhow::Result;
+use opencode_rust::agent::registry::AgentRegistry;
+use opencode_rust::agent::spec::ModelHandle;
+use opencode_rust::session::{LocalModel, ProjectContext, SessionRequest, SessionRuntime};
+use opencode_rust::snapshot::Snapshot;
+use opencode_rust::tool::core::Tool;
+use opencode_rust::util::config;
+use tempfile::tempdir;
+use tokio::sync::mpsc;
+
+#[tokio::test]
+async fn tracks_patches_diffs_and_restores_worktree() -> Result<()> {
+    let worktree = tempdir()?;
+    let store = tempdir()?;
+    let root = worktree.path();
+    fs::create_dir_all(root.join("src"))?;
+    fs::write(
+        root.join("src/lib.rs"),
+        "pub fn answer() -> u32 {\n    41\n}\n",
+    )?;
+    fs::write(root.join("README.md"), "# demo\n")?;
+    fs::write(root.join(".gitignore"), "target/\n")?;
+
+    let snapshot = Snapshot::new(root, store.path().join("git"));
+    let hash = snapshot.track().await?.expect("snapshot hash");
+
+    fs::write(
+        root.join("src/lib.rs"),
+        "pub fn answer() -> u32 {\n    42\n}\n",
+    )?;
+    fs::remove_file(root.join("README.md"))?;
+    fs::write(root.join("src/new.rs"), "// new\n")?;
+    fs::create_dir_all(root.join("target"))?;
+    fs::write(root.join("target/build.log"), "ignored\n")?;
+
+    let patch = snapshot.patch(&hash).await?;
+    let mut files = patch.files.clone();
+    files.sort();
+    assert_eq!(
+        files,
+        vec![
+            root.join("README.md"),
+            root.join("src/lib.rs"),
+            root.join("src/new.rs")
+        ]
+    );
+
+    let diff = snapshot.diff(&hash).await?;
+    assert!(diff.contains("--- a/src/lib.rs"));
+    assert!(diff.contains("-    41\n+    42"));
+    assert!(diff.contains("+++ b/src/new.rs"));
+
+    snapshot.restore(&hash).await?;
+    assert_eq!(
+        fs::read_to_string(root.join("src/lib.rs"))?,
+        "pub fn answer() -> u32 {\n    41\n}\n"
+    );
+    assert_eq!(fs::read_to_string(root.join("README.md"))?, "# demo\n");
+    assert!(!root.join("src/new.rs").exists());
+    assert!(root.join("target/build.log").exists());
+    assert!(snapshot.patch(&hash).await?.files.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn reverts_only_patched_files() -> Result<()> {
+    let worktree = tempdir()?;
+    let store = tempdir()?;
+    let root = worktree.path();
+    fs::write(root.join("a.txt"), "a1")?;
+    fs::write(root.join("b.txt"), "b1")?;
+
+    let snapshot = Snapshot::new(root, store.path());
+    let first = snapshot.track().await?.unwrap();
+    fs::write(root.join("a.txt"), "a2")?;
+    fs::write(root.join("c.txt"), "c2")?;
+    let patch = snapshot.patch(&first).await?;
+    fs::write(root.join("b.txt"), "b2")?;
+
+    snapshot.revert(&[patch]).await?;
+    assert_eq!(fs::read_to_string(root.join("a.txt"))?, "a1");
+    assert_eq!(fs::read_to_string(root.join("b.txt"))?, "b2");
+    assert!(!root.join("c.txt").exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn runtime_records_snapshot_before_each_step() -> Result<()> {
+    let worktree = tempdir()?;
+    let store = tempdir()?;
+    fs::write(worktree.path().join("main.rs"), "fn main() {}")?;
+    let info = config::Info::default();
+    let context = Arc::new(ProjectContext::gather(worktree.path(), &info)?);
+    let (event_tx, _event_rx) = mpsc::channel(16);
+    let snapshot = Arc::new(Snapshot::new(worktree.path(), store.path()));
+    let runtime = SessionRuntime::new(
+        context,
+        Arc::new(AgentRegistry::new()),
+        Arc::new(LocalModel),
+        Vec::<Arc<dyn Tool>>::new(),
+        event_tx,
+        ModelHandle::new("baseline/model"),
+    )
+    .with_snapshot(snapshot.clone());
+
+    let result = runtime.execute(SessionRequest::new("Ship it")).await?;
+    let hash = result.primary.snapshot.expect("primary snapshot");
+    assert!(snapshot.patch(&hash).await?.files.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn disabled_snapshots_track_nothing() -> Result<()> {
+    let worktree = tempdir()?;
+    let info = config::parse_info(r#"{"snapshot": false}"#)?;
+    let snapshot = Snapshot::from_info(worktree.path(), &info);
+    assert!(!snapshot.is_enabled());
+    assert_eq!(snapshot.track().await?, None);
+    assert!(!snapshot.git_dir().exists());
+    Ok(())
+}
@@ -0,0 +1,203 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use opencode_rust::agent::registry::{AgentRegistry, parse_agents_json};
+use opencode_rust::agent::spec::ModelHandle;
+use opencode_rust::session::tokens;
+use opencode_rust::session::{
+    AgentEvent, CompletionRequest, CompletionResponse, LanguageModel, LocalModel, ProjectContext,
+    SessionRequest, SessionRuntime, StoredSession, SubagentInvocation, TokenEstimator, TokenUsage,
+};
+use opencode_rust::util::config::Info;
+use tempfile::tempdir;
+use tokio::sync::mpsc;
+
+const MODEL: &str = "openai/gpt-4o";
+
+/// Streams words into the partial output and never finishes on its own.
+#[derive(Default)]
+struct StreamingModel {
+    finished: AtomicBool,
+}
+
+#[async_trait]
+impl LanguageModel for StreamingModel {
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        for step in 0..1000 {
+            request.partial.push(&format!("step {step}. "));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        self.finished.store(true, Ordering::SeqCst);
+        Ok(CompletionResponse {
+            summary: request.partial.get(),
+            raw_output: String::new(),
+            usage: None,
+        })
+    }
+}
+
+/// Answers at length, optionally reporting usage like a provider would.
+#[derive(Default)]
+struct VerboseModel {
+    usage: Option<TokenUsage>,
+    calls: AtomicUsize,
+}
+
+#[async_trait]
+impl LanguageModel for VerboseModel {
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Ok(CompletionResponse {
+            summary: format!("{} says {}", request.agent, "many words ".repeat(20)),
+            raw_output: String::new(),
+            usage: self.usage,
+        })
+    }
+}
+
+fn budgeted_runtime(
+    model: Arc<dyn LanguageModel>,
+    agents: &str,
+) -> Result<(SessionRuntime, mpsc::Receiver<AgentEvent>)> {
+    let temp = tempdir()?;
+    let context = Arc::new(ProjectContext::gather(temp.path(), &Info::default())?);
+    let mut registry = AgentRegistry::new();
+    registry.apply_runtime_map(&parse_agents_json(agents)?);
+    registry.ensure_primary();
+    let (event_tx, event_rx) = mpsc::channel(64);
+    let runtime = SessionRuntime::new(
+        context,
+        Arc::new(registry),
+        model,
+        Vec::new(),
+        event_tx,
+        ModelHandle::new(MODEL),
+    );
+    Ok((runtime, event_rx))
+}
+
+#[tokio::test(start_paused = true)]
+async fn stops_an_agent_when_its_token_budget_runs_out() -> Result<()> {
+    let model = Arc::new(StreamingModel::default());
+    let (runtime, mut events) = budgeted_runtime(
+        model.clone(),
+        r#"{ "primary": { "budgets": { "maxTokens": 12 } } }"#,
+    )?;
+
+    let result = runtime.execute(SessionRequest::new("Count")).await?;
+    let primary = &result.primary;
+    assert!(primary.budget_exhausted);
+    assert!(!primary.timed_out);
+    assert!(!model.finished.load(Ordering::SeqCst));
+    assert!(primary.summary.starts_with("step 0. step 1."));
+    let handle = ModelHandle::new(MODEL);
+    assert!(tokens::estimate_for(&handle, &primary.summary) <= 12);
+    assert_eq!(
+        runtime.usage(primary.session_id).output_tokens,
+        tokens::estimate_for(&handle, &primary.summary)
+    );
+
+    drop(runtime);
+    let mut exhausted = false;
+    while let Some(event) = events.recv().await {
+        if let AgentEvent::Completed {
+            budget_exhausted, ..
+        } = event
+        {
+            exhausted = budget_exhausted;
+        }
+    }
+    assert!(exhausted);
+    Ok(())
+}
+
+#[tokio::test]
+async fn cuts_long_responses_and_tracks_reported_usage() -> Result<()> {
+    let model = Arc::new(VerboseModel {
+        usage: Some(TokenUsage::new(300, 42)),
+        ..VerboseModel::default()
+    });
+    let (runtime, _events) = budgeted_runtime(
+        model,
+        r#"{ "primary": { "budgets": { "maxTokens": 10 } } }"#,
+    )?;
+    let result = runtime.execute(SessionRequest::new("Explain")).await?;
+    let primary = &result.primary;
+    assert!(primary.budget_exhausted);
+    assert!(primary.summary.starts_with("primary says many words"));
+    assert!(tokens::estimate_for(&ModelHandle::new(MODEL), &primary.summary) <= 10);
+    assert_eq!(primary.usage, TokenUsage::new(300, 42));
+    assert_eq!(runtime.usage(primary.session_id), TokenUsage::new(300, 42));
+
+    let (runtime, _events) = budgeted_runtime(Arc::new(LocalModel), "{}")?;
+    let result = runtime.execute(SessionRequest::new("Explain")).await?;
+    let usage = result.primary.usage;
+    assert!(!result.primary.budget_exhausted);
+    assert!(usage.input_tokens > usage.output_tokens && usage.output_tokens > 0);
+
+    let mut session = StoredSession::new(result.primary.session_id, "explain");
+    session.record_turn("Explain", &result);
+    session.record_turn("Explain", &result);
+    assert_eq!(session.usage, usage + usage);
+    Ok(())
+}
+
+#[tokio::test]
+async fn synthesis_shares_the_primary_session_budget() -> Result<()> {
+    let model = Arc::new(VerboseModel::default());
+    let (runtime, _events) = budgeted_runtime(
+        model.clone(),
+        r#"{
+            "primary": { "budgets": { "maxTokens": 8 } },
+            "worker": { "mode": "subagent" }
+        }"#,
+    )?;
+    let mut request = SessionRequest::new("Plan").with_synthesis();
+    request.subtasks = vec![SubagentInvocation::new("worker", "Research")];
+
+    let result = runtime.execute(request).await?;
+    assert!(result.primary.budget_exhausted);
+    assert!(!result.subtasks[0].budget_exhausted);
+    let synthesis = result.synthesis.as_ref().expect("synthesis ran");
+    assert!(synthesis.budget_exhausted);
+    assert_eq!(
+        synthesis.summary,
+        "primary has no token budget left (max 8 tokens)."
+    );
+    assert_eq!(synthesis.usage, TokenUsage::default());
+    assert_eq!(model.calls.load(Ordering::SeqCst), 2);
+    assert_eq!(
+        result.usage(),
+        result.primary.usage + result.subtasks[0].usage
+    );
+    Ok(())
+}
+
+#[test]
+fn counts_gpt_tokens_with_their_vocabulary() {
+    let gpt4 = ModelHandle::new("openai/gpt-4");
+    assert_eq!(tokens::estimate_for(&gpt4, "tiktoken is great!"), 6);
+    let gpt4o = ModelHandle::new(MODEL);
+    assert_eq!(tokens::estimate_for(&gpt4o, "hello world"), 2);
+
+    let text = "naïve café résumé ".repeat(20);
+    let cut = tokens::truncate(&gpt4o, &text, 7);
+    assert!(text.starts_with(cut) && !cut.is_empty());
+    assert!(tokens::estimate_for(&gpt4o, cut) <= 7);
+    assert!(tokens::estimate_for(&gpt4o, &text[..cut.len() + 4]) > 7);
+
+    let mut estimator = TokenEstimator::new(&gpt4o);
+    for word in text.split_inclusive(' ') {
+        estimator.push(word);
+    }
+    assert_eq!(estimator.total(), tokens::estimate_for(&gpt4o, &text));
+
+    let unknown = ModelHandle::new("local/tiny");
+    assert_eq!(
+        tokens::estimate_for(&unknown, &text),
+        tokens::estimate(&text)
+    );
+}
@@ -0,0 +1,906 @@
+use std::fs::{self, File};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use opencode_rust::agent::spec::AgentSpec;
+use opencode_rust::session::{AgentEvent, TodoLists, TodoPriority, TodoStatus};
+use opencode_rust::tool::bash::{BashTool, MAX_JOBS, MAX_OUTPUT_BYTES};
+use opencode_rust::tool::core::{Tool, ToolContext};
+use opencode_rust::tool::edit::EditTool;
+use opencode_rust::tool::fs::{ListFilesTool, ReadFileTool};
+use opencode_rust::tool::patch::ApplyPatchTool;
+use opencode_rust::tool::search::{GlobTool, GrepTool, MAX_MATCHES, MAX_RESULTS};
+use opencode_rust::tool::todo::{TodoReadTool, TodoWriteTool};
+use tempfile::tempdir;
+
+fn write(root: &Path, path: &str, content: &str, age_secs: u64) {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, content).unwrap();
+    let modified = SystemTime::now() - Duration::from_secs(age_secs);
+    File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+}
+
+fn args(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+#[tokio::test]
+async fn grep_reports_matches_newest_first() -> Result<()> {
+    let temp = tempdir()?;
+    let root = temp.path();
+    let base = root.display().to_string();
+    write(root, ".gitignore", "ignored.rs\n", 0);
+    write(root, "old.rs", "fn old() {}\n", 100);
+    write(root, "src/new.rs", "// header\nfn new() {}\n", 10);
+    write(root, "notes.md", "fn in markdown\n", 5);
+    write(root, "ignored.rs", "fn ignored() {}\n", 0);
+    write(root, "node_modules/dep.rs", "fn dep() {}\n", 0);
+
+    let output = GrepTool
+        .execute(&args(&[r"fn \w+\(", &base, "*.rs"]))
+        .await?;
+    assert_eq!(
+        output,
+        format!("{base}/src/new.rs:2:fn new() {{}}\n{base}/old.rs:1:fn old() {{}}")
+    );
+
+    let output = GrepTool.execute(&args(&["nothing-matches", &base])).await?;
+    assert_eq!(output, "No files found");
+    assert!(GrepTool.execute(&args(&["(", &base])).await.is_err());
+    assert!(GrepTool.execute(&[]).await?.starts_with("Usage:"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn grep_caps_results() -> Result<()> {
+    let temp = tempdir()?;
+    let content = "match\n".repeat(MAX_RESULTS + 5);
+    write(temp.path(), "many.txt", &content, 0);
+
+    let output = GrepTool
+        .execute(&args(&["match", &temp.path().display().to_string()]))
+        .await?;
+    assert_eq!(
+        output
+            .lines()
+            .filter(|line| line.contains(":match"))
+            .count(),
+        MAX_RESULTS
+    );
+    assert!(
+        output
+            .ends_with("(Results are truncated. Consider using a more specific path or pattern.)")
+    );
+
+    write(
+        temp.path(),
+        "huge.txt",
+        &"match\n".repeat(3 * MAX_MATCHES),
+        0,
+    );
+    let output = GrepTool
+        .execute(&args(&["match", &temp.path().display().to_string()]))
+        .await?;
+    assert_eq!(output.lines().count(), MAX_RESULTS + 2);
+    assert!(output.ends_with(
+        "(Results are truncated and the search stopped early, so newer matches may be missing. Consider using a more specific path or pattern.)"
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn glob_lists_matching_paths_newest_first() -> Result<()> {
+    let temp = tempdir()?;
+    let root = temp.path();
+    let base = root.display().to_string();
+    write(root, ".gitignore", "gen/\n", 0);
+    write(root, "src/lib.rs", "", 50);
+    write(root, "src/tool/mod.rs", "", 5);
+    write(root, "README.md", "", 0);
+    write(root, "gen/out.rs", "", 0);
+    write(root, "target/debug/build.rs", "", 0);
+
+    let output = GlobTool.execute(&args(&["**/*.rs", &base])).await?;
+    assert_eq!(output, format!("{base}/src/tool/mod.rs\n{base}/src/lib.rs"));
+    assert_eq!(
+        GlobTool.execute(&args(&["*.toml", &base])).await?,
+        "No files found"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn edit_replaces_unique_match_and_returns_diff() -> Result<()> {
+    let temp = tempdir()?;
+    let path = temp.path().join("main.rs");
+    let file = path.display().to_string();
+    fs::write(&path, "fn main() {\n    println!(\"hi\");\n}\n")?;
+
+    let output = EditTool::new()
+        .execute(&args(&[&file, "println!(\"hi\")", "println!(\"hello\")"]))
+        .await?;
+    assert_eq!(
+        fs::read_to_string(&path)?,
+        "fn main() {\n    println!(\"hello\");\n}\n"
+    );
+    assert!(output.contains(&format!("--- {file}")));
+    assert!(output.contains("-    println!(\"hi\");"));
+    assert!(output.contains("+    println!(\"hello\");"));
+
+    let missing = EditTool::new()
+        .execute(&args(&[&file, "absent", "x"]))
+        .await;
+    assert!(missing.unwrap_err().to_string().contains("not found"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn edit_requires_replace_all_for_ambiguous_matches() -> Result<()> {
+    let temp = tempdir()?;
+    let path = temp.path().join("list.txt");
+    let file = path.display().to_string();
+    fs::write(&path, "item\nitem\nother\n")?;
+
+    let err = EditTool::new()
+        .execute(&args(&[&file, "item", "entry"]))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("Found 2 matches"));
+    assert_eq!(fs::read_to_string(&path)?, "item\nitem\nother\n");
+
+    EditTool::new()
+        .execute(&args(&[&file, "item", "entry", "true"]))
+        .await?;
+    assert_eq!(fs::read_to_string(&path)?, "entry\nentry\nother\n");
+    Ok(())
+}
+
+#[tokio::test]
+async fn edit_tolerates_crlf_and_indentation_differences() -> Result<()> {
+    let temp = tempdir()?;
+    let path = temp.path().join("lib.rs");
+    let file = path.display().to_string();
+    fs::write(
+        &path,
+        "impl A {\r\n        fn a() {\r\n            1\r\n        }\r\n}\r\n",
+    )?;
+
+    EditTool::new()
+        .execute(&args(&[
+            &file,
+            "fn a() {\n    1\n}",
+            "fn a() {\n    2\n}\nfn b() {}",
+        ]))
+        .await?;
+    assert_eq!(
+        fs::read_to_string(&path)?,
+        "impl A {\r\n        fn a() {\r\n            2\r\n        }\r\n        fn b() {}\r\n}\r\n"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn edit_refuses_files_changed_since_last_read() -> Result<()> {
+    let temp = tempdir()?;
+    let path = temp.path().join("config.toml");
+    let file = path.display().to_string();
+    write(temp.path(), "config.toml", "a = 1\n", 60);
+    let ctx = ToolContext::default();
+
+    ReadFileTool
+        .execute_with_context(&ctx, &args(&[&file]))
+        .await?;
+    EditTool::new()
+        .execute_with_context(&ctx, &args(&[&file, "a = 1", "a = 2"]))
+        .await?;
+    // The agent's own edit does not make the file stale.
+    EditTool::new()
+        .execute_with_context(&ctx, &args(&[&file, "a = 2", "a = 3"]))
+        .await?;
+
+    write(temp.path(), "config.toml", "a = 3\nb = 1\n", 30);
+    let err = EditTool::new()
+        .execute_with_context(&ctx, &args(&[&file, "a = 3", "a = 4"]))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("modified since it was last read"));
+
+    // Replies that show none of the file do not count as reading it.
+    for reply in [&args(&[&file, "first"]), &args(&[&file, "99"])] {
+        ReadFileTool.execute_with_context(&ctx, reply).await?;
+        let err = EditTool::new()
+            .execute_with_context(&ctx, &args(&[&file, "a = 3", "a = 4"]))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("modified since it was last read"));
+    }
+    write(temp.path(), "logo.png", "not really a png", 30);
+    let logo = temp.path().join("logo.png");
+    ReadFileTool
+        .execute_with_context(&ctx, &args(&[&logo.display().to_string()]))
+        .await?;
+    assert!(ctx.file_times.get(ctx.session_id, &logo).is_none());
+
+    ReadFileTool
+        .execute_with_context(&ctx, &args(&[&file]))
+        .await?;
+    EditTool::new()
+        .execute_with_context(&ctx, &args(&[&file, "a = 3", "a = 4"]))
+        .await?;
+    assert_eq!(fs::read_to_string(&path)?, "a = 4\nb = 1\n");
+    Ok(())
+}
+
+#[tokio::test]
+async fn edit_with_empty_old_string_creates_file() -> Result<()> {
+    let temp = tempdir()?;
+    let path = temp.path().join("new/notes.md");
+    let file = path.display().to_string();
+
+    EditTool::new()
+        .execute(&args(&[&file, "", "# Notes\n"]))
+        .await?;
+    assert_eq!(fs::read_to_string(&path)?, "# Notes\n");
+    assert!(
+        EditTool::new()
+            .execute(&args(&[&file, "", "other"]))
+            .await
+            .is_err()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn apply_patch_adds_deletes_renames_and_modifies() -> Result<()> {
+    let temp = tempdir()?;
+    let root = temp.path();
+    let base = root.display().to_string();
+    write(
+        root,
+        "src/lib.rs",
+        "// lib\nmod a;\nmod b;\n\nfn main() {}\n",
+        0,
+    );
+    write(root, "old.txt", "gone\n", 0);
+    write(root, "docs/draft.md", "# Draft\ntext\n", 0);
+
+    let patch = "\
+diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -10,3 +10,4 @@
+ mod a;
+ mod b;
++mod c;
+ 
+diff --git a/new.txt b/new.txt
+new file mode 100644
+--- /dev/null
++++ b/new.txt
+@@ -0,0 +1,2 @@
++hello
++world
+diff --git a/old.txt b/old.txt
+deleted file mode 100644
+--- a/old.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-gone
+diff --git a/docs/draft.md b/docs/final.md
+similarity index 50%
+rename from docs/draft.md
+rename to docs/final.md
+--- a/docs/draft.md
++++ b/docs/final.md
+@@ -1,2 +1,2 @@
+-# Draft
++# Final
+ text
+";
+    let output = ApplyPatchTool::new()
+        .execute(&args(&[patch, &base]))
+        .await?;
+    assert_eq!(
+        output,
+        "Patch applied to 4 file(s):\nM src/lib.rs (+1 -0)\nA new.txt (+2 -0)\nD old.txt (+0 -1)\nR docs/draft.md -> docs/final.md (+1 -1)"
+    );
+    assert_eq!(
+        fs::read_to_string(root.join("src/lib.rs"))?,
+        "// lib\nmod a;\nmod b;\nmod c;\n\nfn main() {}\n"
+    );
+    assert_eq!(fs::read_to_string(root.join("new.txt"))?, "hello\nworld\n");
+    assert!(!root.join("old.txt").exists());
+    assert!(!root.join("docs/draft.md").exists());
+    assert_eq!(
+        fs::read_to_string(root.join("docs/final.md"))?,
+        "# Final\ntext\n"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn apply_patch_refuses_to_delete_content_it_does_not_remove() -> Result<()> {
+    let temp = tempdir()?;
+    let root = temp.path();
+    let base = root.display().to_string();
+    write(root, "old.txt", "one\ntwo\n", 0);
+
+    let patch = "\
+--- a/old.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-one
+";
+    let err = ApplyPatchTool::new()
+        .execute(&args(&[patch, &base]))
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("D old.txt: deleting the file would drop 4 byte(s)"),
+        "{err}"
+    );
+    assert_eq!(fs::read_to_string(root.join("old.txt"))?, "one\ntwo\n");
+    Ok(())
+}
+
+#[tokio::test]
+async fn apply_patch_is_all_or_nothing() -> Result<()> {
+    let temp = tempdir()?;
+    let root = temp.path();
+    let base = root.display().to_string();
+    write(root, "a.txt", "one\ntwo\n", 0);
+    write(root, "b.txt", "three\n", 0);
+
+    let patch = "\
+--- a/a.txt
++++ b/a.txt
+@@ -1,2 +1,2 @@
+ one
+-two
++2
+--- a/b.txt
++++ b/b.txt
+@@ -1 +1 @@
+-four
++4
+--- /dev/null
++++ b/../escape.txt
+@@ -0,0 +1 @@
++x
+";
+    let err = ApplyPatchTool::new()
+        .execute(&args(&[patch, &base]))
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("no files were changed"));
+    assert!(
+        err.contains("M b.txt: hunk 1 (@@ -1 +1 @@) does not match"),
+        "{err}"
+    );
+    assert!(err.contains("A ../escape.txt: path ../escape.txt must be relative"));
+    assert!(!err.contains("a.txt:"));
+    assert_eq!(fs::read_to_string(root.join("a.txt"))?, "one\ntwo\n");
+    assert_eq!(fs::read_to_string(root.join("b.txt"))?, "three\n");
+    Ok(())
+}
+
+#[tokio::test]
+async fn apply_patch_tolerates_fuzz_crlf_and_missing_newlines() -> Result<()> {
+    let temp = tempdir()?;
+    let root = temp.path();
+    let base = root.display().to_string();
+    write(root, "win.txt", "alpha\r\nbeta\r\ngamma\r\ndelta\r\n", 0);
+    write(root, "tail.txt", "first\nlast", 0);
+
+    // The leading context line is stale and the trailing one differs in
+    // indentation.
+    let patch = "\
+--- a/win.txt
++++ b/win.txt
+@@ -1,4 +1,4 @@
+ ALPHA
+ beta
+-gamma
++GAMMA
+   delta
+--- a/tail.txt
++++ b/tail.txt
+@@ -1,2 +1,2 @@
+ first
+-last
+\\ No newline at end of file
++last
+";
+    ApplyPatchTool::new()
+        .execute(&args(&[patch, &base]))
+        .await?;
+    assert_eq!(
+        fs::read_to_string(root.join("win.txt"))?,
+        "alpha\r\nbeta\r\nGAMMA\r\ndelta\r\n"
+    );
+    assert_eq!(fs::read_to_string(root.join("tail.txt"))?, "first\nlast\n");
+    Ok(())
+}
+
+#[tokio::test]
+async fn read_file_numbers_lines_within_window() -> Result<()> {
+    let temp = tempdir()?;
+    let path = temp.path().join("lines.txt");
+    let file = path.display().to_string();
+    let long = "x".repeat(2500);
+    fs::write(&path, format!("one\r\ntwo\nthree\n{long}\nfive\n"))?;
+
+    let output = ReadFileTool.execute(&args(&[&file])).await?;
+    let expected_long = format!("     4\t{}...", "x".repeat(2000));
+    assert_eq!(
+        output,
+        format!(
+            "     1\tone\n     2\ttwo\n     3\tthree\n{expected_long}\n     5\tfive\n\n(End of file - total 5 lines)"
+        )
+    );
+
+    let window = ReadFileTool.execute(&args(&[&file, "1", "2"])).await?;
+    assert_eq!(
+        window,
+        "     2\ttwo\n     3\tthree\n\n(File has more lines. Use offset 3 to read beyond line 3)"
+    );
+    assert!(
+        ReadFileTool
+            .execute(&args(&[&file, "10"]))
+            .await?
+            .contains("past the end of the file")
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn read_file_refuses_binaries_and_suggests_similar_names() -> Result<()> {
+    let temp = tempdir()?;
+    let root = temp.path();
+    fs::write(root.join("data.bin"), b"\x00\x01\x02")?;
+    fs::write(root.join("blob"), b"text\x00more")?;
+    fs::write(root.join("config.json"), "{}")?;
+    fs::write(root.join("config.toml"), "")?;
+
+    for name in ["data.bin", "blob"] {
+        let output = ReadFileTool
+            .execute(&args(&[&root.join(name).display().to_string()]))
+            .await?;
+        assert!(output.starts_with("Cannot read binary file"), "{output}");
+    }
+
+    let err = ReadFileTool
+        .execute(&args(&[&root.join("config").display().to_string()]))
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("Did you mean one of these?"), "{err}");
+    assert!(err.contains(&root.join("config.json").display().to_string()));
+    assert!(err.contains(&root.join("config.toml").display().to_string()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn list_files_renders_ignore_aware_tree() -> Result<()> {
+    let temp = tempdir()?;
+    let root = temp.path();
+    let base = root.display().to_string();
+    write(root, ".gitignore", "secret.txt\n", 0);
+    write(root, "Cargo.toml", "", 0);
+    write(root, "secret.txt", "", 0);
+    write(root, "src/main.rs", "", 0);
+    write(root, "src/util/deep/mod.rs", "", 0);
+    write(root, "docs/guide.md", "", 0);
+    write(root, "target/debug/app", "", 0);
+    write(root, "node_modules/pkg/index.js", "", 0);
+    write(root, "logs/run.log", "", 0);
+    write(root, ".git/HEAD", "", 0);
+
+    let output = ListFilesTool.execute(&args(&[&base])).await?;
+    assert_eq!(
+        output,
+        format!(
+            "{base}/\n  docs/\n    guide.md\n  src/\n    util/\n      deep/\n    main.rs\n  .gitignore\n  Cargo.toml"
+        )
+    );
+
+    let shallow = ListFilesTool
+        .execute(&args(&[&base, "1", "*.md", "*.toml"]))
+        .await?;
+    assert_eq!(shallow, format!("{base}/\n  docs/\n  src/\n  .gitignore"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn list_files_truncates_large_directories() -> Result<()> {
+    let temp = tempdir()?;
+    let root = temp.path();
+    for idx in 0..60 {
+        write(root, &format!("many/file{idx:02}.txt"), "", 0);
+    }
+    write(root, "z.txt", "", 0);
+
+    let output = ListFilesTool
+        .execute(&args(&[&root.display().to_string()]))
+        .await?;
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines[1], "  many/");
+    assert_eq!(lines[2], "    file00.txt");
+    assert_eq!(lines[51], "    file49.txt");
+    assert_eq!(lines[52], "    [10 truncated]");
+    assert_eq!(lines[53], "  z.txt");
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn bash_keeps_cwd_and_env_between_calls() -> Result<()> {
+    let temp = tempdir()?;
+    let base = temp.path().canonicalize()?.display().to_string();
+    let tool = BashTool::new();
+    let ctx = ToolContext::default();
+
+    tool.execute_with_context(&ctx, &args(&[&format!("cd {base} && export GREETING=hi")]))
+        .await?;
+    let output = tool
+        .execute_with_context(&ctx, &args(&["pwd; echo $GREETING | tr a-z A-Z"]))
+        .await?;
+    assert_eq!(output, format!("---STDOUT---\n{base}\nHI\nExit code: 0"));
+
+    // Another agent gets its own shell.
+    let other = ToolContext::new(ctx.session_id, Arc::new(AgentSpec::new("other")));
+    let output = tool
+        .execute_with_context(&other, &args(&["echo \"[$GREETING]\""]))
+        .await?;
+    assert!(output.starts_with("---STDOUT---\n[]\n"), "{output}");
+
+    let failed = tool
+        .execute_with_context(&ctx, &args(&["echo oops >&2; exit 3"]))
+        .await?;
+    assert_eq!(failed, "---STDERR---\noops\nExit code: 3");
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn bash_quotes_each_of_several_arguments() -> Result<()> {
+    let tool = BashTool::new();
+    let output = tool
+        .execute(&args(&[
+            "printf", "[%s]", "a  b", "it's", "$HOME", "|", "wc",
+        ]))
+        .await?;
+    assert_eq!(
+        output,
+        "---STDOUT---\n[a  b][it's][$HOME][|][wc]\nExit code: 0"
+    );
+    let output = tool.execute(&args(&["echo $((1 + 2)) | tr 3 x"])).await?;
+    assert_eq!(output, "---STDOUT---\nx\nExit code: 0");
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn bash_kills_process_group_on_timeout() -> Result<()> {
+    let temp = tempdir()?;
+    let marker = temp.path().join("survived");
+    let mut spec = AgentSpec::new("primary");
+    spec.budgets.tool_timeout = Some(Duration::from_millis(300));
+    let ctx = ToolContext::new(uuid::Uuid::new_v4(), Arc::new(spec));
+
+    let started = std::time::Instant::now();
+    let output = BashTool::new()
+        .execute_with_context(
+            &ctx,
+            &args(&[&format!(
+                "echo started; (sleep 1 && touch {}) & sleep 5",
+                marker.display()
+            )]),
+        )
+        .await?;
+    assert!(started.elapsed() < Duration::from_secs(3));
+    assert!(output.starts_with("---STDOUT---\nstarted\n"), "{output}");
+    assert!(output.ends_with("timed out after 300ms; its process group was killed."));
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    assert!(!marker.exists());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn bash_truncates_long_output_in_the_middle() -> Result<()> {
+    let output = BashTool::new().execute(&args(&["seq 1 20000"])).await?;
+    assert!(output.starts_with("---STDOUT---\n1\n2\n"));
+    assert!(output.contains("bytes truncated"));
+    assert!(output.ends_with("19999\n20000\nExit code: 0"));
+    assert!(output.len() < MAX_OUTPUT_BYTES + 200);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn bash_caps_output_of_chatty_commands() -> Result<()> {
+    let printed = 20 * MAX_OUTPUT_BYTES;
+    let command = format!("head -c {printed} /dev/zero | tr '\\0' a; echo; echo end");
+    let output = BashTool::new().execute(&args(&[&command])).await?;
+    let full = "---STDOUT---\n".len() + printed + "\nend\n".len();
+    let truncated = full - MAX_OUTPUT_BYTES;
+    assert!(output.starts_with("---STDOUT---\naaa"));
+    assert!(output.contains(&format!("... [{truncated} bytes truncated] ...")));
+    assert!(output.ends_with("aaa\nend\nExit code: 0"));
+    assert!(output.len() < MAX_OUTPUT_BYTES + 200);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn bash_runs_background_jobs() -> Result<()> {
+    let tool = BashTool::new();
+    let ctx = ToolContext::default();
+    let started = tool
+        .execute_with_context(&ctx, &args(&["--background", "echo ready; sleep 30"]))
+        .await?;
+    assert!(
+        started.starts_with("Started background job 1."),
+        "{started}"
+    );
+
+    let mut polled = String::new();
+    for _ in 0..50 {
+        polled = tool.execute(&args(&["--poll", "1"])).await?;
+        if polled.contains("ready") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(polled, "Job 1 is still running.\nready\n");
+
+    assert_eq!(
+        tool.execute(&args(&["--kill", "1"])).await?,
+        "Killed job 1."
+    );
+    for _ in 0..50 {
+        polled = tool.execute(&args(&["--poll", "1"])).await?;
+        if !polled.contains("still running") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(polled.starts_with("Job 1 was terminated by a signal."));
+    assert_eq!(
+        tool.execute(&args(&["--poll", "7"])).await?,
+        "No background job with ID 7"
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn bash_background_output_is_drained_capped_and_released() -> Result<()> {
+    let tool = BashTool::new();
+    let ctx = ToolContext::default();
+    tool.execute_with_context(&ctx, &args(&["--background", "echo one; sleep 30"]))
+        .await?;
+    let mut polled = String::new();
+    for _ in 0..50 {
+        polled = tool.execute(&args(&["--poll", "1"])).await?;
+        if polled.contains("one") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(polled, "Job 1 is still running.\none\n");
+    assert_eq!(
+        tool.execute(&args(&["--poll", "1"])).await?,
+        "Job 1 is still running.\n[No output yet]"
+    );
+    tool.execute(&args(&["--kill", "1"])).await?;
+
+    let command = format!(
+        "head -c {} /dev/zero | tr '\\0' a; echo; echo end",
+        3 * MAX_OUTPUT_BYTES
+    );
+    tool.execute_with_context(&ctx, &args(&["--background", &command]))
+        .await?;
+    for _ in 0..100 {
+        polled = tool.execute(&args(&["--poll", "2"])).await?;
+        if !polled.contains("still running") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    let (status, output) = polled.split_once('\n').unwrap();
+    assert_eq!(status, "Job 2 exited with code 0.");
+    assert!(output.starts_with("... ["), "{}", &output[..80]);
+    assert!(output.ends_with("a\nend\n"));
+    assert!(output.len() < MAX_OUTPUT_BYTES + 100);
+    assert_eq!(
+        tool.execute(&args(&["--poll", "2"])).await?,
+        "No background job with ID 2"
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn bash_limits_background_jobs_and_kills_them_when_dropped() -> Result<()> {
+    let temp = tempdir()?;
+    let marker = temp.path().join("survived");
+    let tool = BashTool::new();
+    let command = format!("sleep 1 && touch {}", marker.display());
+    for _ in 0..MAX_JOBS {
+        let started = tool.execute(&args(&["--background", &command])).await?;
+        assert!(started.starts_with("Started background job"), "{started}");
+    }
+    assert_eq!(
+        tool.execute(&args(&["--background", "true"])).await?,
+        format!(
+            "{MAX_JOBS} background jobs are already running. Use `bash --kill <job_id>` to stop one first."
+        )
+    );
+    tool.execute(&args(&["--kill", "1"])).await?;
+    for _ in 0..50 {
+        if tool.execute(&args(&["--kill", "1"])).await? == "Job 1 has already exited." {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let started = tool.execute(&args(&["--background", &command])).await?;
+    assert!(started.starts_with("Started background job"), "{started}");
+
+    drop(tool);
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(!marker.exists());
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+async fn wait_for_screen(tool: &BashTool, id: &str, text: &str) -> Result<String> {
+    let mut screen = String::new();
+    for _ in 0..100 {
+        screen = tool.execute(&args(&["--screen", id])).await?;
+        if screen.contains(text) {
+            return Ok(screen);
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("{text:?} never appeared on screen:\n{screen}");
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn bash_drives_interactive_programs_on_a_pty() -> Result<()> {
+    let tool = BashTool::new();
+    let script = "printf 'Name? '; read name; echo \"Hello, $name\"; \
+                  read -s -p 'Password: ' pw; echo; echo \"length ${#pw}\"; \
+                  [ -t 0 ] && echo tty; sleep 30";
+    let started = tool.execute(&args(&["--pty", script])).await?;
+    assert!(started.starts_with("Started PTY session 1."), "{started}");
+    wait_for_screen(&tool, "1", "Name?").await?;
+
+    tool.execute(&args(&["--send", "1", "Ada<enter>"])).await?;
+    wait_for_screen(&tool, "1", "Hello, Ada").await?;
+
+    tool.execute(&args(&["--send", "1", "secret<enter>"]))
+        .await?;
+    let screen = wait_for_screen(&tool, "1", "tty").await?;
+    assert!(screen.starts_with("PTY session 1 is running.\nName? Ada\nHello, Ada\nPassword:"));
+    assert!(screen.contains("length 6"));
+    assert!(!screen.contains("secret"));
+
+    tool.execute(&args(&["--send", "1", "<ctrl-c>"])).await?;
+    wait_for_screen(&tool, "1", "terminated by a signal").await?;
+    assert_eq!(
+        tool.execute(&args(&["--terminate", "1"])).await?,
+        "Terminated PTY session 1."
+    );
+    assert_eq!(
+        tool.execute(&args(&["--screen", "1"])).await?,
+        "No PTY session with ID 1"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn todo_tools_replace_list_per_session_and_emit_events() -> Result<()> {
+    let todos = Arc::new(TodoLists::new());
+    let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+    let write = TodoWriteTool::new(todos.clone()).with_events(tx);
+    let read = TodoReadTool::new(todos.clone());
+    let ctx = ToolContext::new(uuid::Uuid::new_v4(), Arc::new(AgentSpec::new("build")));
+
+    assert_eq!(read.execute_with_context(&ctx, &[]).await?, "No todos.");
+    let output = write
+        .execute_with_context(
+            &ctx,
+            &args(&[r#"[
+                {"content": "Write parser", "status": "completed", "priority": "high"},
+                {"content": "Add tests", "status": "in_progress"},
+                {"id": "docs", "content": "Update docs", "priority": "low"}
+            ]"#]),
+        )
+        .await?;
+    assert_eq!(
+        output,
+        "2 of 3 todos remaining\n[x] 1 (high) Write parser\n[>] 2 (medium) Add tests\n[ ] docs (low) Update docs"
+    );
+    assert_eq!(read.execute_with_context(&ctx, &[]).await?, output);
+    assert_eq!(read.execute(&[]).await?, "No todos.");
+
+    let Some(AgentEvent::TodoUpdated {
+        session_id,
+        agent,
+        todos: list,
+    }) = rx.recv().await
+    else {
+        panic!("expected a todo event");
+    };
+    assert_eq!((session_id, agent.as_str()), (ctx.session_id, "build"));
+    assert_eq!(list[1].status, TodoStatus::InProgress);
+    assert_eq!(list[2].priority, TodoPriority::Low);
+    let event = serde_json::to_value(AgentEvent::TodoUpdated {
+        session_id,
+        agent,
+        todos: list,
+    })?;
+    assert_eq!(event["type"], "todo_updated");
+    assert_eq!(event["todos"][1]["status"], "in_progress");
+
+    write
+        .execute_with_context(&ctx, &args(&[r#"{"todos": []}"#]))
+        .await?;
+    assert_eq!(todos.get(ctx.session_id), []);
+
+    let err = write
+        .execute_with_context(
+            &ctx,
+            &args(&[r#"[{"id": "a", "content": "x"}, {"id": "a", "content": "y"}]"#]),
+        )
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("duplicate todo id a"), "{err}");
+    let err = write
+        .execute_with_context(&ctx, &args(&[r#"[{"content": "x", "status": "done"}]"#]))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("invalid todo list"), "{err}");
+    assert!(write.execute(&[]).await?.starts_with("Usage:"));
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn bash_pty_writes_to_a_stalled_process_do_not_block_other_calls() -> Result<()> {
+    let tool = Arc::new(BashTool::new());
+    tool.execute(&args(&["--pty", "stty -echo; sleep 2; cat > /dev/null"]))
+        .await?;
+
+    let keys = ("x".repeat(1023) + "<enter>").repeat(1024);
+    let sender = tokio::spawn({
+        let tool = tool.clone();
+        async move { tool.execute(&args(&["--send", "1", &keys])).await }
+    });
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(!sender.is_finished(), "the write should stall");
+
+    let asked = std::time::Instant::now();
+    let screen = tool.execute(&args(&["--screen", "1"])).await?;
+    assert!(asked.elapsed() < Duration::from_secs(1));
+    assert!(screen.starts_with("PTY session 1 is running."), "{screen}");
+    tokio::time::timeout(Duration::from_secs(10), sender).await???;
+    assert_eq!(
+        tool.execute(&args(&["--terminate", "1"])).await?,
+        "Terminated PTY session 1."
+    );
+    Ok(())
+}
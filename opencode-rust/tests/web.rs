@@ -0,0 +1,381 @@
+use std::time::Duration;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::Json;
+use axum::Router;
+use axum::extract::Query;
+use axum::http::HeaderMap;
+use axum::http::header::CONTENT_TYPE;
+use axum::routing::get;
+use opencode_rust::tool::core::Tool;
+use opencode_rust::tool::web::{MAX_RESPONSE_BYTES, WebFetchTool};
+use opencode_rust::tool::web_search::{
+    JsonSearchBackend, MockSearchBackend, SearchBackend, SearchResult, SearxngBackend,
+    WebSearchTool,
+};
+use opencode_rust::util::config::{Info, ProviderOptions};
+use opencode_rust::util::html;
+use serde_json::{Value, json};
+use tokio::net::TcpListener;
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>Docs</title><style>body { color: red; }</style></head>
+<body>
+  <nav><a href="/">Home</a></nav>
+  <h1>Getting   started</h1>
+  <p>Install the <strong>CLI</strong> with <code>cargo install</code>, then read
+     the <a href="https://example.com/guide">guide</a>.</p>
+  <script>alert("hidden")</script>
+  <pre><code class="language-rust">fn main() {
+    println!("hi");
+}</code></pre>
+  <ul>
+    <li>First</li>
+    <li>Second
+      <ol><li>Nested</li></ol>
+    </li>
+  </ul>
+  <table>
+    <tr><th>Name</th><th>Value</th></tr>
+    <tr><td>a|b</td><td>1</td></tr>
+  </table>
+  <blockquote><p>Quoted text</p></blockquote>
+</body>
+</html>"#;
+
+async fn serve() -> Result<String> {
+    let router = Router::new()
+        .route(
+            "/page",
+            get(|| async { ([(CONTENT_TYPE, "text/html; charset=utf-8")], PAGE) }),
+        )
+        .route(
+            "/fragment",
+            get(|| async { ([(CONTENT_TYPE, "text/html")], "<p>no body tag") }),
+        )
+        .route("/plain", get(|| async { "just <b>text</b>" }))
+        .route(
+            "/image",
+            get(|| async { ([(CONTENT_TYPE, "image/png")], vec![0u8, 1, 2]) }),
+        )
+        .route(
+            "/big",
+            get(|| async {
+                (
+                    [(CONTENT_TYPE, "text/plain")],
+                    "x".repeat(MAX_RESPONSE_BYTES + 1),
+                )
+            }),
+        )
+        .route(
+            "/slow",
+            get(|| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                "late"
+            }),
+        );
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let base = format!("http://{}", listener.local_addr()?);
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    Ok(base)
+}
+
+fn args(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+#[test]
+fn converts_deeply_nested_html_without_overflowing() {
+    let depth = 100_000;
+    let page = format!(
+        "<html><body>{}<p>deep <b>text</b></p>{}</body></html>",
+        "<div>".repeat(depth),
+        "</div>".repeat(depth)
+    );
+    assert_eq!(html::to_markdown(&page), "deep text");
+    assert_eq!(html::to_text(&page), "deep text");
+
+    // Implied table sections nest deeper than the tags that were counted.
+    let depth = 1_000;
+    let page = format!(
+        "<table><tr><td>{}cell <script>hidden()</script>{}</td></tr></table>",
+        "<table><tr><td>".repeat(depth),
+        "</td></tr></table>".repeat(depth)
+    );
+    assert!(html::to_text(&page).contains("cell"));
+    assert!(!html::to_markdown(&page).contains("hidden"));
+}
+
+#[test]
+fn converts_html_to_markdown() {
+    assert_eq!(
+        html::to_markdown(PAGE),
+        "[Home](/)
+
+# Getting started
+
+Install the **CLI** with `cargo install`, then read the [guide](https://example.com/guide).
+
+```rust
+fn main() {
+    println!(\"hi\");
+}
+```
+
+- First
+- Second
+  1. Nested
+
+| Name | Value |
+| --- | --- |
+| a\\|b | 1 |
+
+> Quoted text"
+    );
+}
+
+#[test]
+fn extracts_plain_text() {
+    let text = html::to_text(PAGE);
+    assert!(text.starts_with("Home\n\nGetting started\n\nInstall the CLI with cargo install"));
+    assert!(!text.contains("alert"));
+    assert!(!text.contains("color: red"));
+    assert!(text.contains("Name\tValue"));
+}
+
+#[tokio::test]
+async fn fetches_in_each_format() -> Result<()> {
+    let base = serve().await?;
+    let page = format!("{base}/page");
+
+    let markdown = WebFetchTool.execute(&args(&[&page])).await?;
+    assert!(markdown.contains("# Getting started"));
+    let text = WebFetchTool.execute(&args(&[&page, "text"])).await?;
+    assert!(text.contains("Getting started") && !text.contains('#'));
+    let raw = WebFetchTool.execute(&args(&[&page, "html"])).await?;
+    assert_eq!(raw, PAGE);
+
+    let fragment = WebFetchTool
+        .execute(&args(&[&format!("{base}/fragment")]))
+        .await?;
+    assert_eq!(fragment, "no body tag");
+    let plain = WebFetchTool
+        .execute(&args(&[&format!("{base}/plain")]))
+        .await?;
+    assert_eq!(plain, "just <b>text</b>");
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_bad_urls_binaries_oversized_and_slow_responses() -> Result<()> {
+    let base = serve().await?;
+    let error =
+        |result: opencode_rust::util::error::Result<String>| result.unwrap_err().to_string();
+
+    let err = error(WebFetchTool.execute(&args(&["file:///etc/passwd"])).await);
+    assert!(err.contains("must start with http:// or https://"), "{err}");
+    let err = error(WebFetchTool.execute(&args(&["not a url"])).await);
+    assert!(err.contains("invalid URL"), "{err}");
+
+    let err = error(
+        WebFetchTool
+            .execute(&args(&[&format!("{base}/image")]))
+            .await,
+    );
+    assert!(err.contains("unsupported content type image/png"), "{err}");
+    let err = error(WebFetchTool.execute(&args(&[&format!("{base}/big")])).await);
+    assert!(err.contains("response too large"), "{err}");
+    let err = error(
+        WebFetchTool
+            .execute(&args(&[&format!("{base}/missing")]))
+            .await,
+    );
+    assert!(err.contains("404"), "{err}");
+    let err = error(
+        WebFetchTool
+            .execute(&args(&[&format!("{base}/slow"), "text", "1"]))
+            .await,
+    );
+    assert!(err.contains("timed out after 1s"), "{err}");
+
+    assert!(
+        WebFetchTool
+            .execute(&args(&[&base, "pdf"]))
+            .await?
+            .starts_with("Usage:")
+    );
+    assert!(
+        WebFetchTool
+            .execute(&args(&[&base, "text", "0"]))
+            .await?
+            .starts_with("Usage:")
+    );
+    Ok(())
+}
+
+async fn serve_search() -> Result<String> {
+    let router = Router::new()
+        .route(
+            "/api",
+            get(
+                |Query(params): Query<HashMap<String, String>>, headers: HeaderMap| async move {
+                    let key = headers
+                        .get("x-api-key")
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or_default()
+                        .to_string();
+                    Json(json!({
+                        "data": {
+                            "items": [
+                                {
+                                    "name": format!("{} docs", params["query"]),
+                                    "link": "https://docs.example.com",
+                                    "summary": format!("key={key} n={} lang={}", params["n"], params["lang"]),
+                                },
+                                { "name": "missing url" },
+                            ]
+                        }
+                    }))
+                },
+            ),
+        )
+        .route(
+            "/searx/search",
+            get(|Query(params): Query<HashMap<String, String>>| async move {
+                assert_eq!(params["format"], "json");
+                Json(json!({
+                    "results": [
+                        { "title": "First", "url": "https://one.example.com", "content": "The <b>first</b> result" },
+                        { "title": "Duplicate", "url": "https://one.example.com", "content": "" },
+                        { "title": params["q"], "url": "https://two.example.com", "content": "Second" },
+                    ]
+                }))
+            }),
+        );
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let base = format!("http://{}", listener.local_addr()?);
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    Ok(base)
+}
+
+fn options(value: Value) -> Result<ProviderOptions> {
+    Ok(serde_json::from_value(value)?)
+}
+
+#[tokio::test]
+async fn web_search_ranks_mock_results() -> Result<()> {
+    let backend = Arc::new(MockSearchBackend::new(vec![
+        SearchResult::new(
+            "Rust",
+            "https://www.rust-lang.org",
+            "A  language\nempowering everyone",
+        ),
+        SearchResult::new("", "https://crates.io", ""),
+        SearchResult::new("Book", "https://doc.rust-lang.org/book", "x".repeat(400)),
+    ]));
+    let tool = WebSearchTool::new(backend.clone());
+
+    let output = tool.execute(&args(&["rust language", "2"])).await?;
+    assert_eq!(
+        output,
+        "Search results for \"rust language\":
+
+1. Rust
+   https://www.rust-lang.org
+   A language empowering everyone
+
+2. https://crates.io
+   https://crates.io"
+    );
+    let output = tool.execute(&args(&["rust"])).await?;
+    assert!(output.contains(&format!("\n   {}...", "x".repeat(300))));
+    assert_eq!(backend.queries(), ["rust language", "rust"]);
+
+    let empty = WebSearchTool::new(Arc::new(MockSearchBackend::default()));
+    assert_eq!(
+        empty.execute(&args(&["nothing"])).await?,
+        "No results found for \"nothing\"."
+    );
+    assert!(tool.execute(&args(&[" "])).await?.starts_with("Usage:"));
+    assert!(
+        tool.execute(&args(&["q", "0"]))
+            .await?
+            .starts_with("Usage:")
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn json_backend_follows_configured_layout() -> Result<()> {
+    let base = serve_search().await?;
+    let backend = JsonSearchBackend::from_options(&options(json!({
+        "baseURL": format!("{base}/api"),
+        "apiKey": "secret",
+        "apiKeyHeader": "x-api-key",
+        "queryParam": "query",
+        "limitParam": "n",
+        "params": { "lang": "en" },
+        "resultsPath": "data.items",
+        "titleField": "name",
+        "urlField": "link",
+        "snippetField": "summary",
+    }))?)?;
+    assert_eq!(
+        backend.search("tokio", 5).await?,
+        [SearchResult::new(
+            "tokio docs",
+            "https://docs.example.com",
+            "key=secret n=5 lang=en"
+        )]
+    );
+
+    let wrong_path = JsonSearchBackend::from_options(&options(json!({
+        "baseURL": format!("{base}/api"),
+        "queryParam": "query",
+        "limitParam": "n",
+        "params": { "lang": "en" },
+    }))?)?;
+    let err = wrong_path.search("tokio", 5).await.unwrap_err().to_string();
+    assert!(err.contains("no result array at results"), "{err}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn searxng_backend_through_config() -> Result<()> {
+    let base = serve_search().await?;
+    let backend = SearxngBackend::from_options(&options(json!({
+        "baseURL": format!("{base}/searx/"),
+    }))?)?;
+    assert_eq!(backend.search("axum", 10).await?.len(), 3);
+
+    let info: Info = serde_json::from_value(json!({
+        "search": { "backend": "searxng", "options": { "baseURL": format!("{base}/searx") } }
+    }))?;
+    let tool = WebSearchTool::from_info(&info)?.expect("search is configured");
+    let output = tool.execute(&args(&["axum"])).await?;
+    assert_eq!(
+        output,
+        "Search results for \"axum\":
+
+1. First
+   https://one.example.com
+   The first result
+
+2. axum
+   https://two.example.com
+   Second"
+    );
+
+    assert!(WebSearchTool::from_info(&Info::default())?.is_none());
+    let missing: Info = serde_json::from_value(json!({ "search": { "backend": "json" } }))?;
+    let err = WebSearchTool::from_info(&missing)
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(err.contains("requires baseURL"), "{err}");
+    Ok(())
+}